@@ -1,7 +1,16 @@
 use core::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::str::FromStr;
 
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    category::explicit_null,
+    loan::LoanTerms,
+    money::{Currency, Money},
+};
 
 trait FromRow: Sized {
     fn from_row(row: &rusqlite::Row) -> Result<Box<dyn BankAccount>, rusqlite::Error>;
@@ -12,9 +21,33 @@ pub enum AccountType {
     Savings,
     Credit,
     Chequing,
+    // brokerage accounts, see `InvestmentAccount`
+    NonRegistered,
+    Tfsa,
+    Rrsp,
+    Fhsa,
+    // mortgages and other installment loans, see `LoanAccount`
+    Loan,
     Unknown,
 }
 
+impl AccountType {
+    pub fn is_investment(&self) -> bool {
+        matches!(
+            self,
+            AccountType::NonRegistered | AccountType::Tfsa | AccountType::Rrsp | AccountType::Fhsa
+        )
+    }
+
+    // Registered plans limit how much can be put in each year, see `investment::room`
+    pub fn is_registered(&self) -> bool {
+        matches!(
+            self,
+            AccountType::Tfsa | AccountType::Rrsp | AccountType::Fhsa
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct InvalidAccountType;
 impl fmt::Display for InvalidAccountType {
@@ -31,6 +64,11 @@ impl FromStr for AccountType {
             "credit" => Ok(AccountType::Credit),
             "chequing" => Ok(AccountType::Chequing),
             "checking" => Ok(AccountType::Chequing),
+            "non-registered" | "investment" => Ok(AccountType::NonRegistered),
+            "tfsa" => Ok(AccountType::Tfsa),
+            "rrsp" => Ok(AccountType::Rrsp),
+            "fhsa" => Ok(AccountType::Fhsa),
+            "loan" | "mortgage" => Ok(AccountType::Loan),
             _ => Err(InvalidAccountType),
         }
     }
@@ -42,11 +80,163 @@ impl fmt::Display for AccountType {
             AccountType::Savings => write!(f, "Savings"),
             AccountType::Credit => write!(f, "Credit"),
             AccountType::Chequing => write!(f, "Chequing"),
+            AccountType::NonRegistered => write!(f, "Non-registered"),
+            AccountType::Tfsa => write!(f, "TFSA"),
+            AccountType::Rrsp => write!(f, "RRSP"),
+            AccountType::Fhsa => write!(f, "FHSA"),
+            AccountType::Loan => write!(f, "Loan"),
             AccountType::Unknown => write!(f, "Unknown"),
         }
     }
 }
 
+// The number a bank prints for an account. It is never stored: accounts are
+// found again by `key`, and shown by the last four of the number's letters and
+// digits. The account itself is identified by the `account_number` the database
+// assigns when it is first imported.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BankNumber {
+    normalized: String,
+    pub mask: String,
+    // what versions before stable keys derived from the number, see `legacy_hash`
+    pub legacy_hash: i64,
+}
+
+impl BankNumber {
+    pub fn new(number: &str) -> BankNumber {
+        let normalized: String = number
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        let mask = normalized[normalized.len().saturating_sub(4)..].to_string();
+        BankNumber {
+            legacy_hash: legacy_hash(number),
+            normalized,
+            mask,
+        }
+    }
+
+    // The stored key, an HMAC-SHA256 of the number under the installation's
+    // secret. Bank numbers are short enough to try them all, so a plain hash of
+    // one would give it away to anyone holding the database.
+    pub fn key(&self, secret: &[u8]) -> String {
+        hex(&hmac_sha256(secret, self.normalized.as_bytes()))
+    }
+
+    // The unkeyed SHA-256 that versions before the secret stored as the key, only
+    // used to match their accounts once
+    pub fn unkeyed_key(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(b"account:");
+        hasher.update(self.normalized.as_bytes());
+        hex(&hasher.finalize())
+    }
+
+    // Stands in for the account number on imported rows until the account is
+    // looked up or created. Never stored.
+    pub fn provisional(&self) -> i64 {
+        i64::from_str_radix(&self.unkeyed_key()[..12], 16).unwrap()
+    }
+}
+
+// the number itself stays out of logs
+impl fmt::Debug for BankNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BankNumber")
+            .field("mask", &self.mask)
+            .field("legacy_hash", &self.legacy_hash)
+            .finish_non_exhaustive()
+    }
+}
+
+// HMAC as in RFC 2104, over SHA-256's 64 byte blocks
+fn hmac_sha256(secret: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if secret.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(secret));
+    } else {
+        block[..secret.len()].copy_from_slice(secret);
+    }
+    let pad = |byte: u8| block.map(|b| b ^ byte);
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Account numbers used to be this std hash of the number as exported, which
+// Rust does not promise to keep stable. Only used to match accounts imported
+// before stable keys, whose old number is kept in `Account.legacy_hash`.
+fn legacy_hash(number: &str) -> i64 {
+    let mut hasher = DefaultHasher::new();
+    number.hash(&mut hasher);
+    (hasher.finish() & 0x0000_FFFF_FFFF_FFFF) as i64
+}
+
+// How the owner labels an account, shown with it in listings
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountLabel {
+    pub nickname: Option<String>,
+    pub institution: Option<String>,
+    // last four characters of the bank's number, set on import
+    #[serde(skip_deserializing)]
+    pub mask: Option<String>,
+}
+
+// Renames an account. A missing field is left alone and an explicit null clears it.
+#[derive(Debug, Default, Deserialize)]
+pub struct AccountUpdate {
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub nickname: Option<Option<String>>,
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub institution: Option<Option<String>>,
+}
+
+impl AccountUpdate {
+    pub fn apply(self, label: &mut AccountLabel) {
+        if let Some(nickname) = self.nickname {
+            label.nickname = nickname;
+        }
+        if let Some(institution) = self.institution {
+            label.institution = institution;
+        }
+    }
+}
+
+// When the user held an account. Accounts entered by hand are opened on a date
+// with a balance, imported ones have no opening and count from their first
+// transaction. Any account can be closed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct AccountPeriod {
+    pub opened_on: Option<NaiveDate>,
+    pub opening_balance: Option<Money>,
+    pub closed_on: Option<NaiveDate>,
+}
+
+impl AccountPeriod {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.opened_on.is_none_or(|opened| date >= opened)
+            && self.closed_on.is_none_or(|closed| date <= closed)
+    }
+}
+
+// Amounts on an account row are stored in minor units of the account's own
+// currency, which older rows without the column default to CAD.
+fn row_money(row: &rusqlite::Row, idx: usize) -> Result<Money, rusqlite::Error> {
+    let currency = row.get::<_, Option<Currency>>(6)?.unwrap_or(Currency::CAD);
+    Ok(Money::from_minor(row.get(idx)?, currency))
+}
+
 pub fn bank_account_from_row(row: &rusqlite::Row) -> Result<Box<dyn BankAccount>, rusqlite::Error> {
     let account_type =
         AccountType::from_str(&row.get::<_, String>(1)?).unwrap_or(AccountType::Unknown);
@@ -54,6 +244,10 @@ pub fn bank_account_from_row(row: &rusqlite::Row) -> Result<Box<dyn BankAccount>
         AccountType::Savings => SavingsAccount::from_row(row),
         AccountType::Credit => CreditAccount::from_row(row),
         AccountType::Chequing => ChequingAccount::from_row(row),
+        AccountType::NonRegistered | AccountType::Tfsa | AccountType::Rrsp | AccountType::Fhsa => {
+            InvestmentAccount::from_row(row)
+        }
+        AccountType::Loan => LoanAccount::from_row(row),
         AccountType::Unknown => Err(rusqlite::Error::QueryReturnedNoRows),
     }
 }
@@ -64,19 +258,33 @@ pub enum Account {
     Savings(SavingsAccount),
     Credit(CreditAccount),
     Chequing(ChequingAccount),
+    Investment(InvestmentAccount),
+    Loan(LoanAccount),
+}
+
+impl Account {
+    pub fn account_number(&self) -> i64 {
+        match self {
+            Account::Savings(account) => account.account_number,
+            Account::Credit(account) => account.account_number,
+            Account::Chequing(account) => account.account_number,
+            Account::Investment(account) => account.account_number,
+            Account::Loan(account) => account.account_number,
+        }
+    }
 }
 
 pub trait BankAccount: Send + Sync {
     fn user_id(&self) -> i64;
-    fn deposit(&mut self, amount: f64);
-    fn withdraw(&mut self, amount: f64);
-    fn balance(&self) -> f64;
+    fn deposit(&mut self, amount: Money);
+    fn withdraw(&mut self, amount: Money);
+    fn balance(&self) -> Money;
     fn account_type(&self) -> AccountType;
     fn account_number(&self) -> &i64;
-    fn credit_limit(&self) -> f64;
+    fn credit_limit(&self) -> Money;
     fn interest_rate(&self) -> f64;
-    fn set_balance(&mut self, balance: f64);
-    fn set_credit_limit(&mut self, limit: f64);
+    fn set_balance(&mut self, balance: Money);
+    fn set_credit_limit(&mut self, limit: Money);
     fn as_enum(self: Box<Self>) -> Account;
 }
 
@@ -84,15 +292,17 @@ pub trait BankAccount: Send + Sync {
 pub struct SavingsAccount {
     pub user_id: i64,
     pub account_number: i64,
-    pub balance: f64,
-    pub interest_rate: f64, // will be hard coded
+    pub balance: Money,
+    // yearly rate as a fraction, 0.025 for 2.5%, see `interest::InterestPlan`
+    // for tiers and promotions
+    pub interest_rate: f64,
 }
 
 impl SavingsAccount {
     pub fn new(
         user_id: i64,
         account_number: i64,
-        balance: f64,
+        balance: Money,
         interest_rate: f64,
     ) -> SavingsAccount {
         SavingsAccount {
@@ -111,15 +321,15 @@ impl BankAccount for SavingsAccount {
     fn account_type(&self) -> AccountType {
         AccountType::Savings
     }
-    fn balance(&self) -> f64 {
+    fn balance(&self) -> Money {
         self.balance
     }
 
-    fn deposit(&mut self, amount: f64) {
+    fn deposit(&mut self, amount: Money) {
         self.balance += amount;
     }
 
-    fn withdraw(&mut self, amount: f64) {
+    fn withdraw(&mut self, amount: Money) {
         self.balance -= amount;
     }
 
@@ -131,14 +341,14 @@ impl BankAccount for SavingsAccount {
         self.user_id
     }
 
-    fn credit_limit(&self) -> f64 {
-        0.0
+    fn credit_limit(&self) -> Money {
+        Money::zero(self.balance.currency())
     }
-    fn set_balance(&mut self, balance: f64) {
+    fn set_balance(&mut self, balance: Money) {
         self.balance = balance;
     }
 
-    fn set_credit_limit(&mut self, _limit: f64) {
+    fn set_credit_limit(&mut self, _limit: Money) {
         todo!();
     }
 
@@ -152,7 +362,7 @@ impl FromRow for SavingsAccount {
         Ok(Box::new(SavingsAccount::new(
             row.get(0)?,
             row.get(2)?,
-            row.get(3)?,
+            row_money(row, 3)?,
             row.get(4)?,
         )))
     }
@@ -162,26 +372,30 @@ impl FromRow for SavingsAccount {
 pub struct CreditAccount {
     pub user_id: i64,
     pub account_number: i64,
-    pub balance_owed: f64,
-    pub credit_limit: f64, // will be hard coded
+    pub balance_owed: Money,
+    pub credit_limit: Money, // will be hard coded
+    // APR as a fraction, 0.2099 for 20.99%
+    #[serde(default)]
+    pub interest_rate: f64,
 }
 
 impl CreditAccount {
     pub fn new(
         user_id: i64,
         account_number: i64,
-        balance_owed: f64,
-        credit_limit: f64,
+        balance_owed: Money,
+        credit_limit: Money,
     ) -> CreditAccount {
         CreditAccount {
             user_id,
             account_number,
             balance_owed,
             credit_limit,
+            interest_rate: 0.0,
         }
     }
 
-    pub fn credit_limit(&self) -> f64 {
+    pub fn credit_limit(&self) -> Money {
         self.credit_limit
     }
 }
@@ -195,15 +409,15 @@ impl BankAccount for CreditAccount {
         AccountType::Credit
     }
 
-    fn balance(&self) -> f64 {
+    fn balance(&self) -> Money {
         self.balance_owed
     }
 
-    fn deposit(&mut self, amount: f64) {
+    fn deposit(&mut self, amount: Money) {
         self.balance_owed += amount;
     }
 
-    fn withdraw(&mut self, amount: f64) {
+    fn withdraw(&mut self, amount: Money) {
         self.balance_owed -= amount;
     }
 
@@ -212,18 +426,18 @@ impl BankAccount for CreditAccount {
     }
 
     fn interest_rate(&self) -> f64 {
-        0.0
+        self.interest_rate
     }
 
-    fn credit_limit(&self) -> f64 {
+    fn credit_limit(&self) -> Money {
         self.credit_limit
     }
 
-    fn set_balance(&mut self, balance: f64) {
+    fn set_balance(&mut self, balance: Money) {
         self.balance_owed = balance;
     }
 
-    fn set_credit_limit(&mut self, limit: f64) {
+    fn set_credit_limit(&mut self, limit: Money) {
         self.credit_limit = limit;
     }
     fn as_enum(self: Box<Self>) -> Account {
@@ -233,12 +447,15 @@ impl BankAccount for CreditAccount {
 
 impl FromRow for CreditAccount {
     fn from_row(row: &rusqlite::Row) -> Result<Box<dyn BankAccount>, rusqlite::Error> {
-        Ok(Box::new(CreditAccount::new(
-            row.get(0)?,
-            row.get(2)?,
-            row.get(3)?,
-            row.get(5)?,
-        )))
+        Ok(Box::new(CreditAccount {
+            interest_rate: row.get::<_, Option<f64>>(4)?.unwrap_or_default(),
+            ..CreditAccount::new(
+                row.get(0)?,
+                row.get(2)?,
+                row_money(row, 3)?,
+                row_money(row, 5)?,
+            )
+        }))
     }
 }
 
@@ -246,11 +463,11 @@ impl FromRow for CreditAccount {
 pub struct ChequingAccount {
     pub user_id: i64,
     pub account_number: i64,
-    pub balance: f64,
+    pub balance: Money,
 }
 
 impl ChequingAccount {
-    pub fn new(user_id: i64, account_number: i64, balance: f64) -> ChequingAccount {
+    pub fn new(user_id: i64, account_number: i64, balance: Money) -> ChequingAccount {
         ChequingAccount {
             user_id,
             account_number,
@@ -266,13 +483,13 @@ impl BankAccount for ChequingAccount {
     fn account_type(&self) -> AccountType {
         AccountType::Chequing
     }
-    fn balance(&self) -> f64 {
+    fn balance(&self) -> Money {
         self.balance
     }
-    fn deposit(&mut self, amount: f64) {
+    fn deposit(&mut self, amount: Money) {
         self.balance += amount;
     }
-    fn withdraw(&mut self, amount: f64) {
+    fn withdraw(&mut self, amount: Money) {
         self.balance -= amount;
     }
 
@@ -284,15 +501,15 @@ impl BankAccount for ChequingAccount {
         0.0
     }
 
-    fn credit_limit(&self) -> f64 {
-        0.0
+    fn credit_limit(&self) -> Money {
+        Money::zero(self.balance.currency())
     }
 
-    fn set_balance(&mut self, balance: f64) {
+    fn set_balance(&mut self, balance: Money) {
         self.balance = balance;
     }
 
-    fn set_credit_limit(&mut self, _limit: f64) {
+    fn set_credit_limit(&mut self, _limit: Money) {
         todo!();
     }
 
@@ -306,7 +523,235 @@ impl FromRow for ChequingAccount {
         Ok(Box::new(ChequingAccount::new(
             row.get(0)?,
             row.get(2)?,
-            row.get(3)?,
+            row_money(row, 3)?,
         )))
     }
 }
+
+// A brokerage account, registered or not. The balance is the cash held, the
+// securities bought with it are tracked as trades, see `investment::holdings`.
+#[derive(Serialize, Deserialize)]
+pub struct InvestmentAccount {
+    pub user_id: i64,
+    pub account_number: i64,
+    pub account_type: AccountType,
+    pub cash: Money,
+}
+
+impl InvestmentAccount {
+    pub fn new(
+        user_id: i64,
+        account_number: i64,
+        account_type: AccountType,
+        cash: Money,
+    ) -> InvestmentAccount {
+        InvestmentAccount {
+            user_id,
+            account_number,
+            account_type,
+            cash,
+        }
+    }
+}
+
+impl BankAccount for InvestmentAccount {
+    fn account_number(&self) -> &i64 {
+        &self.account_number
+    }
+    fn account_type(&self) -> AccountType {
+        self.account_type
+    }
+    fn balance(&self) -> Money {
+        self.cash
+    }
+    fn deposit(&mut self, amount: Money) {
+        self.cash += amount;
+    }
+    fn withdraw(&mut self, amount: Money) {
+        self.cash -= amount;
+    }
+
+    fn user_id(&self) -> i64 {
+        self.user_id
+    }
+
+    fn interest_rate(&self) -> f64 {
+        0.0
+    }
+
+    fn credit_limit(&self) -> Money {
+        Money::zero(self.cash.currency())
+    }
+
+    fn set_balance(&mut self, balance: Money) {
+        self.cash = balance;
+    }
+
+    fn set_credit_limit(&mut self, _limit: Money) {
+        // investment accounts have no credit limit
+    }
+
+    fn as_enum(self: Box<Self>) -> Account {
+        Account::Investment(*self)
+    }
+}
+
+impl FromRow for InvestmentAccount {
+    fn from_row(row: &rusqlite::Row) -> Result<Box<dyn BankAccount>, rusqlite::Error> {
+        Ok(Box::new(InvestmentAccount::new(
+            row.get(0)?,
+            row.get(2)?,
+            AccountType::from_str(&row.get::<_, String>(1)?).unwrap_or(AccountType::Unknown),
+            row_money(row, 3)?,
+        )))
+    }
+}
+
+// A mortgage or other loan paid down in installments. The balance is the amount
+// still owed. Imported loans have no terms until the user enters them, see
+// `loan::set_terms`.
+#[derive(Serialize, Deserialize)]
+pub struct LoanAccount {
+    pub user_id: i64,
+    pub account_number: i64,
+    pub balance_owed: Money,
+    pub terms: Option<LoanTerms>,
+}
+
+impl LoanAccount {
+    pub fn new(
+        user_id: i64,
+        account_number: i64,
+        balance_owed: Money,
+        terms: Option<LoanTerms>,
+    ) -> LoanAccount {
+        LoanAccount {
+            user_id,
+            account_number,
+            balance_owed,
+            terms,
+        }
+    }
+}
+
+impl BankAccount for LoanAccount {
+    fn account_number(&self) -> &i64 {
+        &self.account_number
+    }
+    fn account_type(&self) -> AccountType {
+        AccountType::Loan
+    }
+    fn balance(&self) -> Money {
+        self.balance_owed
+    }
+    fn deposit(&mut self, amount: Money) {
+        self.balance_owed += amount;
+    }
+    fn withdraw(&mut self, amount: Money) {
+        self.balance_owed -= amount;
+    }
+
+    fn user_id(&self) -> i64 {
+        self.user_id
+    }
+
+    fn interest_rate(&self) -> f64 {
+        self.terms.as_ref().map(|t| t.rate).unwrap_or_default()
+    }
+
+    fn credit_limit(&self) -> Money {
+        Money::zero(self.balance_owed.currency())
+    }
+
+    fn set_balance(&mut self, balance: Money) {
+        self.balance_owed = balance;
+    }
+
+    fn set_credit_limit(&mut self, _limit: Money) {
+        // a loan has a balance owed rather than a credit limit
+    }
+
+    fn as_enum(self: Box<Self>) -> Account {
+        Account::Loan(*self)
+    }
+}
+
+// Text columns holding a value parsed with `FromStr`
+fn row_parsed<T>(row: &rusqlite::Row, column: &str) -> Result<Option<T>, rusqlite::Error>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let Some(text) = row.get::<_, Option<String>>(column)? else {
+        return Ok(None);
+    };
+    text.parse().map(Some).map_err(|e: T::Err| {
+        rusqlite::Error::FromSqlConversionFailure(
+            row.as_ref().column_index(column).unwrap_or_default(),
+            rusqlite::types::Type::Text,
+            e.to_string().into(),
+        )
+    })
+}
+
+impl FromRow for LoanAccount {
+    fn from_row(row: &rusqlite::Row) -> Result<Box<dyn BankAccount>, rusqlite::Error> {
+        let balance_owed = row_money(row, 3)?;
+        let currency = balance_owed.currency();
+        // the terms are set together, so a principal means all of them are there
+        let terms = match row.get::<_, Option<i64>>("principal")? {
+            Some(principal) => Some(LoanTerms {
+                principal: Money::from_minor(principal, currency),
+                rate: row.get(4)?,
+                amortization_months: row.get("amortization_months")?,
+                term_months: row.get("term_months")?,
+                frequency: row_parsed(row, "payment_frequency")?.unwrap_or_default(),
+                compounding: row_parsed(row, "compounding")?.unwrap_or_default(),
+                first_payment: row.get("first_payment_date")?,
+                payment: row
+                    .get::<_, Option<i64>>("payment")?
+                    .map(|minor| Money::from_minor(minor, currency)),
+            }),
+            None => None,
+        };
+        Ok(Box::new(LoanAccount::new(
+            row.get(0)?,
+            row.get(2)?,
+            balance_owed,
+            terms,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_matches_rfc_4231() {
+        assert_eq!(
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // keys longer than a block are hashed first
+        assert_eq!(
+            hex(&hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn test_bank_number_key_ignores_formatting() {
+        let number = BankNumber::new("00102-5012345");
+        assert_eq!(number.mask, "2345");
+        assert_eq!(
+            number.key(b"secret"),
+            BankNumber::new("00102 5012345").key(b"secret")
+        );
+        assert_ne!(number.key(b"secret"), number.key(b"other secret"));
+        assert!(!format!("{:?}", number).contains("5012345"));
+    }
+}
@@ -1,20 +1,39 @@
 use std::sync::{Arc, Mutex};
 
-use crate::{database::Database, user::User};
+use tokio::task;
 
+use crate::{database::Database, error::ApiError};
+
+// Shared by every request. The logged in user is not part of it,
+// handlers resolve it per request with `auth::AuthUser`.
 #[derive(Clone)]
 pub struct AppState {
     pub db: Arc<Mutex<Database>>,
-    pub user: Arc<Mutex<Option<User>>>,
 }
 
 impl AppState {
     pub fn new(db: Database) -> AppState {
         AppState {
             db: Arc::new(Mutex::new(db)),
-            user: Arc::new(Mutex::new(None)),
         }
     }
+
+    // Runs `f` against the database on the blocking thread pool,
+    // since rusqlite calls would otherwise stall the async runtime
+    pub async fn with_db<T, F>(&self, f: F) -> Result<T, ApiError>
+    where
+        F: FnOnce(&Database) -> Result<T, ApiError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let conn = self.db.clone();
+        task::spawn_blocking(move || {
+            let conn = conn
+                .lock()
+                .map_err(|_| ApiError::internal("database lock poisoned"))?;
+            f(&conn)
+        })
+        .await?
+    }
 }
 
 // pub trait App {
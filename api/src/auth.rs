@@ -0,0 +1,347 @@
+use core::fmt;
+
+use crate::{app::AppState, database::Database, error::ApiError, user::User};
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap},
+};
+use lazy_static::lazy_static;
+use rand::Rng;
+use rusqlite::OptionalExtension;
+use sha2::{Digest, Sha256};
+
+pub const SESSION_COOKIE: &str = "session";
+pub const SESSION_TTL_SECS: i64 = 30 * 24 * 60 * 60;
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+lazy_static! {
+    // Checked when a login names no user with a password, so the answer takes
+    // as long as a wrong password and does not tell which names exist
+    static ref DUMMY_HASH: String = hash_password("not a real password").unwrap();
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    InvalidCredentials,
+    UsernameTaken,
+    WeakPassword,
+    Hash(String),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::InvalidCredentials => write!(f, "Invalid username or password"),
+            AuthError::UsernameTaken => write!(f, "Username is already taken"),
+            AuthError::WeakPassword => write!(
+                f,
+                "Password must be at least {} characters",
+                MIN_PASSWORD_LEN
+            ),
+            AuthError::Hash(msg) => write!(f, "Password hashing error: {}", msg),
+            AuthError::Database(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl From<rusqlite::Error> for AuthError {
+    fn from(err: rusqlite::Error) -> Self {
+        AuthError::Database(err)
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String, AuthError> {
+    let salt_bytes: [u8; 16] = rand::rng().random();
+    let salt = SaltString::encode_b64(&salt_bytes).map_err(|e| AuthError::Hash(e.to_string()))?;
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| AuthError::Hash(e.to_string()))
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    match PasswordHash::new(password_hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+// Random bearer token handed to the client. Only its hash is stored,
+// so a leaked database does not leak live sessions.
+pub fn new_session_token() -> String {
+    let bytes: [u8; 32] = rand::rng().random();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+// Creates a user with a password. Names already in use are rejected, users
+// created before passwords existed are given one with `set_password`.
+pub fn register(db: &Database, name: &str, password: &str) -> Result<User, AuthError> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(AuthError::WeakPassword);
+    }
+    if db.get_user_by_name(name).optional()?.is_some() {
+        return Err(AuthError::UsernameTaken);
+    }
+    let user = User::new(name.to_string());
+    // the check above can race another registration, the unique name settles it
+    db.insert_user_with_password(&user, &hash_password(password)?)
+        .map_err(|err| match err {
+            rusqlite::Error::SqliteFailure(e, _)
+                if e.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                AuthError::UsernameTaken
+            }
+            err => AuthError::Database(err),
+        })?;
+    Ok(user)
+}
+
+// Sets the password of an existing user and signs them out everywhere. Only
+// reachable by whoever runs the server, see `finance-tool set-password`.
+pub fn set_password(db: &Database, name: &str, password: &str) -> Result<User, AuthError> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(AuthError::WeakPassword);
+    }
+    let user = db
+        .get_user_by_name(name)
+        .optional()?
+        .ok_or(AuthError::InvalidCredentials)?;
+    db.set_password_hash(user.id, &hash_password(password)?)?;
+    db.delete_user_sessions(user.id)?;
+    Ok(user)
+}
+
+// Checks the password and opens a new session, returning its token
+pub fn login(db: &Database, name: &str, password: &str) -> Result<(User, String), AuthError> {
+    let user = db.get_user_by_name(name).optional()?;
+    let password_hash = match &user {
+        Some(user) => db.get_password_hash(user.id)?,
+        None => None,
+    };
+    let (Some(user), Some(password_hash)) = (user, password_hash) else {
+        verify_password(password, &DUMMY_HASH);
+        return Err(AuthError::InvalidCredentials);
+    };
+
+    if !verify_password(password, &password_hash) {
+        return Err(AuthError::InvalidCredentials);
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    db.delete_expired_sessions(now)?;
+
+    let token = new_session_token();
+    db.insert_session(&hash_token(&token), user.id, now + SESSION_TTL_SECS)?;
+    Ok((user, token))
+}
+
+pub fn logout(db: &Database, token: &str) -> Result<(), AuthError> {
+    db.delete_session(&hash_token(token))?;
+    Ok(())
+}
+
+pub fn session_user(db: &Database, token: &str) -> Result<Option<User>, AuthError> {
+    Ok(db.get_session_user(&hash_token(token), chrono::Utc::now().timestamp())?)
+}
+
+// Session token from `Authorization: Bearer <token>` or the session cookie
+pub fn token_from_headers(headers: &HeaderMap) -> Option<String> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|t| t.trim().to_string());
+    if bearer.is_some() {
+        return bearer;
+    }
+
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value.to_string())
+}
+
+pub fn session_cookie(token: &str) -> String {
+    format!(
+        "{}={}; HttpOnly; SameSite=Strict; Path=/; Max-Age={}",
+        SESSION_COOKIE, token, SESSION_TTL_SECS
+    )
+}
+
+pub fn expired_session_cookie() -> String {
+    format!(
+        "{}=; HttpOnly; SameSite=Strict; Path=/; Max-Age=0",
+        SESSION_COOKIE
+    )
+}
+
+// Extractor for the logged in user. Handlers that take it answer 401
+// when the request has no valid session.
+pub struct AuthUser(pub User);
+
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let token = token_from_headers(&parts.headers).ok_or_else(ApiError::unauthorized)?;
+
+        state
+            .with_db(move |db| Ok(session_user(db, &token)?))
+            .await?
+            .map(AuthUser)
+            .ok_or_else(ApiError::unauthorized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn setup_test_db() -> Database {
+        Database::new(":memory:".to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_hash_and_verify_password() {
+        let hash = hash_password("correct horse").unwrap();
+        assert_ne!(hash, "correct horse");
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("wrong horse", &hash));
+        assert!(!verify_password("correct horse", "not a hash"));
+    }
+
+    #[test]
+    fn test_register_login_logout() {
+        let db = setup_test_db();
+        let user = register(&db, "Alice", "hunter2hunter2").unwrap();
+
+        assert!(matches!(
+            login(&db, "Alice", "wrong password"),
+            Err(AuthError::InvalidCredentials)
+        ));
+        assert!(matches!(
+            login(&db, "Bob", "hunter2hunter2"),
+            Err(AuthError::InvalidCredentials)
+        ));
+
+        let (logged_in, token) = login(&db, "Alice", "hunter2hunter2").unwrap();
+        assert_eq!(logged_in.id, user.id);
+        assert_eq!(session_user(&db, &token).unwrap().unwrap().id, user.id);
+
+        logout(&db, &token).unwrap();
+        assert!(session_user(&db, &token).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_sessions_are_per_client() {
+        let db = setup_test_db();
+        let alice = register(&db, "Alice", "hunter2hunter2").unwrap();
+        let bob = register(&db, "Bob", "swordfish123").unwrap();
+
+        let (_, alice_token) = login(&db, "Alice", "hunter2hunter2").unwrap();
+        let (_, bob_token) = login(&db, "Bob", "swordfish123").unwrap();
+
+        assert_ne!(alice_token, bob_token);
+        assert_eq!(
+            session_user(&db, &alice_token).unwrap().unwrap().id,
+            alice.id
+        );
+        assert_eq!(session_user(&db, &bob_token).unwrap().unwrap().id, bob.id);
+        assert!(session_user(&db, "made up token").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_register_rules() {
+        let db = setup_test_db();
+        assert!(matches!(
+            register(&db, "Alice", "short"),
+            Err(AuthError::WeakPassword)
+        ));
+        register(&db, "Alice", "hunter2hunter2").unwrap();
+        assert!(matches!(
+            register(&db, "Alice", "another password"),
+            Err(AuthError::UsernameTaken)
+        ));
+    }
+
+    #[test]
+    fn test_legacy_user_needs_password_set() {
+        let db = setup_test_db();
+        let legacy = User::new("Alex".to_string());
+        db.insert_user(&legacy).unwrap();
+
+        // no password yet, so no way in, and nobody can claim the name
+        assert!(login(&db, "Alex", "").is_err());
+        assert!(matches!(
+            register(&db, "Alex", "hunter2hunter2"),
+            Err(AuthError::UsernameTaken)
+        ));
+        assert!(login(&db, "Alex", "hunter2hunter2").is_err());
+
+        let user = set_password(&db, "Alex", "hunter2hunter2").unwrap();
+        assert_eq!(user.id, legacy.id);
+        let (_, token) = login(&db, "Alex", "hunter2hunter2").unwrap();
+
+        // a new password ends the sessions opened with the old one
+        set_password(&db, "Alex", "correct horse").unwrap();
+        assert!(session_user(&db, &token).unwrap().is_none());
+        assert!(matches!(
+            set_password(&db, "Nobody", "hunter2hunter2"),
+            Err(AuthError::InvalidCredentials)
+        ));
+    }
+
+    #[test]
+    fn test_expired_sessions_are_rejected() {
+        let db = setup_test_db();
+        let user = register(&db, "Alice", "hunter2hunter2").unwrap();
+        let token = new_session_token();
+        db.insert_session(
+            &hash_token(&token),
+            user.id,
+            chrono::Utc::now().timestamp() - 1,
+        )
+        .unwrap();
+
+        assert!(session_user(&db, &token).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_token_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(token_from_headers(&headers), None);
+
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("theme=dark; session=abc123"),
+        );
+        assert_eq!(token_from_headers(&headers).as_deref(), Some("abc123"));
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer xyz789"),
+        );
+        assert_eq!(token_from_headers(&headers).as_deref(), Some("xyz789"));
+    }
+}
@@ -0,0 +1,478 @@
+use core::fmt;
+use std::str::FromStr;
+
+use chrono::{Datelike, Days, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::Database,
+    exchange::{self, ExchangeError},
+    money::{Currency, Money},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Weekly,
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+#[derive(Debug, Clone)]
+pub struct InvalidPeriod(String);
+impl fmt::Display for InvalidPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid budget period: {}", self.0)
+    }
+}
+
+impl FromStr for Period {
+    type Err = InvalidPeriod;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().trim() {
+            "weekly" => Ok(Period::Weekly),
+            "monthly" => Ok(Period::Monthly),
+            "quarterly" => Ok(Period::Quarterly),
+            "yearly" => Ok(Period::Yearly),
+            _ => Err(InvalidPeriod(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Period::Weekly => write!(f, "weekly"),
+            Period::Monthly => write!(f, "monthly"),
+            Period::Quarterly => write!(f, "quarterly"),
+            Period::Yearly => write!(f, "yearly"),
+        }
+    }
+}
+
+impl Period {
+    // First day of the period containing `date`. Weeks start on Monday,
+    // the others follow the calendar.
+    pub fn start_of(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Weekly => date - Days::new(date.weekday().num_days_from_monday() as u64),
+            Period::Monthly => date.with_day(1).unwrap(),
+            Period::Quarterly => {
+                let month = (date.month0() / 3) * 3 + 1;
+                NaiveDate::from_ymd_opt(date.year(), month, 1).unwrap()
+            }
+            Period::Yearly => NaiveDate::from_ymd_opt(date.year(), 1, 1).unwrap(),
+        }
+    }
+
+    // First day of the following period, `start` must be a period start
+    pub fn next(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Weekly => start + Days::new(7),
+            Period::Monthly => start + Months::new(1),
+            Period::Quarterly => start + Months::new(3),
+            Period::Yearly => start + Months::new(12),
+        }
+    }
+
+    // Last day of the period starting at `start`
+    pub fn end(&self, start: NaiveDate) -> NaiveDate {
+        self.next(start) - Days::new(1)
+    }
+}
+
+// Spending limit for a category and its subcategories. With `rollover` whatever
+// is left at the end of a period (or overspent) carries into the next one,
+// counting from the period containing `start_date`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    #[serde(default)]
+    pub id: i64,
+    #[serde(default)]
+    pub user_id: i64,
+    pub category_id: i64,
+    pub period: Period,
+    pub amount: Money,
+    #[serde(default)]
+    pub rollover: bool,
+    #[serde(default = "today")]
+    pub start_date: NaiveDate,
+}
+
+fn today() -> NaiveDate {
+    chrono::Local::now().date_naive()
+}
+
+impl Budget {
+    pub fn from_row(row: &rusqlite::Row) -> Result<Budget, rusqlite::Error> {
+        let period: String = row.get(3)?;
+        Ok(Budget {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            category_id: row.get(2)?,
+            period: Period::from_str(&period).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    3,
+                    rusqlite::types::Type::Text,
+                    e.to_string().into(),
+                )
+            })?,
+            amount: Money::cad(row.get(4)?),
+            rollover: row.get(5)?,
+            start_date: row.get(6)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeriodSpending {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    // the budget amount plus anything carried over
+    pub budgeted: Money,
+    pub spent: Money,
+    pub remaining: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetStatus {
+    pub budget: Budget,
+    pub category: String,
+    pub current: PeriodSpending,
+    pub carried_over: Money,
+    // spending by the end of the period if it continues at the current pace
+    pub projected: Money,
+    pub over_budget: bool,
+    pub projected_over_budget: bool,
+    // earlier periods, oldest first
+    pub history: Vec<PeriodSpending>,
+}
+
+#[derive(Debug)]
+pub enum BudgetError {
+    NotFound,
+    UnknownCategory,
+    InvalidAmount,
+    AlreadyExists,
+    Exchange(ExchangeError),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for BudgetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BudgetError::NotFound => write!(f, "Budget not found"),
+            BudgetError::UnknownCategory => write!(f, "Unknown category"),
+            BudgetError::InvalidAmount => write!(f, "Budget amount must be a positive CAD amount"),
+            BudgetError::AlreadyExists => {
+                write!(f, "This category already has a budget for that period")
+            }
+            BudgetError::Exchange(err) => write!(f, "{}", err),
+            BudgetError::Database(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for BudgetError {}
+
+impl From<rusqlite::Error> for BudgetError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err {
+            rusqlite::Error::SqliteFailure(e, _)
+                if e.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                BudgetError::AlreadyExists
+            }
+            err => BudgetError::Database(err),
+        }
+    }
+}
+
+impl From<ExchangeError> for BudgetError {
+    fn from(err: ExchangeError) -> Self {
+        BudgetError::Exchange(err)
+    }
+}
+
+fn validate(db: &Database, budget: &Budget) -> Result<(), BudgetError> {
+    if !budget.amount.is_positive() || budget.amount.currency() != Currency::CAD {
+        return Err(BudgetError::InvalidAmount);
+    }
+    db.get_category(budget.user_id, budget.category_id)?
+        .ok_or(BudgetError::UnknownCategory)?;
+    Ok(())
+}
+
+pub fn create(db: &Database, mut budget: Budget) -> Result<Budget, BudgetError> {
+    validate(db, &budget)?;
+    budget.id = db.insert_budget(&budget)?;
+    Ok(budget)
+}
+
+pub fn update(db: &Database, budget: Budget) -> Result<Budget, BudgetError> {
+    validate(db, &budget)?;
+    if !db.update_budget(&budget)? {
+        return Err(BudgetError::NotFound);
+    }
+    Ok(budget)
+}
+
+// Walks the budget's periods from its start up to the one containing `today`.
+// `daily` is the category's net amount per day, spending negative, as returned
+// by `Database::category_daily_totals`.
+pub fn evaluate(
+    budget: &Budget,
+    category: &str,
+    daily: &[(NaiveDate, Money)],
+    today: NaiveDate,
+    history: usize,
+) -> BudgetStatus {
+    let period = budget.period;
+    let first = period.start_of(budget.start_date);
+    let current_start = period.start_of(today).max(first);
+
+    let mut periods = Vec::new();
+    let zero = Money::zero(budget.amount.currency());
+    let mut carry = zero;
+    let mut start = first;
+    loop {
+        let end = period.end(start);
+        let spent = -daily
+            .iter()
+            .filter(|(date, _)| *date >= start && *date <= end)
+            .fold(zero, |total, (_, amount)| total + *amount);
+        let budgeted = budget.amount + carry;
+        let remaining = budgeted - spent;
+        periods.push((
+            carry,
+            PeriodSpending {
+                start,
+                end,
+                budgeted,
+                spent,
+                remaining,
+            },
+        ));
+
+        carry = if budget.rollover { remaining } else { zero };
+        if start >= current_start {
+            break;
+        }
+        start = period.next(start);
+    }
+
+    let (carried_over, current) = periods.pop().unwrap();
+    let skip = periods.len().saturating_sub(history);
+    let history = periods.into_iter().skip(skip).map(|(_, p)| p).collect();
+
+    let total_days = (current.end - current.start).num_days() + 1;
+    let elapsed = ((today - current.start).num_days() + 1).clamp(1, total_days);
+    let projected = Money::from_minor(
+        (current.spent.minor_units() as i128 * total_days as i128 / elapsed as i128) as i64,
+        budget.amount.currency(),
+    );
+
+    BudgetStatus {
+        budget: budget.clone(),
+        category: category.to_string(),
+        over_budget: current.spent > current.budgeted,
+        projected_over_budget: projected > current.budgeted,
+        current,
+        carried_over,
+        projected,
+        history,
+    }
+}
+
+// Status of every budget the user has, as of `today`
+pub fn status(
+    db: &Database,
+    user_id: i64,
+    today: NaiveDate,
+    history: usize,
+) -> Result<Vec<BudgetStatus>, BudgetError> {
+    let budgets = db.get_budgets(user_id)?;
+    // spending in other currencies counts at the rate on the day it happened
+    if !budgets.is_empty() {
+        exchange::check_rates(db, user_id, Currency::CAD)?;
+    }
+    let mut statuses = Vec::new();
+    for budget in budgets {
+        let category = db
+            .get_category(user_id, budget.category_id)?
+            .map(|c| c.name)
+            .unwrap_or_default();
+        let from = budget.period.start_of(budget.start_date);
+        let to = budget.period.end(budget.period.start_of(today).max(from));
+        let daily = db.category_daily_totals(
+            user_id,
+            budget.category_id,
+            from,
+            to,
+            budget.amount.currency(),
+        )?;
+        statuses.push(evaluate(&budget, &category, &daily, today, history));
+    }
+    Ok(statuses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::{AccountType, ChequingAccount};
+    use crate::transaction::Transaction;
+    use crate::user::User;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn budget(period: Period, amount: i64, rollover: bool, start_date: NaiveDate) -> Budget {
+        Budget {
+            id: 1,
+            user_id: 1,
+            category_id: 1,
+            period,
+            amount: Money::cad(amount),
+            rollover,
+            start_date,
+        }
+    }
+
+    #[test]
+    fn test_period_bounds() {
+        // a Wednesday
+        let day = date(2025, 5, 14);
+        assert_eq!(Period::Weekly.start_of(day), date(2025, 5, 12));
+        assert_eq!(Period::Weekly.end(date(2025, 5, 12)), date(2025, 5, 18));
+        assert_eq!(Period::Monthly.start_of(day), date(2025, 5, 1));
+        assert_eq!(Period::Monthly.end(date(2025, 2, 1)), date(2025, 2, 28));
+        assert_eq!(Period::Quarterly.start_of(day), date(2025, 4, 1));
+        assert_eq!(Period::Quarterly.end(date(2025, 4, 1)), date(2025, 6, 30));
+        assert_eq!(Period::Yearly.start_of(day), date(2025, 1, 1));
+        assert_eq!("Monthly".parse::<Period>().unwrap(), Period::Monthly);
+        assert!("daily".parse::<Period>().is_err());
+    }
+
+    #[test]
+    fn test_spent_remaining_and_projection() {
+        let b = budget(Period::Monthly, 40000, false, date(2025, 6, 1));
+        let daily = vec![
+            (date(2025, 6, 2), Money::cad(-10000)),
+            (date(2025, 6, 9), Money::cad(-5000)),
+            // a refund brings spending down
+            (date(2025, 6, 10), Money::cad(2000)),
+        ];
+
+        let status = evaluate(&b, "Groceries", &daily, date(2025, 6, 10), 3);
+        assert_eq!(status.current.start, date(2025, 6, 1));
+        assert_eq!(status.current.end, date(2025, 6, 30));
+        assert_eq!(status.current.spent, Money::cad(13000));
+        assert_eq!(status.current.remaining, Money::cad(27000));
+        // 130.00 in 10 of 30 days
+        assert_eq!(status.projected, Money::cad(39000));
+        assert!(!status.over_budget);
+        assert!(!status.projected_over_budget);
+        assert!(status.history.is_empty());
+    }
+
+    #[test]
+    fn test_over_budget_and_projected_over() {
+        let b = budget(Period::Weekly, 10000, false, date(2025, 6, 2));
+        let daily = vec![(date(2025, 6, 3), Money::cad(-6000))];
+
+        let status = evaluate(&b, "Restaurants", &daily, date(2025, 6, 4), 0);
+        assert!(!status.over_budget);
+        assert!(status.projected_over_budget);
+
+        let daily = vec![(date(2025, 6, 3), Money::cad(-12000))];
+        let status = evaluate(&b, "Restaurants", &daily, date(2025, 6, 4), 0);
+        assert!(status.over_budget);
+        assert_eq!(status.current.remaining, Money::cad(-2000));
+    }
+
+    #[test]
+    fn test_rollover_carries_leftover_and_overspend() {
+        let b = budget(Period::Monthly, 10000, true, date(2025, 1, 15));
+        let daily = vec![
+            (date(2025, 1, 20), Money::cad(-4000)),
+            (date(2025, 2, 5), Money::cad(-19000)),
+        ];
+
+        let status = evaluate(&b, "Fun", &daily, date(2025, 3, 1), 12);
+        // january leaves 60.00, february overspends by 30.00 after that
+        assert_eq!(status.history.len(), 2);
+        assert_eq!(status.history[0].remaining, Money::cad(6000));
+        assert_eq!(status.history[1].budgeted, Money::cad(16000));
+        assert_eq!(status.history[1].remaining, Money::cad(-3000));
+        assert_eq!(status.carried_over, Money::cad(-3000));
+        assert_eq!(status.current.budgeted, Money::cad(7000));
+
+        let no_rollover = Budget {
+            rollover: false,
+            ..b
+        };
+        let status = evaluate(&no_rollover, "Fun", &daily, date(2025, 3, 1), 1);
+        assert_eq!(status.current.budgeted, Money::cad(10000));
+        assert_eq!(status.history.len(), 1);
+        assert_eq!(status.history[0].start, date(2025, 2, 1));
+    }
+
+    #[test]
+    fn test_status_counts_subcategories() {
+        let db = Database::new(":memory:".to_string()).unwrap();
+        db.insert_user(&User {
+            id: 1,
+            name: "Alice".into(),
+        })
+        .unwrap();
+        db.insert_account(&ChequingAccount {
+            user_id: 1,
+            account_number: 1001,
+            balance: Money::cad(0),
+        })
+        .unwrap();
+        for (day, cad, category) in [(2, -3000, "Groceries"), (3, -2000, "Restaurants")] {
+            db.insert_transaction(&Transaction {
+                user_id: 1,
+                account_number: 1001,
+                account_type: AccountType::Chequing,
+                transaction_date: date(2025, 6, day),
+                cad: Money::cad(cad),
+                category: category.to_string(),
+                ..Transaction::dummy()
+            })
+            .unwrap();
+        }
+        let food = db.get_category_by_name(1, "Food").unwrap().unwrap();
+
+        let created = create(
+            &db,
+            Budget {
+                category_id: food.id,
+                ..budget(Period::Monthly, 60000, false, date(2025, 6, 1))
+            },
+        )
+        .unwrap();
+        assert!(matches!(
+            create(&db, created.clone()),
+            Err(BudgetError::AlreadyExists)
+        ));
+        assert!(matches!(
+            create(
+                &db,
+                Budget {
+                    category_id: 999,
+                    ..created.clone()
+                }
+            ),
+            Err(BudgetError::UnknownCategory)
+        ));
+
+        let statuses = status(&db, 1, date(2025, 6, 15), 3).unwrap();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].category, "Food");
+        assert_eq!(statuses[0].current.spent, Money::cad(5000));
+    }
+}
@@ -0,0 +1,556 @@
+use core::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{database::Database, money::Money};
+
+// Deepest nesting allowed, also bounds the walk up the tree when checking for cycles
+pub const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Category {
+    pub id: i64,
+    pub user_id: i64,
+    pub parent_id: Option<i64>,
+    pub name: String,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+}
+
+impl Category {
+    pub fn from_row(row: &rusqlite::Row) -> Result<Category, rusqlite::Error> {
+        Ok(Category {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            parent_id: row.get(2)?,
+            name: row.get(3)?,
+            color: row.get(4)?,
+            icon: row.get(5)?,
+        })
+    }
+}
+
+// Total of a user's transactions in one category. `category_id` is None for
+// uncategorized transactions.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryTotal {
+    pub category_id: Option<i64>,
+    pub name: String,
+    pub total: Money,
+    pub count: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewCategory {
+    pub name: String,
+    #[serde(default)]
+    pub parent_id: Option<i64>,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+// Partial update. For the nullable fields a missing key leaves the value alone
+// and an explicit null clears it, so `"parent_id": null` makes a top-level category.
+#[derive(Debug, Default, Deserialize)]
+pub struct CategoryUpdate {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub parent_id: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub color: Option<Option<String>>,
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub icon: Option<Option<String>>,
+}
+
+pub(crate) fn explicit_null<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug)]
+pub enum CategoryError {
+    NotFound,
+    NameTaken,
+    InvalidName,
+    Cycle,
+    SameCategory,
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for CategoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CategoryError::NotFound => write!(f, "Category not found"),
+            CategoryError::NameTaken => write!(f, "A category with that name already exists"),
+            CategoryError::InvalidName => write!(f, "Category name must not be empty"),
+            CategoryError::Cycle => write!(
+                f,
+                "A category cannot be moved under itself or nested more than {} levels",
+                MAX_DEPTH
+            ),
+            CategoryError::SameCategory => write!(f, "Cannot merge a category into itself"),
+            CategoryError::Database(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CategoryError {}
+
+impl From<rusqlite::Error> for CategoryError {
+    fn from(err: rusqlite::Error) -> Self {
+        CategoryError::Database(err)
+    }
+}
+
+pub struct DefaultCategory {
+    pub name: &'static str,
+    pub color: &'static str,
+    pub icon: &'static str,
+    pub children: &'static [&'static str],
+}
+
+// The tree the built-in categorization rules file into. These categories are
+// only created once something is filed under them, see `get_or_create_category`.
+pub const DEFAULT_CATEGORIES: &[DefaultCategory] = &[
+    DefaultCategory {
+        name: "Food",
+        color: "#e67e22",
+        icon: "utensils",
+        children: &["Groceries", "Restaurants", "Coffee Shops", "Alcohol"],
+    },
+    DefaultCategory {
+        name: "Transportation",
+        color: "#3498db",
+        icon: "bus",
+        children: &["Public Transit", "Rideshare", "Gas"],
+    },
+    DefaultCategory {
+        name: "Bills",
+        color: "#9b59b6",
+        icon: "file-invoice",
+        children: &["Utilities", "Phone & Internet", "Subscriptions"],
+    },
+    DefaultCategory {
+        name: "Health",
+        color: "#e74c3c",
+        icon: "heart-pulse",
+        children: &["Pharmacy"],
+    },
+    DefaultCategory {
+        name: "Shopping",
+        color: "#f1c40f",
+        icon: "bag-shopping",
+        children: &[],
+    },
+    DefaultCategory {
+        name: "Income",
+        color: "#2ecc71",
+        icon: "sack-dollar",
+        children: &[],
+    },
+    DefaultCategory {
+        name: "Transfers",
+        color: "#95a5a6",
+        icon: "right-left",
+        children: &[],
+    },
+];
+
+// Where a category of this name goes in the default tree: its parent, if it
+// is a default child, and the color and icon to start with.
+pub fn default_placement(
+    name: &str,
+) -> (
+    Option<&'static str>,
+    Option<&'static str>,
+    Option<&'static str>,
+) {
+    for default in DEFAULT_CATEGORIES {
+        if default.name.eq_ignore_ascii_case(name) {
+            return (None, Some(default.color), Some(default.icon));
+        }
+        if default
+            .children
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(name))
+        {
+            return (Some(default.name), Some(default.color), None);
+        }
+    }
+    (None, None, None)
+}
+
+fn clean_name(name: &str) -> Result<String, CategoryError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(CategoryError::InvalidName);
+    }
+    Ok(name.to_string())
+}
+
+fn check_name_free(
+    db: &Database,
+    user_id: i64,
+    name: &str,
+    except: Option<i64>,
+) -> Result<(), CategoryError> {
+    match db.get_category_by_name(user_id, name)? {
+        Some(existing) if Some(existing.id) != except => Err(CategoryError::NameTaken),
+        _ => Ok(()),
+    }
+}
+
+// Fails if putting `category_id` under `parent_id` would make it its own
+// ancestor. `category_id` is None for a category that does not exist yet.
+fn check_parent(
+    db: &Database,
+    user_id: i64,
+    category_id: Option<i64>,
+    parent_id: i64,
+) -> Result<(), CategoryError> {
+    let mut current = Some(parent_id);
+    let mut depth = 0;
+    while let Some(id) = current {
+        if Some(id) == category_id || depth >= MAX_DEPTH {
+            return Err(CategoryError::Cycle);
+        }
+        let ancestor = db
+            .get_category(user_id, id)?
+            .ok_or(CategoryError::NotFound)?;
+        current = ancestor.parent_id;
+        depth += 1;
+    }
+    Ok(())
+}
+
+pub fn create(db: &Database, user_id: i64, new: NewCategory) -> Result<Category, CategoryError> {
+    let name = clean_name(&new.name)?;
+    check_name_free(db, user_id, &name, None)?;
+    if let Some(parent_id) = new.parent_id {
+        check_parent(db, user_id, None, parent_id)?;
+    }
+
+    let mut category = Category {
+        id: 0,
+        user_id,
+        parent_id: new.parent_id,
+        name,
+        color: new.color,
+        icon: new.icon,
+    };
+    category.id = db.insert_category(&category)?;
+    Ok(category)
+}
+
+// Renames, moves or restyles a category. Rules that file into it follow a rename.
+pub fn update(
+    db: &Database,
+    user_id: i64,
+    category_id: i64,
+    changes: CategoryUpdate,
+) -> Result<Category, CategoryError> {
+    let mut category = db
+        .get_category(user_id, category_id)?
+        .ok_or(CategoryError::NotFound)?;
+
+    if let Some(name) = changes.name {
+        let name = clean_name(&name)?;
+        check_name_free(db, user_id, &name, Some(category_id))?;
+        category.name = name;
+    }
+    if let Some(parent_id) = changes.parent_id {
+        if let Some(parent_id) = parent_id {
+            check_parent(db, user_id, Some(category_id), parent_id)?;
+        }
+        category.parent_id = parent_id;
+    }
+    if let Some(color) = changes.color {
+        category.color = color;
+    }
+    if let Some(icon) = changes.icon {
+        category.icon = icon;
+    }
+
+    db.update_category(&category)?;
+    Ok(category)
+}
+
+// Moves everything filed under `source` (transactions, subcategories and rules)
+// to `target` and removes `source`. Returns the number of transactions moved.
+pub fn merge(
+    db: &Database,
+    user_id: i64,
+    source_id: i64,
+    target_id: i64,
+) -> Result<usize, CategoryError> {
+    if source_id == target_id {
+        return Err(CategoryError::SameCategory);
+    }
+    db.get_category(user_id, source_id)?
+        .ok_or(CategoryError::NotFound)?;
+    db.get_category(user_id, target_id)?
+        .ok_or(CategoryError::NotFound)?;
+
+    Ok(db.merge_categories(user_id, source_id, target_id)?)
+}
+
+// Deletes a category. Its transactions, subcategories and rules move up to its
+// parent, or become uncategorized if it was top-level. Returns the number of
+// transactions re-pointed.
+pub fn delete(db: &Database, user_id: i64, category_id: i64) -> Result<usize, CategoryError> {
+    db.get_category(user_id, category_id)?
+        .ok_or(CategoryError::NotFound)?;
+    Ok(db.delete_category(user_id, category_id)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::ChequingAccount;
+    use crate::catergorization::CategoryRule;
+    use crate::money::Currency;
+    use crate::transaction::Transaction;
+    use crate::user::User;
+    use chrono::NaiveDate;
+
+    fn setup_test_db() -> Database {
+        let db = Database::new(":memory:".to_string()).unwrap();
+        db.insert_user(&User {
+            id: 1,
+            name: "Alice".into(),
+        })
+        .unwrap();
+        db.insert_account(&ChequingAccount {
+            user_id: 1,
+            account_number: 1001,
+            balance: Money::cad(0),
+        })
+        .unwrap();
+        db.insert_user(&User {
+            id: 2,
+            name: "Bob".into(),
+        })
+        .unwrap();
+        db
+    }
+
+    fn new_category(name: &str, parent_id: Option<i64>) -> NewCategory {
+        NewCategory {
+            name: name.to_string(),
+            parent_id,
+            color: None,
+            icon: None,
+        }
+    }
+
+    fn insert_transaction(db: &Database, day: u32, cad: i64, category: &str) {
+        db.insert_transaction(&Transaction {
+            user_id: 1,
+            account_number: 1001,
+            account_type: crate::account::AccountType::Chequing,
+            transaction_date: NaiveDate::from_ymd_opt(2025, 1, day).unwrap(),
+            description_1: format!("PURCHASE {}", day),
+            cad: Money::cad(cad),
+            category: category.to_string(),
+            ..Transaction::dummy()
+        })
+        .unwrap();
+    }
+
+    fn categories_of(db: &Database) -> Vec<String> {
+        db.get_transactions(1)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.category)
+            .collect()
+    }
+
+    #[test]
+    fn test_default_placement() {
+        assert_eq!(default_placement("groceries").0, Some("Food"));
+        assert_eq!(default_placement("Food").0, None);
+        assert_eq!(default_placement("Food").2, Some("utensils"));
+        assert_eq!(default_placement("Pottery"), (None, None, None));
+    }
+
+    #[test]
+    fn test_create_and_nest() {
+        let db = setup_test_db();
+        let food = create(&db, 1, new_category("Food", None)).unwrap();
+        let groceries = create(&db, 1, new_category(" Groceries ", Some(food.id))).unwrap();
+        assert_eq!(groceries.name, "Groceries");
+        assert_eq!(groceries.parent_id, Some(food.id));
+
+        assert!(matches!(
+            create(&db, 1, new_category("groceries", None)),
+            Err(CategoryError::NameTaken)
+        ));
+        assert!(matches!(
+            create(&db, 1, new_category("  ", None)),
+            Err(CategoryError::InvalidName)
+        ));
+        // another user's category is not a valid parent
+        assert!(matches!(
+            create(&db, 2, new_category("Snacks", Some(food.id))),
+            Err(CategoryError::NotFound)
+        ));
+        // but the name is free for them
+        assert!(create(&db, 2, new_category("Food", None)).is_ok());
+    }
+
+    #[test]
+    fn test_update_rejects_cycles() {
+        let db = setup_test_db();
+        let food = create(&db, 1, new_category("Food", None)).unwrap();
+        let groceries = create(&db, 1, new_category("Groceries", Some(food.id))).unwrap();
+
+        let moved = CategoryUpdate {
+            parent_id: Some(Some(groceries.id)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            update(&db, 1, food.id, moved),
+            Err(CategoryError::Cycle)
+        ));
+
+        let top_level = CategoryUpdate {
+            parent_id: Some(None),
+            color: Some(Some("#00ff00".to_string())),
+            ..Default::default()
+        };
+        let updated = update(&db, 1, groceries.id, top_level).unwrap();
+        assert_eq!(updated.parent_id, None);
+        assert_eq!(updated.color.as_deref(), Some("#00ff00"));
+    }
+
+    #[test]
+    fn test_update_json_distinguishes_null_from_missing() {
+        let changes: CategoryUpdate = serde_json::from_str(r#"{"parent_id": null}"#).unwrap();
+        assert_eq!(changes.parent_id, Some(None));
+        assert_eq!(changes.color, None);
+    }
+
+    #[test]
+    fn test_transactions_reference_categories_by_id() {
+        let db = setup_test_db();
+        insert_transaction(&db, 1, -500, "Groceries");
+
+        let groceries = db.get_category_by_name(1, "Groceries").unwrap().unwrap();
+        let food = db.get_category_by_name(1, "Food").unwrap().unwrap();
+        assert_eq!(groceries.parent_id, Some(food.id));
+
+        let transaction = &db.get_transactions(1).unwrap()[0];
+        assert_eq!(transaction.category_id, Some(groceries.id));
+
+        let renamed = CategoryUpdate {
+            name: Some("Supermarket".to_string()),
+            ..Default::default()
+        };
+        update(&db, 1, groceries.id, renamed).unwrap();
+        assert_eq!(categories_of(&db), vec!["Supermarket"]);
+    }
+
+    #[test]
+    fn test_rename_updates_rules() {
+        let db = setup_test_db();
+        let coffee = create(&db, 1, new_category("Coffee", None)).unwrap();
+        let mut rule = CategoryRule::new(1, "Coffee");
+        rule.description_contains = Some("BRIDGEHEAD".to_string());
+        db.insert_category_rule(&rule).unwrap();
+
+        let renamed = CategoryUpdate {
+            name: Some("Cafes".to_string()),
+            ..Default::default()
+        };
+        update(&db, 1, coffee.id, renamed).unwrap();
+        assert_eq!(db.get_category_rules(1).unwrap()[0].category, "Cafes");
+    }
+
+    #[test]
+    fn test_merge_repoints_transactions() {
+        let db = setup_test_db();
+        insert_transaction(&db, 1, -500, "Coffee Shops");
+        insert_transaction(&db, 2, -700, "Cafe");
+        let cafe = db.get_category_by_name(1, "Cafe").unwrap().unwrap();
+        let coffee = db.get_category_by_name(1, "Coffee Shops").unwrap().unwrap();
+
+        assert!(matches!(
+            merge(&db, 1, cafe.id, cafe.id),
+            Err(CategoryError::SameCategory)
+        ));
+        assert_eq!(merge(&db, 1, cafe.id, coffee.id).unwrap(), 1);
+        assert_eq!(categories_of(&db), vec!["Coffee Shops", "Coffee Shops"]);
+        assert!(db.get_category(1, cafe.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_merge_into_own_child() {
+        let db = setup_test_db();
+        insert_transaction(&db, 1, -500, "Groceries");
+        insert_transaction(&db, 2, -700, "Restaurants");
+        let food = db.get_category_by_name(1, "Food").unwrap().unwrap();
+        let groceries = db.get_category_by_name(1, "Groceries").unwrap().unwrap();
+
+        merge(&db, 1, food.id, groceries.id).unwrap();
+
+        let groceries = db.get_category(1, groceries.id).unwrap().unwrap();
+        let restaurants = db.get_category_by_name(1, "Restaurants").unwrap().unwrap();
+        assert_eq!(groceries.parent_id, None);
+        assert_eq!(restaurants.parent_id, Some(groceries.id));
+    }
+
+    #[test]
+    fn test_delete_moves_up_to_parent() {
+        let db = setup_test_db();
+        insert_transaction(&db, 1, -500, "Groceries");
+        insert_transaction(&db, 2, -700, "Income");
+        let groceries = db.get_category_by_name(1, "Groceries").unwrap().unwrap();
+        let income = db.get_category_by_name(1, "Income").unwrap().unwrap();
+
+        assert_eq!(delete(&db, 1, groceries.id).unwrap(), 1);
+        assert_eq!(delete(&db, 1, income.id).unwrap(), 1);
+        assert_eq!(categories_of(&db), vec!["Food", ""]);
+        assert!(matches!(
+            delete(&db, 1, income.id),
+            Err(CategoryError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_totals_roll_up_to_parent() {
+        let db = setup_test_db();
+        insert_transaction(&db, 1, -500, "Groceries");
+        insert_transaction(&db, 2, -700, "Restaurants");
+        insert_transaction(&db, 3, -300, "Gas");
+        insert_transaction(&db, 4, -100, "");
+        let from = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2025, 1, 31).unwrap();
+
+        let flat = db
+            .category_totals(1, from, to, false, Currency::CAD)
+            .unwrap();
+        assert_eq!(flat.len(), 4);
+
+        let rolled = db
+            .category_totals(1, from, to, true, Currency::CAD)
+            .unwrap();
+        let summary: Vec<(&str, i64, i64)> = rolled
+            .iter()
+            .map(|t| (t.name.as_str(), t.total.minor_units(), t.count))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Food", -1200, 2),
+                ("Transportation", -300, 1),
+                ("Uncategorized", -100, 1)
+            ]
+        );
+    }
+}
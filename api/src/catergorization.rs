@@ -1,46 +1,570 @@
-use crate::transaction::Transaction;
-use reqwest::{
-    header::{HeaderMap, CONTENT_TYPE},
-    Error,
-};
+use core::fmt;
+use std::future::Future;
+use std::str::FromStr;
 
+use crate::{account::AccountType, money::Money, transaction::Transaction};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+
+use regex::Regex;
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
 
 use lazy_static::lazy_static;
 
-const API_ENDPOINT: &'static str = "https://app.fina.money/api/resource/categorize";
+const API_ENDPOINT: &str = "https://app.fina.money/api/resource/categorize";
+
+// Priority of the built-in rules. Rules a user creates default above this,
+// so they win over the defaults without having to pick a number.
+pub const DEFAULT_RULE_PRIORITY: i64 = 0;
+pub const USER_RULE_PRIORITY: i64 = 100;
 
 lazy_static! {
     static ref CLIENT: Client = Client::new();
 }
 
-pub async fn catergorize_transactions(transaction: &[Transaction]) -> Result<Vec<String>, Error> {
-    let mut headers = HeaderMap::new();
-    headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
-    headers.insert("x-api-key", "fina-api-test".parse().unwrap()); //api key is free and open to everyone
-    headers.insert("x-partner-id", "<client-parter-id>".parse().unwrap());
-    headers.insert("x-api-model", "v3".parse().unwrap());
-    headers.insert("x-api-mapping", "true".parse().unwrap());
-
-    let data: Vec<serde_json::Value> = transaction
-        .iter()
-        .map(|t| t.seriazlize_to_catergorize())
-        .collect();
-
-    let mut all_responses = vec![];
-    for chunk in data.chunks(100) {
-        let response = CLIENT
-            .post(API_ENDPOINT.to_string())
-            .headers(headers.clone())
-            .json(&chunk)
-            .send()
+#[derive(Debug)]
+pub enum CategorizeError {
+    Http(reqwest::Error),
+    InvalidRule(String),
+    // FINA_API_KEY cannot be sent as a header
+    InvalidApiKey(String),
+}
+
+impl fmt::Display for CategorizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CategorizeError::Http(err) => write!(f, "Categorization request failed: {}", err),
+            CategorizeError::InvalidRule(msg) => write!(f, "Invalid rule: {}", msg),
+            CategorizeError::InvalidApiKey(msg) => write!(f, "Invalid API key: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for CategorizeError {}
+
+impl From<reqwest::Error> for CategorizeError {
+    fn from(err: reqwest::Error) -> Self {
+        CategorizeError::Http(err)
+    }
+}
+
+// Something that can suggest categories. The result has one entry per
+// transaction, None where the categorizer has no opinion, so backends can be
+// chained with `FallbackCategorizer`.
+pub trait Categorizer: Send + Sync {
+    fn categorize(
+        &self,
+        transactions: &[Transaction],
+    ) -> impl Future<Output = Result<Vec<Option<String>>, CategorizeError>> + Send;
+}
+
+// A categorization rule. Every condition that is set has to match, a rule
+// with no conditions matches nothing. Amounts are the signed CAD amount of
+// the transaction, so spending is negative.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CategoryRule {
+    #[serde(default)]
+    pub id: i64,
+    #[serde(default)]
+    pub user_id: i64,
+    pub category: String,
+    #[serde(default = "user_rule_priority")]
+    pub priority: i64,
+    #[serde(default)]
+    pub description_contains: Option<String>,
+    #[serde(default)]
+    pub description_regex: Option<String>,
+    #[serde(default)]
+    pub min_amount: Option<Money>,
+    #[serde(default)]
+    pub max_amount: Option<Money>,
+    #[serde(default)]
+    pub account_type: Option<AccountType>,
+}
+
+fn user_rule_priority() -> i64 {
+    USER_RULE_PRIORITY
+}
+
+impl CategoryRule {
+    pub fn new(user_id: i64, category: &str) -> CategoryRule {
+        CategoryRule {
+            id: 0,
+            user_id,
+            category: category.to_string(),
+            priority: USER_RULE_PRIORITY,
+            description_contains: None,
+            description_regex: None,
+            min_amount: None,
+            max_amount: None,
+            account_type: None,
+        }
+    }
+
+    fn has_conditions(&self) -> bool {
+        self.description_contains.is_some()
+            || self.description_regex.is_some()
+            || self.min_amount.is_some()
+            || self.max_amount.is_some()
+            || self.account_type.is_some()
+    }
+
+    // Checked before a rule is saved so a bad rule is rejected up front
+    // instead of silently never matching.
+    pub fn validate(&self) -> Result<(), CategorizeError> {
+        if self.category.trim().is_empty() {
+            return Err(CategorizeError::InvalidRule(
+                "category must not be empty".to_string(),
+            ));
+        }
+        if !self.has_conditions() {
+            return Err(CategorizeError::InvalidRule(
+                "rule needs at least one condition".to_string(),
+            ));
+        }
+        if let Some(pattern) = &self.description_regex {
+            Regex::new(pattern).map_err(|e| CategorizeError::InvalidRule(e.to_string()))?;
+        }
+        if let (Some(min), Some(max)) = (self.min_amount, self.max_amount) {
+            if min.currency() != max.currency() || min > max {
+                return Err(CategorizeError::InvalidRule(
+                    "min_amount must not be above max_amount".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn from_row(row: &rusqlite::Row) -> Result<CategoryRule, rusqlite::Error> {
+        Ok(CategoryRule {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            category: row.get(2)?,
+            priority: row.get(3)?,
+            description_contains: row.get(4)?,
+            description_regex: row.get(5)?,
+            min_amount: row.get::<_, Option<i64>>(6)?.map(Money::cad),
+            max_amount: row.get::<_, Option<i64>>(7)?.map(Money::cad),
+            account_type: row
+                .get::<_, Option<String>>(8)?
+                .and_then(|t| AccountType::from_str(&t).ok()),
+        })
+    }
+}
+
+// A rule with its regex compiled once
+struct CompiledRule {
+    rule: CategoryRule,
+    contains: Option<String>,
+    regex: Option<Regex>,
+}
+
+impl CompiledRule {
+    fn new(rule: CategoryRule) -> Result<CompiledRule, CategorizeError> {
+        rule.validate()?;
+        let regex = match &rule.description_regex {
+            Some(pattern) => {
+                Some(Regex::new(pattern).map_err(|e| CategorizeError::InvalidRule(e.to_string()))?)
+            }
+            None => None,
+        };
+        Ok(CompiledRule {
+            contains: rule.description_contains.as_ref().map(|s| s.to_uppercase()),
+            regex,
+            rule,
+        })
+    }
+
+    fn matches(&self, transaction: &Transaction, description: &str) -> bool {
+        if let Some(account_type) = self.rule.account_type {
+            if transaction.account_type != account_type {
+                return false;
+            }
+        }
+        // amount bounds apply in whatever currency the transaction is in
+        let amount = transaction.amount().minor_units();
+        if let Some(min) = self.rule.min_amount {
+            if amount < min.minor_units() {
+                return false;
+            }
+        }
+        if let Some(max) = self.rule.max_amount {
+            if amount > max.minor_units() {
+                return false;
+            }
+        }
+        if let Some(contains) = &self.contains {
+            if !description.to_uppercase().contains(contains.as_str()) {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.regex {
+            if !regex.is_match(description) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// Local, offline categorizer. The highest priority matching rule wins,
+// ties go to the rule listed first.
+pub struct RuleCategorizer {
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleCategorizer {
+    pub fn new(rules: Vec<CategoryRule>) -> Result<RuleCategorizer, CategorizeError> {
+        let mut rules = rules
+            .into_iter()
+            .map(CompiledRule::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        // stable, so equal priorities keep their order
+        rules.sort_by_key(|r| std::cmp::Reverse(r.rule.priority));
+        Ok(RuleCategorizer { rules })
+    }
+
+    // A user's own rules followed by the built-in ones
+    pub fn with_defaults(
+        user_rules: Vec<CategoryRule>,
+    ) -> Result<RuleCategorizer, CategorizeError> {
+        let mut rules = user_rules;
+        rules.extend(default_rules());
+        RuleCategorizer::new(rules)
+    }
+
+    pub fn categorize_one(&self, transaction: &Transaction) -> Option<&str> {
+        let description = format!(
+            "{} {}",
+            transaction.description_1, transaction.description_2
+        );
+        self.rules
+            .iter()
+            .find(|r| r.matches(transaction, &description))
+            .map(|r| r.rule.category.as_str())
+    }
+
+    pub fn categorize_all(&self, transactions: &[Transaction]) -> Vec<Option<String>> {
+        transactions
+            .iter()
+            .map(|t| self.categorize_one(t).map(String::from))
+            .collect()
+    }
+}
+
+impl Default for RuleCategorizer {
+    fn default() -> Self {
+        // the built-in rules are known to be valid
+        RuleCategorizer::new(default_rules()).unwrap()
+    }
+}
+
+impl Categorizer for RuleCategorizer {
+    async fn categorize(
+        &self,
+        transactions: &[Transaction],
+    ) -> Result<Vec<Option<String>>, CategorizeError> {
+        Ok(self.categorize_all(transactions))
+    }
+}
+
+// Categories from the fina.money API. Every description is sent to a third
+// party, so this is opt in and never the default.
+pub struct RemoteCategorizer {
+    endpoint: String,
+    api_key: String,
+}
+
+impl RemoteCategorizer {
+    pub fn new(endpoint: &str, api_key: &str) -> RemoteCategorizer {
+        RemoteCategorizer {
+            endpoint: endpoint.to_string(),
+            api_key: api_key.to_string(),
+        }
+    }
+
+    // Configured with FINA_API_KEY (and optionally FINA_API_ENDPOINT), None when unset
+    pub fn from_env() -> Option<RemoteCategorizer> {
+        let api_key = std::env::var("FINA_API_KEY").ok()?;
+        let endpoint = std::env::var("FINA_API_ENDPOINT").unwrap_or(API_ENDPOINT.to_string());
+        Some(RemoteCategorizer::new(&endpoint, &api_key))
+    }
+
+    fn headers(&self) -> Result<HeaderMap, CategorizeError> {
+        let api_key = HeaderValue::from_str(&self.api_key)
+            .map_err(|e| CategorizeError::InvalidApiKey(e.to_string()))?;
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert("x-api-key", api_key);
+        headers.insert("x-api-model", HeaderValue::from_static("v3"));
+        headers.insert("x-api-mapping", HeaderValue::from_static("true"));
+        Ok(headers)
+    }
+}
+
+impl Categorizer for RemoteCategorizer {
+    async fn categorize(
+        &self,
+        transactions: &[Transaction],
+    ) -> Result<Vec<Option<String>>, CategorizeError> {
+        let headers = self.headers()?;
+
+        let data: Vec<serde_json::Value> = transactions
+            .iter()
+            .map(|t| t.seriazlize_to_catergorize())
+            .collect();
+
+        let mut all_responses = vec![];
+        for chunk in data.chunks(100) {
+            let response = CLIENT
+                .post(&self.endpoint)
+                .headers(headers.clone())
+                .json(&chunk)
+                .send()
+                .await?
+                .error_for_status()?;
+            let response_data = response.json::<Vec<String>>().await?;
+            all_responses.extend(
+                response_data
+                    .into_iter()
+                    .map(|c| Some(c).filter(|c| !c.trim().is_empty())),
+            );
+        }
+
+        // a short answer leaves the rest uncategorized rather than misaligned
+        all_responses.resize(transactions.len(), None);
+        Ok(all_responses)
+    }
+}
+
+// Asks `fallback` only about the transactions `primary` had no answer for,
+// e.g. local rules first and the remote API for the rest.
+pub struct FallbackCategorizer<A, B> {
+    pub primary: A,
+    pub fallback: B,
+}
+
+impl<A: Categorizer, B: Categorizer> Categorizer for FallbackCategorizer<A, B> {
+    async fn categorize(
+        &self,
+        transactions: &[Transaction],
+    ) -> Result<Vec<Option<String>>, CategorizeError> {
+        let mut categories = self.primary.categorize(transactions).await?;
+
+        let missing: Vec<usize> = (0..categories.len())
+            .filter(|&i| categories[i].is_none())
+            .collect();
+        if missing.is_empty() {
+            return Ok(categories);
+        }
+
+        let rest: Vec<Transaction> = missing.iter().map(|&i| transactions[i].clone()).collect();
+        let fallback = self.fallback.categorize(&rest).await?;
+        for (i, category) in missing.into_iter().zip(fallback) {
+            categories[i] = category;
+        }
+        Ok(categories)
+    }
+}
+
+// The categorizer used for imports: the user's rules and the built-in ones,
+// then the remote API for whatever is left if FINA_API_KEY is set.
+pub async fn categorize_for_user(
+    user_rules: Vec<CategoryRule>,
+    transactions: &[Transaction],
+) -> Result<Vec<Option<String>>, CategorizeError> {
+    let rules = RuleCategorizer::with_defaults(user_rules)?;
+    match RemoteCategorizer::from_env() {
+        Some(remote) => {
+            FallbackCategorizer {
+                primary: rules,
+                fallback: remote,
+            }
+            .categorize(transactions)
             .await
-            .unwrap();
-        let response_data = response.json::<Vec<String>>().await.unwrap();
-        all_responses.extend(response_data);
+        }
+        None => rules.categorize(transactions).await,
     }
+}
 
-    Ok(all_responses)
+// Categories from the built-in rules, empty where nothing matched
+pub async fn catergorize_transactions(
+    transactions: &[Transaction],
+) -> Result<Vec<String>, CategorizeError> {
+    let categories = RuleCategorizer::default().categorize(transactions).await?;
+    Ok(categories
+        .into_iter()
+        .map(Option::unwrap_or_default)
+        .collect())
+}
+
+// Sets the category of every transaction that got one
+pub fn apply_categories(transactions: &mut [Transaction], categories: Vec<Option<String>>) {
+    for (transaction, category) in transactions.iter_mut().zip(categories) {
+        if let Some(category) = category {
+            transaction.category = category;
+        }
+    }
+}
+
+fn keyword_rule(category: &str, keywords: &[&str]) -> CategoryRule {
+    let alternatives: Vec<String> = keywords.iter().map(|k| regex::escape(k)).collect();
+    CategoryRule {
+        priority: DEFAULT_RULE_PRIORITY,
+        description_regex: Some(format!(r"(?i)\b(?:{})\b", alternatives.join("|"))),
+        ..CategoryRule::new(0, category)
+    }
+}
+
+// Common Canadian merchants as they show up on RBC and CIBC statements.
+// Matched on whole words so e.g. METRO does not catch METROLINX.
+pub fn default_rules() -> Vec<CategoryRule> {
+    let mut rules = vec![
+        keyword_rule(
+            "Coffee Shops",
+            &["TIM HORTONS", "STARBUCKS", "SECOND CUP", "COFFEE CULTURE"],
+        ),
+        keyword_rule(
+            "Restaurants",
+            &[
+                "MCDONALD'S",
+                "MCDONALDS",
+                "A&W",
+                "HARVEY'S",
+                "SUBWAY",
+                "PIZZA PIZZA",
+                "MARY BROWN'S",
+                "SWISS CHALET",
+                "BOSTON PIZZA",
+                "THE KEG",
+                "UBER EATS",
+                "UBEREATS",
+                "DOORDASH",
+                "SKIPTHEDISHES",
+            ],
+        ),
+        keyword_rule(
+            "Groceries",
+            &[
+                "LOBLAWS",
+                "NO FRILLS",
+                "NOFRILLS",
+                "REAL CANADIAN SUPERSTORE",
+                "ZEHRS",
+                "FORTINOS",
+                "PROVIGO",
+                "MAXI",
+                "METRO",
+                "FOOD BASICS",
+                "SOBEYS",
+                "FRESHCO",
+                "FOODLAND",
+                "IGA",
+                "SAFEWAY",
+                "SAVE-ON-FOODS",
+                "FARM BOY",
+                "T&T",
+                "COSTCO WHOLESALE",
+            ],
+        ),
+        keyword_rule(
+            "Public Transit",
+            &[
+                "PRESTO",
+                "TTC",
+                "OC TRANSPO",
+                "GO TRANSIT",
+                "METROLINX",
+                "STM",
+                "COMPASS",
+                "TRANSLINK",
+                "VIA RAIL",
+            ],
+        ),
+        keyword_rule("Rideshare", &["UBER", "LYFT"]),
+        keyword_rule(
+            "Gas",
+            &[
+                "PETRO-CANADA",
+                "PETRO CANADA",
+                "ESSO",
+                "SHELL",
+                "PIONEER",
+                "ULTRAMAR",
+                "HUSKY",
+                "CANADIAN TIRE GAS",
+            ],
+        ),
+        keyword_rule(
+            "Utilities",
+            &[
+                "HYDRO ONE",
+                "HYDRO OTTAWA",
+                "TORONTO HYDRO",
+                "HYDRO-QUEBEC",
+                "BC HYDRO",
+                "ENBRIDGE",
+                "FORTISBC",
+            ],
+        ),
+        keyword_rule(
+            "Phone & Internet",
+            &[
+                "ROGERS",
+                "BELL",
+                "TELUS",
+                "FIDO",
+                "KOODO",
+                "VIRGIN",
+                "FREEDOM",
+                "VIDEOTRON",
+                "SHAW",
+            ],
+        ),
+        keyword_rule(
+            "Subscriptions",
+            &["NETFLIX", "SPOTIFY", "DISNEY PLUS", "CRAVE", "PRIME VIDEO"],
+        ),
+        keyword_rule("Alcohol", &["LCBO", "SAQ", "BEER STORE", "BC LIQUOR"]),
+        keyword_rule(
+            "Pharmacy",
+            &["SHOPPERS DRUG MART", "REXALL", "JEAN COUTU", "PHARMASAVE"],
+        ),
+        keyword_rule(
+            "Shopping",
+            &[
+                "AMAZON",
+                "AMZN",
+                "CANADIAN TIRE",
+                "WALMART",
+                "BEST BUY",
+                "WINNERS",
+                "DOLLARAMA",
+                "IKEA",
+                "HOME DEPOT",
+            ],
+        ),
+        keyword_rule(
+            "Transfers",
+            &[
+                "E-TRANSFER",
+                "ONLINE TRANSFER",
+                "PAYMENT THANK YOU",
+                "PAYMENT - THANK YOU",
+            ],
+        ),
+    ];
+
+    // Money coming into a chequing account from an employer
+    rules.push(CategoryRule {
+        priority: DEFAULT_RULE_PRIORITY,
+        description_regex: Some(r"(?i)\b(?:PAYROLL|PAY|DIRECT DEP|DEPOSIT)\b".to_string()),
+        min_amount: Some(Money::cad(1)),
+        account_type: Some(AccountType::Chequing),
+        ..CategoryRule::new(0, "Income")
+    });
+
+    rules
 }
 
 #[cfg(test)]
@@ -49,11 +573,170 @@ mod tests {
 
     use crate::transaction::Transaction;
 
+    fn transaction(description: &str, cad: i64) -> Transaction {
+        Transaction {
+            description_1: description.to_string(),
+            cad: Money::cad(cad),
+            ..Transaction::dummy()
+        }
+    }
+
+    struct Fixed(&'static str);
+
+    impl Categorizer for Fixed {
+        async fn categorize(
+            &self,
+            transactions: &[Transaction],
+        ) -> Result<Vec<Option<String>>, CategorizeError> {
+            Ok(vec![Some(self.0.to_string()); transactions.len()])
+        }
+    }
+
     #[tokio::test]
     async fn test_catergorize_transaction() {
         let transaction = vec![Transaction::dummy(), Transaction::dummy()];
         let result = catergorize_transactions(&transaction).await;
-        println!("result: {:#?}", result);
-        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), vec!["Coffee Shops", "Coffee Shops"]);
+    }
+
+    #[test]
+    fn test_default_rules_cover_canadian_merchants() {
+        let categorizer = RuleCategorizer::default();
+        let cases = [
+            ("TIM HORTONS #7525, NEPEAN", "Coffee Shops"),
+            ("LOBLAWS 1019 OTTAWA", "Groceries"),
+            ("PRESTO FARE/S7HKJY9HQT", "Public Transit"),
+            ("METROLINX GO TRANSIT", "Public Transit"),
+            ("PETRO-CANADA 123", "Gas"),
+            ("Amazon.ca*AB12CD", "Shopping"),
+        ];
+        for (description, category) in cases {
+            assert_eq!(
+                categorizer.categorize_one(&transaction(description, -500)),
+                Some(category),
+                "{}",
+                description
+            );
+        }
+        assert_eq!(
+            categorizer.categorize_one(&transaction("SOME LOCAL SHOP", -500)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_user_rules_win_over_defaults() {
+        let mut rule = CategoryRule::new(1, "Work Coffee");
+        rule.description_contains = Some("tim hortons".to_string());
+        let categorizer = RuleCategorizer::with_defaults(vec![rule]).unwrap();
+        assert_eq!(
+            categorizer.categorize_one(&Transaction::dummy()),
+            Some("Work Coffee")
+        );
+    }
+
+    #[test]
+    fn test_priority_orders_rules() {
+        let mut low = CategoryRule::new(1, "Low");
+        low.description_contains = Some("TIM".to_string());
+        low.priority = 1;
+        let mut high = CategoryRule::new(1, "High");
+        high.description_contains = Some("TIM".to_string());
+        high.priority = 2;
+
+        let categorizer = RuleCategorizer::new(vec![low, high]).unwrap();
+        assert_eq!(
+            categorizer.categorize_one(&Transaction::dummy()),
+            Some("High")
+        );
+    }
+
+    #[test]
+    fn test_amount_range_and_account_type() {
+        let mut big = CategoryRule::new(1, "Big Purchase");
+        big.max_amount = Some(Money::cad(-50000));
+        big.account_type = Some(AccountType::Credit);
+        let categorizer = RuleCategorizer::new(vec![big]).unwrap();
+
+        assert_eq!(
+            categorizer.categorize_one(&transaction("BEST BUY", -60000)),
+            Some("Big Purchase")
+        );
+        assert_eq!(
+            categorizer.categorize_one(&transaction("BEST BUY", -100)),
+            None
+        );
+
+        let mut chequing = transaction("BEST BUY", -60000);
+        chequing.account_type = AccountType::Chequing;
+        assert_eq!(categorizer.categorize_one(&chequing), None);
+    }
+
+    #[test]
+    fn test_income_needs_a_deposit() {
+        let categorizer = RuleCategorizer::default();
+        let mut pay = transaction("PAYROLL DEPOSIT ACME CORP", 250000);
+        pay.account_type = AccountType::Chequing;
+        assert_eq!(categorizer.categorize_one(&pay), Some("Income"));
+
+        pay.cad = -pay.cad;
+        assert_eq!(categorizer.categorize_one(&pay), None);
+    }
+
+    #[test]
+    fn test_invalid_rules_are_rejected() {
+        let mut bad_regex = CategoryRule::new(1, "Broken");
+        bad_regex.description_regex = Some("(unclosed".to_string());
+        assert!(matches!(
+            RuleCategorizer::new(vec![bad_regex]),
+            Err(CategorizeError::InvalidRule(_))
+        ));
+
+        assert!(CategoryRule::new(1, "Empty").validate().is_err());
+
+        let mut backwards = CategoryRule::new(1, "Backwards");
+        backwards.min_amount = Some(Money::cad(100));
+        backwards.max_amount = Some(Money::cad(-100));
+        assert!(backwards.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fallback_only_fills_gaps() {
+        let categorizer = FallbackCategorizer {
+            primary: RuleCategorizer::default(),
+            fallback: Fixed("Other"),
+        };
+        let batch = vec![Transaction::dummy(), transaction("SOME LOCAL SHOP", -500)];
+        let categories = categorizer.categorize(&batch).await.unwrap();
+        assert_eq!(
+            categories,
+            vec![Some("Coffee Shops".to_string()), Some("Other".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_apply_categories_keeps_unmatched() {
+        let mut batch = vec![Transaction::dummy(), Transaction::dummy()];
+        batch[1].category = "Manual".to_string();
+        apply_categories(&mut batch, vec![Some("Coffee Shops".to_string()), None]);
+        assert_eq!(batch[0].category, "Coffee Shops");
+        assert_eq!(batch[1].category, "Manual");
+    }
+
+    #[tokio::test]
+    async fn test_unusable_api_key_is_a_config_error() {
+        let remote = RemoteCategorizer::new(API_ENDPOINT, "fina\napi");
+        assert!(matches!(
+            remote.categorize(&[Transaction::dummy()]).await,
+            Err(CategorizeError::InvalidApiKey(_))
+        ));
+    }
+
+    #[tokio::test]
+    #[ignore = "calls the fina.money API, needs network access"]
+    async fn test_remote_categorizer() {
+        let remote = RemoteCategorizer::new(API_ENDPOINT, "fina-api-test");
+        let result = remote.categorize(&[Transaction::dummy()]).await;
+        assert_eq!(result.unwrap().len(), 1);
     }
 }
@@ -1,140 +1,2113 @@
-use rusqlite::{named_params, Connection, Result};
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use rusqlite::{named_params, Connection, OptionalExtension, Result, ToSql};
+use serde::Serialize;
 
 use crate::{
-    account::{bank_account_from_row, AccountType, BankAccount},
-    transaction::Transaction,
+    account::{
+        bank_account_from_row, AccountLabel, AccountPeriod, AccountType, BankAccount, BankNumber,
+    },
+    budget::Budget,
+    category::{self, Category, CategoryTotal},
+    catergorization::CategoryRule,
+    exchange::ExchangeRate,
+    interest::{InterestPlan, InvalidCompounding, Promotion, Tier},
+    investment::{Price, Trade, TradeKind},
+    loan::{LoanPayment, LoanTerms},
+    merchant::{self, Merchant, MerchantAlias},
+    migrations,
+    money::{Currency, Money},
+    networth::BalanceSnapshot,
+    recurring::Charge,
+    report::{MonthTotals, Ranked, ReportFilter},
+    transaction::{
+        self, Transaction, TransactionPage, TransactionQuery, TransactionSort, TransactionUpdate,
+        MAX_PAGE_SIZE,
+    },
+    transfer::{TransferCandidate, TransferPair},
     user::User,
 };
 
+const INSERT_TRANSACTION: &str = "INSERT INTO Transactions (user_id, account_type, account_number, transaction_date, cheque_number, description_1, description_2, cad, usd, category_id, merchant_id, statement_balance, currency, fingerprint) VALUES (?,?,?,?,?,?,?,?,?,?,?,?,?,?)";
+
+// Transactions with the names of their category and merchant appended, see `Transaction::from_row`
+const SELECT_TRANSACTIONS: &str =
+    "SELECT Transactions.*, Categories.name, Merchants.name FROM Transactions
+    LEFT JOIN Categories ON Categories.category_id = Transactions.category_id
+    LEFT JOIN Merchants ON Merchants.merchant_id = Transactions.merchant_id";
+
+// The user's "Transfers" category and everything under it, which reports leave out
+const REPORT_EXCLUDED: &str = "WITH RECURSIVE excluded(category_id) AS (
+        SELECT category_id FROM Categories
+        WHERE user_id = :user_id AND name = 'Transfers' COLLATE NOCASE
+        UNION
+        SELECT Categories.category_id FROM Categories
+        JOIN excluded ON Categories.parent_id = excluded.category_id
+    )";
+
+// A transaction's amount in its own currency
+const NATIVE_AMOUNT: &str =
+    "CASE Transactions.currency WHEN 'USD' THEN Transactions.usd ELSE Transactions.cad END";
+
+// The user's transactions converted into :currency at the rate on their date, to
+// be joined on transaction_id. Falls back the same way as `ExchangeRates::rate`
+// and leaves the amount NULL when no rate is known at all.
+const CONVERTED: &str = "converted(transaction_id, amount) AS (
+        SELECT transaction_id, CAST(ROUND(native * CASE WHEN currency = :currency THEN 1.0 ELSE COALESCE(
+            (SELECT rate FROM ExchangeRates WHERE ExchangeRates.user_id = :user_id
+                AND base = currency AND quote = :currency
+                AND rate_date <= transaction_date ORDER BY rate_date DESC LIMIT 1),
+            (SELECT 1.0 / rate FROM ExchangeRates WHERE ExchangeRates.user_id = :user_id
+                AND base = :currency AND quote = currency
+                AND rate_date <= transaction_date ORDER BY rate_date DESC LIMIT 1),
+            (SELECT rate FROM ExchangeRates WHERE ExchangeRates.user_id = :user_id
+                AND base = currency AND quote = :currency
+                ORDER BY rate_date LIMIT 1),
+            (SELECT 1.0 / rate FROM ExchangeRates WHERE ExchangeRates.user_id = :user_id
+                AND base = :currency AND quote = currency
+                ORDER BY rate_date LIMIT 1)
+        ) END) AS INTEGER)
+        FROM (
+            SELECT transaction_id, currency, transaction_date,
+                CASE currency WHEN 'USD' THEN usd ELSE cad END AS native
+            FROM Transactions WHERE user_id = :user_id
+        )
+    )";
+
+// Rows covered by a `ReportFilter`, bound with `report_params`. Linked transfers
+// are left out along with the Transfers category.
+const REPORT_FILTER: &str = "Transactions.user_id = :user_id
+    AND transaction_date BETWEEN :from AND :to
+    AND (:account_number IS NULL OR account_number = :account_number)
+    AND (Transactions.category_id IS NULL OR Transactions.category_id NOT IN excluded)
+    AND Transactions.transfer_id IS NULL";
+
+fn report_params<'a>(user_id: &'a i64, filter: &'a ReportFilter) -> Vec<(&'a str, &'a dyn ToSql)> {
+    vec![
+        (":user_id", user_id as &dyn ToSql),
+        (":from", &filter.from),
+        (":to", &filter.to),
+        (":account_number", &filter.account_number),
+        (":currency", &filter.currency),
+    ]
+}
+
+// WHERE clause over Transactions for a `TransactionQuery`, with its positional parameters
+fn transaction_filter(user_id: i64, query: &TransactionQuery) -> (String, Vec<Box<dyn ToSql>>) {
+    let mut clauses = vec!["Transactions.user_id = ?".to_string()];
+    let mut params: Vec<Box<dyn ToSql>> = vec![Box::new(user_id)];
+
+    if let Some(from) = query.from {
+        clauses.push("transaction_date >= ?".into());
+        params.push(Box::new(from));
+    }
+    if let Some(to) = query.to {
+        clauses.push("transaction_date <= ?".into());
+        params.push(Box::new(to));
+    }
+    if let Some(account_number) = query.account_number {
+        clauses.push("account_number = ?".into());
+        params.push(Box::new(account_number));
+    }
+    if let Some(account_type) = query.account_type {
+        clauses.push("account_type = ?".into());
+        params.push(Box::new(account_type.to_string()));
+    }
+    if let Some(category_id) = query.category_id {
+        clauses.push(
+            "Transactions.category_id IN (
+                WITH RECURSIVE subtree(category_id) AS (
+                    SELECT ?
+                    UNION
+                    SELECT Categories.category_id FROM Categories
+                    JOIN subtree ON Categories.parent_id = subtree.category_id
+                )
+                SELECT category_id FROM subtree
+            )"
+            .into(),
+        );
+        params.push(Box::new(category_id));
+    }
+    if let Some(merchant_id) = query.merchant_id {
+        clauses.push("Transactions.merchant_id = ?".into());
+        params.push(Box::new(merchant_id));
+    }
+    if query.uncategorized {
+        clauses.push("Transactions.category_id IS NULL".into());
+    }
+    if let Some(min) = query.min_amount {
+        clauses.push(format!("{NATIVE_AMOUNT} >= ?"));
+        params.push(Box::new(min.minor_units()));
+    }
+    if let Some(max) = query.max_amount {
+        clauses.push(format!("{NATIVE_AMOUNT} <= ?"));
+        params.push(Box::new(max.minor_units()));
+    }
+    if let Some(search) = query
+        .search
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+    {
+        let pattern = format!(
+            "%{}%",
+            search
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        clauses.push(
+            "(description_1 LIKE ? ESCAPE '\\' OR description_2 LIKE ? ESCAPE '\\'
+                OR notes LIKE ? ESCAPE '\\')"
+                .into(),
+        );
+        for _ in 0..3 {
+            params.push(Box::new(pattern.clone()));
+        }
+    }
+
+    (clauses.join(" AND "), params)
+}
+
+// Outcome of importing a statement with `batch_insert_transactions`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct ImportResult {
+    pub inserted: usize,
+    pub duplicates: usize,
+    pub conflicts: usize,
+}
+
+fn transaction_params<'a>(
+    transaction: &'a Transaction,
+    category_id: Option<i64>,
+    merchant_id: Option<i64>,
+    fingerprint: &'a str,
+) -> impl rusqlite::Params + 'a {
+    (
+        &transaction.user_id,
+        transaction.account_type.to_string(),
+        &transaction.account_number,
+        &transaction.transaction_date,
+        &transaction.cheque_number,
+        &transaction.description_1,
+        &transaction.description_2,
+        transaction.cad.minor_units(),
+        transaction.usd.minor_units(),
+        category_id,
+        merchant_id,
+        transaction.statement_balance.map(|b| b.minor_units()),
+        transaction.currency,
+        fingerprint,
+    )
+}
+
 pub struct Database {
     _db_path: String,
     connection: Connection,
+    // keys the bank's account numbers, see `BankNumber::key`
+    account_secret: Vec<u8>,
+}
+
+// The secret bank account numbers are keyed with lives in `<database>.key`,
+// readable by the owner only, so that a copy of the database alone does not give
+// the numbers away. It is created with the database. Back it up apart from the
+// database: without it, imports no longer recognize accounts and add new ones.
+fn account_secret(path: &str) -> Result<Vec<u8>> {
+    if path == ":memory:" {
+        return Ok(rand::random::<[u8; 32]>().to_vec());
+    }
+    let key_path = std::path::PathBuf::from(format!("{}.key", path));
+    let invalid = |_| rusqlite::Error::InvalidPath(key_path.clone());
+    match std::fs::read(&key_path) {
+        Ok(secret) => Ok(secret),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let secret = rand::random::<[u8; 32]>().to_vec();
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            std::io::Write::write_all(&mut options.open(&key_path).map_err(invalid)?, &secret)
+                .map_err(invalid)?;
+            Ok(secret)
+        }
+        Err(e) => Err(invalid(e)),
+    }
 }
 
 impl Database {
+    // Opens the database and brings its schema up to the latest migration
     pub fn new(path: String) -> Result<Database> {
-        let conn = Connection::open(&path)?;
+        let mut conn = Connection::open(&path)?;
+        migrations::migrate(&mut conn)?;
         Ok(Database {
+            account_secret: account_secret(&path)?,
             _db_path: path,
             connection: conn,
         })
     }
 
-    fn get_connection(&self) -> &Connection {
-        &self.connection
+    fn get_connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    pub fn schema_version(&self) -> Result<i64> {
+        migrations::current_version(self.get_connection())
+    }
+
+    pub fn close_connection(self) -> Result<()> {
+        self.connection.close().expect("Failed to close connection");
+        Ok(())
+    }
+
+    pub fn reset_values(&self) -> Result<()> {
+        let conn = self.get_connection();
+        conn.execute("DELETE FROM Users", ())?;
+        conn.execute("DELETE FROM Account", ())?;
+        conn.execute("DELETE FROM Transactions", ())?;
+        Ok(())
+    }
+
+    // Inserts a single transaction. An identical transaction already stored is taken as
+    // a separate purchase, so it gets the next free occurrence instead of being skipped.
+    // Returns the new transaction_id.
+    pub fn insert_transaction(&self, transaction: &Transaction) -> Result<i64> {
+        let conn = self.get_connection();
+        let mut occurrence = 0;
+        while self.fingerprint_exists(&transaction.fingerprint(occurrence))? {
+            occurrence += 1;
+        }
+
+        let category_id = self.transaction_category_id(transaction)?;
+        let merchant_id = self.transaction_merchant_id(transaction)?;
+        let mut statement = conn.prepare(INSERT_TRANSACTION)?;
+        statement.execute(transaction_params(
+            transaction,
+            category_id,
+            merchant_id,
+            &transaction.fingerprint(occurrence),
+        ))?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    // Inserts an imported statement, skipping rows that an earlier import already stored.
+    // A row whose fingerprint matches but whose other fields differ is left untouched
+    // and counted as a conflict.
+    pub fn batch_insert_transactions(&self, transactions: &[Transaction]) -> Result<ImportResult> {
+        let legacy_fingerprints = self.legacy_fingerprints(transactions)?;
+        let conn = self.get_connection();
+        let tx = conn.unchecked_transaction()?;
+        let mut result = ImportResult::default();
+
+        {
+            let mut insert = tx.prepare(INSERT_TRANSACTION)?;
+            let mut existing = tx.prepare(
+                "SELECT user_id, account_type, cheque_number FROM Transactions WHERE fingerprint = ?",
+            )?;
+
+            for ((transaction, fingerprint), legacy_fingerprint) in transactions
+                .iter()
+                .zip(transaction::fingerprints(transactions))
+                .zip(legacy_fingerprints)
+            {
+                let mut stored = None;
+                for fingerprint in std::iter::once(&fingerprint).chain(&legacy_fingerprint) {
+                    stored = existing
+                        .query_row([fingerprint], |row| {
+                            Ok((
+                                row.get::<_, i64>(0)?,
+                                row.get::<_, String>(1)?,
+                                row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                            ))
+                        })
+                        .optional()?;
+                    if stored.is_some() {
+                        break;
+                    }
+                }
+
+                match stored {
+                    None => {
+                        let category_id = self.transaction_category_id(transaction)?;
+                        let merchant_id = self.transaction_merchant_id(transaction)?;
+                        insert.execute(transaction_params(
+                            transaction,
+                            category_id,
+                            merchant_id,
+                            &fingerprint,
+                        ))?;
+                        result.inserted += 1;
+                    }
+                    Some((user_id, account_type, cheque_number))
+                        if user_id == transaction.user_id
+                            && account_type == transaction.account_type.to_string()
+                            && cheque_number == transaction.cheque_number =>
+                    {
+                        result.duplicates += 1
+                    }
+                    Some(_) => result.conflicts += 1,
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(result)
+    }
+
+    // Fingerprints of a batch under the numbers its accounts had before stable keys,
+    // for rows of accounts that had one. Rows edited before the upgrade still carry
+    // them, see `migrations::account_keys`.
+    fn legacy_fingerprints(&self, transactions: &[Transaction]) -> Result<Vec<Option<String>>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare("SELECT legacy_hash FROM Account WHERE account_number = ?")?;
+        let mut renumbered = transactions.to_vec();
+        let mut has_legacy = Vec::with_capacity(transactions.len());
+        for transaction in &mut renumbered {
+            let legacy_hash: Option<i64> = stmt
+                .query_row([transaction.account_number], |row| row.get(0))
+                .optional()?
+                .flatten();
+            if let Some(legacy_hash) = legacy_hash {
+                transaction.account_number = legacy_hash;
+            }
+            has_legacy.push(legacy_hash.is_some());
+        }
+        Ok(transaction::fingerprints(&renumbered)
+            .into_iter()
+            .zip(has_legacy)
+            .map(|(fingerprint, has_legacy)| has_legacy.then_some(fingerprint))
+            .collect())
+    }
+
+    fn fingerprint_exists(&self, fingerprint: &str) -> Result<bool> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare("SELECT 1 FROM Transactions WHERE fingerprint = ?")?;
+        stmt.exists([fingerprint])
+    }
+
+    pub fn get_transactions(&self, user_id: i64) -> Result<Vec<Transaction>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE Transactions.user_id = :user_id ORDER BY transaction_date, transaction_id",
+            SELECT_TRANSACTIONS
+        ))?;
+        let rows = stmt.query_map(named_params! {":user_id": user_id}, |row| {
+            Transaction::from_row(row)
+        })?;
+        let mut transactions = Vec::new();
+        for row in rows {
+            transactions.push(row?);
+        }
+        Ok(transactions)
+    }
+
+    // One account's transactions, oldest first
+    pub fn get_account_transactions(
+        &self,
+        user_id: i64,
+        account_number: i64,
+    ) -> Result<Vec<Transaction>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE Transactions.user_id = :user_id AND Transactions.account_number = :account_number
+             ORDER BY transaction_date, transaction_id",
+            SELECT_TRANSACTIONS
+        ))?;
+        let rows = stmt.query_map(
+            named_params! {":user_id": user_id, ":account_number": account_number},
+            Transaction::from_row,
+        )?;
+        let mut transactions = Vec::new();
+        for row in rows {
+            transactions.push(row?);
+        }
+        Ok(transactions)
+    }
+
+    // Transactions across all of the user's accounts dated within `from..=to`
+    pub fn get_transactions_between(
+        &self,
+        user_id: i64,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Transaction>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE Transactions.user_id = :user_id AND transaction_date BETWEEN :from AND :to
+            ORDER BY transaction_date, transaction_id",
+            SELECT_TRANSACTIONS
+        ))?;
+        let rows = stmt.query_map(
+            named_params! {":user_id": user_id, ":from": from, ":to": to},
+            Transaction::from_row,
+        )?;
+        rows.collect()
+    }
+
+    // One page of the transactions matching `query` along with the number of matches
+    pub fn query_transactions(
+        &self,
+        user_id: i64,
+        query: &TransactionQuery,
+    ) -> Result<TransactionPage> {
+        let conn = self.get_connection();
+        let (filter, mut params) = transaction_filter(user_id, query);
+
+        let total = conn.query_row(
+            &format!("SELECT COUNT(*) FROM Transactions WHERE {}", filter),
+            rusqlite::params_from_iter(params.iter()),
+            |row| row.get(0),
+        )?;
+
+        let order = match query.sort {
+            TransactionSort::DateAsc => "transaction_date, Transactions.transaction_id".to_string(),
+            TransactionSort::DateDesc => {
+                "transaction_date DESC, Transactions.transaction_id DESC".to_string()
+            }
+            TransactionSort::AmountAsc => format!("{NATIVE_AMOUNT}, Transactions.transaction_id"),
+            TransactionSort::AmountDesc => {
+                format!("{NATIVE_AMOUNT} DESC, Transactions.transaction_id DESC")
+            }
+        };
+        let limit = query.limit.min(MAX_PAGE_SIZE);
+        params.push(Box::new(limit as i64));
+        params.push(Box::new(query.offset as i64));
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE {} ORDER BY {} LIMIT ? OFFSET ?",
+            SELECT_TRANSACTIONS, filter, order
+        ))?;
+        let transactions = stmt
+            .query_map(
+                rusqlite::params_from_iter(params.iter()),
+                Transaction::from_row,
+            )?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(TransactionPage {
+            transactions,
+            total,
+            limit,
+            offset: query.offset,
+        })
+    }
+
+    // Like `query_transactions` for the transactions matching an FTS5 query over
+    // their descriptions, best match first
+    pub fn search_transactions(
+        &self,
+        user_id: i64,
+        fts_query: &str,
+        query: &TransactionQuery,
+    ) -> Result<TransactionPage> {
+        let conn = self.get_connection();
+        let (filter, mut params) = transaction_filter(user_id, query);
+        params.insert(0, Box::new(fts_query.to_string()));
+
+        let total = conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM Transactions
+                JOIN TransactionSearch ON TransactionSearch.rowid = Transactions.transaction_id
+                WHERE TransactionSearch MATCH ? AND {}",
+                filter
+            ),
+            rusqlite::params_from_iter(params.iter()),
+            |row| row.get(0),
+        )?;
+
+        let limit = query.limit.min(MAX_PAGE_SIZE);
+        params.push(Box::new(limit as i64));
+        params.push(Box::new(query.offset as i64));
+        let mut stmt = conn.prepare(&format!(
+            "{} JOIN TransactionSearch ON TransactionSearch.rowid = Transactions.transaction_id
+            WHERE TransactionSearch MATCH ? AND {}
+            ORDER BY TransactionSearch.rank, transaction_date DESC, Transactions.transaction_id DESC
+            LIMIT ? OFFSET ?",
+            SELECT_TRANSACTIONS, filter
+        ))?;
+        let transactions = stmt
+            .query_map(
+                rusqlite::params_from_iter(params.iter()),
+                Transaction::from_row,
+            )?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(TransactionPage {
+            transactions,
+            total,
+            limit,
+            offset: query.offset,
+        })
+    }
+
+    pub fn get_transaction(
+        &self,
+        user_id: i64,
+        transaction_id: i64,
+    ) -> Result<Option<Transaction>> {
+        let conn = self.get_connection();
+        conn.query_row(
+            &format!(
+                "{} WHERE Transactions.user_id = :user_id AND transaction_id = :transaction_id",
+                SELECT_TRANSACTIONS
+            ),
+            named_params! {":user_id": user_id, ":transaction_id": transaction_id},
+            Transaction::from_row,
+        )
+        .optional()
+    }
+
+    // Applies the edits and returns the updated transaction, None if the
+    // user has no such transaction. A category given by id must already be
+    // checked to belong to the user.
+    pub fn update_transaction(
+        &self,
+        user_id: i64,
+        transaction_id: i64,
+        changes: &TransactionUpdate,
+    ) -> Result<Option<Transaction>> {
+        let Some(mut transaction) = self.get_transaction(user_id, transaction_id)? else {
+            return Ok(None);
+        };
+
+        if let Some(category_id) = changes.category_id {
+            transaction.category_id = category_id;
+        }
+        if let Some(name) = &changes.category {
+            transaction.category_id = match name.trim() {
+                "" => None,
+                name => Some(self.get_or_create_category(user_id, name)?),
+            };
+        }
+        if let Some(notes) = &changes.notes {
+            transaction.notes = notes.clone();
+        }
+        if let Some(description_1) = &changes.description_1 {
+            transaction.description_1 = description_1.clone();
+            transaction.merchant_id = self.get_or_create_merchant(user_id, description_1)?;
+        }
+        if let Some(description_2) = &changes.description_2 {
+            transaction.description_2 = description_2.clone();
+        }
+
+        let conn = self.get_connection();
+        conn.execute(
+            "UPDATE Transactions SET category_id = ?, merchant_id = ?, notes = ?, description_1 = ?,
+                description_2 = ?
+            WHERE transaction_id = ? AND user_id = ?",
+            (
+                transaction.category_id,
+                transaction.merchant_id,
+                &transaction.notes,
+                &transaction.description_1,
+                &transaction.description_2,
+                transaction_id,
+                user_id,
+            ),
+        )?;
+        self.get_transaction(user_id, transaction_id)
+    }
+
+    pub fn delete_transaction(&self, user_id: i64, transaction_id: i64) -> Result<bool> {
+        let conn = self.get_connection();
+        let deleted = conn.execute(
+            "DELETE FROM Transactions WHERE transaction_id = ? AND user_id = ?",
+            (transaction_id, user_id),
+        )?;
+        Ok(deleted == 1)
+    }
+
+    // The category a new transaction is filed under: its id if set, otherwise the
+    // category named by the categorizer, created on first use.
+    fn transaction_category_id(&self, transaction: &Transaction) -> Result<Option<i64>> {
+        if transaction.category_id.is_some() {
+            return Ok(transaction.category_id);
+        }
+        if transaction.category.trim().is_empty() {
+            return Ok(None);
+        }
+        self.get_or_create_category(transaction.user_id, &transaction.category)
+            .map(Some)
+    }
+
+    fn transaction_merchant_id(&self, transaction: &Transaction) -> Result<Option<i64>> {
+        if transaction.merchant_id.is_some() {
+            return Ok(transaction.merchant_id);
+        }
+        self.get_or_create_merchant(transaction.user_id, &transaction.description_1)
+    }
+
+    // Merchant named by a description: the one it is an alias of, else the one
+    // keyed by it, created when new. None for descriptions with nothing to go on.
+    pub fn get_or_create_merchant(&self, user_id: i64, description: &str) -> Result<Option<i64>> {
+        let key = merchant::normalize(description);
+        if key.is_empty() {
+            return Ok(None);
+        }
+        let conn = self.get_connection();
+        let existing = conn
+            .query_row(
+                "SELECT merchant_id FROM MerchantAliases WHERE user_id = :user_id AND alias = :key
+                UNION ALL
+                SELECT merchant_id FROM Merchants WHERE user_id = :user_id AND key = :key",
+                named_params! {":user_id": user_id, ":key": key},
+                |row| row.get(0),
+            )
+            .optional()?;
+        if existing.is_some() {
+            return Ok(existing);
+        }
+        conn.execute(
+            "INSERT INTO Merchants (user_id, name, key) VALUES (?, ?, ?)",
+            (user_id, merchant::display_name(&key), &key),
+        )?;
+        Ok(Some(conn.last_insert_rowid()))
+    }
+
+    pub fn get_merchant(&self, user_id: i64, merchant_id: i64) -> Result<Option<Merchant>> {
+        let conn = self.get_connection();
+        let merchant = conn
+            .query_row(
+                "SELECT * FROM Merchants WHERE merchant_id = ? AND user_id = ?",
+                (merchant_id, user_id),
+                Merchant::from_row,
+            )
+            .optional()?;
+        let Some(mut merchant) = merchant else {
+            return Ok(None);
+        };
+        let mut stmt =
+            conn.prepare("SELECT * FROM MerchantAliases WHERE merchant_id = ? ORDER BY alias")?;
+        merchant.aliases = stmt
+            .query_map([merchant_id], MerchantAlias::from_row)?
+            .collect::<Result<_>>()?;
+        Ok(Some(merchant))
+    }
+
+    // Every merchant of the user with its aliases, by name
+    pub fn get_merchants(&self, user_id: i64) -> Result<Vec<Merchant>> {
+        let conn = self.get_connection();
+        let mut stmt =
+            conn.prepare("SELECT * FROM Merchants WHERE user_id = ? ORDER BY name COLLATE NOCASE")?;
+        let mut merchants = stmt
+            .query_map([user_id], Merchant::from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut stmt =
+            conn.prepare("SELECT * FROM MerchantAliases WHERE user_id = ? ORDER BY alias")?;
+        for alias in stmt.query_map([user_id], MerchantAlias::from_row)? {
+            let alias = alias?;
+            if let Some(merchant) = merchants.iter_mut().find(|m| m.id == alias.merchant_id) {
+                merchant.aliases.push(alias);
+            }
+        }
+        Ok(merchants)
+    }
+
+    // Returns false when the user has no such merchant
+    pub fn rename_merchant(&self, user_id: i64, merchant_id: i64, name: &str) -> Result<bool> {
+        let conn = self.get_connection();
+        let updated = conn.execute(
+            "UPDATE Merchants SET name = ? WHERE merchant_id = ? AND user_id = ?",
+            (name, merchant_id, user_id),
+        )?;
+        Ok(updated > 0)
+    }
+
+    // Adds the alias and folds the merchant keyed by it, if any, into `merchant_id`
+    pub fn insert_merchant_alias(
+        &self,
+        user_id: i64,
+        merchant_id: i64,
+        alias: &str,
+    ) -> Result<i64> {
+        let conn = self.get_connection();
+        let tx = conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO MerchantAliases (user_id, merchant_id, alias) VALUES (?, ?, ?)",
+            (user_id, merchant_id, alias),
+        )?;
+        let alias_id = tx.last_insert_rowid();
+
+        let source: Option<i64> = tx
+            .query_row(
+                "SELECT merchant_id FROM Merchants WHERE user_id = ? AND key = ? AND merchant_id != ?",
+                (user_id, alias, merchant_id),
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(source) = source {
+            tx.execute(
+                "UPDATE Transactions SET merchant_id = ? WHERE merchant_id = ?",
+                (merchant_id, source),
+            )?;
+            tx.execute(
+                "UPDATE MerchantAliases SET merchant_id = ? WHERE merchant_id = ?",
+                (merchant_id, source),
+            )?;
+            tx.execute("DELETE FROM Merchants WHERE merchant_id = ?", [source])?;
+        }
+
+        tx.commit()?;
+        Ok(alias_id)
+    }
+
+    // Returns false when the merchant has no such alias
+    pub fn delete_merchant_alias(
+        &self,
+        user_id: i64,
+        merchant_id: i64,
+        alias_id: i64,
+    ) -> Result<bool> {
+        let conn = self.get_connection();
+        let deleted = conn.execute(
+            "DELETE FROM MerchantAliases WHERE alias_id = ? AND merchant_id = ? AND user_id = ?",
+            (alias_id, merchant_id, user_id),
+        )?;
+        Ok(deleted > 0)
+    }
+
+    pub fn get_category(&self, user_id: i64, category_id: i64) -> Result<Option<Category>> {
+        let conn = self.get_connection();
+        conn.query_row(
+            "SELECT * FROM Categories WHERE category_id = :category_id AND user_id = :user_id",
+            named_params! {":category_id": category_id, ":user_id": user_id},
+            Category::from_row,
+        )
+        .optional()
+    }
+
+    // Names are unique per user, ignoring case
+    pub fn get_category_by_name(&self, user_id: i64, name: &str) -> Result<Option<Category>> {
+        let conn = self.get_connection();
+        conn.query_row(
+            "SELECT * FROM Categories WHERE user_id = :user_id AND name = :name COLLATE NOCASE",
+            named_params! {":user_id": user_id, ":name": name.trim()},
+            Category::from_row,
+        )
+        .optional()
+    }
+
+    pub fn get_categories(&self, user_id: i64) -> Result<Vec<Category>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(
+            "SELECT * FROM Categories WHERE user_id = :user_id ORDER BY name COLLATE NOCASE",
+        )?;
+        let rows = stmt.query_map(named_params! {":user_id": user_id}, Category::from_row)?;
+        rows.collect()
+    }
+
+    pub fn insert_category(&self, category: &Category) -> Result<i64> {
+        let conn = self.get_connection();
+        conn.execute(
+            "INSERT INTO Categories (user_id, parent_id, name, color, icon) VALUES (?,?,?,?,?)",
+            (
+                category.user_id,
+                category.parent_id,
+                &category.name,
+                &category.color,
+                &category.icon,
+            ),
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    // Looks a category up by name and creates it if missing. Names from the
+    // default tree are created under their default parent.
+    pub fn get_or_create_category(&self, user_id: i64, name: &str) -> Result<i64> {
+        if let Some(existing) = self.get_category_by_name(user_id, name)? {
+            return Ok(existing.id);
+        }
+
+        let (parent, color, icon) = category::default_placement(name.trim());
+        let parent_id = match parent {
+            Some(parent) => Some(self.get_or_create_category(user_id, parent)?),
+            None => None,
+        };
+        self.insert_category(&Category {
+            id: 0,
+            user_id,
+            parent_id,
+            name: name.trim().to_string(),
+            color: color.map(String::from),
+            icon: icon.map(String::from),
+        })
+    }
+
+    // Saves the category. Rules filing into it are renamed along with it.
+    pub fn update_category(&self, category: &Category) -> Result<()> {
+        let conn = self.get_connection();
+        let tx = conn.unchecked_transaction()?;
+        let old_name: String = tx.query_row(
+            "SELECT name FROM Categories WHERE category_id = ? AND user_id = ?",
+            (category.id, category.user_id),
+            |row| row.get(0),
+        )?;
+        tx.execute(
+            "UPDATE Categories SET parent_id = ?, name = ?, color = ?, icon = ? WHERE category_id = ? AND user_id = ?",
+            (
+                category.parent_id,
+                &category.name,
+                &category.color,
+                &category.icon,
+                category.id,
+                category.user_id,
+            ),
+        )?;
+        tx.execute(
+            "UPDATE CategoryRules SET category = ? WHERE user_id = ? AND category = ? COLLATE NOCASE",
+            (&category.name, category.user_id, &old_name),
+        )?;
+        tx.commit()
+    }
+
+    // Moves transactions, subcategories, rules and budgets from `source_id` to `target_id`
+    // and deletes `source_id`. If the target sits below the source it is first
+    // lifted to the source's parent, so the tree stays acyclic.
+    pub fn merge_categories(&self, user_id: i64, source_id: i64, target_id: i64) -> Result<usize> {
+        let conn = self.get_connection();
+        let tx = conn.unchecked_transaction()?;
+        let (source_parent, source_name): (Option<i64>, String) = tx.query_row(
+            "SELECT parent_id, name FROM Categories WHERE category_id = ? AND user_id = ?",
+            (source_id, user_id),
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let target_name: String = tx.query_row(
+            "SELECT name FROM Categories WHERE category_id = ? AND user_id = ?",
+            (target_id, user_id),
+            |row| row.get(0),
+        )?;
+
+        tx.execute(
+            "WITH RECURSIVE below(category_id) AS (
+                SELECT category_id FROM Categories WHERE parent_id = :source
+                UNION
+                SELECT Categories.category_id FROM Categories JOIN below ON Categories.parent_id = below.category_id
+            )
+            UPDATE Categories SET parent_id = :source_parent
+            WHERE category_id = :target AND category_id IN below",
+            named_params! {":source": source_id, ":source_parent": source_parent, ":target": target_id},
+        )?;
+        tx.execute(
+            "UPDATE Categories SET parent_id = ? WHERE parent_id = ? AND user_id = ?",
+            (target_id, source_id, user_id),
+        )?;
+        let moved = tx.execute(
+            "UPDATE Transactions SET category_id = ? WHERE category_id = ? AND user_id = ?",
+            (target_id, source_id, user_id),
+        )?;
+        tx.execute(
+            "UPDATE CategoryRules SET category = ? WHERE user_id = ? AND category = ? COLLATE NOCASE",
+            (&target_name, user_id, &source_name),
+        )?;
+        // the target's own budget wins, the source's is dropped with it
+        tx.execute(
+            "UPDATE OR IGNORE Budgets SET category_id = ? WHERE category_id = ? AND user_id = ?",
+            (target_id, source_id, user_id),
+        )?;
+        tx.execute(
+            "DELETE FROM Categories WHERE category_id = ? AND user_id = ?",
+            (source_id, user_id),
+        )?;
+
+        tx.commit()?;
+        Ok(moved)
+    }
+
+    // Deletes a category, moving its transactions, subcategories, rules and budgets
+    // up to its parent. Rules and budgets of a top-level category are deleted with it.
+    pub fn delete_category(&self, user_id: i64, category_id: i64) -> Result<usize> {
+        let conn = self.get_connection();
+        let tx = conn.unchecked_transaction()?;
+        let (parent_id, name): (Option<i64>, String) = tx.query_row(
+            "SELECT parent_id, name FROM Categories WHERE category_id = ? AND user_id = ?",
+            (category_id, user_id),
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        tx.execute(
+            "UPDATE Categories SET parent_id = ? WHERE parent_id = ? AND user_id = ?",
+            (parent_id, category_id, user_id),
+        )?;
+        let moved = tx.execute(
+            "UPDATE Transactions SET category_id = ? WHERE category_id = ? AND user_id = ?",
+            (parent_id, category_id, user_id),
+        )?;
+        match parent_id {
+            Some(parent_id) => tx.execute(
+                "UPDATE CategoryRules SET category = (SELECT name FROM Categories WHERE category_id = ?)
+                WHERE user_id = ? AND category = ? COLLATE NOCASE",
+                (parent_id, user_id, &name),
+            )?,
+            None => tx.execute(
+                "DELETE FROM CategoryRules WHERE user_id = ? AND category = ? COLLATE NOCASE",
+                (user_id, &name),
+            )?,
+        };
+        if let Some(parent_id) = parent_id {
+            // budgets the parent already has for the same period win
+            tx.execute(
+                "UPDATE OR IGNORE Budgets SET category_id = ? WHERE category_id = ? AND user_id = ?",
+                (parent_id, category_id, user_id),
+            )?;
+        }
+        tx.execute(
+            "DELETE FROM Categories WHERE category_id = ? AND user_id = ?",
+            (category_id, user_id),
+        )?;
+
+        tx.commit()?;
+        Ok(moved)
+    }
+
+    // Totals in `currency` per category for transactions dated within `from..=to`,
+    // largest spending first. With `rollup` subcategories are counted towards
+    // their top-level category.
+    pub fn category_totals(
+        &self,
+        user_id: i64,
+        from: NaiveDate,
+        to: NaiveDate,
+        rollup: bool,
+        currency: Currency,
+    ) -> Result<Vec<CategoryTotal>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(&format!(
+            "WITH RECURSIVE tree(category_id, group_id) AS (
+                SELECT category_id, category_id FROM Categories
+                WHERE user_id = :user_id AND (parent_id IS NULL OR NOT :rollup)
+                UNION
+                SELECT Categories.category_id, tree.group_id FROM Categories
+                JOIN tree ON Categories.parent_id = tree.category_id
+                WHERE :rollup
+            ),
+            {CONVERTED}
+            SELECT tree.group_id, COALESCE(Categories.name, 'Uncategorized'), SUM(converted.amount), COUNT(*)
+            FROM Transactions
+            JOIN converted ON converted.transaction_id = Transactions.transaction_id
+            LEFT JOIN tree ON tree.category_id = Transactions.category_id
+            LEFT JOIN Categories ON Categories.category_id = tree.group_id
+            WHERE Transactions.user_id = :user_id AND transaction_date BETWEEN :from AND :to
+                AND Transactions.transfer_id IS NULL
+            GROUP BY tree.group_id
+            ORDER BY SUM(converted.amount), 2"
+        ))?;
+        let rows = stmt.query_map(
+            named_params! {
+                ":user_id": user_id,
+                ":from": from,
+                ":to": to,
+                ":rollup": rollup,
+                ":currency": currency,
+            },
+            |row| {
+                Ok(CategoryTotal {
+                    category_id: row.get(0)?,
+                    name: row.get(1)?,
+                    total: Money::from_minor(row.get::<_, Option<i64>>(2)?.unwrap_or(0), currency),
+                    count: row.get(3)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+
+    pub fn insert_budget(&self, budget: &Budget) -> Result<i64> {
+        let conn = self.get_connection();
+        conn.execute(
+            "INSERT INTO Budgets (user_id, category_id, period, amount, rollover, start_date) VALUES (?,?,?,?,?,?)",
+            (
+                budget.user_id,
+                budget.category_id,
+                budget.period.to_string(),
+                budget.amount.minor_units(),
+                budget.rollover,
+                budget.start_date,
+            ),
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    // Returns false when the user has no such budget
+    pub fn update_budget(&self, budget: &Budget) -> Result<bool> {
+        let conn = self.get_connection();
+        let updated = conn.execute(
+            "UPDATE Budgets SET category_id = ?, period = ?, amount = ?, rollover = ?, start_date = ?
+            WHERE budget_id = ? AND user_id = ?",
+            (
+                budget.category_id,
+                budget.period.to_string(),
+                budget.amount.minor_units(),
+                budget.rollover,
+                budget.start_date,
+                budget.id,
+                budget.user_id,
+            ),
+        )?;
+        Ok(updated == 1)
+    }
+
+    pub fn delete_budget(&self, user_id: i64, budget_id: i64) -> Result<bool> {
+        let conn = self.get_connection();
+        let deleted = conn.execute(
+            "DELETE FROM Budgets WHERE budget_id = ? AND user_id = ?",
+            (budget_id, user_id),
+        )?;
+        Ok(deleted == 1)
+    }
+
+    pub fn get_budgets(&self, user_id: i64) -> Result<Vec<Budget>> {
+        let conn = self.get_connection();
+        let mut stmt =
+            conn.prepare("SELECT * FROM Budgets WHERE user_id = :user_id ORDER BY budget_id")?;
+        let rows = stmt.query_map(named_params! {":user_id": user_id}, Budget::from_row)?;
+        rows.collect()
+    }
+
+    // Net amount in `currency` per day for a category and everything below it,
+    // for days within `from..=to` that have transactions
+    pub fn category_daily_totals(
+        &self,
+        user_id: i64,
+        category_id: i64,
+        from: NaiveDate,
+        to: NaiveDate,
+        currency: Currency,
+    ) -> Result<Vec<(NaiveDate, Money)>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(&format!(
+            "WITH RECURSIVE subtree(category_id) AS (
+                SELECT :category_id
+                UNION
+                SELECT Categories.category_id FROM Categories
+                JOIN subtree ON Categories.parent_id = subtree.category_id
+            ),
+            {CONVERTED}
+            SELECT transaction_date, COALESCE(SUM(converted.amount), 0) FROM Transactions
+            JOIN converted ON converted.transaction_id = Transactions.transaction_id
+            WHERE user_id = :user_id AND category_id IN subtree
+                AND transaction_date BETWEEN :from AND :to
+                AND transfer_id IS NULL
+            GROUP BY transaction_date
+            ORDER BY transaction_date"
+        ))?;
+        let rows = stmt.query_map(
+            named_params! {
+                ":user_id": user_id,
+                ":category_id": category_id,
+                ":from": from,
+                ":to": to,
+                ":currency": currency,
+            },
+            |row| Ok((row.get(0)?, Money::from_minor(row.get(1)?, currency))),
+        )?;
+        rows.collect()
+    }
+
+    pub fn monthly_totals(&self, user_id: i64, filter: &ReportFilter) -> Result<Vec<MonthTotals>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(&format!(
+            "{REPORT_EXCLUDED},
+            {CONVERTED}
+            SELECT substr(transaction_date, 1, 7) AS month,
+                TOTAL(CASE WHEN amount > 0 THEN amount ELSE 0 END),
+                TOTAL(CASE WHEN amount < 0 THEN -amount ELSE 0 END),
+                COUNT(*)
+            FROM Transactions
+            JOIN converted ON converted.transaction_id = Transactions.transaction_id
+            WHERE {REPORT_FILTER}
+            GROUP BY month
+            ORDER BY month"
+        ))?;
+        let rows = stmt.query_map(report_params(&user_id, filter).as_slice(), |row| {
+            Ok(MonthTotals {
+                month: row.get(0)?,
+                income: Money::from_minor(row.get::<_, f64>(1)? as i64, filter.currency),
+                expenses: Money::from_minor(row.get::<_, f64>(2)? as i64, filter.currency),
+                transactions: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    // The `limit` categories with the most spending in each month
+    pub fn monthly_top_categories(
+        &self,
+        user_id: i64,
+        filter: &ReportFilter,
+        limit: usize,
+    ) -> Result<Vec<(String, Ranked)>> {
+        self.monthly_top(
+            user_id,
+            filter,
+            limit,
+            "COALESCE(Categories.name, 'Uncategorized')",
+            "Transactions.category_id",
+        )
+    }
+
+    // The `limit` merchants with the most spending in each month
+    pub fn monthly_top_merchants(
+        &self,
+        user_id: i64,
+        filter: &ReportFilter,
+        limit: usize,
+    ) -> Result<Vec<(String, Ranked)>> {
+        self.monthly_top(
+            user_id,
+            filter,
+            limit,
+            "COALESCE(Merchants.name, 'Unknown')",
+            "Transactions.merchant_id",
+        )
+    }
+
+    fn monthly_top(
+        &self,
+        user_id: i64,
+        filter: &ReportFilter,
+        limit: usize,
+        name: &str,
+        group: &str,
+    ) -> Result<Vec<(String, Ranked)>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(&format!(
+            "{REPORT_EXCLUDED},
+            {CONVERTED},
+            ranked AS (
+                SELECT substr(transaction_date, 1, 7) AS month, {name} AS name,
+                    -SUM(amount) AS total, COUNT(*) AS count,
+                    ROW_NUMBER() OVER (
+                        PARTITION BY substr(transaction_date, 1, 7) ORDER BY SUM(amount), {name}
+                    ) AS rank
+                FROM Transactions
+                JOIN converted ON converted.transaction_id = Transactions.transaction_id
+                LEFT JOIN Categories ON Categories.category_id = Transactions.category_id
+                LEFT JOIN Merchants ON Merchants.merchant_id = Transactions.merchant_id
+                WHERE {REPORT_FILTER} AND amount < 0
+                GROUP BY month, {group}
+            )
+            SELECT month, name, total, count FROM ranked
+            WHERE rank <= :limit
+            ORDER BY month, rank"
+        ))?;
+        let limit = limit as i64;
+        let mut params = report_params(&user_id, filter);
+        params.push((":limit", &limit));
+        let rows = stmt.query_map(params.as_slice(), |row| {
+            Ok((
+                row.get(0)?,
+                Ranked {
+                    name: row.get(1)?,
+                    total: Money::from_minor(row.get(2)?, filter.currency),
+                    count: row.get(3)?,
+                },
+            ))
+        })?;
+        rows.collect()
+    }
+
+    // Spending at a known merchant since `from` in the currency it was charged in,
+    // grouped by merchant and currency and oldest first. Transfers are left out
+    // as in the reports.
+    pub fn merchant_charges(&self, user_id: i64, from: NaiveDate) -> Result<Vec<Charge>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(&format!(
+            "{REPORT_EXCLUDED}
+            SELECT Transactions.merchant_id, Merchants.name, COALESCE(Categories.name, ''),
+                COALESCE(Parents.name, ''), transaction_date, {NATIVE_AMOUNT}, Transactions.currency
+            FROM Transactions
+            JOIN Merchants ON Merchants.merchant_id = Transactions.merchant_id
+            LEFT JOIN Categories ON Categories.category_id = Transactions.category_id
+            LEFT JOIN Categories AS Parents ON Parents.category_id = Categories.parent_id
+            WHERE Transactions.user_id = :user_id AND transaction_date >= :from
+                AND {NATIVE_AMOUNT} < 0
+                AND (Transactions.category_id IS NULL OR Transactions.category_id NOT IN excluded)
+                AND Transactions.transfer_id IS NULL
+            ORDER BY Transactions.merchant_id, Transactions.currency, transaction_date,
+                Transactions.transaction_id"
+        ))?;
+        let rows = stmt.query_map(named_params! {":user_id": user_id, ":from": from}, |row| {
+            Ok(Charge {
+                merchant_id: row.get(0)?,
+                merchant: row.get(1)?,
+                category: row.get(2)?,
+                parent_category: row.get(3)?,
+                date: row.get(4)?,
+                amount: Money::from_minor(row.get(5)?, row.get(6)?),
+            })
+        })?;
+        rows.collect()
+    }
+
+    // Unlinked outflows and inflows of the same amount in two of the user's
+    // accounts, at most `window_days` apart. Buying, selling and dividends stay
+    // inside an investment account and are never one side of a transfer.
+    pub fn transfer_candidates(
+        &self,
+        user_id: i64,
+        window_days: i64,
+    ) -> Result<Vec<TransferCandidate>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(
+            "SELECT Outflow.transaction_id, Inflow.transaction_id,
+                CAST(ABS(julianday(Inflow.transaction_date) - julianday(Outflow.transaction_date)) AS INTEGER) AS days_apart,
+                (Outflow.description_1 LIKE '%TRANSFER%' OR Outflow.description_1 LIKE '%PAYMENT%'
+                    OR Inflow.description_1 LIKE '%TRANSFER%' OR Inflow.description_1 LIKE '%PAYMENT%')
+            FROM Transactions AS Outflow
+            JOIN Transactions AS Inflow ON Inflow.user_id = Outflow.user_id
+                AND Inflow.currency = Outflow.currency
+                AND Inflow.cad = -Outflow.cad AND Inflow.usd = -Outflow.usd
+                AND Inflow.account_number != Outflow.account_number
+            WHERE Outflow.user_id = :user_id
+                AND (Outflow.cad < 0 OR (Outflow.cad = 0 AND Outflow.usd < 0))
+                AND Outflow.transfer_id IS NULL AND Inflow.transfer_id IS NULL
+                AND Outflow.transaction_id NOT IN (SELECT transaction_id FROM Trades WHERE kind IN ('Buy', 'Sell', 'Dividend'))
+                AND Inflow.transaction_id NOT IN (SELECT transaction_id FROM Trades WHERE kind IN ('Buy', 'Sell', 'Dividend'))
+                AND days_apart <= :window_days",
+        )?;
+        let rows = stmt.query_map(
+            named_params! {":user_id": user_id, ":window_days": window_days},
+            |row| {
+                Ok(TransferCandidate {
+                    outflow_id: row.get(0)?,
+                    inflow_id: row.get(1)?,
+                    days_apart: row.get(2)?,
+                    described: row.get(3)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+
+    pub fn link_transfer(&self, user_id: i64, outflow_id: i64, inflow_id: i64) -> Result<()> {
+        let conn = self.get_connection();
+        let tx = conn.unchecked_transaction()?;
+        for (id, other) in [(outflow_id, inflow_id), (inflow_id, outflow_id)] {
+            tx.execute(
+                "UPDATE Transactions SET transfer_id = ? WHERE transaction_id = ? AND user_id = ?",
+                (other, id, user_id),
+            )?;
+        }
+        tx.commit()
+    }
+
+    // Clears both sides of the transfer the transaction belongs to. Returns
+    // false when it is not part of one.
+    pub fn unlink_transfer(&self, user_id: i64, transaction_id: i64) -> Result<bool> {
+        let conn = self.get_connection();
+        let updated = conn.execute(
+            "UPDATE Transactions SET transfer_id = NULL
+            WHERE user_id = :user_id AND transfer_id IS NOT NULL
+                AND (transaction_id = :transaction_id OR transfer_id = :transaction_id)",
+            named_params! {":user_id": user_id, ":transaction_id": transaction_id},
+        )?;
+        Ok(updated > 0)
+    }
+
+    // Linked transfers, newest first
+    pub fn get_transfers(&self, user_id: i64) -> Result<Vec<TransferPair>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE Transactions.user_id = :user_id AND Transactions.transfer_id IS NOT NULL
+            ORDER BY transaction_date DESC, Transactions.transaction_id DESC",
+            SELECT_TRANSACTIONS
+        ))?;
+        let linked = stmt
+            .query_map(named_params! {":user_id": user_id}, Transaction::from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let pairs = linked
+            .iter()
+            .filter(|t| t.amount().is_negative())
+            .filter_map(|from| {
+                let to = linked.iter().find(|t| Some(t.id) == from.transfer_id)?;
+                Some(TransferPair {
+                    from: from.clone(),
+                    to: to.clone(),
+                })
+            })
+            .collect();
+        Ok(pairs)
+    }
+
+    // Records an account's balance as of `date`, replacing one already recorded
+    // for that day
+    pub fn upsert_balance_snapshot(
+        &self,
+        user_id: i64,
+        account_number: i64,
+        date: NaiveDate,
+        balance: Money,
+    ) -> Result<()> {
+        let conn = self.get_connection();
+        conn.execute(
+            "INSERT INTO BalanceSnapshots (user_id, account_number, snapshot_date, balance)
+            VALUES (:user_id, :account_number, :date, :balance)
+            ON CONFLICT(account_number, snapshot_date) DO UPDATE SET balance = excluded.balance",
+            named_params! {
+                ":user_id": user_id,
+                ":account_number": account_number,
+                ":date": date,
+                ":balance": balance.minor_units(),
+            },
+        )?;
+        Ok(())
+    }
+
+    // The user's balance snapshots by account, oldest first
+    pub fn get_balance_snapshots(&self, user_id: i64) -> Result<Vec<BalanceSnapshot>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(
+            "SELECT BalanceSnapshots.account_number, snapshot_date, BalanceSnapshots.balance,
+                COALESCE(Account.currency, 'CAD')
+            FROM BalanceSnapshots
+            LEFT JOIN Account ON Account.account_number = BalanceSnapshots.account_number
+            WHERE BalanceSnapshots.user_id = :user_id
+            ORDER BY BalanceSnapshots.account_number, snapshot_date",
+        )?;
+        let rows = stmt.query_map(named_params! {":user_id": user_id}, |row| {
+            Ok(BalanceSnapshot {
+                account_number: row.get(0)?,
+                date: row.get(1)?,
+                balance: Money::from_minor(row.get(2)?, row.get(3)?),
+            })
+        })?;
+        rows.collect()
+    }
+
+    // Adds historical rates to the user's, replacing any already known for the
+    // same pair and day
+    pub fn insert_exchange_rates(&self, user_id: i64, rates: &[ExchangeRate]) -> Result<()> {
+        let conn = self.get_connection();
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO ExchangeRates (user_id, base, quote, rate_date, rate) VALUES (?,?,?,?,?)
+                ON CONFLICT(user_id, base, quote, rate_date) DO UPDATE SET rate = excluded.rate",
+            )?;
+            for rate in rates {
+                stmt.execute((user_id, rate.base, rate.quote, rate.date, rate.rate))?;
+            }
+        }
+        tx.commit()
+    }
+
+    pub fn get_exchange_rates(&self, user_id: i64) -> Result<Vec<ExchangeRate>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(
+            "SELECT rate_date, base, quote, rate FROM ExchangeRates WHERE user_id = ?
+            ORDER BY base, quote, rate_date",
+        )?;
+        let rows = stmt.query_map([user_id], |row| {
+            Ok(ExchangeRate {
+                date: row.get(0)?,
+                base: row.get(1)?,
+                quote: row.get(2)?,
+                rate: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn insert_trade(
+        &self,
+        user_id: i64,
+        account_number: i64,
+        transaction_id: i64,
+        kind: TradeKind,
+        symbol: Option<&str>,
+        quantity: f64,
+    ) -> Result<i64> {
+        let conn = self.get_connection();
+        conn.execute(
+            "INSERT INTO Trades (user_id, account_number, transaction_id, kind, symbol, quantity)
+            VALUES (?,?,?,?,?,?)",
+            (
+                user_id,
+                account_number,
+                transaction_id,
+                kind.to_string(),
+                symbol,
+                quantity,
+            ),
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    // The user's trades with the date and amount of their cash transaction, oldest first
+    pub fn get_trades(&self, user_id: i64) -> Result<Vec<Trade>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT Trades.trade_id, Trades.account_number, Trades.transaction_id,
+                Transactions.transaction_date, Trades.kind, Trades.symbol, Trades.quantity,
+                {NATIVE_AMOUNT}, Transactions.currency
+            FROM Trades
+            JOIN Transactions ON Transactions.transaction_id = Trades.transaction_id
+            WHERE Trades.user_id = ?
+            ORDER BY Transactions.transaction_date, Trades.trade_id"
+        ))?;
+        let rows = stmt.query_map([user_id], |row| {
+            Ok(Trade {
+                id: row.get(0)?,
+                account_number: row.get(1)?,
+                transaction_id: row.get(2)?,
+                date: row.get(3)?,
+                kind: row.get::<_, String>(4)?.parse().unwrap(),
+                symbol: row.get(5)?,
+                quantity: row.get(6)?,
+                amount: Money::from_minor(row.get(7)?, row.get(8)?),
+            })
+        })?;
+        rows.collect()
+    }
+
+    // Adds prices to the user's, replacing any already known for the same symbol and day
+    pub fn insert_prices(&self, user_id: i64, prices: &[Price]) -> Result<()> {
+        let conn = self.get_connection();
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO Prices (user_id, symbol, price_date, price, currency) VALUES (?,?,?,?,?)
+                ON CONFLICT(user_id, symbol, price_date) DO UPDATE
+                    SET price = excluded.price, currency = excluded.currency",
+            )?;
+            for price in prices {
+                stmt.execute((
+                    user_id,
+                    &price.symbol,
+                    price.date,
+                    price.price,
+                    price.currency,
+                ))?;
+            }
+        }
+        tx.commit()
+    }
+
+    pub fn get_prices(&self, user_id: i64) -> Result<Vec<Price>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(
+            "SELECT price_date, symbol, price, currency FROM Prices WHERE user_id = ?
+            ORDER BY symbol, price_date",
+        )?;
+        let rows = stmt.query_map([user_id], |row| {
+            Ok(Price {
+                date: row.get(0)?,
+                symbol: row.get(1)?,
+                price: row.get(2)?,
+                currency: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    // Contribution room the CRA reported for a registered plan, by year
+    pub fn get_contribution_room(
+        &self,
+        user_id: i64,
+        account_type: AccountType,
+    ) -> Result<Vec<(i32, Money)>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(
+            "SELECT year, room FROM ContributionRoom
+            WHERE user_id = ? AND account_type = ? ORDER BY year",
+        )?;
+        let rows = stmt.query_map((user_id, account_type.to_string()), |row| {
+            Ok((row.get(0)?, Money::cad(row.get(1)?)))
+        })?;
+        rows.collect()
+    }
+
+    pub fn set_contribution_room(
+        &self,
+        user_id: i64,
+        account_type: AccountType,
+        year: i32,
+        room: Money,
+    ) -> Result<()> {
+        let conn = self.get_connection();
+        conn.execute(
+            "INSERT INTO ContributionRoom (user_id, account_type, year, room) VALUES (?,?,?,?)
+            ON CONFLICT(user_id, account_type, year) DO UPDATE SET room = excluded.room",
+            (user_id, account_type.to_string(), year, room.minor_units()),
+        )?;
+        Ok(())
+    }
+
+    // Stores a loan's terms on its account, the rate as its interest rate
+    pub fn set_loan_terms(&self, account_number: i64, terms: &LoanTerms) -> Result<()> {
+        let conn = self.get_connection();
+        conn.execute(
+            "UPDATE Account SET interest_rate = ?, principal = ?, amortization_months = ?,
+                term_months = ?, payment_frequency = ?, compounding = ?, first_payment_date = ?,
+                payment = ?
+            WHERE account_number = ?",
+            (
+                terms.rate,
+                terms.principal.minor_units(),
+                terms.amortization_months,
+                terms.term_months,
+                terms.frequency.to_string(),
+                terms.compounding.to_string(),
+                terms.first_payment,
+                terms.payment.map(|p| p.minor_units()),
+                account_number,
+            ),
+        )?;
+        Ok(())
+    }
+
+    pub fn insert_loan_payment(
+        &self,
+        user_id: i64,
+        account_number: i64,
+        transaction_id: i64,
+        installment: u32,
+        principal: Money,
+        interest: Money,
+    ) -> Result<()> {
+        let conn = self.get_connection();
+        conn.execute(
+            "INSERT INTO LoanPayments
+            (transaction_id, user_id, account_number, installment, principal, interest)
+            VALUES (?,?,?,?,?,?)",
+            (
+                transaction_id,
+                user_id,
+                account_number,
+                installment,
+                principal.minor_units(),
+                interest.minor_units(),
+            ),
+        )?;
+        Ok(())
+    }
+
+    // Payments matched to the user's loans with the day they posted, oldest first
+    pub fn get_loan_payments(&self, user_id: i64) -> Result<Vec<LoanPayment>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(
+            "SELECT LoanPayments.transaction_id, LoanPayments.account_number, installment,
+                Transactions.transaction_date, LoanPayments.principal, interest,
+                COALESCE(Account.currency, 'CAD')
+            FROM LoanPayments
+            JOIN Transactions ON Transactions.transaction_id = LoanPayments.transaction_id
+            JOIN Account ON Account.account_number = LoanPayments.account_number
+            WHERE LoanPayments.user_id = ?
+            ORDER BY Transactions.transaction_date, installment",
+        )?;
+        let rows = stmt.query_map([user_id], |row| {
+            let currency: Currency = row.get(6)?;
+            Ok(LoanPayment {
+                transaction_id: row.get(0)?,
+                account_number: row.get(1)?,
+                installment: row.get(2)?,
+                date: row.get(3)?,
+                principal: Money::from_minor(row.get(4)?, currency),
+                interest: Money::from_minor(row.get(5)?, currency),
+            })
+        })?;
+        rows.collect()
+    }
+
+    // Currency the user's reports and net worth are shown in
+    pub fn get_reporting_currency(&self, user_id: i64) -> Result<Currency> {
+        let conn = self.get_connection();
+        conn.query_row(
+            "SELECT reporting_currency FROM Users WHERE user_id = ?",
+            [user_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map(|currency| currency.unwrap_or(Currency::CAD))
+    }
+
+    pub fn set_reporting_currency(&self, user_id: i64, currency: Currency) -> Result<()> {
+        let conn = self.get_connection();
+        conn.execute(
+            "UPDATE Users SET reporting_currency = ? WHERE user_id = ?",
+            (currency, user_id),
+        )?;
+        Ok(())
+    }
+
+    // Every currency the user has transactions or accounts in
+    pub fn get_user_currencies(&self, user_id: i64) -> Result<Vec<Currency>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(
+            "SELECT currency FROM Transactions WHERE user_id = :user_id
+            UNION
+            SELECT currency FROM Account WHERE user_id = :user_id
+            ORDER BY 1",
+        )?;
+        let rows = stmt.query_map(named_params! {":user_id": user_id}, |row| row.get(0))?;
+        rows.collect()
+    }
+
+    pub fn get_interest_plan(
+        &self,
+        user_id: i64,
+        account_number: i64,
+    ) -> Result<Option<InterestPlan>> {
+        let conn = self.get_connection();
+        let plan = conn
+            .query_row(
+                "SELECT account_number, compounding, grace_period, statement_day, payment_due_days
+                FROM InterestPlans WHERE user_id = ? AND account_number = ?",
+                (user_id, account_number),
+                |row| {
+                    let compounding: String = row.get(1)?;
+                    Ok(InterestPlan {
+                        account_number: row.get(0)?,
+                        compounding: compounding.parse().map_err(|e: InvalidCompounding| {
+                            rusqlite::Error::FromSqlConversionFailure(
+                                1,
+                                rusqlite::types::Type::Text,
+                                e.to_string().into(),
+                            )
+                        })?,
+                        tiers: Vec::new(),
+                        promotions: Vec::new(),
+                        grace_period: row.get(2)?,
+                        statement_day: row.get(3)?,
+                        payment_due_days: row.get(4)?,
+                    })
+                },
+            )
+            .optional()?;
+        let Some(mut plan) = plan else {
+            return Ok(None);
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT min_balance, rate FROM InterestTiers WHERE account_number = ?
+            ORDER BY min_balance",
+        )?;
+        plan.tiers = stmt
+            .query_map([account_number], |row| {
+                Ok(Tier {
+                    min_balance: Money::cad(row.get(0)?),
+                    rate: row.get(1)?,
+                })
+            })?
+            .collect::<Result<_>>()?;
+        let mut stmt = conn.prepare(
+            "SELECT rate, start_date, end_date FROM InterestPromotions WHERE account_number = ?
+            ORDER BY start_date",
+        )?;
+        plan.promotions = stmt
+            .query_map([account_number], |row| {
+                Ok(Promotion {
+                    rate: row.get(0)?,
+                    start_date: row.get(1)?,
+                    end_date: row.get(2)?,
+                })
+            })?
+            .collect::<Result<_>>()?;
+        Ok(Some(plan))
+    }
+
+    // Replaces the account's plan, its tiers and promotions
+    pub fn save_interest_plan(&self, user_id: i64, plan: &InterestPlan) -> Result<()> {
+        let conn = self.get_connection();
+        let tx = conn.unchecked_transaction()?;
+        tx.execute(
+            "DELETE FROM InterestPlans WHERE account_number = ?",
+            [plan.account_number],
+        )?;
+        tx.execute(
+            "INSERT INTO InterestPlans
+            (account_number, user_id, compounding, grace_period, statement_day, payment_due_days)
+            VALUES (?, ?, ?, ?, ?, ?)",
+            (
+                plan.account_number,
+                user_id,
+                plan.compounding.to_string(),
+                plan.grace_period,
+                plan.statement_day,
+                plan.payment_due_days,
+            ),
+        )?;
+        for tier in &plan.tiers {
+            tx.execute(
+                "INSERT INTO InterestTiers (account_number, min_balance, rate) VALUES (?, ?, ?)",
+                (
+                    plan.account_number,
+                    tier.min_balance.minor_units(),
+                    tier.rate,
+                ),
+            )?;
+        }
+        for promotion in &plan.promotions {
+            tx.execute(
+                "INSERT INTO InterestPromotions (account_number, rate, start_date, end_date)
+                VALUES (?, ?, ?, ?)",
+                (
+                    plan.account_number,
+                    promotion.rate,
+                    promotion.start_date,
+                    promotion.end_date,
+                ),
+            )?;
+        }
+        tx.commit()
+    }
+
+    pub fn insert_user(&self, user: &User) -> Result<()> {
+        let conn = self.get_connection();
+        conn.execute(
+            "INSERT INTO Users (user_id, name) VALUES (?,?)",
+            (&user.id, &user.name),
+        )?;
+        Ok(())
+    }
+
+    // Inserts the user and their password in one statement, so a user never
+    // exists without the password they registered with
+    pub fn insert_user_with_password(&self, user: &User, password_hash: &str) -> Result<()> {
+        let conn = self.get_connection();
+        conn.execute(
+            "INSERT INTO Users (user_id, name, password_hash) VALUES (?,?,?)",
+            (&user.id, &user.name, password_hash),
+        )?;
+        Ok(())
+    }
+
+    pub fn get_user_by_name(&self, name: &str) -> Result<User> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare("SELECT * FROM Users WHERE name = :name")?;
+        let mut rows = stmt.query_map(named_params! {":name": name}, User::from_row)?;
+
+        match rows.next() {
+            Some(Ok(user)) => Ok(user),
+            Some(Err(e)) => Err(e),
+            None => Err(rusqlite::Error::QueryReturnedNoRows),
+        }
+    }
+
+    pub fn get_user(&self, user_id: i64) -> Result<User> {
+        let conn = self.get_connection();
+        conn.query_row(
+            "SELECT * FROM Users WHERE user_id = :user_id",
+            named_params! {":user_id": user_id},
+            User::from_row,
+        )
+    }
+
+    // None for users created before passwords existed, they have to set one first
+    pub fn get_password_hash(&self, user_id: i64) -> Result<Option<String>> {
+        let conn = self.get_connection();
+        conn.query_row(
+            "SELECT password_hash FROM Users WHERE user_id = :user_id",
+            named_params! {":user_id": user_id},
+            |row| row.get(0),
+        )
     }
 
-    pub fn close_connection(self) -> Result<()> {
-        self.connection.close().expect("Failed to close connection");
+    pub fn set_password_hash(&self, user_id: i64, password_hash: &str) -> Result<()> {
+        let conn = self.get_connection();
+        conn.execute(
+            "UPDATE Users SET password_hash = ? WHERE user_id = ?",
+            (password_hash, user_id),
+        )?;
         Ok(())
     }
 
-    pub fn reset_values(&self) -> Result<()> {
+    pub fn insert_session(&self, token_hash: &str, user_id: i64, expires_at: i64) -> Result<()> {
         let conn = self.get_connection();
-        conn.execute("DELETE FROM Users", ())?;
-        conn.execute("DELETE FROM Account", ())?;
-        conn.execute("DELETE FROM Transactions", ())?;
+        conn.execute(
+            "INSERT INTO Sessions (token_hash, user_id, created_at, expires_at) VALUES (?,?,?,?)",
+            (
+                token_hash,
+                user_id,
+                chrono::Utc::now().timestamp(),
+                expires_at,
+            ),
+        )?;
         Ok(())
     }
 
-    pub fn insert_transaction(&self, transaction: &Transaction) -> Result<()> {
-        let conn = self.get_connection();
-        let mut statement = conn.prepare("INSERT INTO Transactions (user_id, account_type, account_number, transaction_date, cheque_number, description_1, description_2, cad, usd, category) VALUES (?,?,?,?,?,?,?,?,?,?)")?;
-        match statement.execute((
-            &transaction.user_id,
-            &transaction.account_type.to_string(),
-            &transaction.account_number,
-            &transaction.transaction_date,
-            &transaction.cheque_number,
-            &transaction.description_1,
-            &transaction.description_2,
-            &transaction.cad,
-            &transaction.usd,
-            &transaction.category,
-        )) {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                println!("Failed to insert transaction: {}", e);
-                println!("Error:");
-                Err(e)
-            }
-        }
+    // The user owning an unexpired session, if any
+    pub fn get_session_user(&self, token_hash: &str, now: i64) -> Result<Option<User>> {
+        let conn = self.get_connection();
+        conn.query_row(
+            "SELECT Users.* FROM Sessions JOIN Users ON Users.user_id = Sessions.user_id
+            WHERE Sessions.token_hash = :token_hash AND Sessions.expires_at > :now",
+            named_params! {":token_hash": token_hash, ":now": now},
+            User::from_row,
+        )
+        .optional()
     }
 
-    pub fn batch_insert_transactions(&self, transactions: &Vec<Transaction>) -> Result<()> {
+    pub fn delete_session(&self, token_hash: &str) -> Result<()> {
         let conn = self.get_connection();
-        let mut statement = conn.prepare("INSERT INTO Transactions (user_id, account_type, account_number, transaction_date, cheque_number, description_1, description_2, cad, usd, category) VALUES (?,?,?,?,?,?,?,?,?,?)")?;
-
-        for transaction in transactions {
-            statement.execute((
-                &transaction.user_id,
-                &transaction.account_type.to_string(),
-                &transaction.account_number,
-                &transaction.transaction_date,
-                &transaction.cheque_number,
-                &transaction.description_1,
-                &transaction.description_2,
-                &transaction.cad,
-                &transaction.usd,
-                &transaction.category,
-            ))?;
-        }
-
+        conn.execute("DELETE FROM Sessions WHERE token_hash = ?", [token_hash])?;
         Ok(())
     }
 
-    pub fn get_transactions(&self, user_id: i64) -> Result<Vec<Transaction>> {
+    pub fn delete_user_sessions(&self, user_id: i64) -> Result<usize> {
         let conn = self.get_connection();
-        let mut stmt = conn.prepare("SELECT * FROM Transactions WHERE user_id = :user_id")?;
-        let rows = stmt.query_map(named_params! {":user_id": user_id}, |row| {
-            Transaction::from_row(row)
-        })?;
-        let mut transactions = Vec::new();
-        for row in rows {
-            transactions.push(row?);
-        }
-        Ok(transactions)
+        conn.execute("DELETE FROM Sessions WHERE user_id = ?", [user_id])
     }
 
-    pub fn insert_user(&self, user: &User) -> Result<()> {
+    pub fn delete_expired_sessions(&self, now: i64) -> Result<usize> {
+        let conn = self.get_connection();
+        conn.execute("DELETE FROM Sessions WHERE expires_at <= ?", [now])
+    }
+
+    // Saves a new rule and returns its id
+    pub fn insert_category_rule(&self, rule: &CategoryRule) -> Result<i64> {
         let conn = self.get_connection();
         conn.execute(
-            "INSERT INTO Users (user_id, name) VALUES (?,?)",
-            (&user.id, &user.name),
+            "INSERT INTO CategoryRules (user_id, category, priority, description_contains, description_regex, min_amount, max_amount, account_type) VALUES (?,?,?,?,?,?,?,?)",
+            (
+                rule.user_id,
+                &rule.category,
+                rule.priority,
+                &rule.description_contains,
+                &rule.description_regex,
+                rule.min_amount.map(|m| m.minor_units()),
+                rule.max_amount.map(|m| m.minor_units()),
+                rule.account_type.map(|t| t.to_string()),
+            ),
         )?;
-        Ok(())
+        Ok(conn.last_insert_rowid())
     }
 
-    pub fn get_user_by_name(&self, name: &str) -> Result<User> {
+    // Returns false when the rule does not exist or belongs to someone else
+    pub fn update_category_rule(&self, rule: &CategoryRule) -> Result<bool> {
         let conn = self.get_connection();
-        let mut stmt = conn.prepare("SELECT * FROM Users WHERE name = :name")?;
-        let mut rows = stmt.query_map(named_params! {":name": name}, User::from_row)?;
+        let updated = conn.execute(
+            "UPDATE CategoryRules SET category = ?, priority = ?, description_contains = ?, description_regex = ?, min_amount = ?, max_amount = ?, account_type = ?
+            WHERE rule_id = ? AND user_id = ?",
+            (
+                &rule.category,
+                rule.priority,
+                &rule.description_contains,
+                &rule.description_regex,
+                rule.min_amount.map(|m| m.minor_units()),
+                rule.max_amount.map(|m| m.minor_units()),
+                rule.account_type.map(|t| t.to_string()),
+                rule.id,
+                rule.user_id,
+            ),
+        )?;
+        Ok(updated == 1)
+    }
 
-        match rows.next() {
-            Some(Ok(user)) => Ok(user),
-            Some(Err(e)) => Err(e),
-            None => Err(rusqlite::Error::QueryReturnedNoRows),
-        }
+    pub fn delete_category_rule(&self, user_id: i64, rule_id: i64) -> Result<bool> {
+        let conn = self.get_connection();
+        let deleted = conn.execute(
+            "DELETE FROM CategoryRules WHERE rule_id = ? AND user_id = ?",
+            (rule_id, user_id),
+        )?;
+        Ok(deleted == 1)
+    }
+
+    // Highest priority first
+    pub fn get_category_rules(&self, user_id: i64) -> Result<Vec<CategoryRule>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(
+            "SELECT * FROM CategoryRules WHERE user_id = :user_id ORDER BY priority DESC, rule_id",
+        )?;
+        let rows = stmt.query_map(named_params! {":user_id": user_id}, CategoryRule::from_row)?;
+        rows.collect()
     }
 
-    pub fn insert_account(&self, account: &Box<dyn BankAccount>) -> Result<()> {
+    pub fn insert_account(&self, account: &dyn BankAccount) -> Result<()> {
         let conn = self.get_connection();
 
-        conn.execute("INSERT INTO Account (user_id, account_type, account_number, balance, interest_rate, credit_limit) VALUES (?,?,?,?,?,?)", 
-        (&account.user_id(), &account.account_type().to_string(), &account.account_number(), &account.balance(), &account.interest_rate(), &account.credit_limit()))?;
+        conn.execute("INSERT INTO Account (user_id, account_type, account_number, balance, interest_rate, credit_limit, currency) VALUES (?,?,?,?,?,?,?)", 
+        (&account.user_id(), &account.account_type().to_string(), &account.account_number(), &account.balance().minor_units(), &account.interest_rate(), &account.credit_limit().minor_units(), &account.balance().currency()))?;
 
         Ok(())
     }
 
-    pub fn update_account(&self, account: &Box<dyn BankAccount>) -> Result<()> {
+    pub fn update_account(&self, account: &dyn BankAccount) -> Result<()> {
         let conn = self.get_connection();
         conn.execute(
-            "UPDATE Account SET balance = ?, interest_rate = ?, credit_limit = ? WHERE account_number = ?",
-            (&account.balance(), &account.interest_rate(), &account.credit_limit(), &account.account_number()),
+            "UPDATE Account SET balance = ?, interest_rate = ?, credit_limit = ?, currency = ? WHERE account_number = ?",
+            (&account.balance().minor_units(), &account.interest_rate(), &account.credit_limit().minor_units(), &account.balance().currency(), &account.account_number()),
         )?;
         Ok(())
     }
 
+    // The user's account with this bank number. An account imported before
+    // stable keys is recognized by its legacy hash, and one keyed before the
+    // secret by its unkeyed key, and is given the key.
+    pub fn find_bank_account(&self, user_id: i64, bank_number: &BankNumber) -> Result<Option<i64>> {
+        let conn = self.get_connection();
+        let key = bank_number.key(&self.account_secret);
+        let found = conn
+            .query_row(
+                "SELECT account_number FROM Account WHERE user_id = ? AND account_key = ?",
+                (user_id, &key),
+                |row| row.get(0),
+            )
+            .optional()?;
+        if found.is_some() {
+            return Ok(found);
+        }
+        let legacy: Option<i64> = conn
+            .query_row(
+                "SELECT account_number FROM Account
+                WHERE user_id = ? AND account_key IS NULL AND (unkeyed_key = ? OR legacy_hash = ?)",
+                (user_id, bank_number.unkeyed_key(), bank_number.legacy_hash),
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(account_number) = legacy {
+            conn.execute(
+                "UPDATE Account SET account_key = ?, mask = ?, unkeyed_key = NULL
+                WHERE account_number = ?",
+                (&key, &bank_number.mask, account_number),
+            )?;
+        }
+        Ok(legacy)
+    }
+
+    // Adds an account first seen in an import under a newly assigned account
+    // number, which is returned
+    pub fn insert_bank_account(
+        &self,
+        account: &dyn BankAccount,
+        bank_number: &BankNumber,
+        institution: Option<&str>,
+    ) -> Result<i64> {
+        let conn = self.get_connection();
+        conn.execute(
+            "INSERT INTO Account (user_id, account_type, balance, interest_rate, credit_limit, currency,
+                account_key, mask, institution)
+            VALUES (?,?,?,?,?,?,?,?,?)",
+            (
+                account.user_id(),
+                account.account_type().to_string(),
+                account.balance().minor_units(),
+                account.interest_rate(),
+                account.credit_limit().minor_units(),
+                account.balance().currency(),
+                bank_number.key(&self.account_secret),
+                &bank_number.mask,
+                institution,
+            ),
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    // Labels of the user's accounts by account number
+    pub fn get_account_labels(&self, user_id: i64) -> Result<HashMap<i64, AccountLabel>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(
+            "SELECT account_number, nickname, institution, mask FROM Account WHERE user_id = ?",
+        )?;
+        let rows = stmt.query_map([user_id], |row| {
+            Ok((
+                row.get(0)?,
+                AccountLabel {
+                    nickname: row.get(1)?,
+                    institution: row.get(2)?,
+                    mask: row.get(3)?,
+                },
+            ))
+        })?;
+        rows.collect()
+    }
+
+    // Sets the nickname and institution. Returns false when the user has no such account.
+    pub fn update_account_label(
+        &self,
+        user_id: i64,
+        account_number: i64,
+        label: &AccountLabel,
+    ) -> Result<bool> {
+        let conn = self.get_connection();
+        let updated = conn.execute(
+            "UPDATE Account SET nickname = ?, institution = ? WHERE account_number = ? AND user_id = ?",
+            (&label.nickname, &label.institution, account_number, user_id),
+        )?;
+        Ok(updated == 1)
+    }
+
+    // Adds an account the user keeps track of by hand, such as cash or a gift
+    // card, opened on `opened_on` with its current balance. Returns the new
+    // account number.
+    pub fn insert_manual_account(
+        &self,
+        account: &dyn BankAccount,
+        label: &AccountLabel,
+        opened_on: NaiveDate,
+    ) -> Result<i64> {
+        let conn = self.get_connection();
+        conn.execute(
+            "INSERT INTO Account (user_id, account_type, balance, interest_rate, credit_limit, currency,
+                nickname, institution, opened_on, opening_balance)
+            VALUES (?,?,?,?,?,?,?,?,?,?)",
+            (
+                account.user_id(),
+                account.account_type().to_string(),
+                account.balance().minor_units(),
+                account.interest_rate(),
+                account.credit_limit().minor_units(),
+                account.balance().currency(),
+                &label.nickname,
+                &label.institution,
+                opened_on,
+                account.balance().minor_units(),
+            ),
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    // When each of the user's accounts was opened and closed, by account number
+    pub fn get_account_periods(&self, user_id: i64) -> Result<HashMap<i64, AccountPeriod>> {
+        let conn = self.get_connection();
+        let mut stmt = conn.prepare(
+            "SELECT account_number, opened_on, opening_balance, closed_on, COALESCE(currency, 'CAD')
+            FROM Account WHERE user_id = ?",
+        )?;
+        let rows = stmt.query_map([user_id], |row| {
+            let currency: Currency = row.get(4)?;
+            Ok((
+                row.get(0)?,
+                AccountPeriod {
+                    opened_on: row.get(1)?,
+                    opening_balance: row
+                        .get::<_, Option<i64>>(2)?
+                        .map(|minor| Money::from_minor(minor, currency)),
+                    closed_on: row.get(3)?,
+                },
+            ))
+        })?;
+        rows.collect()
+    }
+
+    // Marks the account closed on `closed_on`. Returns false when the user has no such account.
+    pub fn close_account(
+        &self,
+        user_id: i64,
+        account_number: i64,
+        closed_on: NaiveDate,
+    ) -> Result<bool> {
+        let conn = self.get_connection();
+        let updated = conn.execute(
+            "UPDATE Account SET closed_on = ? WHERE account_number = ? AND user_id = ?",
+            (closed_on, account_number, user_id),
+        )?;
+        Ok(updated == 1)
+    }
+
+    // Deletes the account along with its transactions, balance snapshots and
+    // interest plan. Transfers into the account's transactions are unlinked.
+    // Returns false when the user has no such account.
+    pub fn delete_account(&self, user_id: i64, account_number: i64) -> Result<bool> {
+        let conn = self.get_connection();
+        let deleted = conn.execute(
+            "DELETE FROM Account WHERE account_number = ? AND user_id = ?",
+            (account_number, user_id),
+        )?;
+        Ok(deleted == 1)
+    }
+
     pub fn account_exists(&self, account_number: &i64) -> Result<bool> {
         let conn = self.get_connection();
         let mut stmt = conn
@@ -191,71 +2164,19 @@ impl Database {
             Err(rusqlite::Error::QueryReturnedNoRows)
         }
     }
-
-    // Used once to create the database schema
-    pub fn _execute_schema(&self) -> Result<()> {
-        let conn = self.get_connection();
-
-        conn.execute("PRAGMA foreign_keys = ON;", ())?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS Users (
-            user_id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL
-            )",
-            (),
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS Account (
-                user_id INTEGER NOT NULL,
-                account_type TEXT,
-                account_number INTEGER PRIMARY KEY,
-                balance REAL,
-                interest_rate REAL,
-                credit_limit REAL,
-
-                FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE
-            )",
-            (),
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS Transactions(
-            transaction_id INTEGER PRIMARY KEY AUTOINCREMENT,
-            user_id INTEGER NOT NULL,
-            account_number INTEGER NOT NULL,
-            account_type TEXT NOT NULL,
-            transaction_date TEXT NOT NULL,
-            cheque_number TEXT,
-            description_1 TEXT,
-            description_2 TEXT,
-            cad REAL,
-            usd REAL,
-            category TEXT,
-
-            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE,
-            FOREIGN KEY(account_number) REFERENCES Account(account_number) ON DELETE CASCADE ON UPDATE CASCADE
-        
-        )",
-            (),
-        )?;
-
-        Ok(())
-    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::account::{AccountType, BankAccount, ChequingAccount};
-    use crate::transaction::Transaction;
+    use crate::catergorization::CategoryRule;
+    use crate::money::Money;
+    use crate::transaction::{Transaction, TransactionUpdate};
     use crate::user::User;
 
     fn setup_test_db() -> Database {
-        let db = Database::new(":memory:".to_string()).unwrap();
-        db._execute_schema().unwrap();
-        db
+        Database::new(":memory:".to_string()).unwrap()
     }
 
     fn sample_user() -> User {
@@ -269,7 +2190,7 @@ mod tests {
         Box::new(ChequingAccount {
             user_id: 1,
             account_number: 1001,
-            balance: 500.0,
+            balance: Money::cad(50000),
         })
     }
 
@@ -278,12 +2199,20 @@ mod tests {
             user_id: 1,
             account_type: AccountType::Chequing,
             account_number: 1001,
-            transaction_date: "2025-01-01".into(),
+            transaction_date: NaiveDate::from_ymd_opt(2025, 1, 1).unwrap(),
             cheque_number: "123".into(),
             description_1: "Grocery".into(),
             description_2: "Store".into(),
-            cad: 100.0,
-            usd: 0.0,
+            cad: Money::cad(10000),
+            usd: Money::usd(0),
+            id: 0,
+            category_id: None,
+            notes: String::new(),
+            merchant_id: None,
+            merchant: String::new(),
+            transfer_id: None,
+            statement_balance: None,
+            currency: Currency::CAD,
             category: "Food".into(),
         }
     }
@@ -293,18 +2222,26 @@ mod tests {
         let db = setup_test_db();
 
         db.insert_user(&sample_user()).unwrap();
-        db.insert_account(&sample_account()).unwrap();
+        db.insert_account(sample_account().as_ref()).unwrap();
 
         let transaction1 = Transaction {
             user_id: 1,
             account_type: AccountType::Chequing,
             account_number: 1001,
-            transaction_date: "2025-05-22".to_string(),
+            transaction_date: NaiveDate::from_ymd_opt(2025, 5, 22).unwrap(),
             cheque_number: ("CHK001".to_string()),
             description_1: ("Groceries".to_string()),
             description_2: ("Walmart".to_string()),
-            cad: (150.25),
-            usd: 0.0,
+            cad: Money::cad(15025),
+            usd: Money::usd(0),
+            id: 0,
+            category_id: None,
+            notes: String::new(),
+            merchant_id: None,
+            merchant: String::new(),
+            transfer_id: None,
+            statement_balance: None,
+            currency: Currency::CAD,
             category: ("Food".to_string()),
         };
 
@@ -312,12 +2249,20 @@ mod tests {
             user_id: 1,
             account_type: AccountType::Chequing,
             account_number: 1001,
-            transaction_date: "2025-05-21".to_string(),
+            transaction_date: NaiveDate::from_ymd_opt(2025, 5, 21).unwrap(),
             cheque_number: "".to_string(),
             description_1: ("Gas".to_string()),
             description_2: ("Shell".to_string()),
-            cad: (60.0),
-            usd: 0.0,
+            cad: Money::cad(6000),
+            usd: Money::usd(0),
+            id: 0,
+            category_id: None,
+            notes: String::new(),
+            merchant_id: None,
+            merchant: String::new(),
+            transfer_id: None,
+            statement_balance: None,
+            currency: Currency::CAD,
             category: ("Transport".to_string()),
         };
 
@@ -329,12 +2274,35 @@ mod tests {
         assert_eq!(transactions.len(), 2);
 
         // Validate the content of the transactions
-        assert!(transactions
-            .iter()
-            .any(|t| t.description_1 == ("Groceries".to_string())));
-        assert!(transactions
+        assert!(transactions.iter().any(|t| t.description_1 == "Groceries"));
+        assert!(transactions.iter().any(|t| t.description_1 == "Gas"));
+    }
+
+    #[test]
+    fn test_get_transactions_between_sorts_by_date() {
+        let db = setup_test_db();
+        db.insert_user(&sample_user()).unwrap();
+        db.insert_account(sample_account().as_ref()).unwrap();
+
+        for (month, day) in [(3, 1), (1, 15), (2, 10), (12, 31)] {
+            let mut tx = sample_transaction();
+            tx.transaction_date = NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+            db.insert_transaction(&tx).unwrap();
+        }
+
+        let transactions = db
+            .get_transactions_between(
+                1,
+                NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+                NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            )
+            .unwrap();
+
+        let dates: Vec<String> = transactions
             .iter()
-            .any(|t| t.description_1 == ("Gas".to_string())));
+            .map(|t| t.transaction_date.to_string())
+            .collect();
+        assert_eq!(dates, vec!["2024-01-15", "2024-02-10", "2024-03-01"]);
     }
 
     #[test]
@@ -354,13 +2322,13 @@ mod tests {
         db.insert_user(&sample_user()).unwrap();
 
         let account = sample_account();
-        db.insert_account(&account).unwrap();
+        db.insert_account(account.as_ref()).unwrap();
 
-        let exists = db.account_exists(&account.account_number()).unwrap();
+        let exists = db.account_exists(account.account_number()).unwrap();
         assert!(exists);
 
         let fetched = db
-            .get_account(&account.account_number())
+            .get_account(account.account_number())
             .map_err(|e| println!("{}", e))
             .unwrap();
         assert_eq!(fetched.account_number(), account.account_number());
@@ -370,7 +2338,7 @@ mod tests {
     fn test_get_accounts_by_user() {
         let db = setup_test_db();
         db.insert_user(&sample_user()).unwrap();
-        db.insert_account(&sample_account()).unwrap();
+        db.insert_account(sample_account().as_ref()).unwrap();
 
         let accounts = db.get_accounts_by_user(1).unwrap();
         assert_eq!(accounts.len(), 1);
@@ -381,7 +2349,7 @@ mod tests {
         let db = setup_test_db();
         db.insert_user(&sample_user()).unwrap();
         let account = sample_account();
-        db.insert_account(&account).unwrap();
+        db.insert_account(account.as_ref()).unwrap();
 
         let acc_num = db
             .get_account_number_by_type(1, &AccountType::Chequing)
@@ -389,25 +2357,104 @@ mod tests {
         assert_eq!(acc_num, *account.account_number());
     }
 
+    #[test]
+    fn test_bank_accounts_found_by_key() {
+        let db = setup_test_db();
+        db.insert_user(&sample_user()).unwrap();
+        let bank_number = BankNumber::new("00102-5012345");
+        assert_eq!(db.find_bank_account(1, &bank_number).unwrap(), None);
+
+        let account_number = db
+            .insert_bank_account(
+                sample_account().as_ref(),
+                &bank_number,
+                Some("RBC Royal Bank"),
+            )
+            .unwrap();
+        assert_ne!(account_number, 1001);
+        assert_eq!(
+            db.find_bank_account(1, &BankNumber::new("00102 5012345"))
+                .unwrap(),
+            Some(account_number)
+        );
+        // the same bank number belongs to nobody else
+        assert_eq!(db.find_bank_account(2, &bank_number).unwrap(), None);
+
+        let mut label = db
+            .get_account_labels(1)
+            .unwrap()
+            .remove(&account_number)
+            .unwrap();
+        assert_eq!(label.mask.as_deref(), Some("2345"));
+        assert_eq!(label.institution.as_deref(), Some("RBC Royal Bank"));
+        label.nickname = Some("Everyday".into());
+        assert!(db.update_account_label(1, account_number, &label).unwrap());
+        assert!(!db.update_account_label(2, account_number, &label).unwrap());
+        assert_eq!(db.get_account_labels(1).unwrap()[&account_number], label);
+    }
+
+    #[test]
+    fn test_legacy_account_claimed_on_import() {
+        let db = setup_test_db();
+        db.insert_user(&sample_user()).unwrap();
+        db.insert_account(sample_account().as_ref()).unwrap();
+        let bank_number = BankNumber::new("5012345");
+        db.get_connection()
+            .execute(
+                "UPDATE Account SET legacy_hash = ? WHERE account_number = 1001",
+                [bank_number.legacy_hash],
+            )
+            .unwrap();
+
+        assert_eq!(db.find_bank_account(1, &bank_number).unwrap(), Some(1001));
+        // from then on it is found by its key
+        db.get_connection()
+            .execute("UPDATE Account SET legacy_hash = NULL", [])
+            .unwrap();
+        assert_eq!(db.find_bank_account(1, &bank_number).unwrap(), Some(1001));
+    }
+
+    #[test]
+    fn test_account_keyed_before_the_secret_claimed_on_import() {
+        let db = setup_test_db();
+        db.insert_user(&sample_user()).unwrap();
+        db.insert_account(sample_account().as_ref()).unwrap();
+        let bank_number = BankNumber::new("5012345");
+        db.get_connection()
+            .execute(
+                "UPDATE Account SET unkeyed_key = ? WHERE account_number = 1001",
+                [bank_number.unkeyed_key()],
+            )
+            .unwrap();
+
+        assert_eq!(db.find_bank_account(1, &bank_number).unwrap(), Some(1001));
+        let unkeyed: Option<String> = db
+            .get_connection()
+            .query_row("SELECT unkeyed_key FROM Account", (), |row| row.get(0))
+            .unwrap();
+        assert_eq!(unkeyed, None);
+        assert_eq!(db.find_bank_account(1, &bank_number).unwrap(), Some(1001));
+    }
+
     #[test]
     fn test_update_account() {
         let db = setup_test_db();
         db.insert_user(&sample_user()).unwrap();
         let mut account = sample_account();
-        db.insert_account(&account).unwrap();
+        db.insert_account(account.as_ref()).unwrap();
 
-        account.set_balance(999.0);
-        db.update_account(&account).unwrap();
+        account.set_balance(Money::cad(99900));
+        db.update_account(account.as_ref()).unwrap();
 
-        let updated = db.get_account(&account.account_number()).unwrap();
-        assert_eq!(updated.balance(), 999.0);
+        let updated = db.get_account(account.account_number()).unwrap();
+        assert_eq!(updated.balance(), Money::cad(99900));
     }
 
     #[test]
     fn test_insert_transaction() {
         let db = setup_test_db();
         db.insert_user(&sample_user()).unwrap();
-        db.insert_account(&sample_account()).unwrap();
+        db.insert_account(sample_account().as_ref()).unwrap();
 
         let tx = sample_transaction();
         db.insert_transaction(&tx).unwrap();
@@ -418,17 +2465,183 @@ mod tests {
     fn test_batch_insert_transactions() {
         let db = setup_test_db();
         db.insert_user(&sample_user()).unwrap();
-        db.insert_account(&sample_account()).unwrap();
+        db.insert_account(sample_account().as_ref()).unwrap();
 
         let txs = vec![sample_transaction(), sample_transaction()];
-        db.batch_insert_transactions(&txs).unwrap();
+        let result = db.batch_insert_transactions(&txs).unwrap();
+        assert_eq!(result.inserted, 2);
+    }
+
+    #[test]
+    fn test_reimport_skips_duplicates() {
+        let db = setup_test_db();
+        db.insert_user(&sample_user()).unwrap();
+        db.insert_account(sample_account().as_ref()).unwrap();
+
+        let mut other = sample_transaction();
+        other.transaction_date = NaiveDate::from_ymd_opt(2025, 1, 2).unwrap();
+        let first = vec![sample_transaction(), sample_transaction()];
+        let overlapping = vec![sample_transaction(), sample_transaction(), other];
+
+        db.batch_insert_transactions(&first).unwrap();
+        let result = db.batch_insert_transactions(&overlapping).unwrap();
+
+        assert_eq!(
+            result,
+            ImportResult {
+                inserted: 1,
+                duplicates: 2,
+                conflicts: 0
+            }
+        );
+        assert_eq!(db.get_transactions(1).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_reimport_reports_conflicts() {
+        let db = setup_test_db();
+        db.insert_user(&sample_user()).unwrap();
+        db.insert_account(sample_account().as_ref()).unwrap();
+
+        db.batch_insert_transactions(&[sample_transaction()])
+            .unwrap();
+
+        let mut changed = sample_transaction();
+        changed.cheque_number = "456".into();
+        let result = db.batch_insert_transactions(&[changed]).unwrap();
+
+        assert_eq!(result.conflicts, 1);
+        assert_eq!(result.inserted, 0);
+        assert_eq!(db.get_transactions(1).unwrap()[0].cheque_number, "123");
+    }
+
+    #[test]
+    fn test_reimport_matches_rows_edited_before_renumbering() {
+        let db = setup_test_db();
+        db.insert_user(&sample_user()).unwrap();
+        db.insert_account(sample_account().as_ref()).unwrap();
+        db.batch_insert_transactions(&[sample_transaction()])
+            .unwrap();
+
+        // as left by the upgrade: an edited row keeps its fingerprint under the old number
+        let mut before = sample_transaction();
+        before.account_number = 98765;
+        let conn = db.get_connection();
+        conn.execute(
+            "UPDATE Account SET legacy_hash = 98765 WHERE account_number = 1001",
+            (),
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE Transactions SET fingerprint = ?, description_1 = 'Groceries'",
+            [before.fingerprint(0)],
+        )
+        .unwrap();
+
+        let result = db
+            .batch_insert_transactions(&[sample_transaction()])
+            .unwrap();
+        assert_eq!(result.duplicates, 1);
+        assert_eq!(result.inserted, 0);
+    }
+
+    #[test]
+    fn test_insert_transaction_allows_repeats() {
+        let db = setup_test_db();
+        db.insert_user(&sample_user()).unwrap();
+        db.insert_account(sample_account().as_ref()).unwrap();
+
+        db.insert_transaction(&sample_transaction()).unwrap();
+        db.insert_transaction(&sample_transaction()).unwrap();
+
+        assert_eq!(db.get_transactions(1).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_category_rules_crud() {
+        let db = setup_test_db();
+        db.insert_user(&sample_user()).unwrap();
+
+        let mut rule = CategoryRule::new(1, "Coffee");
+        rule.description_contains = Some("BRIDGEHEAD".to_string());
+        rule.max_amount = Some(Money::cad(-1));
+        rule.account_type = Some(AccountType::Credit);
+        rule.id = db.insert_category_rule(&rule).unwrap();
+
+        let mut low = CategoryRule::new(1, "Misc");
+        low.description_regex = Some("(?i)shop".to_string());
+        low.priority = 1;
+        db.insert_category_rule(&low).unwrap();
+
+        let rules = db.get_category_rules(1).unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0], rule);
+
+        rule.category = "Cafes".to_string();
+        assert!(db.update_category_rule(&rule).unwrap());
+        assert_eq!(db.get_category_rules(1).unwrap()[0].category, "Cafes");
+
+        // other users cannot touch it
+        assert!(!db.delete_category_rule(2, rule.id).unwrap());
+        assert!(db.delete_category_rule(1, rule.id).unwrap());
+        assert_eq!(db.get_category_rules(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_update_and_delete_transaction() {
+        let db = setup_test_db();
+        db.insert_user(&sample_user()).unwrap();
+        db.insert_account(sample_account().as_ref()).unwrap();
+        db.insert_transaction(&sample_transaction()).unwrap();
+        let stored = db.get_transactions(1).unwrap().remove(0);
+        assert!(stored.id > 0);
+
+        let changes = TransactionUpdate {
+            category: Some("Groceries".to_string()),
+            notes: Some("weekly shop".to_string()),
+            description_1: Some("Farm Boy".to_string()),
+            ..Default::default()
+        };
+        let updated = db
+            .update_transaction(1, stored.id, &changes)
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.category, "Groceries");
+        assert_eq!(updated.notes, "weekly shop");
+        assert_eq!(updated.description_1, "Farm Boy");
+        assert_eq!(updated.description_2, stored.description_2);
+
+        // edits keep the fingerprint, so the original row is still a duplicate
+        let result = db
+            .batch_insert_transactions(&[sample_transaction()])
+            .unwrap();
+        assert_eq!(result.duplicates, 1);
+
+        let cleared = TransactionUpdate {
+            category_id: Some(None),
+            ..Default::default()
+        };
+        let updated = db
+            .update_transaction(1, stored.id, &cleared)
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.category_id, None);
+        assert_eq!(updated.notes, "weekly shop");
+
+        assert!(db
+            .update_transaction(2, stored.id, &cleared)
+            .unwrap()
+            .is_none());
+        assert!(!db.delete_transaction(2, stored.id).unwrap());
+        assert!(db.delete_transaction(1, stored.id).unwrap());
+        assert!(db.get_transaction(1, stored.id).unwrap().is_none());
     }
 
     #[test]
     fn test_reset_values() {
         let db = setup_test_db();
         db.insert_user(&sample_user()).unwrap();
-        db.insert_account(&sample_account()).unwrap();
+        db.insert_account(sample_account().as_ref()).unwrap();
         db.insert_transaction(&sample_transaction()).unwrap();
 
         db.reset_values().unwrap();
@@ -442,4 +2655,160 @@ mod tests {
         db.close_connection().unwrap();
         // If no panic, test passes
     }
+
+    #[test]
+    fn test_new_applies_migrations() {
+        let db = setup_test_db();
+        assert_eq!(db.schema_version().unwrap(), migrations::latest_version());
+    }
+
+    #[test]
+    fn test_reopen_keeps_data() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+
+        let db = Database::new(path.clone()).unwrap();
+        db.insert_user(&sample_user()).unwrap();
+        db.close_connection().unwrap();
+
+        let db = Database::new(path).unwrap();
+        assert_eq!(db.get_user_by_name("Alice").unwrap().id, 1);
+    }
+
+    fn seed_query_transactions(db: &Database) {
+        db.insert_user(&sample_user()).unwrap();
+        db.insert_account(sample_account().as_ref()).unwrap();
+        let rows = [
+            (5, "TIM HORTONS #1", -250, "Coffee Shops"),
+            (6, "LOBLAWS", -8000, "Groceries"),
+            (7, "PAYROLL", 250000, "Income"),
+            (8, "100% PURE JUICE", -600, ""),
+            (9, "STARBUCKS", -475, "Coffee Shops"),
+        ];
+        for (day, description, cad, category) in rows {
+            db.insert_transaction(&Transaction {
+                transaction_date: NaiveDate::from_ymd_opt(2025, 3, day).unwrap(),
+                description_1: description.into(),
+                description_2: String::new(),
+                cad: Money::cad(cad),
+                category: category.into(),
+                ..sample_transaction()
+            })
+            .unwrap();
+        }
+    }
+
+    fn descriptions(page: &TransactionPage) -> Vec<&str> {
+        page.transactions
+            .iter()
+            .map(|t| t.description_1.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_query_transactions_pages_with_total() {
+        let db = setup_test_db();
+        seed_query_transactions(&db);
+
+        let query = TransactionQuery {
+            limit: 2,
+            ..TransactionQuery::default()
+        };
+        let first = db.query_transactions(1, &query).unwrap();
+        assert_eq!(first.total, 5);
+        assert_eq!(descriptions(&first), vec!["STARBUCKS", "100% PURE JUICE"]);
+
+        let last = db
+            .query_transactions(
+                1,
+                &TransactionQuery {
+                    offset: 4,
+                    ..query.clone()
+                },
+            )
+            .unwrap();
+        assert_eq!(last.total, 5);
+        assert_eq!(descriptions(&last), vec!["TIM HORTONS #1"]);
+
+        // other users see nothing
+        assert_eq!(db.query_transactions(2, &query).unwrap().total, 0);
+    }
+
+    #[test]
+    fn test_query_transactions_filters() {
+        let db = setup_test_db();
+        seed_query_transactions(&db);
+
+        let spending = TransactionQuery {
+            max_amount: Some(Money::cad(-300)),
+            sort: TransactionSort::AmountAsc,
+            ..TransactionQuery::default()
+        };
+        let page = db.query_transactions(1, &spending).unwrap();
+        assert_eq!(
+            descriptions(&page),
+            vec!["LOBLAWS", "100% PURE JUICE", "STARBUCKS"]
+        );
+
+        let dated = TransactionQuery {
+            from: NaiveDate::from_ymd_opt(2025, 3, 6),
+            to: NaiveDate::from_ymd_opt(2025, 3, 7),
+            account_type: Some(AccountType::Chequing),
+            account_number: Some(1001),
+            ..TransactionQuery::default()
+        };
+        assert_eq!(db.query_transactions(1, &dated).unwrap().total, 2);
+
+        let other_type = TransactionQuery {
+            account_type: Some(AccountType::Credit),
+            ..TransactionQuery::default()
+        };
+        assert_eq!(db.query_transactions(1, &other_type).unwrap().total, 0);
+
+        let uncategorized = TransactionQuery {
+            uncategorized: true,
+            ..TransactionQuery::default()
+        };
+        let page = db.query_transactions(1, &uncategorized).unwrap();
+        assert_eq!(descriptions(&page), vec!["100% PURE JUICE"]);
+    }
+
+    #[test]
+    fn test_query_transactions_by_category_includes_children() {
+        let db = setup_test_db();
+        seed_query_transactions(&db);
+
+        // Coffee Shops is created under Food
+        let food = db.get_category_by_name(1, "Food").unwrap().unwrap();
+        let query = TransactionQuery {
+            category_id: Some(food.id),
+            sort: TransactionSort::DateAsc,
+            ..TransactionQuery::default()
+        };
+        let page = db.query_transactions(1, &query).unwrap();
+        assert_eq!(
+            descriptions(&page),
+            vec!["TIM HORTONS #1", "LOBLAWS", "STARBUCKS"]
+        );
+    }
+
+    #[test]
+    fn test_query_transactions_search() {
+        let db = setup_test_db();
+        seed_query_transactions(&db);
+
+        let search = |text: &str| {
+            let query = TransactionQuery {
+                search: Some(text.into()),
+                ..TransactionQuery::default()
+            };
+            db.query_transactions(1, &query).unwrap()
+        };
+
+        assert_eq!(descriptions(&search("hortons")), vec!["TIM HORTONS #1"]);
+        // wildcards are matched literally
+        assert_eq!(descriptions(&search("100%")), vec!["100% PURE JUICE"]);
+        assert_eq!(search("%").total, 1);
+        assert_eq!(search("  ").total, 5);
+    }
 }
@@ -0,0 +1,291 @@
+use core::fmt;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+use crate::{
+    auth::AuthError, budget::BudgetError, category::CategoryError,
+    catergorization::CategorizeError, exchange::ExchangeError, interest::InterestError,
+    investment::InvestmentError, loan::LoanError, manual::ManualError, merchant::MerchantError,
+    networth::NetWorthError, parser::ParseError, reconcile::ReconcileError, search::SearchError,
+    transfer::TransferError,
+};
+
+// Error returned by the HTTP handlers, rendered as {"error": message}.
+// Internal failures are logged and reported without their details.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> ApiError {
+        ApiError {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> ApiError {
+        ApiError::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> ApiError {
+        ApiError::new(StatusCode::NOT_FOUND, message)
+    }
+
+    pub fn unauthorized() -> ApiError {
+        ApiError::new(StatusCode::UNAUTHORIZED, "Not logged in")
+    }
+
+    pub fn internal(error: impl fmt::Display) -> ApiError {
+        eprintln!("Request failed: {}", error);
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+impl From<rusqlite::Error> for ApiError {
+    fn from(err: rusqlite::Error) -> Self {
+        ApiError::internal(err)
+    }
+}
+
+impl From<tokio::task::JoinError> for ApiError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        ApiError::internal(err)
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(err: AuthError) -> Self {
+        let status = match err {
+            AuthError::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            AuthError::UsernameTaken => StatusCode::CONFLICT,
+            AuthError::WeakPassword => StatusCode::BAD_REQUEST,
+            AuthError::Hash(_) | AuthError::Database(_) => return ApiError::internal(err),
+        };
+        ApiError::new(status, err.to_string())
+    }
+}
+
+impl From<CategoryError> for ApiError {
+    fn from(err: CategoryError) -> Self {
+        let status = match err {
+            CategoryError::NotFound => StatusCode::NOT_FOUND,
+            CategoryError::NameTaken => StatusCode::CONFLICT,
+            CategoryError::InvalidName | CategoryError::Cycle | CategoryError::SameCategory => {
+                StatusCode::BAD_REQUEST
+            }
+            CategoryError::Database(_) => return ApiError::internal(err),
+        };
+        ApiError::new(status, err.to_string())
+    }
+}
+
+impl From<BudgetError> for ApiError {
+    fn from(err: BudgetError) -> Self {
+        let status = match err {
+            BudgetError::NotFound => StatusCode::NOT_FOUND,
+            BudgetError::AlreadyExists => StatusCode::CONFLICT,
+            BudgetError::UnknownCategory | BudgetError::InvalidAmount => StatusCode::BAD_REQUEST,
+            BudgetError::Exchange(err) => return err.into(),
+            BudgetError::Database(_) => return ApiError::internal(err),
+        };
+        ApiError::new(status, err.to_string())
+    }
+}
+
+impl From<MerchantError> for ApiError {
+    fn from(err: MerchantError) -> Self {
+        let status = match err {
+            MerchantError::NotFound => StatusCode::NOT_FOUND,
+            MerchantError::AliasTaken => StatusCode::CONFLICT,
+            MerchantError::InvalidName | MerchantError::InvalidAlias => StatusCode::BAD_REQUEST,
+            MerchantError::Database(_) => return ApiError::internal(err),
+        };
+        ApiError::new(status, err.to_string())
+    }
+}
+
+impl From<TransferError> for ApiError {
+    fn from(err: TransferError) -> Self {
+        let status = match err {
+            TransferError::NotFound => StatusCode::NOT_FOUND,
+            TransferError::AlreadyLinked => StatusCode::CONFLICT,
+            TransferError::NotATransfer => StatusCode::BAD_REQUEST,
+            TransferError::Database(_) => return ApiError::internal(err),
+        };
+        ApiError::new(status, err.to_string())
+    }
+}
+
+impl From<InterestError> for ApiError {
+    fn from(err: InterestError) -> Self {
+        match err {
+            InterestError::AccountNotFound => ApiError::new(StatusCode::NOT_FOUND, err.to_string()),
+            InterestError::InvalidPlan(_)
+            | InterestError::EstimateOnly
+            | InterestError::TooManyMonths => ApiError::bad_request(err.to_string()),
+            InterestError::Database(_) => ApiError::internal(err),
+        }
+    }
+}
+
+impl From<InvestmentError> for ApiError {
+    fn from(err: InvestmentError) -> Self {
+        let status = match err {
+            InvestmentError::AccountNotFound => StatusCode::NOT_FOUND,
+            InvestmentError::NotEnoughShares { .. } => StatusCode::CONFLICT,
+            InvestmentError::NotAnInvestmentAccount
+            | InvestmentError::NotRegistered
+            | InvestmentError::MissingSymbol
+            | InvestmentError::InvalidQuantity
+            | InvestmentError::InvalidAmount
+            | InvestmentError::MissingHeader
+            | InvestmentError::InvalidRow(_) => StatusCode::BAD_REQUEST,
+            InvestmentError::Manual(err) => return err.into(),
+            InvestmentError::Exchange(err) => return err.into(),
+            InvestmentError::Database(_) => return ApiError::internal(err),
+        };
+        ApiError::new(status, err.to_string())
+    }
+}
+
+impl From<LoanError> for ApiError {
+    fn from(err: LoanError) -> Self {
+        let status = match err {
+            LoanError::AccountNotFound => StatusCode::NOT_FOUND,
+            LoanError::NoTerms => StatusCode::CONFLICT,
+            LoanError::NotALoan | LoanError::InvalidTerms(_) | LoanError::InvalidPrepayment => {
+                StatusCode::BAD_REQUEST
+            }
+            LoanError::Database(_) => return ApiError::internal(err),
+        };
+        ApiError::new(status, err.to_string())
+    }
+}
+
+impl From<ManualError> for ApiError {
+    fn from(err: ManualError) -> Self {
+        let status = match err {
+            ManualError::AccountNotFound => StatusCode::NOT_FOUND,
+            ManualError::AccountClosed | ManualError::BalanceNotZero(_) => StatusCode::CONFLICT,
+            ManualError::UnknownCategory
+            | ManualError::InvalidName
+            | ManualError::InvalidAccountType
+            | ManualError::InvalidLoanTerms(_)
+            | ManualError::WrongCurrency { .. }
+            | ManualError::OutsidePeriod => StatusCode::BAD_REQUEST,
+            ManualError::Database(_) => return ApiError::internal(err),
+        };
+        ApiError::new(status, err.to_string())
+    }
+}
+
+impl From<NetWorthError> for ApiError {
+    fn from(err: NetWorthError) -> Self {
+        match err {
+            NetWorthError::InvalidRange | NetWorthError::TooManyPoints => {
+                ApiError::bad_request(err.to_string())
+            }
+            NetWorthError::Exchange(err) => err.into(),
+            NetWorthError::Database(_) => ApiError::internal(err),
+        }
+    }
+}
+
+impl From<ExchangeError> for ApiError {
+    fn from(err: ExchangeError) -> Self {
+        match err {
+            ExchangeError::Database(_) => ApiError::internal(err),
+            _ => ApiError::bad_request(err.to_string()),
+        }
+    }
+}
+
+impl From<ReconcileError> for ApiError {
+    fn from(err: ReconcileError) -> Self {
+        match err {
+            ReconcileError::AccountNotFound => {
+                ApiError::new(StatusCode::NOT_FOUND, err.to_string())
+            }
+            ReconcileError::Database(_) => ApiError::internal(err),
+        }
+    }
+}
+
+impl From<CategorizeError> for ApiError {
+    fn from(err: CategorizeError) -> Self {
+        match err {
+            CategorizeError::InvalidRule(_) => ApiError::bad_request(err.to_string()),
+            CategorizeError::Http(_) => ApiError::new(StatusCode::BAD_GATEWAY, err.to_string()),
+            // the server's configuration, nothing the client can fix
+            CategorizeError::InvalidApiKey(_) => ApiError::internal(err),
+        }
+    }
+}
+
+impl From<SearchError> for ApiError {
+    fn from(err: SearchError) -> Self {
+        match err {
+            SearchError::EmptyQuery => ApiError::bad_request(err.to_string()),
+            SearchError::Database(_) => ApiError::internal(err),
+        }
+    }
+}
+
+impl From<ParseError> for ApiError {
+    fn from(err: ParseError) -> Self {
+        match err {
+            ParseError::Io(_) => ApiError::internal(err),
+            _ => ApiError::bad_request(err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_mapping() {
+        assert_eq!(
+            ApiError::from(AuthError::UsernameTaken).status,
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            ApiError::from(CategoryError::NotFound).status,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            ApiError::from(ParseError::InvalidFormat("x".to_string())).status,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn test_internal_errors_hide_details() {
+        let err = ApiError::from(rusqlite::Error::InvalidQuery);
+        assert_eq!(err.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(err.message, "Internal server error");
+    }
+}
@@ -0,0 +1,325 @@
+use core::fmt;
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::Database,
+    money::{Currency, Money},
+    parser::{self, LineError},
+};
+
+// One unit of `base` was worth `rate` units of `quote` on `date`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ExchangeRate {
+    pub date: NaiveDate,
+    pub base: Currency,
+    pub quote: Currency,
+    pub rate: f64,
+}
+
+#[derive(Debug)]
+pub enum ExchangeError {
+    MissingHeader,
+    InvalidRow(LineError),
+    MissingRate { from: Currency, to: Currency },
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for ExchangeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExchangeError::MissingHeader => write!(
+                f,
+                "Expected a date,base,quote,rate header or a Bank of Canada date,FXUSDCAD header"
+            ),
+            ExchangeError::InvalidRow(err) => write!(f, "Invalid exchange rate on {}", err),
+            ExchangeError::MissingRate { from, to } => write!(
+                f,
+                "No exchange rate from {} to {}, import rates before reporting in {}",
+                from, to, to
+            ),
+            ExchangeError::Database(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ExchangeError {}
+
+impl From<rusqlite::Error> for ExchangeError {
+    fn from(err: rusqlite::Error) -> Self {
+        ExchangeError::Database(err)
+    }
+}
+
+// Historical rates held in memory, each pair sorted by date
+#[derive(Debug, Clone, Default)]
+pub struct ExchangeRates {
+    rates: HashMap<(Currency, Currency), Vec<(NaiveDate, f64)>>,
+}
+
+impl ExchangeRates {
+    pub fn new(rates: Vec<ExchangeRate>) -> ExchangeRates {
+        let mut pairs: HashMap<(Currency, Currency), Vec<(NaiveDate, f64)>> = HashMap::new();
+        for rate in rates {
+            pairs
+                .entry((rate.base, rate.quote))
+                .or_default()
+                .push((rate.date, rate.rate));
+        }
+        for series in pairs.values_mut() {
+            series.sort_by_key(|(date, _)| *date);
+        }
+        ExchangeRates { rates: pairs }
+    }
+
+    fn latest(&self, from: Currency, to: Currency, date: NaiveDate) -> Option<f64> {
+        let series = self.rates.get(&(from, to))?;
+        series
+            .iter()
+            .rev()
+            .find(|(day, _)| *day <= date)
+            .map(|(_, rate)| *rate)
+    }
+
+    fn earliest(&self, from: Currency, to: Currency) -> Option<f64> {
+        self.rates.get(&(from, to))?.first().map(|(_, rate)| *rate)
+    }
+
+    // Units of `to` per unit of `from` on `date`. Uses the most recent rate on or
+    // before the date, so weekends and holidays take the last business day, and
+    // the earliest known rate for dates before the history starts. A rate quoted
+    // the other way round is inverted. Has to agree with `database::CONVERTED`.
+    pub fn rate(&self, from: Currency, to: Currency, date: NaiveDate) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+        self.latest(from, to, date)
+            .or_else(|| self.latest(to, from, date).map(|rate| 1.0 / rate))
+            .or_else(|| self.earliest(from, to))
+            .or_else(|| self.earliest(to, from).map(|rate| 1.0 / rate))
+    }
+
+    pub fn convert(&self, amount: Money, to: Currency, date: NaiveDate) -> Option<Money> {
+        let rate = self.rate(amount.currency(), to, date)?;
+        Some(Money::from_minor(
+            (amount.minor_units() as f64 * rate).round() as i64,
+            to,
+        ))
+    }
+
+    // Errors unless every one of `currencies` can be converted into `to`
+    pub fn check(&self, currencies: &[Currency], to: Currency) -> Result<(), ExchangeError> {
+        match currencies
+            .iter()
+            .find(|from| self.rate(**from, to, NaiveDate::MIN).is_none())
+        {
+            Some(from) => Err(ExchangeError::MissingRate { from: *from, to }),
+            None => Ok(()),
+        }
+    }
+}
+
+fn invalid(line: usize, message: String) -> ExchangeError {
+    ExchangeError::InvalidRow(LineError {
+        line,
+        error: parser::ParseError::InvalidFormat(message),
+    })
+}
+
+// Reads either a plain `date,base,quote,rate` file or a Bank of Canada Valet
+// export, whose observations follow a block of series metadata under a
+// `date,FXUSDCAD,...` header. Blank observations (holidays) are skipped.
+pub fn parse_rates(input: &str) -> Result<Vec<ExchangeRate>, ExchangeError> {
+    let mut records = parser::parse_csv(input).into_iter();
+    let header = loop {
+        match records.next() {
+            Some(Ok(record)) if record.fields[0].trim().eq_ignore_ascii_case("date") => {
+                break record
+                    .fields
+                    .iter()
+                    .map(|f| f.trim().to_uppercase())
+                    .collect::<Vec<_>>();
+            }
+            Some(_) => continue,
+            None => return Err(ExchangeError::MissingHeader),
+        }
+    };
+
+    // each rate column with the pair it quotes
+    let columns: Vec<(usize, Option<(Currency, Currency)>)> =
+        if header[1..] == ["BASE", "QUOTE", "RATE"] {
+            vec![(3, None)]
+        } else {
+            header
+                .iter()
+                .enumerate()
+                .skip(1)
+                .filter_map(|(i, name)| {
+                    let pair = name.strip_prefix("FX").filter(|pair| pair.len() == 6)?;
+                    let base = pair[..3].parse().ok()?;
+                    let quote = pair[3..].parse().ok()?;
+                    Some((i, Some((base, quote))))
+                })
+                .collect()
+        };
+    if columns.is_empty() {
+        return Err(ExchangeError::MissingHeader);
+    }
+
+    let mut rates = Vec::new();
+    for record in records {
+        let record = record.map_err(ExchangeError::InvalidRow)?;
+        let line = record.line;
+        let field = |i: usize| record.fields.get(i).map(|f| f.trim()).unwrap_or("");
+        let date = NaiveDate::parse_from_str(field(0), "%Y-%m-%d")
+            .map_err(|_| invalid(line, format!("unrecognized date {:?}", field(0))))?;
+
+        for (i, pair) in &columns {
+            if field(*i).is_empty() {
+                continue;
+            }
+            let (base, quote) = match pair {
+                Some(pair) => *pair,
+                None => (
+                    field(1)
+                        .parse()
+                        .map_err(|e| invalid(line, format!("{}", e)))?,
+                    field(2)
+                        .parse()
+                        .map_err(|e| invalid(line, format!("{}", e)))?,
+                ),
+            };
+            let rate: f64 = field(*i)
+                .parse()
+                .ok()
+                .filter(|rate: &f64| rate.is_finite() && *rate > 0.0)
+                .ok_or_else(|| invalid(line, format!("invalid rate {:?}", field(*i))))?;
+            if base == quote {
+                return Err(invalid(
+                    line,
+                    format!("{} cannot be quoted in itself", base),
+                ));
+            }
+            rates.push(ExchangeRate {
+                date,
+                base,
+                quote,
+                rate,
+            });
+        }
+    }
+    Ok(rates)
+}
+
+// Stores the rates in a user's CSV upload, replacing any they already have for
+// the same pair and day. Returns how many were read.
+pub fn import_rates(db: &Database, user_id: i64, input: &str) -> Result<usize, ExchangeError> {
+    let rates = parse_rates(input)?;
+    db.insert_exchange_rates(user_id, &rates)?;
+    Ok(rates.len())
+}
+
+pub fn load_rates(db: &Database, user_id: i64) -> Result<ExchangeRates, ExchangeError> {
+    Ok(ExchangeRates::new(db.get_exchange_rates(user_id)?))
+}
+
+// Errors if some of the user's accounts or transactions could not be shown in `currency`
+pub fn check_rates(db: &Database, user_id: i64, currency: Currency) -> Result<(), ExchangeError> {
+    load_rates(db, user_id)?.check(&db.get_user_currencies(user_id)?, currency)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    const VALET_CSV: &str = "\"TERMS AND CONDITIONS\"
+\"https://www.bankofcanada.ca/terms/\"
+
+\"SERIES\"
+\"id\",\"label\",\"description\"
+\"FXUSDCAD\",\"USD/CAD\",\"US dollar to Canadian dollar daily exchange rate\"
+
+\"OBSERVATIONS\"
+\"date\",\"FXUSDCAD\"
+\"2025-01-02\",\"1.4389\"
+\"2025-01-03\",\"1.4420\"
+\"2025-01-06\",\"\"
+\"2025-01-07\",\"1.4301\"
+";
+
+    #[test]
+    fn test_parse_bank_of_canada_export() {
+        let rates = parse_rates(VALET_CSV).unwrap();
+        assert_eq!(rates.len(), 3);
+        assert_eq!(
+            rates[0],
+            ExchangeRate {
+                date: date(2025, 1, 2),
+                base: Currency::USD,
+                quote: Currency::CAD,
+                rate: 1.4389,
+            }
+        );
+        assert_eq!(rates[2].date, date(2025, 1, 7));
+    }
+
+    #[test]
+    fn test_parse_plain_rates() {
+        let rates = parse_rates("date,base,quote,rate\n2025-01-02,CAD,USD,0.695\n").unwrap();
+        assert_eq!(rates[0].base, Currency::CAD);
+        assert_eq!(rates[0].quote, Currency::USD);
+
+        assert!(matches!(
+            parse_rates("when,rate\n2025-01-02,1.4\n"),
+            Err(ExchangeError::MissingHeader)
+        ));
+        match parse_rates("date,base,quote,rate\n2025-01-02,USD,CAD,0\n") {
+            Err(ExchangeError::InvalidRow(err)) => assert_eq!(err.line, 2),
+            other => panic!("expected an invalid row, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_conversion_uses_rate_on_or_before_date() {
+        let rates = ExchangeRates::new(parse_rates(VALET_CSV).unwrap());
+        let usd = Money::usd(10000);
+
+        // Saturday takes Friday's rate
+        assert_eq!(
+            rates.convert(usd, Currency::CAD, date(2025, 1, 4)),
+            Some(Money::cad(14420))
+        );
+        // a blank day takes the one before
+        assert_eq!(
+            rates.convert(usd, Currency::CAD, date(2025, 1, 6)),
+            Some(Money::cad(14420))
+        );
+        // before the history starts the earliest rate is used
+        assert_eq!(
+            rates.convert(usd, Currency::CAD, date(2024, 12, 1)),
+            Some(Money::cad(14389))
+        );
+        // and the pair is inverted for the other direction
+        assert_eq!(
+            rates.convert(Money::cad(14301), Currency::USD, date(2025, 2, 1)),
+            Some(Money::usd(10000))
+        );
+        assert_eq!(
+            rates.convert(Money::cad(500), Currency::CAD, date(2025, 2, 1)),
+            Some(Money::cad(500))
+        );
+
+        let none = ExchangeRates::default();
+        assert_eq!(none.convert(usd, Currency::CAD, date(2025, 1, 4)), None);
+        assert!(matches!(
+            none.check(&[Currency::CAD, Currency::USD], Currency::CAD),
+            Err(ExchangeError::MissingRate { from, .. }) if from == Currency::USD
+        ));
+    }
+}
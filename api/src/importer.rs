@@ -0,0 +1,612 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use crate::{
+    account::{AccountType, BankNumber},
+    money::{Currency, Money},
+    parser::{
+        parse_amount, parse_csv, parse_date, parse_statement_text, CsvRecord, LineError,
+        ParseError, ParsedStatement,
+    },
+    transaction::Transaction,
+};
+
+// Where imported transactions should go. Formats that carry the account in
+// every row ignore the account fields, the others require them.
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    pub user_id: i64,
+    pub account_number: Option<i64>,
+    pub account_type: Option<AccountType>,
+    // currency of the target account, for formats that print plain amounts
+    pub currency: Option<Currency>,
+}
+
+// One bank statement format. Supporting a new bank means adding an impl
+// and registering it in `ImporterRegistry::default`.
+pub trait Importer: Send + Sync {
+    // short identifier, also accepted as a format hint
+    fn name(&self) -> &'static str;
+
+    // whether this format produced the given header row
+    fn detect(&self, header: &str) -> bool;
+
+    // parses the whole file, header included
+    fn parse(&self, input: &str, options: &ImportOptions) -> Result<ParsedStatement, ParseError>;
+
+    // bank that issues the accounts in this format, recorded on new accounts
+    fn institution(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+pub struct ImporterRegistry {
+    importers: Vec<Box<dyn Importer>>,
+}
+
+impl ImporterRegistry {
+    pub fn new() -> ImporterRegistry {
+        ImporterRegistry {
+            importers: Vec::new(),
+        }
+    }
+
+    // Importers are tried in registration order, so register more specific formats first
+    pub fn register(&mut self, importer: Box<dyn Importer>) {
+        self.importers.push(importer);
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.importers.iter().map(|i| i.name()).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Importer> {
+        self.importers
+            .iter()
+            .find(|i| i.name().eq_ignore_ascii_case(name))
+            .map(|i| i.as_ref())
+    }
+
+    pub fn detect(&self, input: &str) -> Option<&dyn Importer> {
+        let header = header_row(input)?;
+        self.importers
+            .iter()
+            .find(|i| i.detect(header))
+            .map(|i| i.as_ref())
+    }
+
+    pub fn import(
+        &self,
+        input: &str,
+        options: &ImportOptions,
+    ) -> Result<ParsedStatement, ParseError> {
+        match self.detect(input) {
+            Some(importer) => importer.parse(input, options),
+            None => Err(ParseError::InvalidFormat(
+                "unrecognized statement format".to_string(),
+            )),
+        }
+    }
+}
+
+impl Default for ImporterRegistry {
+    fn default() -> Self {
+        let mut registry = ImporterRegistry::new();
+        registry.register(Box::new(RbcCsvImporter));
+        registry.register(Box::new(CibcCsvImporter));
+        registry.register(Box::new(StatementTextImporter));
+        registry
+    }
+}
+
+fn header_row(input: &str) -> Option<&str> {
+    input
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .find(|line| !line.trim().is_empty())
+}
+
+fn header_fields(header: &str) -> Vec<String> {
+    match parse_csv(header).into_iter().next() {
+        Some(Ok(record)) => record
+            .fields
+            .iter()
+            .map(|f| f.trim().to_lowercase())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// data rows of a CSV export, the header row is skipped
+fn csv_rows(input: &str) -> impl Iterator<Item = Result<CsvRecord, LineError>> {
+    parse_csv(input).into_iter().skip(1)
+}
+
+fn csv_amount(field: Option<&String>, currency: Currency) -> Result<Money, ParseError> {
+    match field.map(|f| f.trim()) {
+        None | Some("") => Ok(Money::zero(currency)),
+        Some(amount) => parse_amount(amount, currency),
+    }
+}
+
+fn csv_text(field: &str) -> String {
+    field.trim().to_string()
+}
+
+fn require_columns(line: usize, parts: &[String], count: usize) -> Result<(), LineError> {
+    if parts.len() < count {
+        return Err(LineError {
+            line,
+            error: ParseError::InvalidFormat(format!(
+                "expected at least {} columns, found {}",
+                count,
+                parts.len()
+            )),
+        });
+    }
+    Ok(())
+}
+
+// Rows of a USD account fill in the USD$ column and leave CAD$ empty
+fn row_currency(cad: Money, usd: Money) -> Currency {
+    if cad.is_zero() && !usd.is_zero() {
+        Currency::USD
+    } else {
+        Currency::CAD
+    }
+}
+
+// A row with nothing in either amount column says nothing about its currency,
+// so it takes the column the rest of its account's rows use
+fn assign_currencies(transactions: &mut [Transaction]) {
+    let mut cad_accounts = HashSet::new();
+    let mut usd_accounts = HashSet::new();
+    for transaction in transactions.iter() {
+        if !transaction.cad.is_zero() {
+            cad_accounts.insert(transaction.account_number);
+        } else if !transaction.usd.is_zero() {
+            usd_accounts.insert(transaction.account_number);
+        }
+    }
+    for transaction in transactions
+        .iter_mut()
+        .filter(|t| t.cad.is_zero() && t.usd.is_zero())
+    {
+        let account_number = transaction.account_number;
+        if usd_accounts.contains(&account_number) && !cad_accounts.contains(&account_number) {
+            transaction.currency = Currency::USD;
+        }
+    }
+}
+
+// "Account Type","Account Number","Transaction Date","Cheque Number","Description 1","Description 2","CAD$","USD$"
+pub struct RbcCsvImporter;
+
+impl RbcCsvImporter {
+    fn parse_row(
+        user_id: i64,
+        line: usize,
+        parts: &[String],
+    ) -> Result<(Transaction, BankNumber), LineError> {
+        require_columns(line, parts, 7)?;
+
+        let account_type = csv_text(&parts[0]).to_lowercase();
+        let real_account_type = AccountType::from_str(match account_type.as_str() {
+            "visa" => "credit",
+            _ => account_type.as_str(),
+        })
+        .unwrap_or(AccountType::Unknown);
+
+        let amount = |idx: usize, currency: Currency| {
+            csv_amount(parts.get(idx), currency).map_err(|error| LineError { line, error })
+        };
+
+        let cad = amount(6, Currency::CAD)?;
+        let usd = amount(7, Currency::USD)?;
+        let bank_number = BankNumber::new(&csv_text(&parts[1]).replace("-", ""));
+        let transaction = Transaction {
+            user_id,
+            account_type: real_account_type,
+            account_number: bank_number.provisional(),
+            transaction_date: parse_date(&parts[2]).map_err(|error| LineError { line, error })?,
+            cheque_number: csv_text(&parts[3]),
+            description_1: csv_text(&parts[4]),
+            description_2: csv_text(&parts[5]),
+            cad,
+            usd,
+            id: 0,
+            category_id: None,
+            notes: String::new(),
+            merchant_id: None,
+            merchant: String::new(),
+            transfer_id: None,
+            statement_balance: None,
+            currency: row_currency(cad, usd),
+            category: "".to_string(),
+        };
+        Ok((transaction, bank_number))
+    }
+}
+
+impl Importer for RbcCsvImporter {
+    fn name(&self) -> &'static str {
+        "rbc-csv"
+    }
+
+    fn institution(&self) -> Option<&'static str> {
+        Some("RBC Royal Bank")
+    }
+
+    fn detect(&self, header: &str) -> bool {
+        let fields = header_fields(header);
+        fields.first().map(String::as_str) == Some("account type")
+            && fields.get(1).map(String::as_str) == Some("account number")
+    }
+
+    fn parse(&self, input: &str, options: &ImportOptions) -> Result<ParsedStatement, ParseError> {
+        let mut statement = ParsedStatement::default();
+        for record in csv_rows(input) {
+            match record.and_then(|r| RbcCsvImporter::parse_row(options.user_id, r.line, &r.fields))
+            {
+                Ok((transaction, bank_number)) => {
+                    statement
+                        .bank_numbers
+                        .insert(transaction.account_number, bank_number);
+                    statement.transactions.push(transaction);
+                }
+                Err(error) => statement.errors.push(error),
+            }
+        }
+        assign_currencies(&mut statement.transactions);
+        Ok(statement)
+    }
+}
+
+// Same columns as RBC without the account type, which has to be given on import
+pub struct CibcCsvImporter;
+
+impl CibcCsvImporter {
+    fn parse_row(
+        user_id: i64,
+        account_type: AccountType,
+        line: usize,
+        parts: &[String],
+    ) -> Result<(Transaction, BankNumber), LineError> {
+        require_columns(line, parts, 6)?;
+
+        let amount = |idx: usize, currency: Currency| {
+            csv_amount(parts.get(idx), currency).map_err(|error| LineError { line, error })
+        };
+
+        let cad = amount(5, Currency::CAD)?;
+        let usd = amount(6, Currency::USD)?;
+        let bank_number = BankNumber::new(&csv_text(&parts[0]).replace("-", ""));
+        let transaction = Transaction {
+            user_id,
+            account_type,
+            account_number: bank_number.provisional(),
+            transaction_date: parse_date(&parts[1]).map_err(|error| LineError { line, error })?,
+            cheque_number: csv_text(&parts[2]),
+            description_1: csv_text(&parts[3]),
+            description_2: csv_text(&parts[4]),
+            cad,
+            usd,
+            id: 0,
+            category_id: None,
+            notes: String::new(),
+            merchant_id: None,
+            merchant: String::new(),
+            transfer_id: None,
+            statement_balance: None,
+            currency: row_currency(cad, usd),
+            category: "".to_string(),
+        };
+        Ok((transaction, bank_number))
+    }
+}
+
+impl Importer for CibcCsvImporter {
+    fn name(&self) -> &'static str {
+        "cibc-csv"
+    }
+
+    fn institution(&self) -> Option<&'static str> {
+        Some("CIBC")
+    }
+
+    fn detect(&self, header: &str) -> bool {
+        header_fields(header).first().map(String::as_str) == Some("account number")
+    }
+
+    fn parse(&self, input: &str, options: &ImportOptions) -> Result<ParsedStatement, ParseError> {
+        let account_type = options.account_type.ok_or_else(|| {
+            ParseError::InvalidFormat("CIBC exports need an account type".to_string())
+        })?;
+
+        let mut statement = ParsedStatement::default();
+        for record in csv_rows(input) {
+            match record.and_then(|r| {
+                CibcCsvImporter::parse_row(options.user_id, account_type, r.line, &r.fields)
+            }) {
+                Ok((transaction, bank_number)) => {
+                    statement
+                        .bank_numbers
+                        .insert(transaction.account_number, bank_number);
+                    statement.transactions.push(transaction);
+                }
+                Err(error) => statement.errors.push(error),
+            }
+        }
+        assign_currencies(&mut statement.transactions);
+        Ok(statement)
+    }
+}
+
+// Text extracted from a PDF statement, see `parser::parse_statement_text`
+pub struct StatementTextImporter;
+
+impl Importer for StatementTextImporter {
+    fn name(&self) -> &'static str {
+        "statement-text"
+    }
+
+    fn detect(&self, header: &str) -> bool {
+        !header.contains(',') && header.contains("Balance")
+    }
+
+    fn parse(&self, input: &str, options: &ImportOptions) -> Result<ParsedStatement, ParseError> {
+        match (options.account_number, options.account_type) {
+            (Some(account_number), Some(account_type)) => {
+                let mut statement =
+                    parse_statement_text(input, options.user_id, account_number, account_type);
+                // the amounts are printed in the account's own currency
+                if let Some(currency) = options.currency {
+                    let in_account =
+                        |amount: Money| Money::from_minor(amount.minor_units(), currency);
+                    for transaction in &mut statement.transactions {
+                        transaction.set_amount(in_account(transaction.amount()));
+                        transaction.statement_balance =
+                            transaction.statement_balance.map(in_account);
+                    }
+                }
+                Ok(statement)
+            }
+            _ => Err(ParseError::InvalidFormat(
+                "statement text imports need a target account".to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RBC_CSV: &str = "\"Account Type\",\"Account Number\",\"Transaction Date\",\"Cheque Number\",\"Description 1\",\"Description 2\",\"CAD$\",\"USD$\"
+Chequing,00000-1234567,5/12/2025,,\"TIM HORTONS #7525\",\"NEPEAN\",-2.45,
+Visa,4500123412341234,5/13/2025,,\"AMAZON\",,-30.00,
+";
+
+    fn options() -> ImportOptions {
+        ImportOptions {
+            user_id: 7,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_detects_rbc_header() {
+        let registry = ImporterRegistry::default();
+        assert_eq!(registry.detect(RBC_CSV).unwrap().name(), "rbc-csv");
+    }
+
+    #[test]
+    fn test_detects_cibc_header() {
+        let registry = ImporterRegistry::default();
+        let input =
+            "Account Number,Transaction Date,Cheque Number,Description 1,Description 2,CAD$,USD$\n";
+        assert_eq!(registry.detect(input).unwrap().name(), "cibc-csv");
+    }
+
+    #[test]
+    fn test_detects_statement_text() {
+        let registry = ImporterRegistry::default();
+        let input =
+            "Current Balance Available Balance Authorized Overdraft\n$467.17 $467.17 $0.00\n";
+        assert_eq!(registry.detect(input).unwrap().name(), "statement-text");
+    }
+
+    #[test]
+    fn test_unknown_format_is_an_error() {
+        let registry = ImporterRegistry::default();
+        assert!(registry.detect("Date,Amount,Memo\n").is_none());
+        assert!(matches!(
+            registry.import("Date,Amount,Memo\n", &options()),
+            Err(ParseError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_get_by_name() {
+        let registry = ImporterRegistry::default();
+        assert_eq!(registry.get("RBC-CSV").unwrap().name(), "rbc-csv");
+        assert!(registry.get("td-csv").is_none());
+    }
+
+    #[test]
+    fn test_rbc_import() {
+        let statement = ImporterRegistry::default()
+            .import(RBC_CSV, &options())
+            .unwrap();
+
+        assert!(statement.errors.is_empty());
+        assert_eq!(statement.transactions.len(), 2);
+
+        let coffee = &statement.transactions[0];
+        assert_eq!(coffee.user_id, 7);
+        assert_eq!(coffee.account_type, AccountType::Chequing);
+        // rows carry a provisional number derived from the bank's until the
+        // import assigns the account
+        let bank_number = &statement.bank_numbers[&coffee.account_number];
+        assert_eq!(bank_number.mask, "4567");
+        assert_eq!(
+            bank_number.unkeyed_key(),
+            "a94d3232e3e4bfab62877f54f7d8f8df165dabe0a5c5c787c808efe9b12cbbda"
+        );
+        assert_ne!(bank_number.key(b"one secret"), bank_number.key(b"another"));
+        assert_eq!(coffee.account_number, 186149019771876);
+        assert_eq!(
+            coffee.transaction_date,
+            chrono::NaiveDate::from_ymd_opt(2025, 5, 12).unwrap()
+        );
+        assert_eq!(coffee.description_1, "TIM HORTONS #7525");
+        assert_eq!(coffee.description_2, "NEPEAN");
+        assert_eq!(coffee.cad, Money::cad(-245));
+        assert_eq!(coffee.usd, Money::usd(0));
+        assert_eq!(coffee.currency, Currency::CAD);
+
+        assert_eq!(statement.transactions[1].account_type, AccountType::Credit);
+    }
+
+    #[test]
+    fn test_rbc_usd_rows() {
+        // a USD card only fills in the USD$ column
+        let input = format!(
+            "{}Visa,4500987698769876,5/14/2025,,NETFLIX.COM,,,-15.99\n",
+            RBC_CSV
+        );
+        let statement = ImporterRegistry::default()
+            .import(&input, &options())
+            .unwrap();
+
+        let netflix = &statement.transactions[2];
+        assert_eq!(netflix.currency, Currency::USD);
+        assert_eq!(netflix.amount(), Money::usd(-1599));
+        assert_eq!(netflix.cad, Money::cad(0));
+    }
+
+    #[test]
+    fn test_empty_row_takes_its_accounts_currency() {
+        let input = format!(
+            "{}Visa,4500987698769876,5/14/2025,,NETFLIX.COM,,,-15.99\n\
+            Visa,4500987698769876,5/15/2025,,ADJUSTMENT,,0.00,\n\
+            Chequing,00000-1234567,5/15/2025,,FEE WAIVED,,,\n",
+            RBC_CSV
+        );
+        let statement = ImporterRegistry::default()
+            .import(&input, &options())
+            .unwrap();
+
+        assert_eq!(statement.transactions[3].currency, Currency::USD);
+        assert_eq!(statement.transactions[3].amount(), Money::usd(0));
+        assert_eq!(statement.transactions[4].currency, Currency::CAD);
+    }
+
+    #[test]
+    fn test_rbc_reports_bad_rows() {
+        let input = format!(
+            "{}Chequing,123\nChequing,123,5/14/2025,,A,B,abc,\n",
+            RBC_CSV
+        );
+        let statement = ImporterRegistry::default()
+            .import(&input, &options())
+            .unwrap();
+
+        assert_eq!(statement.transactions.len(), 2);
+        assert_eq!(statement.errors.len(), 2);
+        assert_eq!(statement.errors[0].line, 4);
+        assert!(matches!(
+            statement.errors[0].error,
+            ParseError::InvalidFormat(_)
+        ));
+        assert_eq!(statement.errors[1].line, 5);
+        assert!(matches!(
+            statement.errors[1].error,
+            ParseError::ParseFloat(_)
+        ));
+    }
+
+    #[test]
+    fn test_rbc_quoted_commas_do_not_shift_columns() {
+        let input = format!(
+            "{}Visa,4500123412341234,5/14/2025,,\"AMAZON.CA, MARKETPLACE\",\"SEATTLE, WA\",-42.10,\r\n\r\n",
+            RBC_CSV
+        );
+        let statement = ImporterRegistry::default()
+            .import(&input, &options())
+            .unwrap();
+
+        assert!(statement.errors.is_empty());
+        let amazon = &statement.transactions[2];
+        assert_eq!(amazon.description_1, "AMAZON.CA, MARKETPLACE");
+        assert_eq!(amazon.description_2, "SEATTLE, WA");
+        assert_eq!(amazon.cad, Money::cad(-4210));
+    }
+
+    #[test]
+    fn test_rbc_unterminated_quote_is_reported() {
+        let input = format!("{}Visa,4500,5/14/2025,,\"AMAZON,,-1.00,\n", RBC_CSV);
+        let statement = ImporterRegistry::default()
+            .import(&input, &options())
+            .unwrap();
+
+        assert_eq!(statement.transactions.len(), 2);
+        assert_eq!(statement.errors.len(), 1);
+        assert_eq!(statement.errors[0].line, 4);
+    }
+
+    #[test]
+    fn test_rbc_invalid_date_is_reported() {
+        let input = format!("{}Visa,4500,13/45/2025,,AMAZON,,-1.00,\n", RBC_CSV);
+        let statement = ImporterRegistry::default()
+            .import(&input, &options())
+            .unwrap();
+
+        assert_eq!(statement.errors.len(), 1);
+        assert_eq!(statement.errors[0].line, 4);
+        assert!(matches!(
+            statement.errors[0].error,
+            ParseError::InvalidDate(_)
+        ));
+    }
+
+    #[test]
+    fn test_cibc_requires_account_type() {
+        let input = "Account Number,Transaction Date,Cheque Number,Description 1,Description 2,CAD$,USD$\n123,5/12/2025,,A,B,-1.00,\n";
+        let registry = ImporterRegistry::default();
+        assert!(registry.import(input, &options()).is_err());
+
+        let statement = registry
+            .import(
+                input,
+                &ImportOptions {
+                    account_type: Some(AccountType::Savings),
+                    ..options()
+                },
+            )
+            .unwrap();
+        assert_eq!(statement.transactions.len(), 1);
+        assert_eq!(statement.transactions[0].account_type, AccountType::Savings);
+    }
+
+    #[test]
+    fn test_statement_text_requires_account() {
+        let input = "Current Balance Available Balance Authorized Overdraft\nJan 01, 2024 Deposit $100.00\n";
+        let registry = ImporterRegistry::default();
+        assert!(registry.import(input, &options()).is_err());
+
+        let statement = registry
+            .import(
+                input,
+                &ImportOptions {
+                    account_number: Some(42),
+                    account_type: Some(AccountType::Chequing),
+                    ..options()
+                },
+            )
+            .unwrap();
+        assert_eq!(statement.transactions.len(), 1);
+        assert_eq!(statement.transactions[0].account_number, 42);
+        assert_eq!(statement.transactions[0].user_id, 7);
+    }
+}
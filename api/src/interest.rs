@@ -0,0 +1,759 @@
+use core::fmt;
+use std::str::FromStr;
+
+use chrono::{Datelike, Days, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    account::{AccountType, BankAccount},
+    database::Database,
+    money::Money,
+    networth::{self, AccountHistory},
+    transaction::Transaction,
+};
+
+pub const DAYS_PER_YEAR: f64 = 365.0;
+
+// Longest range worked out at once, every day of it is accrued
+pub const MAX_MONTHS: u32 = 120;
+
+// description_1 of the interest transactions recorded for an account
+pub const INTEREST_DESCRIPTION: &str = "INTEREST";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compounding {
+    // each day's interest earns interest from the next day on
+    #[default]
+    Daily,
+    // interest is worked out daily but only earns interest once it is paid
+    Monthly,
+}
+
+#[derive(Debug, Clone)]
+pub struct InvalidCompounding(String);
+impl fmt::Display for InvalidCompounding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid compounding: {}", self.0)
+    }
+}
+
+impl FromStr for Compounding {
+    type Err = InvalidCompounding;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "daily" => Ok(Compounding::Daily),
+            "monthly" => Ok(Compounding::Monthly),
+            _ => Err(InvalidCompounding(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Compounding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Compounding::Daily => write!(f, "daily"),
+            Compounding::Monthly => write!(f, "monthly"),
+        }
+    }
+}
+
+// A yearly rate, as a fraction, paid on the part of the balance from
+// `min_balance` up to where the next tier starts
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Tier {
+    #[serde(default)]
+    pub min_balance: Money,
+    pub rate: f64,
+}
+
+// A rate on the whole balance from `start_date` to `end_date` inclusive, in
+// place of the tiers
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Promotion {
+    pub rate: f64,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+fn default_grace_period() -> bool {
+    true
+}
+
+fn default_statement_day() -> u32 {
+    1
+}
+
+fn default_payment_due_days() -> u32 {
+    21
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InterestPlan {
+    #[serde(skip_deserializing)]
+    pub account_number: i64,
+    #[serde(default)]
+    pub compounding: Compounding,
+    #[serde(default)]
+    pub tiers: Vec<Tier>,
+    #[serde(default)]
+    pub promotions: Vec<Promotion>,
+    // cards: new purchases are interest free when the previous statement was
+    // paid in full by its due date
+    #[serde(default = "default_grace_period")]
+    pub grace_period: bool,
+    // cards: day of the month statements are issued
+    #[serde(default = "default_statement_day")]
+    pub statement_day: u32,
+    // cards: days from a statement to its payment due date
+    #[serde(default = "default_payment_due_days")]
+    pub payment_due_days: u32,
+}
+
+// Interest over one month of savings or one card statement cycle
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct InterestPeriod {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    // balance at the end of the period, for cards the statement balance
+    pub balance: Money,
+    // earned on savings, charged on cards
+    pub interest: Money,
+    // the period ends after today, so the balance is assumed to stay where it
+    // is today
+    pub projected: bool,
+    // cards: purchases were interest free as the previous statement was paid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grace: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InterestEstimate {
+    pub account_number: i64,
+    pub account_type: AccountType,
+    pub plan: InterestPlan,
+    pub periods: Vec<InterestPeriod>,
+    pub total: Money,
+}
+
+#[derive(Debug)]
+pub enum InterestError {
+    AccountNotFound,
+    InvalidPlan(String),
+    EstimateOnly,
+    TooManyMonths,
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for InterestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterestError::AccountNotFound => write!(f, "Account not found"),
+            InterestError::InvalidPlan(msg) => write!(f, "Invalid interest plan: {}", msg),
+            InterestError::EstimateOnly => write!(
+                f,
+                "Interest on cards and loans can only be estimated, the lender charges it"
+            ),
+            InterestError::TooManyMonths => write!(
+                f,
+                "Interest is limited to {} months at a time, use a shorter range",
+                MAX_MONTHS
+            ),
+            InterestError::Database(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for InterestError {}
+
+impl From<rusqlite::Error> for InterestError {
+    fn from(err: rusqlite::Error) -> Self {
+        InterestError::Database(err)
+    }
+}
+
+impl InterestPlan {
+    // The plan of an account that has not been given one, its flat rate
+    // compounded daily
+    pub fn flat(account: &dyn BankAccount) -> InterestPlan {
+        InterestPlan {
+            account_number: *account.account_number(),
+            compounding: Compounding::Daily,
+            tiers: vec![Tier {
+                min_balance: Money::zero(account.balance().currency()),
+                rate: account.interest_rate(),
+            }],
+            promotions: Vec::new(),
+            grace_period: default_grace_period(),
+            statement_day: default_statement_day(),
+            payment_due_days: default_payment_due_days(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), InterestError> {
+        let rates = self
+            .tiers
+            .iter()
+            .map(|t| t.rate)
+            .chain(self.promotions.iter().map(|p| p.rate));
+        for rate in rates {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(InterestError::InvalidPlan(format!(
+                    "rate {} is not between 0 and 1",
+                    rate
+                )));
+            }
+        }
+        if self.tiers.iter().any(|t| t.min_balance.is_negative()) {
+            return Err(InterestError::InvalidPlan(
+                "tiers cannot start below zero".to_string(),
+            ));
+        }
+        if self.promotions.iter().any(|p| p.start_date > p.end_date) {
+            return Err(InterestError::InvalidPlan(
+                "a promotion ends before it starts".to_string(),
+            ));
+        }
+        if !(1..=28).contains(&self.statement_day) {
+            return Err(InterestError::InvalidPlan(
+                "statement_day must be between 1 and 28".to_string(),
+            ));
+        }
+        if self.payment_due_days > 60 {
+            return Err(InterestError::InvalidPlan(
+                "payment_due_days must be at most 60".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // Interest a year of `balance` (in minor units) would earn at the rates of `date`
+    fn yearly(&self, balance: f64, date: NaiveDate) -> f64 {
+        if balance <= 0.0 {
+            return 0.0;
+        }
+        if let Some(promotion) = self
+            .promotions
+            .iter()
+            .find(|p| p.start_date <= date && date <= p.end_date)
+        {
+            return balance * promotion.rate;
+        }
+
+        let mut tiers = self.tiers.clone();
+        tiers.sort_by_key(|t| t.min_balance.minor_units());
+        let mut interest = 0.0;
+        for (idx, tier) in tiers.iter().enumerate() {
+            let floor = tier.min_balance.minor_units() as f64;
+            let ceiling = tiers
+                .get(idx + 1)
+                .map(|next| next.min_balance.minor_units() as f64)
+                .unwrap_or(f64::INFINITY);
+            if balance > floor {
+                interest += (balance.min(ceiling) - floor) * tier.rate;
+            }
+        }
+        interest
+    }
+
+    pub fn annual_interest(&self, balance: Money, date: NaiveDate) -> Money {
+        Money::from_minor(
+            self.yearly(balance.minor_units() as f64, date).round() as i64,
+            balance.currency(),
+        )
+    }
+
+    // Interest over `start..=end` on the balance of each day, in fractional
+    // minor units
+    fn accrue(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+        balance_on: impl Fn(NaiveDate) -> Money,
+    ) -> f64 {
+        let mut accrued = 0.0;
+        let mut day = start;
+        while day <= end {
+            let mut balance = balance_on(day).minor_units() as f64;
+            if self.compounding == Compounding::Daily {
+                balance += accrued;
+            }
+            accrued += self.yearly(balance, day) / DAYS_PER_YEAR;
+            day = day + Days::new(1);
+        }
+        accrued
+    }
+}
+
+// Balance on `day` from the account's history up to today, and after that
+// today's balance plus `extra`, interest projected to be paid by then
+fn balance_on(history: &AccountHistory, today: NaiveDate, extra: Money, day: NaiveDate) -> Money {
+    if day <= today {
+        history.balance_on(day)
+    } else {
+        history.balance_on(today) + extra
+    }
+}
+
+// Interest a savings account earns each calendar month that starts on or after
+// the month of `from` and ends by `to`, paid on the last day of the month.
+// Projected months assume the interest of the months before them was paid in.
+pub fn savings_interest(
+    plan: &InterestPlan,
+    history: &AccountHistory,
+    from: NaiveDate,
+    to: NaiveDate,
+    today: NaiveDate,
+) -> Vec<InterestPeriod> {
+    let currency = history.currency();
+    let mut periods = Vec::new();
+    let mut extra = Money::zero(currency);
+    let mut start = from.with_day(1).unwrap();
+    loop {
+        let end = start + Months::new(1) - Days::new(1);
+        if end > to {
+            break;
+        }
+        let interest = plan.accrue(start, end, |day| balance_on(history, today, extra, day));
+        let interest = Money::from_minor(interest.round() as i64, currency);
+        let projected = end > today;
+        let balance = balance_on(history, today, extra, end);
+        if projected {
+            extra += interest;
+        }
+        periods.push(InterestPeriod {
+            start,
+            end,
+            balance,
+            interest,
+            projected,
+            grace: None,
+        });
+        start = end + Days::new(1);
+    }
+    periods
+}
+
+// Estimated interest charged for each card statement issued within `from..=to`.
+// Interest is charged on the daily balance of a cycle unless the previous
+// statement was paid in full by its due date. Interest the issuer charges
+// back to the purchase date once the grace period is lost is not included.
+pub fn credit_interest(
+    plan: &InterestPlan,
+    history: &AccountHistory,
+    from: NaiveDate,
+    to: NaiveDate,
+    today: NaiveDate,
+) -> Vec<InterestPeriod> {
+    let currency = history.currency();
+    let mut periods = Vec::new();
+    let mut extra = Money::zero(currency);
+    let mut closing = from.with_day(plan.statement_day).unwrap();
+    if closing < from {
+        closing = closing + Months::new(1);
+    }
+    while closing <= to {
+        let previous = closing - Months::new(1);
+        let previous_balance = balance_on(history, today, extra, previous);
+        let due = (previous + Days::new(plan.payment_due_days as u64)).min(today);
+        let paid = history
+            .changes()
+            .iter()
+            .filter(|(day, change)| *day > previous && *day <= due && change.is_negative())
+            .fold(Money::zero(currency), |paid, (_, change)| paid - *change);
+        let grace =
+            plan.grace_period && (!previous_balance.is_positive() || paid >= previous_balance);
+
+        let start = previous + Days::new(1);
+        let interest = if grace {
+            Money::zero(currency)
+        } else {
+            let accrued = plan.accrue(start, closing, |day| balance_on(history, today, extra, day));
+            Money::from_minor(accrued.round() as i64, currency)
+        };
+        let projected = closing > today;
+        let balance = balance_on(history, today, extra, closing);
+        if projected {
+            extra += interest;
+        }
+        periods.push(InterestPeriod {
+            start,
+            end: closing,
+            balance,
+            interest,
+            projected,
+            grace: Some(grace),
+        });
+        closing = closing + Months::new(1);
+    }
+    periods
+}
+
+fn find_account(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+) -> Result<Box<dyn BankAccount>, InterestError> {
+    db.get_accounts_by_user(user_id)?
+        .into_iter()
+        .find(|a| *a.account_number() == account_number)
+        .ok_or(InterestError::AccountNotFound)
+}
+
+fn plan_for(
+    db: &Database,
+    user_id: i64,
+    account: &dyn BankAccount,
+) -> Result<InterestPlan, InterestError> {
+    Ok(db
+        .get_interest_plan(user_id, *account.account_number())?
+        .unwrap_or_else(|| InterestPlan::flat(account)))
+}
+
+pub fn get_plan(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+) -> Result<InterestPlan, InterestError> {
+    let account = find_account(db, user_id, account_number)?;
+    plan_for(db, user_id, account.as_ref())
+}
+
+pub fn set_plan(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+    plan: InterestPlan,
+) -> Result<InterestPlan, InterestError> {
+    find_account(db, user_id, account_number)?;
+    let plan = InterestPlan {
+        account_number,
+        ..plan
+    };
+    plan.validate()?;
+    db.save_interest_plan(user_id, &plan)?;
+    Ok(plan)
+}
+
+// Interest earned or charged on an account over `from..=to`, periods ending
+// after `today` are projected
+fn check_range(from: NaiveDate, to: NaiveDate) -> Result<(), InterestError> {
+    let months = |date: NaiveDate| date.year() * 12 + date.month0() as i32;
+    if months(to) - months(from) >= MAX_MONTHS as i32 {
+        return Err(InterestError::TooManyMonths);
+    }
+    Ok(())
+}
+
+pub fn estimate(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+    from: NaiveDate,
+    to: NaiveDate,
+    today: NaiveDate,
+) -> Result<InterestEstimate, InterestError> {
+    check_range(from, to)?;
+    let account = find_account(db, user_id, account_number)?;
+    let plan = plan_for(db, user_id, account.as_ref())?;
+    let history = networth::account_history(db, user_id, account.as_ref())?;
+    let periods = match account.account_type() {
+        AccountType::Credit => credit_interest(&plan, &history, from, to, today),
+        _ => savings_interest(&plan, &history, from, to, today),
+    };
+    let total = periods
+        .iter()
+        .fold(Money::zero(account.balance().currency()), |total, p| {
+            total + p.interest
+        });
+    Ok(InterestEstimate {
+        account_number,
+        account_type: account.account_type(),
+        plan,
+        periods,
+        total,
+    })
+}
+
+// Records the interest a savings or chequing account earned in each finished
+// month of `from..=to` as a transaction on the last day of the month, and adds
+// it to the balance. Months that already have one are left alone.
+pub fn record_interest(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+    from: NaiveDate,
+    to: NaiveDate,
+    today: NaiveDate,
+) -> Result<Vec<InterestPeriod>, InterestError> {
+    check_range(from, to)?;
+    let mut account = find_account(db, user_id, account_number)?;
+    if matches!(
+        account.account_type(),
+        AccountType::Credit | AccountType::Loan
+    ) {
+        return Err(InterestError::EstimateOnly);
+    }
+    let plan = plan_for(db, user_id, account.as_ref())?;
+    let recorded: Vec<NaiveDate> = db
+        .get_account_transactions(user_id, account_number)?
+        .into_iter()
+        .filter(|t| t.description_1 == INTEREST_DESCRIPTION)
+        .map(|t| t.transaction_date)
+        .collect();
+
+    let mut periods = Vec::new();
+    let mut start = from.with_day(1).unwrap();
+    // one month at a time so each month earns on the interest paid before it
+    loop {
+        let end = start + Months::new(1) - Days::new(1);
+        if end > to.min(today) {
+            break;
+        }
+        let history = networth::account_history(db, user_id, account.as_ref())?;
+        let month = savings_interest(&plan, &history, start, end, today);
+        let Some(period) = month.first() else {
+            break;
+        };
+        if !recorded.contains(&end) && period.interest.is_positive() {
+            let mut transaction = Transaction {
+                user_id,
+                account_type: account.account_type(),
+                account_number,
+                transaction_date: end,
+                description_1: INTEREST_DESCRIPTION.to_string(),
+                ..Transaction::dummy()
+            };
+            transaction.set_amount(period.interest);
+            db.insert_transaction(&transaction)?;
+            account.deposit(period.interest);
+            db.update_account(account.as_ref())?;
+            periods.push(*period);
+        }
+        start = end + Days::new(1);
+    }
+    Ok(periods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::{CreditAccount, SavingsAccount};
+    use crate::user::User;
+
+    fn date(m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, m, d).unwrap()
+    }
+
+    fn plan(compounding: Compounding, rate: f64) -> InterestPlan {
+        InterestPlan {
+            account_number: 0,
+            compounding,
+            tiers: vec![Tier {
+                min_balance: Money::cad(0),
+                rate,
+            }],
+            promotions: Vec::new(),
+            grace_period: true,
+            statement_day: 15,
+            payment_due_days: 21,
+        }
+    }
+
+    #[test]
+    fn test_tiers_and_promotions() {
+        let plan = InterestPlan {
+            tiers: vec![
+                Tier {
+                    min_balance: Money::cad(1000000),
+                    rate: 0.03,
+                },
+                Tier {
+                    min_balance: Money::cad(0),
+                    rate: 0.01,
+                },
+                Tier {
+                    min_balance: Money::cad(500000),
+                    rate: 0.02,
+                },
+            ],
+            promotions: vec![Promotion {
+                rate: 0.05,
+                start_date: date(2, 1),
+                end_date: date(4, 30),
+            }],
+            ..plan(Compounding::Daily, 0.0)
+        };
+        assert_eq!(
+            plan.annual_interest(Money::cad(1200000), date(1, 31)),
+            Money::cad(5000 + 10000 + 6000)
+        );
+        assert_eq!(
+            plan.annual_interest(Money::cad(1200000), date(2, 1)),
+            Money::cad(60000)
+        );
+        assert_eq!(
+            plan.annual_interest(Money::cad(-5000), date(1, 1)),
+            Money::cad(0)
+        );
+
+        assert!(plan.validate().is_ok());
+        let bad = InterestPlan {
+            statement_day: 31,
+            ..plan.clone()
+        };
+        assert!(matches!(bad.validate(), Err(InterestError::InvalidPlan(_))));
+    }
+
+    fn interest_db() -> Database {
+        let db = Database::new(":memory:".to_string()).unwrap();
+        db.insert_user(&User {
+            id: 1,
+            name: "Alice".into(),
+        })
+        .unwrap();
+        db
+    }
+
+    fn add(
+        db: &Database,
+        account_number: i64,
+        account_type: AccountType,
+        day: NaiveDate,
+        cad: i64,
+    ) {
+        db.insert_transaction(&Transaction {
+            user_id: 1,
+            account_number,
+            account_type,
+            transaction_date: day,
+            description_1: format!("ROW {}", cad),
+            cad: Money::cad(cad),
+            ..Transaction::dummy()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_savings_compounding() {
+        let db = interest_db();
+        // $10,000 at 3.65% earns a dollar a day
+        let account = SavingsAccount::new(1, 3003, Money::cad(1000000), 0.0365);
+        db.insert_account(&account).unwrap();
+        add(&db, 3003, AccountType::Savings, date(1, 1), 1000000);
+        let history = networth::account_history(&db, 1, &account).unwrap();
+
+        let monthly = savings_interest(
+            &plan(Compounding::Monthly, 0.0365),
+            &history,
+            date(1, 1),
+            date(3, 15),
+            date(3, 10),
+        );
+        assert_eq!(monthly.len(), 2);
+        assert_eq!(monthly[0].interest, Money::cad(3100));
+        assert!(!monthly[0].projected);
+
+        let daily = savings_interest(
+            &plan(Compounding::Daily, 0.0365),
+            &history,
+            date(1, 1),
+            date(1, 31),
+            date(3, 10),
+        );
+        assert_eq!(daily[0].interest, Money::cad(3105));
+
+        // a projected month earns on the interest projected before it
+        let projected = savings_interest(
+            &plan(Compounding::Monthly, 0.0365),
+            &history,
+            date(3, 1),
+            date(5, 31),
+            date(2, 28),
+        );
+        assert!(projected.iter().all(|p| p.projected));
+        assert_eq!(projected[0].interest, Money::cad(3100));
+        assert_eq!(projected[1].balance, Money::cad(1003100));
+        assert_eq!(projected[1].interest, Money::cad(3009));
+    }
+
+    #[test]
+    fn test_record_savings_interest() {
+        let db = interest_db();
+        db.insert_account(&SavingsAccount::new(1, 3003, Money::cad(1000000), 0.0365))
+            .unwrap();
+        add(&db, 3003, AccountType::Savings, date(1, 1), 1000000);
+        set_plan(&db, 1, 3003, plan(Compounding::Monthly, 0.0365)).unwrap();
+
+        let recorded = record_interest(&db, 1, 3003, date(1, 1), date(3, 31), date(3, 10)).unwrap();
+        let amounts: Vec<i64> = recorded.iter().map(|p| p.interest.minor_units()).collect();
+        // February earns on January's interest too
+        assert_eq!(amounts, vec![3100, 2809]);
+        assert_eq!(
+            db.get_account(&3003).unwrap().balance(),
+            Money::cad(1000000 + 3100 + 2809)
+        );
+
+        // already recorded
+        assert!(
+            record_interest(&db, 1, 3003, date(1, 1), date(3, 31), date(3, 10))
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(db.get_account_transactions(1, 3003).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_card_interest_after_grace_is_lost() {
+        let db = interest_db();
+        db.insert_account(&CreditAccount {
+            interest_rate: 0.1825,
+            ..CreditAccount::new(1, 2002, Money::cad(40000), Money::cad(500000))
+        })
+        .unwrap();
+        let card = |day, cad| add(&db, 2002, AccountType::Credit, day, cad);
+        card(date(1, 1), -100000);
+        // the January statement paid in full on time
+        card(date(2, 1), 100000);
+        card(date(3, 1), -50000);
+        // only part of the March statement paid
+        card(date(3, 20), 10000);
+
+        // without a plan the account's APR applies
+        let flat = estimate(&db, 1, 2002, date(1, 1), date(4, 30), date(4, 30)).unwrap();
+        assert_eq!(flat.plan.tiers[0].rate, 0.1825);
+        assert_eq!(flat.plan.compounding, Compounding::Daily);
+
+        set_plan(&db, 1, 2002, plan(Compounding::Monthly, 0.1825)).unwrap();
+        let estimate = estimate(&db, 1, 2002, date(1, 1), date(5, 31), date(4, 30)).unwrap();
+        let interest: Vec<i64> = estimate
+            .periods
+            .iter()
+            .map(|p| p.interest.minor_units())
+            .collect();
+        // 4 days owing $500 and 27 owing $400 at 0.05% a day, then a
+        // projected cycle with nothing paid
+        assert_eq!(interest, vec![0, 0, 0, 640, 600]);
+        let grace: Vec<bool> = estimate.periods.iter().map(|p| p.grace.unwrap()).collect();
+        assert_eq!(grace, vec![true, true, true, false, false]);
+        assert_eq!(estimate.periods[2].balance, Money::cad(50000));
+        assert!(estimate.periods[4].projected);
+        assert_eq!(estimate.total, Money::cad(1240));
+
+        assert!(matches!(
+            record_interest(&db, 1, 2002, date(1, 1), date(4, 30), date(4, 30)),
+            Err(InterestError::EstimateOnly)
+        ));
+        assert!(matches!(
+            super::estimate(
+                &db,
+                1,
+                2002,
+                date(1, 1),
+                date(1, 1) + Months::new(MAX_MONTHS),
+                date(4, 30)
+            ),
+            Err(InterestError::TooManyMonths)
+        ));
+    }
+}
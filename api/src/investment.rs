@@ -0,0 +1,978 @@
+use core::fmt;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    account::AccountType,
+    database::Database,
+    exchange::{self, ExchangeError},
+    manual::{self, ManualError, NewTransaction},
+    money::{Currency, Money},
+    parser::{self, LineError},
+};
+
+// Quantities closer to zero than this are taken as sold out, fractional shares
+// are stored as floats
+const EPSILON: f64 = 1e-9;
+
+// Most a First Home Savings Account gains in a year, which is also the most
+// unused room it can carry into the next, and over its lifetime
+const FHSA_ANNUAL_LIMIT: i64 = 800000;
+const FHSA_LIFETIME_LIMIT: i64 = 4000000;
+
+// TFSA dollar limit by year as announced by the CRA, in cents
+const TFSA_LIMITS: &[(i32, i64)] = &[
+    (2009, 500000),
+    (2010, 500000),
+    (2011, 500000),
+    (2012, 500000),
+    (2013, 550000),
+    (2014, 550000),
+    (2015, 1000000),
+    (2016, 550000),
+    (2017, 550000),
+    (2018, 550000),
+    (2019, 600000),
+    (2020, 600000),
+    (2021, 600000),
+    (2022, 600000),
+    (2023, 650000),
+    (2024, 700000),
+    (2025, 700000),
+    (2026, 700000),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TradeKind {
+    Buy,
+    Sell,
+    Dividend,
+    // money put into or taken out of the account, counted against a
+    // registered plan's contribution room
+    Contribution,
+    Withdrawal,
+}
+
+#[derive(Debug, Clone)]
+pub struct InvalidTradeKind;
+impl fmt::Display for InvalidTradeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid trade kind")
+    }
+}
+
+impl FromStr for TradeKind {
+    type Err = InvalidTradeKind;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().trim() {
+            "buy" => Ok(TradeKind::Buy),
+            "sell" => Ok(TradeKind::Sell),
+            "dividend" => Ok(TradeKind::Dividend),
+            "contribution" => Ok(TradeKind::Contribution),
+            "withdrawal" => Ok(TradeKind::Withdrawal),
+            _ => Err(InvalidTradeKind),
+        }
+    }
+}
+
+impl fmt::Display for TradeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TradeKind::Buy => write!(f, "Buy"),
+            TradeKind::Sell => write!(f, "Sell"),
+            TradeKind::Dividend => write!(f, "Dividend"),
+            TradeKind::Contribution => write!(f, "Contribution"),
+            TradeKind::Withdrawal => write!(f, "Withdrawal"),
+        }
+    }
+}
+
+impl TradeKind {
+    fn moves_shares(&self) -> bool {
+        matches!(self, TradeKind::Buy | TradeKind::Sell)
+    }
+
+    // Whether the account's cash goes down
+    fn pays_out(&self) -> bool {
+        matches!(self, TradeKind::Buy | TradeKind::Withdrawal)
+    }
+
+    // Only dividends are income, the rest moves the user's own money around
+    // and is filed where reports leave it out
+    fn category(&self) -> &'static str {
+        match self {
+            TradeKind::Dividend => "Income",
+            _ => "Transfers",
+        }
+    }
+}
+
+// A trade as stored, annotating the cash transaction it was paid with
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Trade {
+    pub id: i64,
+    pub account_number: i64,
+    pub transaction_id: i64,
+    pub date: NaiveDate,
+    pub kind: TradeKind,
+    pub symbol: Option<String>,
+    // shares bought or sold, 0 for other kinds
+    pub quantity: f64,
+    // how the trade moved the account's cash, negative for a buy
+    pub amount: Money,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewTrade {
+    pub trade_date: NaiveDate,
+    pub kind: TradeKind,
+    // required for buys and sells, optional for dividends
+    #[serde(default)]
+    pub symbol: Option<String>,
+    #[serde(default)]
+    pub quantity: f64,
+    // cash paid or received including commissions, always positive
+    pub amount: Money,
+    #[serde(default)]
+    pub notes: String,
+}
+
+// Closing price of a security on a day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Price {
+    pub date: NaiveDate,
+    pub symbol: String,
+    pub price: f64,
+    pub currency: Currency,
+}
+
+// Shares of one security held in one account, at their average cost in the
+// account's currency
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Position {
+    pub account_number: i64,
+    pub symbol: String,
+    pub quantity: f64,
+    pub cost_basis: Money,
+}
+
+// A position valued at the latest known price
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Holding {
+    #[serde(flatten)]
+    pub position: Position,
+    // None when the price file has nothing for the symbol yet
+    pub price: Option<Price>,
+    // in the price's currency, the cost basis without a price
+    pub market_value: Money,
+    // market value less cost basis, in the account's currency
+    pub gain: Money,
+}
+
+// Room in one registered plan for one year, all in CAD
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RoomYear {
+    pub year: i32,
+    // room on January 1
+    pub available: Money,
+    // whether `available` is the figure the CRA reported rather than worked out
+    pub reported: bool,
+    pub contributed: Money,
+    pub withdrawn: Money,
+    // negative when over-contributed
+    pub remaining: Money,
+}
+
+#[derive(Debug)]
+pub enum InvestmentError {
+    AccountNotFound,
+    NotAnInvestmentAccount,
+    NotRegistered,
+    MissingSymbol,
+    InvalidQuantity,
+    InvalidAmount,
+    NotEnoughShares { symbol: String, held: f64 },
+    MissingHeader,
+    InvalidRow(LineError),
+    Manual(ManualError),
+    Exchange(ExchangeError),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for InvestmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvestmentError::AccountNotFound => write!(f, "Account not found"),
+            InvestmentError::NotAnInvestmentAccount => {
+                write!(f, "Trades can only be recorded in investment accounts")
+            }
+            InvestmentError::NotRegistered => {
+                write!(
+                    f,
+                    "Contribution room is only tracked for TFSA, RRSP and FHSA"
+                )
+            }
+            InvestmentError::MissingSymbol => write!(f, "Buys and sells need a symbol"),
+            InvestmentError::InvalidQuantity => {
+                write!(f, "Buys and sells need a positive quantity")
+            }
+            InvestmentError::InvalidAmount => write!(f, "Amount must be positive"),
+            InvestmentError::NotEnoughShares { symbol, held } => {
+                write!(f, "Only {} shares of {} are held", held, symbol)
+            }
+            InvestmentError::MissingHeader => write!(
+                f,
+                "Expected a header with date, symbol and price (or close) columns"
+            ),
+            InvestmentError::InvalidRow(err) => write!(f, "Invalid price on {}", err),
+            InvestmentError::Manual(err) => write!(f, "{}", err),
+            InvestmentError::Exchange(err) => write!(f, "{}", err),
+            InvestmentError::Database(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for InvestmentError {}
+
+impl From<rusqlite::Error> for InvestmentError {
+    fn from(err: rusqlite::Error) -> Self {
+        InvestmentError::Database(err)
+    }
+}
+
+impl From<ManualError> for InvestmentError {
+    fn from(err: ManualError) -> Self {
+        InvestmentError::Manual(err)
+    }
+}
+
+impl From<ExchangeError> for InvestmentError {
+    fn from(err: ExchangeError) -> Self {
+        InvestmentError::Exchange(err)
+    }
+}
+
+// Imported prices held in memory, each symbol sorted by date
+#[derive(Debug, Clone, Default)]
+pub struct Prices {
+    prices: HashMap<String, Vec<Price>>,
+}
+
+impl Prices {
+    pub fn new(prices: Vec<Price>) -> Prices {
+        let mut symbols: HashMap<String, Vec<Price>> = HashMap::new();
+        for price in prices {
+            symbols.entry(price.symbol.clone()).or_default().push(price);
+        }
+        for series in symbols.values_mut() {
+            series.sort_by_key(|price| price.date);
+        }
+        Prices { prices: symbols }
+    }
+
+    // The latest price on or before `date`
+    pub fn price_on(&self, symbol: &str, date: NaiveDate) -> Option<&Price> {
+        self.prices
+            .get(symbol)?
+            .iter()
+            .rev()
+            .find(|price| price.date <= date)
+    }
+}
+
+impl Position {
+    // At the latest price on or before `date`, or at cost until the symbol has one
+    pub fn market_value(&self, prices: &Prices, date: NaiveDate) -> Money {
+        match prices.price_on(&self.symbol, date) {
+            Some(price) => Money::from_minor(
+                (self.quantity * price.price * 100.0).round() as i64,
+                price.currency,
+            ),
+            None => self.cost_basis,
+        }
+    }
+}
+
+// Everything needed to value a user's holdings on any day
+#[derive(Debug, Clone, Default)]
+pub struct Portfolio {
+    // oldest first
+    trades: Vec<Trade>,
+    prices: Prices,
+}
+
+impl Portfolio {
+    pub fn load(db: &Database, user_id: i64) -> Result<Portfolio, rusqlite::Error> {
+        Ok(Portfolio {
+            trades: db.get_trades(user_id)?,
+            prices: Prices::new(db.get_prices(user_id)?),
+        })
+    }
+
+    // Market value of each position held at the end of `date`
+    pub fn values_on(&self, date: NaiveDate) -> Vec<Money> {
+        positions(&self.trades, date)
+            .iter()
+            .map(|position| position.market_value(&self.prices, date))
+            .collect()
+    }
+}
+
+fn normalize_symbol(symbol: &str) -> String {
+    symbol.trim().to_uppercase()
+}
+
+// Shares held at the end of `date` by account and symbol, from trades oldest
+// first. Buys add their cost to the cost basis and sells take out the average
+// cost of the shares sold.
+pub fn positions(trades: &[Trade], date: NaiveDate) -> Vec<Position> {
+    let mut positions: Vec<Position> = Vec::new();
+    for trade in trades.iter().filter(|t| t.date <= date) {
+        let Some(symbol) = trade
+            .symbol
+            .as_deref()
+            .filter(|_| trade.kind.moves_shares())
+        else {
+            continue;
+        };
+        let idx = match positions
+            .iter()
+            .position(|p| p.account_number == trade.account_number && p.symbol == symbol)
+        {
+            Some(idx) => idx,
+            None => {
+                positions.push(Position {
+                    account_number: trade.account_number,
+                    symbol: symbol.to_string(),
+                    quantity: 0.0,
+                    cost_basis: Money::zero(trade.amount.currency()),
+                });
+                positions.len() - 1
+            }
+        };
+        let position = &mut positions[idx];
+        match trade.kind {
+            TradeKind::Buy => {
+                position.quantity += trade.quantity;
+                position.cost_basis += trade.amount.abs();
+            }
+            TradeKind::Sell if position.quantity > EPSILON => {
+                let sold = (trade.quantity / position.quantity).min(1.0);
+                let cost = (position.cost_basis.minor_units() as f64 * sold).round() as i64;
+                position.quantity -= trade.quantity;
+                position.cost_basis -= Money::from_minor(cost, position.cost_basis.currency());
+            }
+            _ => {}
+        }
+    }
+    positions.retain(|p| p.quantity > EPSILON);
+    positions
+}
+
+// Records a trade in one of the user's investment accounts along with the cash
+// transaction it moved, which keeps the account's balance and history in step
+pub fn record_trade(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+    new: NewTrade,
+) -> Result<Trade, InvestmentError> {
+    let account = db
+        .get_accounts_by_user(user_id)?
+        .into_iter()
+        .find(|a| *a.account_number() == account_number)
+        .ok_or(InvestmentError::AccountNotFound)?;
+    if !account.account_type().is_investment() {
+        return Err(InvestmentError::NotAnInvestmentAccount);
+    }
+    if !new.amount.is_positive() {
+        return Err(InvestmentError::InvalidAmount);
+    }
+    let symbol = new
+        .symbol
+        .as_deref()
+        .map(normalize_symbol)
+        .filter(|symbol| !symbol.is_empty());
+    let (symbol, quantity) = match new.kind {
+        TradeKind::Buy | TradeKind::Sell => {
+            let symbol = symbol.ok_or(InvestmentError::MissingSymbol)?;
+            if !new.quantity.is_finite() || new.quantity <= 0.0 {
+                return Err(InvestmentError::InvalidQuantity);
+            }
+            (Some(symbol), new.quantity)
+        }
+        TradeKind::Dividend => (symbol, 0.0),
+        TradeKind::Contribution | TradeKind::Withdrawal => (None, 0.0),
+    };
+
+    if let (TradeKind::Sell, Some(symbol)) = (new.kind, &symbol) {
+        // a sale has to be covered on its day and must not uncover a later one
+        let trades: Vec<Trade> = db
+            .get_trades(user_id)?
+            .into_iter()
+            .filter(|t| t.account_number == account_number)
+            .collect();
+        let held = |date: NaiveDate| {
+            positions(&trades, date)
+                .iter()
+                .find(|p| p.symbol == *symbol)
+                .map(|p| p.quantity)
+                .unwrap_or(0.0)
+        };
+        let held = held(new.trade_date).min(held(NaiveDate::MAX));
+        if held + EPSILON < quantity {
+            return Err(InvestmentError::NotEnoughShares {
+                symbol: symbol.clone(),
+                held,
+            });
+        }
+    }
+
+    let description = match (&new.kind, &symbol) {
+        (TradeKind::Buy | TradeKind::Sell, Some(symbol)) => {
+            format!(
+                "{} {} {}",
+                new.kind.to_string().to_uppercase(),
+                quantity,
+                symbol
+            )
+        }
+        (_, Some(symbol)) => format!("{} {}", new.kind.to_string().to_uppercase(), symbol),
+        (_, None) => new.kind.to_string().to_uppercase(),
+    };
+    let amount = if new.kind.pays_out() {
+        -new.amount
+    } else {
+        new.amount
+    };
+    let transaction = manual::add_transaction(
+        db,
+        user_id,
+        NewTransaction {
+            account_number,
+            transaction_date: new.trade_date,
+            description,
+            amount,
+            notes: new.notes,
+            category_id: None,
+            category: Some(new.kind.category().to_string()),
+        },
+    )?;
+    let id = db.insert_trade(
+        user_id,
+        account_number,
+        transaction.id,
+        new.kind,
+        symbol.as_deref(),
+        quantity,
+    )?;
+    Ok(Trade {
+        id,
+        account_number,
+        transaction_id: transaction.id,
+        date: new.trade_date,
+        kind: new.kind,
+        symbol,
+        quantity,
+        amount,
+    })
+}
+
+// The user's positions at the end of `date` valued at the latest prices, with
+// the gain converted at that day's rate
+pub fn holdings(
+    db: &Database,
+    user_id: i64,
+    account_number: Option<i64>,
+    date: NaiveDate,
+) -> Result<Vec<Holding>, InvestmentError> {
+    let portfolio = Portfolio::load(db, user_id)?;
+    let rates = exchange::load_rates(db, user_id)?;
+    positions(&portfolio.trades, date)
+        .into_iter()
+        .filter(|p| account_number.is_none_or(|n| p.account_number == n))
+        .map(|position| {
+            let market_value = position.market_value(&portfolio.prices, date);
+            let currency = position.cost_basis.currency();
+            let converted =
+                rates
+                    .convert(market_value, currency, date)
+                    .ok_or(ExchangeError::MissingRate {
+                        from: market_value.currency(),
+                        to: currency,
+                    })?;
+            Ok(Holding {
+                price: portfolio.prices.price_on(&position.symbol, date).cloned(),
+                market_value,
+                gain: converted - position.cost_basis,
+                position,
+            })
+        })
+        .collect()
+}
+
+fn invalid(line: usize, message: String) -> InvestmentError {
+    InvestmentError::InvalidRow(LineError {
+        line,
+        error: parser::ParseError::InvalidFormat(message),
+    })
+}
+
+// Reads a price file with date, symbol and price columns in any order. A
+// `close` column stands in for `price`, so a broker's or charting site's
+// history export works once a symbol column is added, and a `currency` column
+// is optional, CAD without it. Rows without a price are skipped.
+pub fn parse_prices(input: &str) -> Result<Vec<Price>, InvestmentError> {
+    let mut records = parser::parse_csv(input).into_iter();
+    let header: Vec<String> = match records.next() {
+        Some(Ok(record)) => record
+            .fields
+            .iter()
+            .map(|f| f.trim().to_lowercase())
+            .collect(),
+        _ => return Err(InvestmentError::MissingHeader),
+    };
+    let column = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+    let (Some(date_col), Some(symbol_col), Some(price_col)) = (
+        column(&["date"]),
+        column(&["symbol", "ticker"]),
+        column(&["price", "close"]),
+    ) else {
+        return Err(InvestmentError::MissingHeader);
+    };
+    let currency_col = column(&["currency"]);
+
+    let mut prices = Vec::new();
+    for record in records {
+        let record = record.map_err(InvestmentError::InvalidRow)?;
+        let line = record.line;
+        let field = |i: usize| record.fields.get(i).map(|f| f.trim()).unwrap_or("");
+        if field(price_col).is_empty() {
+            continue;
+        }
+        let date = NaiveDate::parse_from_str(field(date_col), "%Y-%m-%d")
+            .map_err(|_| invalid(line, format!("unrecognized date {:?}", field(date_col))))?;
+        let symbol = normalize_symbol(field(symbol_col));
+        if symbol.is_empty() {
+            return Err(invalid(line, "missing symbol".to_string()));
+        }
+        let price: f64 = field(price_col)
+            .trim_start_matches('$')
+            .parse()
+            .ok()
+            .filter(|price: &f64| price.is_finite() && *price >= 0.0)
+            .ok_or_else(|| invalid(line, format!("invalid price {:?}", field(price_col))))?;
+        let currency = match currency_col.map(field).filter(|c| !c.is_empty()) {
+            Some(code) => code.parse().map_err(|e| invalid(line, format!("{}", e)))?,
+            None => Currency::CAD,
+        };
+        prices.push(Price {
+            date,
+            symbol,
+            price,
+            currency,
+        });
+    }
+    Ok(prices)
+}
+
+// Stores the prices in a user's CSV upload, replacing any they already have for
+// the same symbol and day. Returns how many were read.
+pub fn import_prices(db: &Database, user_id: i64, input: &str) -> Result<usize, InvestmentError> {
+    let prices = parse_prices(input)?;
+    db.insert_prices(user_id, &prices)?;
+    Ok(prices.len())
+}
+
+// New room a plan gains on January 1 of `year` when the CRA's figure is not
+// known. RRSP room depends on the previous year's income, so it has none.
+fn annual_limit(account_type: AccountType, year: i32) -> Money {
+    let cents = match account_type {
+        AccountType::Tfsa => TFSA_LIMITS
+            .iter()
+            .find(|(y, _)| *y == year)
+            .map(|(_, limit)| *limit)
+            .unwrap_or(0),
+        AccountType::Fhsa if year >= 2023 => FHSA_ANNUAL_LIMIT,
+        _ => 0,
+    };
+    Money::cad(cents)
+}
+
+// Contribution room of a registered plan for each year from the first one the
+// user has an account, a contribution or a reported figure for, through
+// `through`. A reported figure is taken as the room on January 1, every other
+// year carries on from the one before: unused room carries forward (at most a
+// year's limit for an FHSA), TFSA withdrawals come back the next year, and the
+// year's new room is added. Contributions in every account of the plan's type
+// count, converted into CAD on their date.
+pub fn room(
+    db: &Database,
+    user_id: i64,
+    account_type: AccountType,
+    through: i32,
+) -> Result<Vec<RoomYear>, InvestmentError> {
+    if !account_type.is_registered() {
+        return Err(InvestmentError::NotRegistered);
+    }
+    let reported: HashMap<i32, Money> = db
+        .get_contribution_room(user_id, account_type)?
+        .into_iter()
+        .collect();
+    let periods = db.get_account_periods(user_id)?;
+    let accounts: Vec<i64> = db
+        .get_accounts_by_user(user_id)?
+        .iter()
+        .filter(|a| a.account_type() == account_type)
+        .map(|a| *a.account_number())
+        .collect();
+    let rates = exchange::load_rates(db, user_id)?;
+
+    let mut flows: HashMap<i32, (Money, Money)> = HashMap::new();
+    for trade in db.get_trades(user_id)? {
+        if !accounts.contains(&trade.account_number)
+            || !matches!(trade.kind, TradeKind::Contribution | TradeKind::Withdrawal)
+        {
+            continue;
+        }
+        let amount = rates
+            .convert(trade.amount.abs(), Currency::CAD, trade.date)
+            .ok_or(ExchangeError::MissingRate {
+                from: trade.amount.currency(),
+                to: Currency::CAD,
+            })?;
+        let (contributed, withdrawn) = flows
+            .entry(trade.date.year())
+            .or_insert((Money::cad(0), Money::cad(0)));
+        match trade.kind {
+            TradeKind::Contribution => *contributed += amount,
+            _ => *withdrawn += amount,
+        }
+    }
+
+    let opened = accounts
+        .iter()
+        .filter_map(|n| periods.get(n).and_then(|p| p.opened_on))
+        .map(|date| date.year());
+    let Some(start) = reported
+        .keys()
+        .copied()
+        .chain(flows.keys().copied())
+        .chain(opened)
+        .min()
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut years = Vec::new();
+    let mut carried = Money::cad(0);
+    let mut granted = Money::cad(0);
+    for year in start..=through {
+        let (available, is_reported) = match reported.get(&year) {
+            Some(room) => (*room, true),
+            None => {
+                let mut limit = annual_limit(account_type, year);
+                if account_type == AccountType::Fhsa {
+                    let left = Money::cad(FHSA_LIFETIME_LIMIT) - granted;
+                    if limit.minor_units() > left.minor_units() {
+                        limit = left;
+                    }
+                }
+                granted += limit;
+                (carried + limit, false)
+            }
+        };
+        let (contributed, withdrawn) = flows
+            .get(&year)
+            .copied()
+            .unwrap_or((Money::cad(0), Money::cad(0)));
+        let remaining = available - contributed;
+        carried = match account_type {
+            AccountType::Tfsa => remaining + withdrawn,
+            AccountType::Fhsa if remaining.minor_units() > FHSA_ANNUAL_LIMIT => {
+                Money::cad(FHSA_ANNUAL_LIMIT)
+            }
+            _ => remaining,
+        };
+        years.push(RoomYear {
+            year,
+            available,
+            reported: is_reported,
+            contributed,
+            withdrawn,
+            remaining,
+        });
+    }
+    Ok(years)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manual::NewAccount;
+    use crate::user::User;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn setup_test_db() -> Database {
+        let db = Database::new(":memory:".to_string()).unwrap();
+        db.insert_user(&User {
+            id: 1,
+            name: "Alice".into(),
+        })
+        .unwrap();
+        db
+    }
+
+    fn open(db: &Database, account_type: AccountType, opened_on: NaiveDate) -> i64 {
+        manual::create_account(
+            db,
+            1,
+            NewAccount {
+                account_type,
+                nickname: account_type.to_string(),
+                institution: Some("Wealthsimple".into()),
+                opened_on,
+                opening_balance: Money::cad(0),
+                credit_limit: None,
+                interest_rate: 0.0,
+                loan: None,
+            },
+        )
+        .unwrap()
+    }
+
+    fn trade(
+        kind: TradeKind,
+        trade_date: NaiveDate,
+        symbol: Option<&str>,
+        quantity: f64,
+        cents: i64,
+    ) -> NewTrade {
+        NewTrade {
+            trade_date,
+            kind,
+            symbol: symbol.map(str::to_string),
+            quantity,
+            amount: Money::cad(cents),
+            notes: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_trades_build_positions_at_average_cost() {
+        let db = setup_test_db();
+        let tfsa = open(&db, AccountType::Tfsa, date(2024, 1, 2));
+        for new in [
+            trade(TradeKind::Contribution, date(2024, 1, 5), None, 0.0, 700000),
+            trade(
+                TradeKind::Buy,
+                date(2024, 1, 8),
+                Some("xeqt"),
+                100.0,
+                300000,
+            ),
+            trade(TradeKind::Buy, date(2024, 3, 8), Some("XEQT"), 50.0, 180000),
+            trade(
+                TradeKind::Sell,
+                date(2024, 6, 3),
+                Some("XEQT"),
+                30.0,
+                110000,
+            ),
+            trade(
+                TradeKind::Dividend,
+                date(2024, 12, 30),
+                Some("XEQT"),
+                0.0,
+                4321,
+            ),
+        ] {
+            record_trade(&db, 1, tfsa, new).unwrap();
+        }
+
+        let held = positions(&db.get_trades(1).unwrap(), date(2024, 12, 31));
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].symbol, "XEQT");
+        assert!((held[0].quantity - 120.0).abs() < EPSILON);
+        // 480000 for 150 shares, a fifth of them sold
+        assert_eq!(held[0].cost_basis, Money::cad(384000));
+
+        // cash followed every trade
+        let account = db.get_accounts_by_user(1).unwrap().remove(0);
+        assert_eq!(
+            account.balance(),
+            Money::cad(700000 - 300000 - 180000 + 110000 + 4321)
+        );
+        // and only the dividend shows up as income in reports
+        let transactions = db.get_transactions(1).unwrap();
+        assert_eq!(transactions[4].category, "Income");
+        assert!(transactions[..4].iter().all(|t| t.category == "Transfers"));
+
+        assert!(matches!(
+            record_trade(
+                &db,
+                1,
+                tfsa,
+                trade(TradeKind::Sell, date(2024, 5, 1), Some("XEQT"), 130.0, 1)
+            ),
+            Err(InvestmentError::NotEnoughShares { .. })
+        ));
+        assert!(matches!(
+            record_trade(
+                &db,
+                1,
+                tfsa,
+                trade(TradeKind::Buy, date(2024, 5, 1), None, 1.0, 100)
+            ),
+            Err(InvestmentError::MissingSymbol)
+        ));
+    }
+
+    #[test]
+    fn test_only_investment_accounts_take_trades() {
+        let db = setup_test_db();
+        let chequing = open(&db, AccountType::Chequing, date(2024, 1, 2));
+        assert!(matches!(
+            record_trade(
+                &db,
+                1,
+                chequing,
+                trade(TradeKind::Contribution, date(2024, 1, 5), None, 0.0, 100)
+            ),
+            Err(InvestmentError::NotAnInvestmentAccount)
+        ));
+    }
+
+    #[test]
+    fn test_holdings_valued_from_price_file() {
+        let db = setup_test_db();
+        let account = open(&db, AccountType::NonRegistered, date(2024, 1, 2));
+        record_trade(
+            &db,
+            1,
+            account,
+            trade(TradeKind::Buy, date(2024, 1, 8), Some("VFV"), 10.0, 120000),
+        )
+        .unwrap();
+
+        // at cost until a price is known
+        let before = holdings(&db, 1, None, date(2024, 1, 31)).unwrap();
+        assert_eq!(before[0].market_value, Money::cad(120000));
+        assert_eq!(before[0].price, None);
+
+        let imported = import_prices(
+            &db,
+            1,
+            "Date,Symbol,Open,Close\n2024-02-01,vfv,120.00,125.505\n2024-03-01,VFV,126,\n",
+        )
+        .unwrap();
+        assert_eq!(imported, 1);
+        let after = holdings(&db, 1, Some(account), date(2024, 3, 15)).unwrap();
+        assert_eq!(after[0].market_value, Money::cad(125505));
+        assert_eq!(after[0].gain, Money::cad(5505));
+        assert_eq!(after[0].price.as_ref().unwrap().date, date(2024, 2, 1));
+        // prices are the user's own
+        db.insert_user(&User {
+            id: 2,
+            name: "Bob".into(),
+        })
+        .unwrap();
+        import_prices(&db, 2, "date,symbol,price\n2024-03-01,VFV,1\n").unwrap();
+        let after = holdings(&db, 1, Some(account), date(2024, 3, 15)).unwrap();
+        assert_eq!(after[0].market_value, Money::cad(125505));
+        assert!(holdings(&db, 1, Some(account + 1), date(2024, 3, 15))
+            .unwrap()
+            .is_empty());
+
+        assert!(matches!(
+            parse_prices("when,what\n"),
+            Err(InvestmentError::MissingHeader)
+        ));
+        match parse_prices("date,symbol,price\n2024-02-01,VFV,-1\n") {
+            Err(InvestmentError::InvalidRow(err)) => assert_eq!(err.line, 2),
+            other => panic!("expected an invalid row, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tfsa_room_carries_forward_and_restores_withdrawals() {
+        let db = setup_test_db();
+        let tfsa = open(&db, AccountType::Tfsa, date(2024, 1, 2));
+        db.set_contribution_room(1, AccountType::Tfsa, 2024, Money::cad(1500000))
+            .unwrap();
+        for new in [
+            trade(
+                TradeKind::Contribution,
+                date(2024, 2, 1),
+                None,
+                0.0,
+                1000000,
+            ),
+            trade(TradeKind::Withdrawal, date(2024, 9, 1), None, 0.0, 200000),
+            trade(
+                TradeKind::Contribution,
+                date(2025, 3, 1),
+                None,
+                0.0,
+                1500000,
+            ),
+        ] {
+            record_trade(&db, 1, tfsa, new).unwrap();
+        }
+
+        let years = room(&db, 1, AccountType::Tfsa, 2026).unwrap();
+        let summary: Vec<(i32, i64, i64)> = years
+            .iter()
+            .map(|y| (y.year, y.available.minor_units(), y.remaining.minor_units()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (2024, 1500000, 500000),
+                // 5,000 unused, 2,000 withdrawn and the 7,000 limit
+                (2025, 1400000, -100000),
+                (2026, 600000, 600000),
+            ]
+        );
+        assert!(years[0].reported);
+        assert!(!years[1].reported);
+
+        assert!(matches!(
+            room(&db, 1, AccountType::NonRegistered, 2026),
+            Err(InvestmentError::NotRegistered)
+        ));
+    }
+
+    #[test]
+    fn test_fhsa_carry_forward_is_capped() {
+        let db = setup_test_db();
+        let fhsa = open(&db, AccountType::Fhsa, date(2023, 6, 1));
+        record_trade(
+            &db,
+            1,
+            fhsa,
+            trade(
+                TradeKind::Contribution,
+                date(2025, 1, 10),
+                None,
+                0.0,
+                1000000,
+            ),
+        )
+        .unwrap();
+
+        let available: Vec<i64> = room(&db, 1, AccountType::Fhsa, 2026)
+            .unwrap()
+            .iter()
+            .map(|y| y.available.minor_units())
+            .collect();
+        // nothing used in 2023, but only 8,000 of it carries into 2024
+        assert_eq!(available, vec![800000, 1600000, 1600000, 1400000]);
+    }
+}
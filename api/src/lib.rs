@@ -1,15 +1,26 @@
 pub mod account;
 pub mod app;
+pub mod auth;
+pub mod budget;
+pub mod category;
 pub mod catergorization;
 pub mod database;
+pub mod error;
+pub mod exchange;
+pub mod importer;
+pub mod interest;
+pub mod investment;
+pub mod loan;
+pub mod manual;
+pub mod merchant;
+pub mod migrations;
+pub mod money;
+pub mod networth;
 pub mod parser;
+pub mod reconcile;
+pub mod recurring;
+pub mod report;
+pub mod search;
 pub mod transaction;
+pub mod transfer;
 pub mod user;
-
-use std::hash::{DefaultHasher, Hash, Hasher};
-
-fn calculate_hash<T: Hash>(t: &T) -> i64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s);
-    (s.finish() & 0x0000_FFFF_FFFF_FFFF) as i64
-}
@@ -0,0 +1,831 @@
+use core::fmt;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use chrono::{Days, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    account::{Account, BankAccount, LoanAccount},
+    database::Database,
+    money::{Currency, Money},
+};
+
+// Days a payment may post before or after the day it is due, a payment due on
+// a weekend or holiday comes out on the next business day
+pub const PAYMENT_WINDOW_DAYS: i64 = 3;
+
+// Longest amortization accepted, 50 years
+const MAX_AMORTIZATION_MONTHS: u32 = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PaymentFrequency {
+    #[default]
+    Monthly,
+    // twice a month, the second payment 15 days after the first
+    SemiMonthly,
+    BiWeekly,
+    Weekly,
+    // every two weeks, half the monthly payment, which adds up to one extra
+    // monthly payment a year
+    AcceleratedBiWeekly,
+    // every week, a quarter of the monthly payment
+    AcceleratedWeekly,
+}
+
+#[derive(Debug, Clone)]
+pub struct InvalidFrequency(String);
+impl fmt::Display for InvalidFrequency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid payment frequency: {}", self.0)
+    }
+}
+
+impl FromStr for PaymentFrequency {
+    type Err = InvalidFrequency;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "monthly" => Ok(PaymentFrequency::Monthly),
+            "semi-monthly" => Ok(PaymentFrequency::SemiMonthly),
+            "bi-weekly" => Ok(PaymentFrequency::BiWeekly),
+            "weekly" => Ok(PaymentFrequency::Weekly),
+            "accelerated-bi-weekly" => Ok(PaymentFrequency::AcceleratedBiWeekly),
+            "accelerated-weekly" => Ok(PaymentFrequency::AcceleratedWeekly),
+            _ => Err(InvalidFrequency(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for PaymentFrequency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaymentFrequency::Monthly => write!(f, "monthly"),
+            PaymentFrequency::SemiMonthly => write!(f, "semi-monthly"),
+            PaymentFrequency::BiWeekly => write!(f, "bi-weekly"),
+            PaymentFrequency::Weekly => write!(f, "weekly"),
+            PaymentFrequency::AcceleratedBiWeekly => write!(f, "accelerated-bi-weekly"),
+            PaymentFrequency::AcceleratedWeekly => write!(f, "accelerated-weekly"),
+        }
+    }
+}
+
+impl PaymentFrequency {
+    pub fn payments_per_year(&self) -> u32 {
+        match self {
+            PaymentFrequency::Monthly => 12,
+            PaymentFrequency::SemiMonthly => 24,
+            PaymentFrequency::BiWeekly | PaymentFrequency::AcceleratedBiWeekly => 26,
+            PaymentFrequency::Weekly | PaymentFrequency::AcceleratedWeekly => 52,
+        }
+    }
+
+    // Day the payment `index` places after `first` is due
+    pub fn due_date(&self, first: NaiveDate, index: u32) -> NaiveDate {
+        match self {
+            PaymentFrequency::Monthly => first + Months::new(index),
+            PaymentFrequency::SemiMonthly => {
+                let date = first + Months::new(index / 2);
+                if index % 2 == 1 {
+                    date + Days::new(15)
+                } else {
+                    date
+                }
+            }
+            PaymentFrequency::BiWeekly | PaymentFrequency::AcceleratedBiWeekly => {
+                first + Days::new(14 * index as u64)
+            }
+            PaymentFrequency::Weekly | PaymentFrequency::AcceleratedWeekly => {
+                first + Days::new(7 * index as u64)
+            }
+        }
+    }
+}
+
+// How often interest is added to the balance. The Interest Act has fixed-rate
+// Canadian mortgages compound semi-annually, most other loans compound monthly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoanCompounding {
+    #[default]
+    SemiAnnual,
+    Monthly,
+}
+
+#[derive(Debug, Clone)]
+pub struct InvalidLoanCompounding(String);
+impl fmt::Display for InvalidLoanCompounding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid compounding: {}", self.0)
+    }
+}
+
+impl FromStr for LoanCompounding {
+    type Err = InvalidLoanCompounding;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "semi-annual" => Ok(LoanCompounding::SemiAnnual),
+            "monthly" => Ok(LoanCompounding::Monthly),
+            _ => Err(InvalidLoanCompounding(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for LoanCompounding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoanCompounding::SemiAnnual => write!(f, "semi-annual"),
+            LoanCompounding::Monthly => write!(f, "monthly"),
+        }
+    }
+}
+
+impl LoanCompounding {
+    pub fn periods_per_year(&self) -> u32 {
+        match self {
+            LoanCompounding::SemiAnnual => 2,
+            LoanCompounding::Monthly => 12,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LoanTerms {
+    // amount borrowed, also sets the loan's currency
+    pub principal: Money,
+    // yearly nominal rate as a fraction, 0.0479 for 4.79%
+    pub rate: f64,
+    // time to pay the loan off at the regular payment
+    pub amortization_months: u32,
+    // time the rate is fixed for, after which the loan is renewed
+    #[serde(default)]
+    pub term_months: Option<u32>,
+    #[serde(default)]
+    pub frequency: PaymentFrequency,
+    #[serde(default)]
+    pub compounding: LoanCompounding,
+    pub first_payment: NaiveDate,
+    // the payment the lender set, worked out from the rest when missing
+    #[serde(default)]
+    pub payment: Option<Money>,
+}
+
+// One payment of an amortization schedule
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Installment {
+    // counted from 1
+    pub number: u32,
+    pub date: NaiveDate,
+    pub payment: Money,
+    pub interest: Money,
+    pub principal: Money,
+    // lump sums paid since the previous installment, see `Prepayment`
+    pub prepayment: Money,
+    // owed after the payment and prepayment
+    pub balance: Money,
+    // the payment matched to this installment, see `match_payments`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<i64>,
+}
+
+// A lump sum paid on top of the regular payments, which goes entirely to the
+// principal with the next installment
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Prepayment {
+    pub date: NaiveDate,
+    pub amount: Money,
+    // paid again on the same day every year until the loan is paid off
+    #[serde(default)]
+    pub yearly: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ScheduleSummary {
+    pub payment: Money,
+    pub payments: usize,
+    pub payoff_date: NaiveDate,
+    pub total_interest: Money,
+    // owed when the term ends and the loan comes up for renewal
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance_at_term_end: Option<Money>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Amortization {
+    pub account_number: i64,
+    pub terms: LoanTerms,
+    pub summary: ScheduleSummary,
+    pub schedule: Vec<Installment>,
+}
+
+// The schedule with the prepayments against the one without
+#[derive(Debug, Clone, Serialize)]
+pub struct WhatIf {
+    pub account_number: i64,
+    pub without_prepayments: ScheduleSummary,
+    pub with_prepayments: ScheduleSummary,
+    pub interest_saved: Money,
+    pub payments_saved: usize,
+    pub schedule: Vec<Installment>,
+}
+
+// A payment matched to an installment, split the way the schedule splits it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LoanPayment {
+    pub transaction_id: i64,
+    pub account_number: i64,
+    pub installment: u32,
+    // day the payment posted
+    pub date: NaiveDate,
+    pub principal: Money,
+    pub interest: Money,
+}
+
+#[derive(Debug)]
+pub enum LoanError {
+    AccountNotFound,
+    NotALoan,
+    NoTerms,
+    InvalidTerms(String),
+    InvalidPrepayment,
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for LoanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoanError::AccountNotFound => write!(f, "Account not found"),
+            LoanError::NotALoan => write!(f, "Account is not a loan"),
+            LoanError::NoTerms => write!(f, "Loan terms have not been entered"),
+            LoanError::InvalidTerms(msg) => write!(f, "Invalid loan terms: {}", msg),
+            LoanError::InvalidPrepayment => {
+                write!(f, "Prepayments must be positive and in the loan's currency")
+            }
+            LoanError::Database(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for LoanError {}
+
+impl From<rusqlite::Error> for LoanError {
+    fn from(err: rusqlite::Error) -> Self {
+        LoanError::Database(err)
+    }
+}
+
+impl LoanTerms {
+    pub fn currency(&self) -> Currency {
+        self.principal.currency()
+    }
+
+    // Interest charged on the balance at each payment. The nominal rate is
+    // compounded over its own periods and spread evenly over the payments.
+    pub fn periodic_rate(&self) -> f64 {
+        let compounding = self.compounding.periods_per_year() as f64;
+        let payments = self.frequency.payments_per_year() as f64;
+        (1.0 + self.rate / compounding).powf(compounding / payments) - 1.0
+    }
+
+    // Payments in the amortization at the regular payment
+    pub fn payment_count(&self) -> u32 {
+        (self.amortization_months * self.frequency.payments_per_year()).div_ceil(12)
+    }
+
+    // Payments in the longest amortization accepted, a schedule never runs
+    // past them
+    pub fn max_payment_count(&self) -> u32 {
+        (MAX_AMORTIZATION_MONTHS * self.frequency.payments_per_year()).div_ceil(12)
+    }
+
+    // The payment the lender set or else the one that pays the loan off over
+    // the amortization, rounded to the cent. An accelerated payment is a share
+    // of the monthly one.
+    pub fn regular_payment(&self) -> Money {
+        if let Some(payment) = self.payment {
+            return payment;
+        }
+        let share = match self.frequency {
+            PaymentFrequency::AcceleratedBiWeekly => 2,
+            PaymentFrequency::AcceleratedWeekly => 4,
+            _ => {
+                return Money::from_minor(
+                    annuity(
+                        self.principal.minor_units(),
+                        self.periodic_rate(),
+                        self.payment_count(),
+                    ),
+                    self.currency(),
+                );
+            }
+        };
+        let monthly = LoanTerms {
+            frequency: PaymentFrequency::Monthly,
+            ..*self
+        }
+        .regular_payment();
+        Money::from_minor((monthly.minor_units() + share / 2) / share, self.currency())
+    }
+
+    pub fn validate(&self) -> Result<(), LoanError> {
+        let invalid = |msg: &str| Err(LoanError::InvalidTerms(msg.to_string()));
+        if !self.principal.is_positive() {
+            return invalid("principal must be positive");
+        }
+        if !(0.0..=1.0).contains(&self.rate) {
+            return invalid("rate must be between 0 and 1");
+        }
+        if self.amortization_months == 0 || self.amortization_months > MAX_AMORTIZATION_MONTHS {
+            return invalid("amortization must be between 1 and 600 months");
+        }
+        if self
+            .term_months
+            .is_some_and(|term| term == 0 || term > self.amortization_months)
+        {
+            return invalid("term must be between 1 month and the amortization");
+        }
+        if let Some(payment) = self.payment {
+            if payment.currency() != self.currency() {
+                return invalid("payment must be in the principal's currency");
+            }
+            // otherwise the balance never goes down
+            let interest =
+                (self.principal.minor_units() as f64 * self.periodic_rate()).round() as i64;
+            if payment.minor_units() <= interest {
+                return invalid("payment does not cover the interest");
+            }
+            let fastest = annuity(
+                self.principal.minor_units(),
+                self.periodic_rate(),
+                self.max_payment_count(),
+            );
+            if payment.minor_units() < fastest {
+                return invalid("payment does not pay the loan off within 600 months");
+            }
+        }
+        Ok(())
+    }
+}
+
+// Payment in minor units that pays `principal` off in `count` payments at
+// `rate` per payment
+fn annuity(principal: i64, rate: f64, count: u32) -> i64 {
+    let principal = principal as f64;
+    let payment = if rate == 0.0 {
+        principal / count as f64
+    } else {
+        principal * rate / (1.0 - (1.0 + rate).powi(-(count as i32)))
+    };
+    payment.round() as i64
+}
+
+// Total of the prepayments falling after `after` and on or before `through`
+fn prepaid(prepayments: &[Prepayment], after: Option<NaiveDate>, through: NaiveDate) -> i64 {
+    let mut total = 0;
+    for prepayment in prepayments {
+        let mut date = prepayment.date;
+        let mut years = 0;
+        while date <= through {
+            if after.is_none_or(|after| date > after) {
+                total += prepayment.amount.minor_units();
+            }
+            if !prepayment.yearly {
+                break;
+            }
+            years += 1;
+            date = prepayment.date + Months::new(12 * years);
+        }
+    }
+    total
+}
+
+// Every installment until the loan is paid off. Interest is rounded to the cent
+// at each payment, and the last payment of the amortization takes up what the
+// rounding left over.
+pub fn schedule(terms: &LoanTerms, prepayments: &[Prepayment]) -> Vec<Installment> {
+    let currency = terms.currency();
+    let money = |minor: i64| Money::from_minor(minor, currency);
+    let rate = terms.periodic_rate();
+    let payment = terms.regular_payment().minor_units();
+    let mut balance = terms.principal.minor_units();
+    let mut installments = Vec::new();
+    let mut previous = None;
+    let mut index = 0;
+    while balance > 0 {
+        let date = terms.frequency.due_date(terms.first_payment, index);
+        let interest = (balance as f64 * rate).round() as i64;
+        let last = (terms.payment.is_none() && index + 1 == terms.payment_count())
+            || index + 1 == terms.max_payment_count();
+        let principal = if last {
+            balance
+        } else {
+            (payment - interest).min(balance)
+        };
+        balance -= principal;
+        let prepayment = prepaid(prepayments, previous, date).min(balance);
+        balance -= prepayment;
+        index += 1;
+        installments.push(Installment {
+            number: index,
+            date,
+            payment: money(interest + principal),
+            interest: money(interest),
+            principal: money(principal),
+            prepayment: money(prepayment),
+            balance: money(balance),
+            transaction_id: None,
+        });
+        previous = Some(date);
+    }
+    installments
+}
+
+pub fn summarize(terms: &LoanTerms, schedule: &[Installment]) -> ScheduleSummary {
+    let zero = Money::zero(terms.currency());
+    let term_payments = terms
+        .term_months
+        .map(|term| (term * terms.frequency.payments_per_year()).div_ceil(12));
+    ScheduleSummary {
+        payment: terms.regular_payment(),
+        payments: schedule.len(),
+        payoff_date: schedule
+            .last()
+            .map(|i| i.date)
+            .unwrap_or(terms.first_payment),
+        total_interest: schedule.iter().fold(zero, |total, i| total + i.interest),
+        balance_at_term_end: term_payments.map(|count| {
+            schedule
+                .iter()
+                .take_while(|i| i.number <= count)
+                .last()
+                .map(|i| i.balance)
+                .unwrap_or(terms.principal)
+        }),
+    }
+}
+
+fn find_loan(db: &Database, user_id: i64, account_number: i64) -> Result<LoanAccount, LoanError> {
+    let account = db
+        .get_accounts_by_user(user_id)?
+        .into_iter()
+        .find(|a| *a.account_number() == account_number)
+        .ok_or(LoanError::AccountNotFound)?;
+    match account.as_enum() {
+        Account::Loan(loan) => Ok(loan),
+        _ => Err(LoanError::NotALoan),
+    }
+}
+
+fn terms_of(loan: &LoanAccount) -> Result<LoanTerms, LoanError> {
+    loan.terms.ok_or(LoanError::NoTerms)
+}
+
+// Enters or replaces the terms of one of the user's loans
+pub fn set_terms(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+    terms: LoanTerms,
+) -> Result<LoanTerms, LoanError> {
+    let loan = find_loan(db, user_id, account_number)?;
+    terms.validate()?;
+    if terms.currency() != loan.balance_owed.currency() {
+        return Err(LoanError::InvalidTerms(format!(
+            "the loan is in {}",
+            loan.balance_owed.currency()
+        )));
+    }
+    db.set_loan_terms(account_number, &terms)?;
+    Ok(terms)
+}
+
+pub fn get_terms(db: &Database, user_id: i64, account_number: i64) -> Result<LoanTerms, LoanError> {
+    terms_of(&find_loan(db, user_id, account_number)?)
+}
+
+// The loan's schedule with the payments matched to it so far
+pub fn amortization(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+) -> Result<Amortization, LoanError> {
+    let terms = terms_of(&find_loan(db, user_id, account_number)?)?;
+    let mut schedule = schedule(&terms, &[]);
+    for payment in db.get_loan_payments(user_id)? {
+        if payment.account_number != account_number {
+            continue;
+        }
+        if let Some(installment) = schedule
+            .iter_mut()
+            .find(|i| i.number == payment.installment)
+        {
+            installment.transaction_id = Some(payment.transaction_id);
+        }
+    }
+    Ok(Amortization {
+        account_number,
+        terms,
+        summary: summarize(&terms, &schedule),
+        schedule,
+    })
+}
+
+// How lump-sum prepayments would shorten the loan and what they would save
+pub fn what_if(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+    prepayments: &[Prepayment],
+) -> Result<WhatIf, LoanError> {
+    let terms = terms_of(&find_loan(db, user_id, account_number)?)?;
+    if prepayments
+        .iter()
+        .any(|p| !p.amount.is_positive() || p.amount.currency() != terms.currency())
+    {
+        return Err(LoanError::InvalidPrepayment);
+    }
+    let without = summarize(&terms, &schedule(&terms, &[]));
+    let schedule = schedule(&terms, prepayments);
+    let with = summarize(&terms, &schedule);
+    Ok(WhatIf {
+        account_number,
+        without_prepayments: without,
+        with_prepayments: with,
+        interest_saved: without.total_interest - with.total_interest,
+        payments_saved: without.payments - with.payments,
+        schedule,
+    })
+}
+
+// Matches withdrawals from the user's other accounts to the installments of
+// a loan they pay, closest dates first. A matched payment lowers the loan by
+// the installment's principal, the interest is what the loan cost. Payments
+// already matched or linked as transfers are left alone.
+pub fn match_payments(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+) -> Result<usize, LoanError> {
+    let mut loan = find_loan(db, user_id, account_number)?;
+    let terms = terms_of(&loan)?;
+    let schedule = schedule(&terms, &[]);
+    let matched = db.get_loan_payments(user_id)?;
+    let mut used: HashSet<i64> = matched.iter().map(|p| p.transaction_id).collect();
+    let mut paid: HashSet<u32> = matched
+        .iter()
+        .filter(|p| p.account_number == account_number)
+        .map(|p| p.installment)
+        .collect();
+
+    let transactions = db.get_transactions(user_id)?;
+    let mut candidates = Vec::new();
+    for transaction in &transactions {
+        if transaction.account_number == account_number
+            || transaction.transfer_id.is_some()
+            || used.contains(&transaction.id)
+        {
+            continue;
+        }
+        let amount = transaction.amount();
+        for installment in schedule.iter().filter(|i| !paid.contains(&i.number)) {
+            let days_apart = (transaction.transaction_date - installment.date)
+                .num_days()
+                .abs();
+            if amount == -installment.payment && days_apart <= PAYMENT_WINDOW_DAYS {
+                candidates.push((days_apart, installment.number, transaction.id));
+            }
+        }
+    }
+    candidates.sort();
+
+    let mut count = 0;
+    for (_, number, transaction_id) in candidates {
+        if used.contains(&transaction_id) || paid.contains(&number) {
+            continue;
+        }
+        let installment = &schedule[number as usize - 1];
+        db.insert_loan_payment(
+            user_id,
+            account_number,
+            transaction_id,
+            number,
+            installment.principal,
+            installment.interest,
+        )?;
+        loan.withdraw(installment.principal);
+        used.insert(transaction_id);
+        paid.insert(number);
+        count += 1;
+    }
+    if count > 0 {
+        db.update_account(&loan)?;
+    }
+    Ok(count)
+}
+
+// Matches payments for each of the user's loans that has terms, returning
+// the number matched
+pub fn match_all_payments(db: &Database, user_id: i64) -> Result<usize, LoanError> {
+    let mut count = 0;
+    for account in db.get_accounts_by_user(user_id)? {
+        if let Account::Loan(loan) = account.as_enum() {
+            if loan.terms.is_some() {
+                count += match_payments(db, user_id, loan.account_number)?;
+            }
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::AccountType;
+    use crate::manual::{self, NewAccount, NewTransaction};
+    use crate::networth;
+    use crate::user::User;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    // $500,000 over 25 years at 5%, compounded semi-annually like a fixed-rate mortgage
+    fn mortgage() -> LoanTerms {
+        LoanTerms {
+            principal: Money::cad(50000000),
+            rate: 0.05,
+            amortization_months: 300,
+            term_months: Some(60),
+            frequency: PaymentFrequency::Monthly,
+            compounding: LoanCompounding::SemiAnnual,
+            first_payment: date(2025, 2, 1),
+            payment: None,
+        }
+    }
+
+    #[test]
+    fn test_semi_annual_schedule() {
+        let terms = mortgage();
+        assert_eq!(terms.regular_payment(), Money::cad(290802));
+
+        let schedule = schedule(&terms, &[]);
+        assert_eq!(schedule.len(), 300);
+        assert_eq!(schedule[0].interest, Money::cad(206196));
+        assert_eq!(schedule[0].principal, Money::cad(290802 - 206196));
+        assert_eq!(schedule[1].date, date(2025, 3, 1));
+        assert_eq!(schedule[299].date, date(2050, 1, 1));
+        assert!(schedule[299].balance.is_zero());
+
+        let summary = summarize(&terms, &schedule);
+        assert_eq!(summary.payoff_date, date(2050, 1, 1));
+        assert_eq!(summary.balance_at_term_end, Some(schedule[59].balance));
+
+        // the same rate compounded monthly costs more
+        let monthly = LoanTerms {
+            compounding: LoanCompounding::Monthly,
+            ..terms
+        };
+        assert!(monthly.regular_payment() > terms.regular_payment());
+    }
+
+    #[test]
+    fn test_accelerated_and_prepayments_pay_off_sooner() {
+        let terms = mortgage();
+        let without = summarize(&terms, &schedule(&terms, &[]));
+
+        let accelerated = LoanTerms {
+            frequency: PaymentFrequency::AcceleratedBiWeekly,
+            ..terms
+        };
+        assert_eq!(accelerated.regular_payment(), Money::cad(145401));
+        let faster = summarize(&accelerated, &schedule(&accelerated, &[]));
+        assert!(faster.payoff_date < without.payoff_date);
+        assert!(faster.total_interest < without.total_interest);
+
+        let prepayments = [Prepayment {
+            date: date(2026, 1, 15),
+            amount: Money::cad(1000000),
+            yearly: true,
+        }];
+        let schedule = schedule(&terms, &prepayments);
+        // goes in with the first payment after it, every year
+        assert_eq!(schedule[12].prepayment, Money::cad(1000000));
+        assert_eq!(schedule[24].prepayment, Money::cad(1000000));
+        assert!(schedule[13].prepayment.is_zero());
+        let with = summarize(&terms, &schedule);
+        assert!(with.payments < without.payments);
+        assert!(with.total_interest < without.total_interest);
+        assert!(schedule.last().unwrap().balance.is_zero());
+    }
+
+    #[test]
+    fn test_terms_are_validated() {
+        let terms = mortgage();
+        assert!(terms.validate().is_ok());
+        for bad in [
+            LoanTerms {
+                principal: Money::cad(0),
+                ..terms
+            },
+            LoanTerms { rate: 5.0, ..terms },
+            LoanTerms {
+                term_months: Some(360),
+                ..terms
+            },
+            // below the first month's interest
+            LoanTerms {
+                payment: Some(Money::cad(200000)),
+                ..terms
+            },
+            // covers the interest but would take forever
+            LoanTerms {
+                principal: Money::cad(100_000_000),
+                rate: 0.0,
+                payment: Some(Money::cad(1)),
+                ..terms
+            },
+        ] {
+            assert!(matches!(bad.validate(), Err(LoanError::InvalidTerms(_))));
+        }
+    }
+
+    #[test]
+    fn test_schedule_stops_at_longest_amortization() {
+        let terms = LoanTerms {
+            principal: Money::cad(100_000_000),
+            rate: 0.0,
+            payment: Some(Money::cad(1)),
+            ..mortgage()
+        };
+        let schedule = schedule(&terms, &[]);
+        assert_eq!(schedule.len(), terms.max_payment_count() as usize);
+        assert!(schedule.last().unwrap().balance.is_zero());
+    }
+
+    #[test]
+    fn test_payments_matched_to_schedule() {
+        let db = Database::new(":memory:".to_string()).unwrap();
+        db.insert_user(&User {
+            id: 1,
+            name: "Alice".into(),
+        })
+        .unwrap();
+        let open = |account_type, balance, loan| {
+            manual::create_account(
+                &db,
+                1,
+                NewAccount {
+                    account_type,
+                    nickname: account_type.to_string(),
+                    institution: None,
+                    opened_on: date(2025, 1, 1),
+                    opening_balance: balance,
+                    credit_limit: None,
+                    interest_rate: 0.0,
+                    loan,
+                },
+            )
+            .unwrap()
+        };
+        let mortgage_number = open(AccountType::Loan, Money::cad(50000000), Some(mortgage()));
+        let chequing = open(AccountType::Chequing, Money::cad(1000000), None);
+        for (day, amount) in [(3, -290802), (10, -290802), (15, -4500)] {
+            manual::add_transaction(
+                &db,
+                1,
+                NewTransaction {
+                    account_number: chequing,
+                    transaction_date: date(2025, 2, day),
+                    description: "MORTGAGE PAYMENT".into(),
+                    amount: Money::cad(amount),
+                    notes: String::new(),
+                    category_id: None,
+                    category: None,
+                },
+            )
+            .unwrap();
+        }
+
+        // only the payment within a few days of the due date matches
+        assert_eq!(match_payments(&db, 1, mortgage_number).unwrap(), 1);
+        assert_eq!(match_payments(&db, 1, mortgage_number).unwrap(), 0);
+        let amortization = amortization(&db, 1, mortgage_number).unwrap();
+        assert!(amortization.schedule[0].transaction_id.is_some());
+        assert!(amortization.schedule[1].transaction_id.is_none());
+
+        let paid = Money::cad(290802 - 206196);
+        let loan = find_loan(&db, 1, mortgage_number).unwrap();
+        assert_eq!(loan.balance_owed, Money::cad(50000000) - paid);
+        let history = networth::account_history(&db, 1, &loan).unwrap();
+        assert_eq!(history.balance_on(date(2025, 2, 2)), Money::cad(50000000));
+        assert_eq!(
+            history.balance_on(date(2025, 2, 3)),
+            Money::cad(50000000) - paid
+        );
+
+        assert!(matches!(
+            match_payments(&db, 1, chequing),
+            Err(LoanError::NotALoan)
+        ));
+    }
+}
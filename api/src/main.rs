@@ -1,260 +1,1710 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use chrono::{Datelike, NaiveDate};
 use dotenv::dotenv;
-use rusqlite::Result;
-use tokio::task;
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::get,
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 
 use finance_tool::{
-    account::{Account, AccountType},
+    account::{Account, AccountLabel, AccountPeriod, AccountType, AccountUpdate, BankAccount},
     app::AppState,
-    catergorization::catergorize_transactions,
-    database::Database,
-    parser,
-    transaction::Transaction,
+    auth::{self, AuthUser},
+    budget::{self, Budget, BudgetStatus},
+    category::{self, Category, CategoryTotal, CategoryUpdate, NewCategory},
+    catergorization::{apply_categories, categorize_for_user, CategoryRule},
+    database::{Database, ImportResult},
+    error::ApiError,
+    exchange::{self, ExchangeRate},
+    importer::{ImportOptions, ImporterRegistry},
+    interest::{self, InterestEstimate, InterestPeriod, InterestPlan},
+    investment::{self, Holding, NewTrade, Price, RoomYear, Trade},
+    loan::{self, Amortization, LoanTerms, Prepayment, WhatIf},
+    manual::{self, NewAccount, NewTransaction},
+    merchant::{self, Merchant, MerchantUpdate},
+    money::{Currency, Money},
+    networth::{self, Interval, NetWorth},
+    parser::ParsedStatement,
+    reconcile::{self, LedgerEntry, Reconciliation},
+    recurring::{self, RecurringPayment},
+    report::{self, MonthlySummary, ReportFilter},
+    search,
+    transaction::{
+        Transaction, TransactionPage, TransactionQuery, TransactionSort, TransactionUpdate,
+        DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE,
+    },
+    transfer::{self, TransferPair},
     user::User,
 };
 
-async fn _test_setup() -> Result<()> {
-    let db = Database::new(std::env::var("DATABASE_PATH").expect("DATABASE_PATH must be set"))?;
-    // db._execute_schema()?;
+// Bank exports are small, but a few years of history can pass the 2 MB default
+const MAX_IMPORT_BYTES: usize = 16 * 1024 * 1024;
 
-    db.reset_values()?;
-    let user_name = String::from("Alex");
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
 
-    let user = User::new(user_name);
+    let db =
+        Database::new(std::env::var("DATABASE_PATH").expect("DATABASE_PATH must be set")).unwrap();
 
-    db.insert_user(&user)?;
+    // `finance-tool set-password <name>` reads the new password from stdin,
+    // which is how users created before passwords existed get one
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, command, name] = args.as_slice() {
+        if command == "set-password" {
+            let mut password = String::new();
+            std::io::stdin().read_line(&mut password).unwrap();
+            match auth::set_password(&db, name, password.trim_end_matches(['\r', '\n'])) {
+                Ok(user) => println!("Password set for {}", user.name),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+    }
 
-    let credit_path = std::path::Path::new("data/credit_data.txt");
-    let savings_path = std::path::Path::new("data/savings_data.txt");
-    let chequing_path = std::path::Path::new("data/chequing_data.txt");
+    let state = AppState::new(db);
 
-    let mut chequing_balance = 0.0;
-    let mut savings_balance = 0.0;
-    let mut credit_balance = 0.0;
-    let mut credit_limit = 0.0;
+    let app = Router::new()
+        .route("/", get(root))
+        .route("/users", post(register_user))
+        .route("/login", post(login_user))
+        .route("/logout", post(logout_user))
+        .route("/me", get(current_user))
+        .route(
+            "/me/currency",
+            get(get_reporting_currency).put(set_reporting_currency),
+        )
+        .route(
+            "/transactions",
+            get(get_transactions).post(create_transaction),
+        )
+        .route("/transactions/search", get(search_transactions))
+        .route(
+            "/transactions/{id}",
+            patch(update_transaction).delete(delete_transaction),
+        )
+        .route(
+            "/imports",
+            post(import_statement).layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES)),
+        )
+        .route("/transfers", get(get_transfers).post(link_transfer))
+        .route("/transfers/match", post(match_transfers))
+        .route("/transfers/{id}", delete(unlink_transfer))
+        .route("/accounts", get(get_accounts).post(create_account))
+        .route(
+            "/accounts/{account_number}",
+            patch(update_account_label).delete(delete_account),
+        )
+        .route("/accounts/{account_number}/close", post(close_account))
+        .route("/accounts/{account_number}/ledger", get(get_account_ledger))
+        .route(
+            "/accounts/{account_number}/interest",
+            get(get_interest).post(record_interest),
+        )
+        .route(
+            "/accounts/{account_number}/interest-plan",
+            get(get_interest_plan).put(update_interest_plan),
+        )
+        .route(
+            "/accounts/{account_number}/trades",
+            get(get_trades).post(record_trade),
+        )
+        .route(
+            "/accounts/{account_number}/loan",
+            get(get_loan_terms).put(update_loan_terms),
+        )
+        .route(
+            "/accounts/{account_number}/amortization",
+            get(get_amortization),
+        )
+        .route(
+            "/accounts/{account_number}/amortization/what-if",
+            post(prepayment_what_if),
+        )
+        .route(
+            "/accounts/{account_number}/loan-payments/match",
+            post(match_loan_payments),
+        )
+        .route("/holdings", get(get_holdings))
+        .route(
+            "/prices",
+            get(get_prices)
+                .post(import_prices)
+                .layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES)),
+        )
+        .route(
+            "/contribution-room/{account_type}",
+            get(get_contribution_room),
+        )
+        .route(
+            "/contribution-room/{account_type}/{year}",
+            put(set_contribution_room),
+        )
+        .route("/networth", get(get_net_worth))
+        .route("/reconciliation", get(get_reconciliation))
+        .route("/categories", get(get_categories).post(create_category))
+        .route("/categories/totals", get(get_category_totals))
+        .route(
+            "/categories/{id}",
+            patch(update_category).delete(delete_category),
+        )
+        .route("/categories/{id}/merge", post(merge_category))
+        .route("/merchants", get(get_merchants))
+        .route("/merchants/{id}", patch(update_merchant))
+        .route("/merchants/{id}/aliases", post(add_merchant_alias))
+        .route(
+            "/merchants/{id}/aliases/{alias_id}",
+            delete(delete_merchant_alias),
+        )
+        .route("/budgets", get(get_budgets).post(create_budget))
+        .route("/budgets/status", get(get_budget_status))
+        .route("/budgets/{id}", put(update_budget).delete(delete_budget))
+        .route("/recurring", get(get_recurring))
+        .route("/reports/monthly", get(get_monthly_report))
+        .route(
+            "/exchange-rates",
+            get(get_exchange_rates)
+                .post(import_exchange_rates)
+                .layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES)),
+        )
+        .route("/rules", get(get_rules).post(create_rule))
+        .route("/rules/{id}", put(update_rule).delete(delete_rule))
+        .with_state(state);
 
-    let mut transactions =
-        parser::parse_csv_to_transactions(user.id, std::path::Path::new("data/csv48685.csv"))
+    let listerner =
+        tokio::net::TcpListener::bind(std::env::var("ADDR").unwrap_or("0.0.0.0:3000".to_string()))
+            .await
             .unwrap();
 
-    for transaction in &mut transactions {
-        let acc = transaction.extract_account().unwrap();
-        if !db.account_exists(acc.account_number()).unwrap_or(true) {
-            db.insert_account(&acc)?;
+    println!("Listening on port 3000");
+    axum::serve(listerner, app).await.unwrap();
+}
+
+async fn root() -> impl IntoResponse {
+    ("hello world").into_response()
+}
+
+#[derive(Deserialize)]
+struct Credentials {
+    name: String,
+    password: String,
+}
+
+#[derive(Serialize)]
+struct LoginResponse {
+    user: User,
+    token: String,
+}
+
+async fn register_user(
+    State(state): State<AppState>,
+    Json(credentials): Json<Credentials>,
+) -> Result<(StatusCode, Json<User>), ApiError> {
+    let user = state
+        .with_db(move |db| {
+            Ok(auth::register(
+                db,
+                &credentials.name,
+                &credentials.password,
+            )?)
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(user)))
+}
+
+async fn login_user(
+    State(state): State<AppState>,
+    Json(credentials): Json<Credentials>,
+) -> Result<Response, ApiError> {
+    let (user, token) = state
+        .with_db(move |db| Ok(auth::login(db, &credentials.name, &credentials.password)?))
+        .await?;
+    Ok((
+        StatusCode::OK,
+        [(header::SET_COOKIE, auth::session_cookie(&token))],
+        Json(LoginResponse { user, token }),
+    )
+        .into_response())
+}
+
+async fn logout_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let token = auth::token_from_headers(&headers).ok_or_else(ApiError::unauthorized)?;
+    state
+        .with_db(move |db| Ok(auth::logout(db, &token)?))
+        .await?;
+    Ok((
+        StatusCode::NO_CONTENT,
+        [(header::SET_COOKIE, auth::expired_session_cookie())],
+    )
+        .into_response())
+}
+
+async fn current_user(AuthUser(user): AuthUser) -> (StatusCode, Json<User>) {
+    (StatusCode::OK, Json(user))
+}
+
+#[derive(Serialize, Deserialize)]
+struct ReportingCurrency {
+    currency: Currency,
+}
+
+async fn get_reporting_currency(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<ReportingCurrency>, ApiError> {
+    let currency = state
+        .with_db(move |db| Ok(db.get_reporting_currency(user.id)?))
+        .await?;
+    Ok(Json(ReportingCurrency { currency }))
+}
+
+async fn set_reporting_currency(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(body): Json<ReportingCurrency>,
+) -> Result<Json<ReportingCurrency>, ApiError> {
+    let currency = body.currency;
+    state
+        .with_db(move |db| {
+            exchange::check_rates(db, user.id, currency)?;
+            Ok(db.set_reporting_currency(user.id, currency)?)
+        })
+        .await?;
+    Ok(Json(ReportingCurrency { currency }))
+}
+
+async fn get_exchange_rates(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ExchangeRate>>, ApiError> {
+    let rates = state
+        .with_db(move |db| Ok(db.get_exchange_rates(user.id)?))
+        .await?;
+    Ok(Json(rates))
+}
+
+// Takes the CSV itself as the body, either date,base,quote,rate rows or a
+// Bank of Canada exchange rate export
+async fn import_exchange_rates(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    body: String,
+) -> Result<(StatusCode, Json<serde_json::Value>), ApiError> {
+    let imported = state
+        .with_db(move |db| Ok(exchange::import_rates(db, user.id, &body)?))
+        .await?;
+    Ok((StatusCode::CREATED, Json(json!({ "imported": imported }))))
+}
+
+#[derive(Deserialize)]
+struct TransactionsQuery {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    // account number
+    account: Option<i64>,
+    account_type: Option<String>,
+    category: Option<i64>,
+    merchant: Option<i64>,
+    #[serde(default)]
+    uncategorized: bool,
+    // decimal amounts in each transaction's own currency, negative for spending
+    min_amount: Option<String>,
+    max_amount: Option<String>,
+    q: Option<String>,
+    #[serde(default)]
+    sort: TransactionSort,
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+}
+
+impl TryFrom<TransactionsQuery> for TransactionQuery {
+    type Error = ApiError;
+
+    fn try_from(params: TransactionsQuery) -> Result<Self, ApiError> {
+        let amount = |value: Option<String>, name: &str| {
+            value
+                .map(|v| Money::parse(&v, Currency::CAD))
+                .transpose()
+                .map_err(|_| ApiError::bad_request(format!("Invalid {}", name)))
+        };
+        let account_type = params
+            .account_type
+            .map(|t| t.parse::<AccountType>())
+            .transpose()
+            .map_err(|err| ApiError::bad_request(err.to_string()))?;
+        let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if limit == 0 || limit > MAX_PAGE_SIZE {
+            return Err(ApiError::bad_request(format!(
+                "limit must be between 1 and {}",
+                MAX_PAGE_SIZE
+            )));
         }
-        transaction.user_id = user.id;
+
+        Ok(TransactionQuery {
+            from: params.from,
+            to: params.to,
+            account_number: params.account,
+            account_type,
+            category_id: params.category,
+            merchant_id: params.merchant,
+            uncategorized: params.uncategorized,
+            min_amount: amount(params.min_amount, "min_amount")?,
+            max_amount: amount(params.max_amount, "max_amount")?,
+            search: params.q,
+            sort: params.sort,
+            limit,
+            offset: params.offset,
+        })
     }
+}
 
-    let categories = catergorize_transactions(&transactions)
-        .await
-        .map_err(|e| println!("{}", e))
-        .unwrap();
+async fn get_transactions(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Query(params): Query<TransactionsQuery>,
+) -> Result<Json<TransactionPage>, ApiError> {
+    let query = TransactionQuery::try_from(params)?;
+    let page = state
+        .with_db(move |db| Ok(db.query_transactions(user.id, &query)?))
+        .await?;
+    Ok(Json(page))
+}
 
-    for (tx, cat) in transactions.iter_mut().zip(categories.into_iter()) {
-        tx.category = cat;
-    }
+async fn search_transactions(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Query(params): Query<TransactionsQuery>,
+) -> Result<Json<TransactionPage>, ApiError> {
+    let mut query = TransactionQuery::try_from(params)?;
+    // q is the full-text query here rather than a substring filter
+    let text = query
+        .search
+        .take()
+        .ok_or_else(|| ApiError::bad_request("Missing search query q"))?;
+    let page = state
+        .with_db(move |db| Ok(search::search(db, user.id, &text, &query)?))
+        .await?;
+    Ok(Json(page))
+}
 
-    db.batch_insert_transactions(&transactions)?;
-
-    let mut credit_transactions = match parser::parse_extracted_transactions(
-        credit_path,
-        db.get_account_number_by_type(user.id, &AccountType::Credit)?,
-        AccountType::Credit,
-        &mut credit_balance,
-        &mut credit_limit,
-    ) {
-        Ok(t) => t,
-        Err(e) => panic!("{}", e),
-    };
-    let savings_transactions = match parser::parse_extracted_transactions(
-        savings_path,
-        db.get_account_number_by_type(user.id, &AccountType::Savings)?,
-        AccountType::Savings,
-        &mut savings_balance,
-        &mut 0.0,
-    ) {
-        Ok(t) => t,
-        Err(e) => panic!("{}", e),
-    };
-    let chequing_transactions = match parser::parse_extracted_transactions(
-        chequing_path,
-        db.get_account_number_by_type(user.id, &AccountType::Chequing)?,
-        AccountType::Chequing,
-        &mut chequing_balance,
-        &mut 0.0,
-    ) {
-        Ok(t) => t,
-        Err(e) => panic!("{}", e),
-    };
+// A transaction entered by hand. Like an import it may complete a transfer
+// and moves the account's balance.
+async fn create_transaction(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(new_transaction): Json<NewTransaction>,
+) -> Result<(StatusCode, Json<Transaction>), ApiError> {
+    let transaction = state
+        .with_db(move |db| {
+            let transaction = manual::add_transaction(db, user.id, new_transaction)?;
+            transfer::match_transfers(db, user.id, transfer::DEFAULT_WINDOW_DAYS)?;
+            loan::match_all_payments(db, user.id)?;
+            networth::take_snapshots(db, user.id, chrono::Local::now().date_naive())?;
+            Ok(db
+                .get_transaction(user.id, transaction.id)?
+                .unwrap_or(transaction))
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(transaction)))
+}
 
-    credit_transactions.extend(savings_transactions);
-    credit_transactions.extend(chequing_transactions);
+async fn update_transaction(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(changes): Json<TransactionUpdate>,
+) -> Result<Json<Transaction>, ApiError> {
+    let transaction = state
+        .with_db(move |db| {
+            if let Some(Some(category_id)) = changes.category_id {
+                db.get_category(user.id, category_id)?
+                    .ok_or_else(|| ApiError::bad_request("Unknown category"))?;
+            }
+            db.update_transaction(user.id, id, &changes)?
+                .ok_or_else(|| ApiError::not_found("Transaction not found"))
+        })
+        .await?;
+    Ok(Json(transaction))
+}
 
-    for transaction in &mut credit_transactions {
-        transaction.user_id = user.id;
+async fn delete_transaction(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let deleted = state
+        .with_db(move |db| Ok(db.delete_transaction(user.id, id)?))
+        .await?;
+    if !deleted {
+        return Err(ApiError::not_found("Transaction not found"));
     }
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    let categories = catergorize_transactions(&credit_transactions)
+#[derive(Serialize)]
+struct ImportResponse {
+    format: &'static str,
+    #[serde(flatten)]
+    result: ImportResult,
+    // rows that could not be read, the rest of the file is still imported
+    errors: Vec<String>,
+    // transfers between the user's accounts linked after the import
+    transfers: usize,
+    // payments matched to the installments of the user's loans
+    loan_payments: usize,
+}
+
+// Reads the upload: a `file` part plus optional `format`, `account_number`
+// and `account_type` text parts.
+async fn read_import_form(
+    mut multipart: Multipart,
+) -> Result<(String, Option<String>, ImportOptions), ApiError> {
+    let mut file = None;
+    let mut format = None;
+    let mut options = ImportOptions::default();
+
+    while let Some(field) = multipart
+        .next_field()
         .await
-        .map_err(|e| println!("{}", e))
-        .unwrap();
+        .map_err(|e| ApiError::new(e.status(), e.body_text()))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+        let bytes = field
+            .bytes()
+            .await
+            .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+        let text = String::from_utf8(bytes.to_vec())
+            .map_err(|_| ApiError::bad_request(format!("{} is not valid UTF-8", name)))?;
 
-    for (tx, cat) in credit_transactions.iter_mut().zip(categories.into_iter()) {
-        tx.category = cat;
+        match name.as_str() {
+            "file" => file = Some(text),
+            "format" if !text.trim().is_empty() => format = Some(text.trim().to_string()),
+            "account_number" if !text.trim().is_empty() => {
+                options.account_number = Some(
+                    text.trim()
+                        .parse()
+                        .map_err(|_| ApiError::bad_request("Invalid account_number"))?,
+                )
+            }
+            "account_type" if !text.trim().is_empty() => {
+                options.account_type = Some(
+                    text.parse()
+                        .map_err(|_| ApiError::bad_request("Invalid account_type"))?,
+                )
+            }
+            _ => {}
+        }
     }
 
-    db.batch_insert_transactions(&credit_transactions)?;
+    let file = file.ok_or_else(|| ApiError::bad_request("Missing file"))?;
+    Ok((file, format, options))
+}
 
-    let accounts = db.get_accounts_by_user(user.id).unwrap();
+// Gives the rows of a bank export the numbers of the user's own accounts, in
+// place of the provisional ones derived from the bank's numbers. Accounts seen
+// for the first time are created. Rows of a new account whose type none of its
+// rows name are left out, and described like row errors.
+fn assign_accounts(
+    db: &Database,
+    user_id: i64,
+    institution: Option<&str>,
+    statement: &mut ParsedStatement,
+) -> Result<Vec<String>, ApiError> {
+    let mut unknown = Vec::new();
+    for (provisional, bank_number) in &statement.bank_numbers {
+        let account_number = match db.find_bank_account(user_id, bank_number)? {
+            Some(account_number) => account_number,
+            None => match statement
+                .transactions
+                .iter()
+                .filter(|t| t.account_number == *provisional)
+                .find_map(|t| t.extract_account())
+            {
+                Some(account) => {
+                    db.insert_bank_account(account.as_ref(), bank_number, institution)?
+                }
+                None => {
+                    unknown.push(*provisional);
+                    continue;
+                }
+            },
+        };
+        for transaction in statement
+            .transactions
+            .iter_mut()
+            .filter(|t| t.account_number == *provisional)
+        {
+            transaction.account_number = account_number;
+        }
+    }
 
-    for mut account in accounts {
-        match account.account_type() {
-            AccountType::Credit => {
-                println!("Credit balance: {}", credit_balance);
-                println!("Credit limit: {}", credit_limit);
+    let mut errors = Vec::new();
+    statement.transactions.retain(|transaction| {
+        if !unknown.contains(&transaction.account_number) {
+            return true;
+        }
+        let mask = &statement.bank_numbers[&transaction.account_number].mask;
+        errors.push(skipped_row(
+            transaction,
+            format!("unknown type for account ending in {}", mask),
+        ));
+        false
+    });
+    Ok(errors)
+}
 
-                account.set_balance(credit_balance);
-                account.set_credit_limit(credit_limit);
-            }
-            AccountType::Savings => {
-                println!("Savings balance: {}", savings_balance);
+// Creates the accounts a statement refers to. Accounts that already exist have
+// to belong to the importing user. Rows of a new account whose type is unknown
+// are left out like in `assign_accounts`.
+fn prepare_accounts(
+    db: &Database,
+    user_id: i64,
+    transactions: &mut Vec<Transaction>,
+) -> Result<Vec<String>, ApiError> {
+    let mut seen = Vec::new();
+    let mut unknown = Vec::new();
+    for transaction in transactions.iter() {
+        if seen.contains(&transaction.account_number) {
+            continue;
+        }
+        seen.push(transaction.account_number);
 
-                account.set_balance(savings_balance);
-                account.set_credit_limit(0.0);
+        if db.account_exists(&transaction.account_number)? {
+            if db.get_account(&transaction.account_number)?.user_id() != user_id {
+                return Err(ApiError::new(
+                    StatusCode::FORBIDDEN,
+                    format!(
+                        "Account {} belongs to another user",
+                        transaction.account_number
+                    ),
+                ));
             }
-            AccountType::Chequing => {
-                println!("Chequing balance: {}", chequing_balance);
-
-                account.set_balance(chequing_balance);
-                account.set_credit_limit(0.0);
+        } else {
+            match transactions
+                .iter()
+                .filter(|t| t.account_number == transaction.account_number)
+                .find_map(|t| t.extract_account())
+            {
+                Some(account) => db.insert_account(account.as_ref())?,
+                None => unknown.push(transaction.account_number),
             }
-            AccountType::Unknown => println!("Unknown account type: {}", account.account_type()),
         }
-        db.update_account(&account)?;
     }
 
+    let mut errors = Vec::new();
+    transactions.retain(|transaction| {
+        if !unknown.contains(&transaction.account_number) {
+            return true;
+        }
+        errors.push(skipped_row(
+            transaction,
+            format!("unknown type for account {}", transaction.account_number),
+        ));
+        false
+    });
+    Ok(errors)
+}
+
+// Leaves out rows that are not in their account's currency, which would mix
+// currencies in its balance, and describes each one like a row error
+fn drop_foreign_currency_rows(
+    db: &Database,
+    transactions: &mut Vec<Transaction>,
+) -> Result<Vec<String>, ApiError> {
+    let mut currencies = HashMap::new();
+    for transaction in transactions.iter() {
+        if let Entry::Vacant(entry) = currencies.entry(transaction.account_number) {
+            let account = db.get_account(&transaction.account_number)?;
+            entry.insert(account.balance().currency());
+        }
+    }
+    let mut errors = Vec::new();
+    transactions.retain(|transaction| {
+        let expected = currencies[&transaction.account_number];
+        if transaction.currency == expected {
+            return true;
+        }
+        errors.push(skipped_row(
+            transaction,
+            format!(
+                "amount is in {} but the account is in {}",
+                transaction.currency, expected
+            ),
+        ));
+        false
+    });
+    Ok(errors)
+}
+
+// A row left out of an import, described like a row error
+fn skipped_row(transaction: &Transaction, reason: String) -> String {
+    format!(
+        "{} {}: {}",
+        transaction.transaction_date, transaction.description_1, reason
+    )
+}
+
+// Statement text files carry the closing balance and credit limit
+fn owned_account(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+) -> Result<Option<Box<dyn BankAccount>>, ApiError> {
+    Ok(db
+        .get_accounts_by_user(user_id)?
+        .into_iter()
+        .find(|account| *account.account_number() == account_number))
+}
+
+fn update_statement_balance(
+    db: &Database,
+    mut account: Box<dyn BankAccount>,
+    statement: &ParsedStatement,
+) -> Result<(), ApiError> {
+    let account_number = *account.account_number();
+    // statements print plain amounts in the account's own currency
+    let currency = account.balance().currency();
+    let in_account = |amount: Money| Money::from_minor(amount.minor_units(), currency);
+    // the account balance anchors the running balance at the account's latest
+    // transaction, so only a statement that reaches it may move it
+    let latest = db
+        .get_account_transactions(account.user_id(), account_number)?
+        .last()
+        .map(|t| t.transaction_date);
+    // statements list the newest transaction first
+    let closing = statement
+        .transactions
+        .iter()
+        .rev()
+        .filter_map(|t| Some((t.transaction_date, t.statement_balance?)))
+        .max_by_key(|(date, _)| *date);
+    match closing {
+        Some((date, balance)) => {
+            if Some(date) >= latest {
+                account.set_balance(in_account(balance));
+            }
+        }
+        None => {
+            if let Some(balance) = statement.balance {
+                account.set_balance(in_account(balance));
+            }
+        }
+    }
+    if let (AccountType::Credit, Some(limit)) = (account.account_type(), statement.credit_limit) {
+        account.set_credit_limit(in_account(limit));
+    }
+    db.update_account(account.as_ref())?;
     Ok(())
 }
 
-#[tokio::main]
-async fn main() {
-    dotenv().ok();
+async fn import_statement(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<ImportResponse>), ApiError> {
+    let (file, format, mut options) = read_import_form(multipart).await?;
+    options.user_id = user.id;
 
-    let db =
-        Database::new(std::env::var("DATABASE_PATH").expect("DATABASE_PATH must be set")).unwrap();
+    // an existing target account supplies its own type and currency; a number
+    // that is not yet an account is created from the statement's rows
+    let (options, rules) = state
+        .with_db(move |db| {
+            if let Some(account_number) = options.account_number {
+                match owned_account(db, user.id, account_number)? {
+                    Some(account) => {
+                        options.account_type.get_or_insert(account.account_type());
+                        options.currency = Some(account.balance().currency());
+                    }
+                    None if db.account_exists(&account_number)? => {
+                        return Err(ApiError::not_found("Account not found"));
+                    }
+                    None => {}
+                }
+            }
+            Ok((options, db.get_category_rules(user.id)?))
+        })
+        .await?;
 
-    let state = AppState::new(db);
+    let registry = ImporterRegistry::default();
+    let importer = match &format {
+        Some(format) => registry.get(format).ok_or_else(|| {
+            ApiError::bad_request(format!(
+                "Unknown format {}, expected one of {}",
+                format,
+                registry.names().join(", ")
+            ))
+        })?,
+        None => registry
+            .detect(&file)
+            .ok_or_else(|| ApiError::bad_request("Unrecognized statement format"))?,
+    };
+    let mut statement = importer.parse(&file, &options)?;
 
-    let app = Router::new()
-        .route("/", get(root))
-        .route("/users/{username}", get(login_user))
-        .route("/transactions", get(get_transactions))
-        .route("/accounts", get(get_accounts))
-        .with_state(state);
+    // categorization failing, e.g. the remote API being down, should not fail the import
+    match categorize_for_user(rules, &statement.transactions).await {
+        Ok(categories) => apply_categories(&mut statement.transactions, categories),
+        Err(e) => eprintln!("Categorization failed, importing uncategorized: {}", e),
+    }
 
-    let listerner =
-        tokio::net::TcpListener::bind(std::env::var("ADDR").unwrap_or("0.0.0.0:3000".to_string()))
-            .await
-            .unwrap();
+    let mut errors: Vec<String> = statement.errors.iter().map(|e| e.to_string()).collect();
+    let institution = importer.institution();
+    let (result, transfers, loan_payments, skipped) = state
+        .with_db(move |db| {
+            let mut skipped = assign_accounts(db, user.id, institution, &mut statement)?;
+            skipped.extend(prepare_accounts(db, user.id, &mut statement.transactions)?);
+            skipped.extend(drop_foreign_currency_rows(db, &mut statement.transactions)?);
+            let result = db.batch_insert_transactions(&statement.transactions)?;
+            if let Some(account_number) = options.account_number {
+                if let Some(account) = owned_account(db, user.id, account_number)? {
+                    update_statement_balance(db, account, &statement)?;
+                }
+            }
+            let transfers = transfer::match_transfers(db, user.id, transfer::DEFAULT_WINDOW_DAYS)?;
+            let loan_payments = loan::match_all_payments(db, user.id)?;
+            networth::take_snapshots(db, user.id, chrono::Local::now().date_naive())?;
+            Ok((result, transfers, loan_payments, skipped))
+        })
+        .await?;
+    errors.extend(skipped);
 
-    println!("Listening on port 3000");
-    axum::serve(listerner, app).await.unwrap();
+    Ok((
+        StatusCode::CREATED,
+        Json(ImportResponse {
+            format: importer.name(),
+            result,
+            errors,
+            transfers,
+            loan_payments,
+        }),
+    ))
 }
 
-async fn root() -> impl IntoResponse {
-    ("hello world").into_response()
+async fn get_transfers(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TransferPair>>, ApiError> {
+    let transfers = state
+        .with_db(move |db| Ok(db.get_transfers(user.id)?))
+        .await?;
+    Ok(Json(transfers))
 }
 
-async fn login_user(
-    Path(username): Path<String>,
-    State(state): State<AppState>,
-) -> (StatusCode, Json<User>) {
-    let conn = state.db.clone();
-    let fetched_user = task::spawn_blocking(move || {
-        let conn = conn.lock().unwrap();
-        match conn.get_user_by_name(&username) {
-            Ok(user) => Some(user),
-            Err(e) => {
-                println!("Users does not exist: {}", e);
-                None
+#[derive(Deserialize)]
+struct NewTransfer {
+    // transaction ids of the two sides, in either order
+    from: i64,
+    to: i64,
+}
+
+async fn link_transfer(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(new_transfer): Json<NewTransfer>,
+) -> Result<(StatusCode, Json<TransferPair>), ApiError> {
+    let pair = state
+        .with_db(move |db| {
+            Ok(transfer::link(
+                db,
+                user.id,
+                new_transfer.from,
+                new_transfer.to,
+            )?)
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(pair)))
+}
+
+#[derive(Deserialize)]
+struct MatchTransfers {
+    #[serde(default = "default_transfer_window")]
+    window_days: i64,
+}
+
+fn default_transfer_window() -> i64 {
+    transfer::DEFAULT_WINDOW_DAYS
+}
+
+async fn match_transfers(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Query(options): Query<MatchTransfers>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    if options.window_days < 0 {
+        return Err(ApiError::bad_request("window_days must not be negative"));
+    }
+    let linked = state
+        .with_db(move |db| Ok(transfer::match_transfers(db, user.id, options.window_days)?))
+        .await?;
+    Ok(Json(json!({ "linked": linked })))
+}
+
+// Takes a transaction id, either side of the transfer
+async fn unlink_transfer(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    state
+        .with_db(move |db| Ok(transfer::unlink(db, user.id, id)?))
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_trades(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+) -> Result<Json<Vec<Trade>>, ApiError> {
+    let trades = state
+        .with_db(move |db| {
+            if !db
+                .get_accounts_by_user(user.id)?
+                .iter()
+                .any(|a| *a.account_number() == account_number)
+            {
+                return Err(ApiError::not_found("Account not found"));
             }
+            Ok(db
+                .get_trades(user.id)?
+                .into_iter()
+                .filter(|t| t.account_number == account_number)
+                .collect())
+        })
+        .await?;
+    Ok(Json(trades))
+}
+
+async fn record_trade(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+    Json(new_trade): Json<NewTrade>,
+) -> Result<(StatusCode, Json<Trade>), ApiError> {
+    let trade = state
+        .with_db(move |db| {
+            let trade = investment::record_trade(db, user.id, account_number, new_trade)?;
+            transfer::match_transfers(db, user.id, transfer::DEFAULT_WINDOW_DAYS)?;
+            networth::take_snapshots(db, user.id, chrono::Local::now().date_naive())?;
+            Ok(trade)
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(trade)))
+}
+
+#[derive(Deserialize)]
+struct HoldingsQuery {
+    account_number: Option<i64>,
+    // defaults to today
+    date: Option<NaiveDate>,
+}
+
+async fn get_holdings(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Query(query): Query<HoldingsQuery>,
+) -> Result<Json<Vec<Holding>>, ApiError> {
+    let date = query
+        .date
+        .unwrap_or_else(|| chrono::Local::now().date_naive());
+    let holdings = state
+        .with_db(move |db| {
+            Ok(investment::holdings(
+                db,
+                user.id,
+                query.account_number,
+                date,
+            )?)
+        })
+        .await?;
+    Ok(Json(holdings))
+}
+
+async fn get_prices(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Price>>, ApiError> {
+    let prices = state.with_db(move |db| Ok(db.get_prices(user.id)?)).await?;
+    Ok(Json(prices))
+}
+
+// Takes the CSV itself as the body, with date, symbol and price (or close) columns
+async fn import_prices(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    body: String,
+) -> Result<(StatusCode, Json<serde_json::Value>), ApiError> {
+    let imported = state
+        .with_db(move |db| Ok(investment::import_prices(db, user.id, &body)?))
+        .await?;
+    Ok((StatusCode::CREATED, Json(json!({ "imported": imported }))))
+}
+
+fn registered_plan(account_type: &str) -> Result<AccountType, ApiError> {
+    account_type
+        .parse()
+        .ok()
+        .filter(AccountType::is_registered)
+        .ok_or_else(|| ApiError::bad_request("Expected tfsa, rrsp or fhsa"))
+}
+
+#[derive(Deserialize)]
+struct RoomQuery {
+    // last year shown, defaults to this year
+    year: Option<i32>,
+}
+
+async fn get_contribution_room(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_type): Path<String>,
+    Query(query): Query<RoomQuery>,
+) -> Result<Json<Vec<RoomYear>>, ApiError> {
+    let account_type = registered_plan(&account_type)?;
+    let year = query
+        .year
+        .unwrap_or_else(|| chrono::Local::now().date_naive().year());
+    let years = state
+        .with_db(move |db| Ok(investment::room(db, user.id, account_type, year)?))
+        .await?;
+    Ok(Json(years))
+}
+
+#[derive(Deserialize)]
+struct ReportedRoom {
+    // the room on January 1 from the CRA's notice of assessment or My Account
+    room: Money,
+}
+
+async fn set_contribution_room(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path((account_type, year)): Path<(String, i32)>,
+    Json(body): Json<ReportedRoom>,
+) -> Result<Json<Vec<RoomYear>>, ApiError> {
+    let account_type = registered_plan(&account_type)?;
+    if body.room.currency() != Currency::CAD {
+        return Err(ApiError::bad_request("Contribution room is in CAD"));
+    }
+    let years = state
+        .with_db(move |db| {
+            db.set_contribution_room(user.id, account_type, year, body.room)?;
+            Ok(investment::room(db, user.id, account_type, year)?)
+        })
+        .await?;
+    Ok(Json(years))
+}
+
+#[derive(Deserialize)]
+struct NetWorthQuery {
+    // defaults to the start of the month a year before `to`
+    from: Option<NaiveDate>,
+    // defaults to today
+    to: Option<NaiveDate>,
+    #[serde(default)]
+    interval: Interval,
+    // defaults to the user's reporting currency
+    currency: Option<Currency>,
+}
+
+async fn get_net_worth(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Query(query): Query<NetWorthQuery>,
+) -> Result<Json<NetWorth>, ApiError> {
+    let today = chrono::Local::now().date_naive();
+    let to = query.to.unwrap_or(today);
+    let from = match query.from {
+        Some(from) => from,
+        None => (to - chrono::Months::new(11)).with_day(1).unwrap(),
+    };
+    let worth = state
+        .with_db(move |db| {
+            networth::take_snapshots(db, user.id, today)?;
+            let currency = match query.currency {
+                Some(currency) => currency,
+                None => db.get_reporting_currency(user.id)?,
+            };
+            Ok(networth::net_worth(
+                db,
+                user.id,
+                from,
+                to,
+                query.interval,
+                currency,
+            )?)
+        })
+        .await?;
+    Ok(Json(worth))
+}
+
+#[derive(Deserialize)]
+struct InterestQuery {
+    // defaults to the start of the month a year before today
+    from: Option<NaiveDate>,
+    // defaults to the end of the month a year from today
+    to: Option<NaiveDate>,
+}
+
+impl InterestQuery {
+    fn range(&self, today: NaiveDate) -> Result<(NaiveDate, NaiveDate), ApiError> {
+        let month = today.with_day(1).unwrap();
+        let from = self.from.unwrap_or(month - chrono::Months::new(12));
+        let to = self
+            .to
+            .unwrap_or(month + chrono::Months::new(13) - chrono::Days::new(1));
+        if from > to {
+            return Err(ApiError::bad_request("from must not be after to"));
         }
-    })
-    .await
-    .unwrap();
+        Ok((from, to))
+    }
+}
+
+async fn get_interest(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+    Query(query): Query<InterestQuery>,
+) -> Result<Json<InterestEstimate>, ApiError> {
+    let today = chrono::Local::now().date_naive();
+    let (from, to) = query.range(today)?;
+    let estimate = state
+        .with_db(move |db| {
+            Ok(interest::estimate(
+                db,
+                user.id,
+                account_number,
+                from,
+                to,
+                today,
+            )?)
+        })
+        .await?;
+    Ok(Json(estimate))
+}
+
+async fn record_interest(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+    Query(query): Query<InterestQuery>,
+) -> Result<(StatusCode, Json<Vec<InterestPeriod>>), ApiError> {
+    let today = chrono::Local::now().date_naive();
+    let (from, to) = query.range(today)?;
+    let recorded = state
+        .with_db(move |db| {
+            Ok(interest::record_interest(
+                db,
+                user.id,
+                account_number,
+                from,
+                to,
+                today,
+            )?)
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(recorded)))
+}
+
+async fn get_interest_plan(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+) -> Result<Json<InterestPlan>, ApiError> {
+    let plan = state
+        .with_db(move |db| Ok(interest::get_plan(db, user.id, account_number)?))
+        .await?;
+    Ok(Json(plan))
+}
+
+async fn update_interest_plan(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+    Json(plan): Json<InterestPlan>,
+) -> Result<Json<InterestPlan>, ApiError> {
+    let plan = state
+        .with_db(move |db| Ok(interest::set_plan(db, user.id, account_number, plan)?))
+        .await?;
+    Ok(Json(plan))
+}
+
+async fn get_loan_terms(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+) -> Result<Json<LoanTerms>, ApiError> {
+    let terms = state
+        .with_db(move |db| Ok(loan::get_terms(db, user.id, account_number)?))
+        .await?;
+    Ok(Json(terms))
+}
+
+async fn update_loan_terms(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+    Json(terms): Json<LoanTerms>,
+) -> Result<Json<LoanTerms>, ApiError> {
+    let terms = state
+        .with_db(move |db| Ok(loan::set_terms(db, user.id, account_number, terms)?))
+        .await?;
+    Ok(Json(terms))
+}
+
+async fn get_amortization(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+) -> Result<Json<Amortization>, ApiError> {
+    let amortization = state
+        .with_db(move |db| Ok(loan::amortization(db, user.id, account_number)?))
+        .await?;
+    Ok(Json(amortization))
+}
+
+#[derive(Deserialize)]
+struct WhatIfRequest {
+    prepayments: Vec<Prepayment>,
+}
+
+async fn prepayment_what_if(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+    Json(body): Json<WhatIfRequest>,
+) -> Result<Json<WhatIf>, ApiError> {
+    let what_if = state
+        .with_db(move |db| {
+            Ok(loan::what_if(
+                db,
+                user.id,
+                account_number,
+                &body.prepayments,
+            )?)
+        })
+        .await?;
+    Ok(Json(what_if))
+}
+
+async fn match_loan_payments(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let matched = state
+        .with_db(move |db| {
+            let matched = loan::match_payments(db, user.id, account_number)?;
+            networth::take_snapshots(db, user.id, chrono::Local::now().date_naive())?;
+            Ok(matched)
+        })
+        .await?;
+    Ok(Json(json!({ "matched": matched })))
+}
+
+async fn get_reconciliation(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Reconciliation>>, ApiError> {
+    let reconciliations = state
+        .with_db(move |db| Ok(reconcile::reconcile(db, user.id)?))
+        .await?;
+    Ok(Json(reconciliations))
+}
+
+async fn get_account_ledger(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+) -> Result<Json<Vec<LedgerEntry>>, ApiError> {
+    let entries = state
+        .with_db(move |db| Ok(reconcile::ledger(db, user.id, account_number)?))
+        .await?;
+    Ok(Json(entries))
+}
+
+#[derive(Serialize)]
+struct LabeledAccount {
+    #[serde(flatten)]
+    account: Account,
+    #[serde(flatten)]
+    label: AccountLabel,
+    #[serde(flatten)]
+    period: AccountPeriod,
+}
+
+fn labeled_accounts(db: &Database, user_id: i64) -> Result<Vec<LabeledAccount>, ApiError> {
+    let mut labels = db.get_account_labels(user_id)?;
+    let periods = db.get_account_periods(user_id)?;
+    Ok(db
+        .get_accounts_by_user(user_id)?
+        .into_iter()
+        .map(|boxed_account| LabeledAccount {
+            label: labels
+                .remove(boxed_account.account_number())
+                .unwrap_or_default(),
+            period: periods
+                .get(boxed_account.account_number())
+                .copied()
+                .unwrap_or_default(),
+            account: boxed_account.as_enum(),
+        })
+        .collect())
+}
+
+fn labeled_account(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+) -> Result<LabeledAccount, ApiError> {
+    labeled_accounts(db, user_id)?
+        .into_iter()
+        .find(|labeled| labeled.account.account_number() == account_number)
+        .ok_or_else(|| ApiError::not_found("Account not found"))
+}
+
+async fn get_accounts(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<LabeledAccount>>, ApiError> {
+    let accounts = state
+        .with_db(move |db| labeled_accounts(db, user.id))
+        .await?;
+    Ok(Json(accounts))
+}
+
+async fn create_account(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(new_account): Json<NewAccount>,
+) -> Result<(StatusCode, Json<LabeledAccount>), ApiError> {
+    let account = state
+        .with_db(move |db| {
+            let account_number = manual::create_account(db, user.id, new_account)?;
+            labeled_account(db, user.id, account_number)
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(account)))
+}
+
+// Renames an account or changes its institution
+async fn update_account_label(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+    Json(changes): Json<AccountUpdate>,
+) -> Result<Json<AccountLabel>, ApiError> {
+    let label = state
+        .with_db(move |db| {
+            let mut label = db
+                .get_account_labels(user.id)?
+                .remove(&account_number)
+                .ok_or_else(|| ApiError::not_found("Account not found"))?;
+            changes.apply(&mut label);
+            db.update_account_label(user.id, account_number, &label)?;
+            Ok(label)
+        })
+        .await?;
+    Ok(Json(label))
+}
 
-    let mut user = state.user.lock().unwrap();
-    *user = fetched_user.clone();
+#[derive(Deserialize)]
+struct CloseAccount {
+    closed_on: NaiveDate,
+}
+
+async fn close_account(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+    Json(body): Json<CloseAccount>,
+) -> Result<Json<LabeledAccount>, ApiError> {
+    let account = state
+        .with_db(move |db| {
+            manual::close_account(db, user.id, account_number, body.closed_on)?;
+            labeled_account(db, user.id, account_number)
+        })
+        .await?;
+    Ok(Json(account))
+}
+
+async fn delete_account(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(account_number): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    state
+        .with_db(move |db| Ok(manual::delete_account(db, user.id, account_number)?))
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_rules(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<CategoryRule>>, ApiError> {
+    let rules = state
+        .with_db(move |db| Ok(db.get_category_rules(user.id)?))
+        .await?;
+    Ok(Json(rules))
+}
 
-    match fetched_user {
-        Some(user) => (StatusCode::OK, Json(user)),
-        None => (StatusCode::UNAUTHORIZED, Json(User::default())),
+async fn create_rule(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(mut rule): Json<CategoryRule>,
+) -> Result<(StatusCode, Json<CategoryRule>), ApiError> {
+    rule.user_id = user.id;
+    rule.validate()?;
+
+    let rule = state
+        .with_db(move |db| {
+            rule.id = db.insert_category_rule(&rule)?;
+            Ok(rule)
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+async fn update_rule(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(mut rule): Json<CategoryRule>,
+) -> Result<Json<CategoryRule>, ApiError> {
+    rule.id = id;
+    rule.user_id = user.id;
+    rule.validate()?;
+
+    let rule = state
+        .with_db(move |db| {
+            if !db.update_category_rule(&rule)? {
+                return Err(ApiError::not_found("Rule not found"));
+            }
+            Ok(rule)
+        })
+        .await?;
+    Ok(Json(rule))
+}
+
+async fn delete_rule(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let deleted = state
+        .with_db(move |db| Ok(db.delete_category_rule(user.id, id)?))
+        .await?;
+    if !deleted {
+        return Err(ApiError::not_found("Rule not found"));
     }
+    Ok(StatusCode::NO_CONTENT)
 }
 
-async fn _get_user(
-    axum::extract::Path(username): axum::extract::Path<String>,
+async fn get_categories(
+    AuthUser(user): AuthUser,
     State(state): State<AppState>,
-) -> (StatusCode, Json<User>) {
-    let conn = state.db.clone();
-    let fetched_user = task::spawn_blocking(move || {
-        let conn = conn.lock().unwrap();
-        conn.get_user_by_name(&username).unwrap()
-    })
-    .await
-    .unwrap();
+) -> Result<Json<Vec<Category>>, ApiError> {
+    let categories = state
+        .with_db(move |db| Ok(db.get_categories(user.id)?))
+        .await?;
+    Ok(Json(categories))
+}
+
+async fn create_category(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(new): Json<NewCategory>,
+) -> Result<(StatusCode, Json<Category>), ApiError> {
+    let category = state
+        .with_db(move |db| Ok(category::create(db, user.id, new)?))
+        .await?;
+    Ok((StatusCode::CREATED, Json(category)))
+}
+
+async fn update_category(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(changes): Json<CategoryUpdate>,
+) -> Result<Json<Category>, ApiError> {
+    let category = state
+        .with_db(move |db| Ok(category::update(db, user.id, id, changes)?))
+        .await?;
+    Ok(Json(category))
+}
 
-    let mut user = state.user.lock().unwrap();
-    *user = Some(fetched_user.clone());
+#[derive(Deserialize)]
+struct MergeRequest {
+    into: i64,
+}
+
+async fn merge_category(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(request): Json<MergeRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let moved = state
+        .with_db(move |db| Ok(category::merge(db, user.id, id, request.into)?))
+        .await?;
+    Ok(Json(json!({ "transactions_moved": moved })))
+}
 
-    (StatusCode::OK, Json(fetched_user))
+async fn delete_category(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let moved = state
+        .with_db(move |db| Ok(category::delete(db, user.id, id)?))
+        .await?;
+    Ok(Json(json!({ "transactions_moved": moved })))
 }
 
-async fn get_transactions(State(state): State<AppState>) -> (StatusCode, Json<Vec<Transaction>>) {
-    let conn = state.db.clone();
+#[derive(Deserialize)]
+struct TotalsQuery {
+    from: NaiveDate,
+    to: NaiveDate,
+    #[serde(default)]
+    rollup: bool,
+    // defaults to the user's reporting currency
+    currency: Option<Currency>,
+}
 
-    // if no user in state (logged in), return 401
-    let user_id = match state.user.lock().unwrap().as_ref() {
-        Some(user) => user.id,
-        None => return (StatusCode::UNAUTHORIZED, Json(Vec::new())),
+// The currency asked for or else the user's own, checked against the rates on file
+fn report_currency(
+    db: &Database,
+    user_id: i64,
+    currency: Option<Currency>,
+) -> Result<Currency, ApiError> {
+    let currency = match currency {
+        Some(currency) => currency,
+        None => db.get_reporting_currency(user_id)?,
     };
+    exchange::check_rates(db, user_id, currency)?;
+    Ok(currency)
+}
 
-    let transactions = task::spawn_blocking(move || {
-        let conn = conn.lock().unwrap();
-        conn.get_transactions(user_id).unwrap()
-    })
-    .await
-    .unwrap();
+async fn get_category_totals(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Query(query): Query<TotalsQuery>,
+) -> Result<Json<Vec<CategoryTotal>>, ApiError> {
+    let totals = state
+        .with_db(move |db| {
+            let currency = report_currency(db, user.id, query.currency)?;
+            Ok(db.category_totals(user.id, query.from, query.to, query.rollup, currency)?)
+        })
+        .await?;
+    Ok(Json(totals))
+}
 
-    (StatusCode::OK, Json(transactions))
+async fn get_merchants(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Merchant>>, ApiError> {
+    let merchants = state
+        .with_db(move |db| Ok(db.get_merchants(user.id)?))
+        .await?;
+    Ok(Json(merchants))
 }
 
-async fn get_accounts(State(state): State<AppState>) -> (StatusCode, Json<Vec<Account>>) {
-    let conn = state.db.clone();
+async fn update_merchant(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(update): Json<MerchantUpdate>,
+) -> Result<Json<Merchant>, ApiError> {
+    let merchant = state
+        .with_db(move |db| Ok(merchant::rename(db, user.id, id, &update)?))
+        .await?;
+    Ok(Json(merchant))
+}
 
-    // if no user in state (logged in), return 401
-    let user_id = match state.user.lock().unwrap().as_ref() {
-        Some(user) => user.id,
-        None => return (StatusCode::UNAUTHORIZED, Json(Vec::new())),
-    };
+#[derive(Deserialize)]
+struct NewAlias {
+    // a description as it appears on statements
+    alias: String,
+}
+
+async fn add_merchant_alias(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(new_alias): Json<NewAlias>,
+) -> Result<(StatusCode, Json<Merchant>), ApiError> {
+    let merchant = state
+        .with_db(move |db| Ok(merchant::add_alias(db, user.id, id, &new_alias.alias)?))
+        .await?;
+    Ok((StatusCode::CREATED, Json(merchant)))
+}
+
+async fn delete_merchant_alias(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path((id, alias_id)): Path<(i64, i64)>,
+) -> Result<StatusCode, ApiError> {
+    state
+        .with_db(move |db| Ok(merchant::remove_alias(db, user.id, id, alias_id)?))
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_budgets(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Budget>>, ApiError> {
+    let budgets = state
+        .with_db(move |db| Ok(db.get_budgets(user.id)?))
+        .await?;
+    Ok(Json(budgets))
+}
+
+async fn create_budget(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Json(mut new): Json<Budget>,
+) -> Result<(StatusCode, Json<Budget>), ApiError> {
+    new.user_id = user.id;
+    let budget = state
+        .with_db(move |db| Ok(budget::create(db, new)?))
+        .await?;
+    Ok((StatusCode::CREATED, Json(budget)))
+}
+
+async fn update_budget(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(mut changed): Json<Budget>,
+) -> Result<Json<Budget>, ApiError> {
+    changed.id = id;
+    changed.user_id = user.id;
+    let budget = state
+        .with_db(move |db| Ok(budget::update(db, changed)?))
+        .await?;
+    Ok(Json(budget))
+}
+
+async fn delete_budget(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let deleted = state
+        .with_db(move |db| Ok(db.delete_budget(user.id, id)?))
+        .await?;
+    if !deleted {
+        return Err(ApiError::not_found("Budget not found"));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    let accounts = task::spawn_blocking(move || {
-        let conn = conn.lock().unwrap();
-        conn.get_accounts_by_user(user_id)
-            .unwrap()
-            .into_iter()
-            .map(|boxed_account| boxed_account.as_enum())
-            .collect()
-    })
-    .await
-    .unwrap();
-
-    (StatusCode::OK, Json(accounts))
+#[derive(Deserialize)]
+struct BudgetStatusQuery {
+    // defaults to today
+    date: Option<NaiveDate>,
+    // number of earlier periods to include
+    #[serde(default = "default_budget_history")]
+    history: usize,
+}
+
+fn default_budget_history() -> usize {
+    3
+}
+
+async fn get_budget_status(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Query(query): Query<BudgetStatusQuery>,
+) -> Result<Json<Vec<BudgetStatus>>, ApiError> {
+    let date = query
+        .date
+        .unwrap_or_else(|| chrono::Local::now().date_naive());
+    let statuses = state
+        .with_db(move |db| Ok(budget::status(db, user.id, date, query.history)?))
+        .await?;
+    Ok(Json(statuses))
+}
+
+#[derive(Deserialize)]
+struct RecurringQuery {
+    // defaults to today
+    date: Option<NaiveDate>,
+}
+
+async fn get_recurring(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Query(query): Query<RecurringQuery>,
+) -> Result<Json<Vec<RecurringPayment>>, ApiError> {
+    let today = query
+        .date
+        .unwrap_or_else(|| chrono::Local::now().date_naive());
+    let payments = state
+        .with_db(move |db| Ok(recurring::detect(db, user.id, today)?))
+        .await?;
+    Ok(Json(payments))
+}
+
+#[derive(Deserialize)]
+struct MonthlyReportQuery {
+    // defaults to the start of the month a year before `to`
+    from: Option<NaiveDate>,
+    // defaults to today
+    to: Option<NaiveDate>,
+    // account number, all accounts when missing
+    account: Option<i64>,
+    // number of categories and merchants listed per month
+    #[serde(default = "default_report_top")]
+    top: usize,
+    // defaults to the user's reporting currency
+    currency: Option<Currency>,
+}
+
+fn default_report_top() -> usize {
+    5
+}
+
+async fn get_monthly_report(
+    AuthUser(user): AuthUser,
+    State(state): State<AppState>,
+    Query(query): Query<MonthlyReportQuery>,
+) -> Result<Json<Vec<MonthlySummary>>, ApiError> {
+    let to = query
+        .to
+        .unwrap_or_else(|| chrono::Local::now().date_naive());
+    let from = match query.from {
+        Some(from) => from,
+        None => (to - chrono::Months::new(11)).with_day(1).unwrap(),
+    };
+    if from > to {
+        return Err(ApiError::bad_request("from must not be after to"));
+    }
+    let report = state
+        .with_db(move |db| {
+            let filter = ReportFilter {
+                from,
+                to,
+                account_number: query.account,
+                currency: report_currency(db, user.id, query.currency)?,
+            };
+            Ok(report::monthly(db, user.id, &filter, query.top)?)
+        })
+        .await?;
+    Ok(Json(report))
 }
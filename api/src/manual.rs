@@ -0,0 +1,443 @@
+use core::fmt;
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::{
+    account::{
+        AccountLabel, AccountType, BankAccount, ChequingAccount, CreditAccount, InvestmentAccount,
+        LoanAccount, SavingsAccount,
+    },
+    database::Database,
+    loan::LoanTerms,
+    money::{Currency, Money},
+    reconcile::balance_change,
+    transaction::{Transaction, TransactionUpdate},
+};
+
+// An account without statements to import, such as cash or a gift card
+#[derive(Debug, Deserialize)]
+pub struct NewAccount {
+    pub account_type: AccountType,
+    pub nickname: String,
+    #[serde(default)]
+    pub institution: Option<String>,
+    pub opened_on: NaiveDate,
+    // also sets the account's currency. For a credit card or loan this is the
+    // amount owed.
+    pub opening_balance: Money,
+    #[serde(default)]
+    pub credit_limit: Option<Money>,
+    // yearly rate as a fraction, see `SavingsAccount::interest_rate`
+    #[serde(default)]
+    pub interest_rate: f64,
+    // loans: the terms the schedule is worked out from, see `loan::set_terms`
+    #[serde(default)]
+    pub loan: Option<LoanTerms>,
+}
+
+// A one-off transaction entered by hand
+#[derive(Debug, Deserialize)]
+pub struct NewTransaction {
+    pub account_number: i64,
+    pub transaction_date: NaiveDate,
+    pub description: String,
+    // signed like an imported row, negative for money spent
+    pub amount: Money,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub category_id: Option<i64>,
+    // category by name, created if the user has none by that name
+    #[serde(default)]
+    pub category: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ManualError {
+    AccountNotFound,
+    UnknownCategory,
+    InvalidName,
+    InvalidAccountType,
+    InvalidLoanTerms(String),
+    WrongCurrency { expected: Currency, found: Currency },
+    AccountClosed,
+    OutsidePeriod,
+    BalanceNotZero(Money),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for ManualError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ManualError::AccountNotFound => write!(f, "Account not found"),
+            ManualError::UnknownCategory => write!(f, "Category not found"),
+            ManualError::InvalidName => write!(f, "Name and description must not be empty"),
+            ManualError::InvalidAccountType => {
+                write!(f, "Accounts entered by hand cannot be of an unknown type")
+            }
+            ManualError::InvalidLoanTerms(msg) => write!(f, "{}", msg),
+            ManualError::WrongCurrency { expected, found } => write!(
+                f,
+                "Amount is in {} but the account is in {}",
+                found, expected
+            ),
+            ManualError::AccountClosed => write!(f, "Account is closed"),
+            ManualError::OutsidePeriod => {
+                write!(f, "Date is outside the time the account was open")
+            }
+            ManualError::BalanceNotZero(balance) => write!(
+                f,
+                "Account still has a balance of {}, bring it to zero before closing",
+                balance
+            ),
+            ManualError::Database(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ManualError {}
+
+impl From<rusqlite::Error> for ManualError {
+    fn from(err: rusqlite::Error) -> Self {
+        ManualError::Database(err)
+    }
+}
+
+fn find_account(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+) -> Result<Box<dyn BankAccount>, ManualError> {
+    db.get_accounts_by_user(user_id)?
+        .into_iter()
+        .find(|a| *a.account_number() == account_number)
+        .ok_or(ManualError::AccountNotFound)
+}
+
+fn check_currency(account: &dyn BankAccount, amount: Money) -> Result<(), ManualError> {
+    let expected = account.balance().currency();
+    if amount.currency() != expected {
+        return Err(ManualError::WrongCurrency {
+            expected,
+            found: amount.currency(),
+        });
+    }
+    Ok(())
+}
+
+// Creates the account with its opening balance recorded as a snapshot on the
+// day it was opened. Returns the new account number.
+pub fn create_account(db: &Database, user_id: i64, new: NewAccount) -> Result<i64, ManualError> {
+    let nickname = new.nickname.trim();
+    if nickname.is_empty() {
+        return Err(ManualError::InvalidName);
+    }
+    let balance = new.opening_balance;
+    let account: Box<dyn BankAccount> = match new.account_type {
+        AccountType::Chequing => Box::new(ChequingAccount::new(user_id, 0, balance)),
+        AccountType::Savings => {
+            Box::new(SavingsAccount::new(user_id, 0, balance, new.interest_rate))
+        }
+        AccountType::Credit => {
+            let limit = new.credit_limit.unwrap_or(Money::zero(balance.currency()));
+            let mut card = CreditAccount::new(user_id, 0, balance, limit);
+            card.interest_rate = new.interest_rate;
+            Box::new(card)
+        }
+        AccountType::NonRegistered | AccountType::Tfsa | AccountType::Rrsp | AccountType::Fhsa => {
+            Box::new(InvestmentAccount::new(
+                user_id,
+                0,
+                new.account_type,
+                balance,
+            ))
+        }
+        AccountType::Loan => {
+            if let Some(terms) = &new.loan {
+                terms
+                    .validate()
+                    .map_err(|e| ManualError::InvalidLoanTerms(e.to_string()))?;
+            }
+            Box::new(LoanAccount::new(user_id, 0, balance, new.loan))
+        }
+        AccountType::Unknown => return Err(ManualError::InvalidAccountType),
+    };
+    check_currency(account.as_ref(), account.credit_limit())?;
+    if let Some(terms) = &new.loan {
+        check_currency(account.as_ref(), terms.principal)?;
+    }
+
+    let label = AccountLabel {
+        nickname: Some(nickname.to_string()),
+        institution: new.institution,
+        mask: None,
+    };
+    let account_number = db.insert_manual_account(account.as_ref(), &label, new.opened_on)?;
+    if let (AccountType::Loan, Some(terms)) = (new.account_type, &new.loan) {
+        db.set_loan_terms(account_number, terms)?;
+    }
+    db.upsert_balance_snapshot(user_id, account_number, new.opened_on, balance)?;
+    Ok(account_number)
+}
+
+// Closes an account whose balance has been brought to zero. It stays in the
+// history up to `closed_on` but takes no new transactions.
+pub fn close_account(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+    closed_on: NaiveDate,
+) -> Result<(), ManualError> {
+    let account = find_account(db, user_id, account_number)?;
+    if !account.balance().is_zero() {
+        return Err(ManualError::BalanceNotZero(account.balance()));
+    }
+    let period = db
+        .get_account_periods(user_id)?
+        .remove(&account_number)
+        .unwrap_or_default();
+    let last = db
+        .get_account_transactions(user_id, account_number)?
+        .last()
+        .map(|t| t.transaction_date);
+    if period.opened_on.is_some_and(|opened| closed_on < opened)
+        || last.is_some_and(|last| closed_on < last)
+    {
+        return Err(ManualError::OutsidePeriod);
+    }
+    db.close_account(user_id, account_number, closed_on)?;
+    Ok(())
+}
+
+// Deletes the account and everything recorded against it
+pub fn delete_account(db: &Database, user_id: i64, account_number: i64) -> Result<(), ManualError> {
+    if !db.delete_account(user_id, account_number)? {
+        return Err(ManualError::AccountNotFound);
+    }
+    Ok(())
+}
+
+// Adds the transaction to one of the user's open accounts and moves the
+// account's balance by it
+pub fn add_transaction(
+    db: &Database,
+    user_id: i64,
+    new: NewTransaction,
+) -> Result<Transaction, ManualError> {
+    let mut account = find_account(db, user_id, new.account_number)?;
+    let period = db
+        .get_account_periods(user_id)?
+        .remove(&new.account_number)
+        .unwrap_or_default();
+    if period.closed_on.is_some() {
+        return Err(ManualError::AccountClosed);
+    }
+    if !period.contains(new.transaction_date) {
+        return Err(ManualError::OutsidePeriod);
+    }
+    check_currency(account.as_ref(), new.amount)?;
+    let description = new.description.trim();
+    if description.is_empty() {
+        return Err(ManualError::InvalidName);
+    }
+    if let Some(category_id) = new.category_id {
+        db.get_category(user_id, category_id)?
+            .ok_or(ManualError::UnknownCategory)?;
+    }
+
+    let mut transaction = Transaction {
+        id: 0,
+        user_id,
+        account_type: account.account_type(),
+        account_number: new.account_number,
+        transaction_date: new.transaction_date,
+        cheque_number: String::new(),
+        description_1: description.to_string(),
+        description_2: String::new(),
+        cad: Money::cad(0),
+        usd: Money::usd(0),
+        category_id: new.category_id,
+        category: new.category.unwrap_or_default(),
+        notes: String::new(),
+        merchant_id: None,
+        merchant: String::new(),
+        transfer_id: None,
+        statement_balance: None,
+        currency: new.amount.currency(),
+    };
+    transaction.set_amount(new.amount);
+    let transaction_id = db.insert_transaction(&transaction)?;
+    if !new.notes.is_empty() {
+        db.update_transaction(
+            user_id,
+            transaction_id,
+            &TransactionUpdate {
+                notes: Some(new.notes),
+                ..TransactionUpdate::default()
+            },
+        )?;
+    }
+
+    account.deposit(balance_change(account.account_type(), new.amount));
+    db.update_account(account.as_ref())?;
+    Ok(db
+        .get_transaction(user_id, transaction_id)?
+        .expect("transaction was just inserted"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::User;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn setup_test_db() -> Database {
+        let db = Database::new(":memory:".to_string()).unwrap();
+        for (id, name) in [(1, "Alice"), (2, "Bob")] {
+            db.insert_user(&User {
+                id,
+                name: name.into(),
+            })
+            .unwrap();
+        }
+        db
+    }
+
+    fn gift_card(balance: Money) -> NewAccount {
+        NewAccount {
+            account_type: AccountType::Savings,
+            nickname: " Gift card ".into(),
+            institution: Some("Indigo".into()),
+            opened_on: date(2025, 1, 10),
+            opening_balance: balance,
+            credit_limit: None,
+            interest_rate: 0.0,
+            loan: None,
+        }
+    }
+
+    fn purchase(account_number: i64, day: u32, amount: Money) -> NewTransaction {
+        NewTransaction {
+            account_number,
+            transaction_date: date(2025, 1, day),
+            description: "INDIGO BOOKS".into(),
+            amount,
+            notes: String::new(),
+            category_id: None,
+            category: Some("Shopping".into()),
+        }
+    }
+
+    #[test]
+    fn test_create_account_with_opening_balance() {
+        let db = setup_test_db();
+        let account_number = create_account(&db, 1, gift_card(Money::cad(5000))).unwrap();
+
+        let account = find_account(&db, 1, account_number).unwrap();
+        assert_eq!(account.balance(), Money::cad(5000));
+        let label = db.get_account_labels(1).unwrap()[&account_number].clone();
+        assert_eq!(label.nickname.as_deref(), Some("Gift card"));
+        assert_eq!(label.institution.as_deref(), Some("Indigo"));
+        let period = db.get_account_periods(1).unwrap()[&account_number];
+        assert_eq!(period.opened_on, Some(date(2025, 1, 10)));
+        assert_eq!(period.opening_balance, Some(Money::cad(5000)));
+        assert_eq!(db.get_balance_snapshots(1).unwrap().len(), 1);
+
+        assert!(matches!(
+            create_account(
+                &db,
+                1,
+                NewAccount {
+                    nickname: "  ".into(),
+                    ..gift_card(Money::cad(0))
+                }
+            ),
+            Err(ManualError::InvalidName)
+        ));
+        assert!(matches!(
+            create_account(
+                &db,
+                1,
+                NewAccount {
+                    account_type: AccountType::Credit,
+                    credit_limit: Some(Money::usd(100000)),
+                    ..gift_card(Money::cad(0))
+                }
+            ),
+            Err(ManualError::WrongCurrency { .. })
+        ));
+    }
+
+    #[test]
+    fn test_add_transaction_moves_balance() {
+        let db = setup_test_db();
+        let account_number = create_account(&db, 1, gift_card(Money::cad(5000))).unwrap();
+
+        let transaction = add_transaction(
+            &db,
+            1,
+            NewTransaction {
+                notes: "birthday present".into(),
+                ..purchase(account_number, 12, Money::cad(-2499))
+            },
+        )
+        .unwrap();
+        assert!(transaction.id > 0);
+        assert_eq!(transaction.amount(), Money::cad(-2499));
+        assert_eq!(transaction.category, "Shopping");
+        assert_eq!(transaction.notes, "birthday present");
+        assert_eq!(
+            find_account(&db, 1, account_number).unwrap().balance(),
+            Money::cad(2501)
+        );
+
+        // another user's account, the wrong currency and a day before opening
+        assert!(matches!(
+            add_transaction(&db, 2, purchase(account_number, 12, Money::cad(-100))),
+            Err(ManualError::AccountNotFound)
+        ));
+        assert!(matches!(
+            add_transaction(&db, 1, purchase(account_number, 12, Money::usd(-100))),
+            Err(ManualError::WrongCurrency { .. })
+        ));
+        assert!(matches!(
+            add_transaction(&db, 1, purchase(account_number, 9, Money::cad(-100))),
+            Err(ManualError::OutsidePeriod)
+        ));
+    }
+
+    #[test]
+    fn test_close_and_delete() {
+        let db = setup_test_db();
+        let account_number = create_account(&db, 1, gift_card(Money::cad(5000))).unwrap();
+        add_transaction(&db, 1, purchase(account_number, 15, Money::cad(-4000))).unwrap();
+
+        assert!(matches!(
+            close_account(&db, 1, account_number, date(2025, 1, 20)),
+            Err(ManualError::BalanceNotZero(balance)) if balance == Money::cad(1000)
+        ));
+        add_transaction(&db, 1, purchase(account_number, 18, Money::cad(-1000))).unwrap();
+        assert!(matches!(
+            close_account(&db, 1, account_number, date(2025, 1, 16)),
+            Err(ManualError::OutsidePeriod)
+        ));
+        close_account(&db, 1, account_number, date(2025, 1, 20)).unwrap();
+        assert!(matches!(
+            add_transaction(&db, 1, purchase(account_number, 19, Money::cad(-1))),
+            Err(ManualError::AccountClosed)
+        ));
+
+        assert!(matches!(
+            delete_account(&db, 2, account_number),
+            Err(ManualError::AccountNotFound)
+        ));
+        delete_account(&db, 1, account_number).unwrap();
+        assert!(db.get_accounts_by_user(1).unwrap().is_empty());
+        assert!(db.get_transactions(1).unwrap().is_empty());
+        assert!(db.get_balance_snapshots(1).unwrap().is_empty());
+    }
+}
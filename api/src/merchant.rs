@@ -0,0 +1,384 @@
+use core::fmt;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::database::Database;
+
+lazy_static! {
+    // Payment processors and platforms that put their own name in front of the merchant's
+    static ref PROCESSOR_PREFIX: Regex =
+        Regex::new(r"^(?:SQ|SQU|PAYPAL|PP|TST|SP|IZ|ZTL|CKO|FS|GOOGLE|APPLE\.COM/BILL)\s*\*\s*")
+            .unwrap();
+    // A store number and whatever follows it, usually the location
+    static ref STORE_NUMBER: Regex = Regex::new(r"#\s*\d.*$").unwrap();
+}
+
+// Trailing words that name where the purchase happened rather than who it was with
+#[rustfmt::skip]
+const LOCATIONS: &[&str] = &[
+    "ON", "QC", "BC", "AB", "MB", "SK", "NS", "NB", "NL", "PE", "YT", "NT", "NU", "CANADA",
+    "OTTAWA", "NEPEAN", "KANATA", "ORLEANS", "GLOUCESTER", "GATINEAU", "TORONTO", "NORTH YORK",
+    "SCARBOROUGH", "ETOBICOKE", "MISSISSAUGA", "BRAMPTON", "MARKHAM", "VAUGHAN", "OAKVILLE",
+    "HAMILTON", "KITCHENER", "WATERLOO", "KINGSTON", "MONTREAL", "LAVAL", "QUEBEC", "VANCOUVER",
+    "BURNABY", "SURREY", "VICTORIA", "CALGARY", "EDMONTON", "WINNIPEG", "REGINA", "SASKATOON",
+    "HALIFAX", "MONCTON", "FREDERICTON", "ST. JOHN'S", "CHARLOTTETOWN",
+];
+
+// Store numbers, terminal ids and reference codes: all digits, or letters mixed
+// with at least two digits (2K3L91, C12345). Short tokens like 7-ELEVEN's are kept.
+fn is_reference(token: &str) -> bool {
+    let digits = token.chars().filter(char::is_ascii_digit).count();
+    let alphanumeric = token.chars().all(|c| c.is_ascii_alphanumeric());
+    alphanumeric && (digits == token.len() || (digits >= 2 && token.len() >= 5))
+}
+
+// Reduces a statement description to the merchant it names, e.g.
+// `TIM HORTONS #7525, NEPEAN` and `SQ *TIM HORTONS 1234 OTTAWA ON` both become
+// `TIM HORTONS`. Returns an empty string when nothing is left to go on.
+pub fn normalize(description: &str) -> String {
+    let upper = description.trim().to_uppercase();
+    let mut rest = PROCESSOR_PREFIX.replace(&upper, "").into_owned();
+
+    // city after a comma, store number, then reference after a `*`
+    if let Some(idx) = rest.find(',') {
+        rest.truncate(idx);
+    }
+    rest = STORE_NUMBER.replace(&rest, "").into_owned();
+    if let Some(idx) = rest.find('*').filter(|&idx| idx > 0) {
+        rest.truncate(idx);
+    }
+
+    let mut tokens: Vec<&str> = rest
+        .split_whitespace()
+        .filter(|token| !is_reference(token))
+        .collect();
+    while tokens.len() > 1 {
+        let joined = tokens.join(" ");
+        let Some(location) = LOCATIONS
+            .iter()
+            .find(|location| joined.ends_with(&format!(" {}", location)))
+        else {
+            break;
+        };
+        let words = location.split_whitespace().count();
+        if tokens.len() <= words {
+            break;
+        }
+        tokens.truncate(tokens.len() - words);
+    }
+
+    let normalized = tokens.join(" ");
+    let normalized = normalized.trim_matches(|c: char| !c.is_alphanumeric() && c != '\'');
+    if normalized.is_empty() {
+        return upper.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+    normalized.to_string()
+}
+
+// Name shown for a newly seen merchant, `TIM HORTONS` becomes `Tim Hortons`
+pub fn display_name(key: &str) -> String {
+    key.split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_string() + &chars.as_str().to_lowercase(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// A merchant is identified by its normalized description, its key. Aliases
+// file other normalized descriptions under the same merchant.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Merchant {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub key: String,
+    pub aliases: Vec<MerchantAlias>,
+}
+
+impl Merchant {
+    pub fn from_row(row: &rusqlite::Row) -> Result<Merchant, rusqlite::Error> {
+        Ok(Merchant {
+            id: row.get("merchant_id")?,
+            user_id: row.get("user_id")?,
+            name: row.get("name")?,
+            key: row.get("key")?,
+            aliases: Vec::new(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MerchantAlias {
+    pub id: i64,
+    pub merchant_id: i64,
+    pub alias: String,
+}
+
+impl MerchantAlias {
+    pub fn from_row(row: &rusqlite::Row) -> Result<MerchantAlias, rusqlite::Error> {
+        Ok(MerchantAlias {
+            id: row.get("alias_id")?,
+            merchant_id: row.get("merchant_id")?,
+            alias: row.get("alias")?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MerchantUpdate {
+    pub name: String,
+}
+
+#[derive(Debug)]
+pub enum MerchantError {
+    NotFound,
+    InvalidName,
+    InvalidAlias,
+    AliasTaken,
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for MerchantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MerchantError::NotFound => write!(f, "Merchant not found"),
+            MerchantError::InvalidName => write!(f, "Merchant name must not be empty"),
+            MerchantError::InvalidAlias => write!(f, "Alias does not name a merchant"),
+            MerchantError::AliasTaken => write!(f, "Alias is already in use"),
+            MerchantError::Database(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MerchantError {}
+
+impl From<rusqlite::Error> for MerchantError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err {
+            rusqlite::Error::SqliteFailure(e, _)
+                if e.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                MerchantError::AliasTaken
+            }
+            err => MerchantError::Database(err),
+        }
+    }
+}
+
+pub fn rename(
+    db: &Database,
+    user_id: i64,
+    merchant_id: i64,
+    update: &MerchantUpdate,
+) -> Result<Merchant, MerchantError> {
+    let name = update.name.trim();
+    if name.is_empty() {
+        return Err(MerchantError::InvalidName);
+    }
+    if !db.rename_merchant(user_id, merchant_id, name)? {
+        return Err(MerchantError::NotFound);
+    }
+    db.get_merchant(user_id, merchant_id)?
+        .ok_or(MerchantError::NotFound)
+}
+
+// Files descriptions normalizing to `alias` under the merchant. A merchant
+// already known by that name is merged into it along with its transactions.
+pub fn add_alias(
+    db: &Database,
+    user_id: i64,
+    merchant_id: i64,
+    alias: &str,
+) -> Result<Merchant, MerchantError> {
+    let key = normalize(alias);
+    if key.is_empty() {
+        return Err(MerchantError::InvalidAlias);
+    }
+    let merchant = db
+        .get_merchant(user_id, merchant_id)?
+        .ok_or(MerchantError::NotFound)?;
+    if merchant.key == key {
+        return Err(MerchantError::AliasTaken);
+    }
+    db.insert_merchant_alias(user_id, merchant_id, &key)?;
+    db.get_merchant(user_id, merchant_id)?
+        .ok_or(MerchantError::NotFound)
+}
+
+// New descriptions matching the alias get a merchant of their own again,
+// transactions already filed under the merchant stay there
+pub fn remove_alias(
+    db: &Database,
+    user_id: i64,
+    merchant_id: i64,
+    alias_id: i64,
+) -> Result<(), MerchantError> {
+    if !db.delete_merchant_alias(user_id, merchant_id, alias_id)? {
+        return Err(MerchantError::NotFound);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::ChequingAccount;
+    use crate::money::Money;
+    use crate::transaction::{Transaction, TransactionQuery};
+    use crate::user::User;
+
+    #[test]
+    fn test_normalize() {
+        let cases = [
+            ("TIM HORTONS #7525, NEPEAN", "TIM HORTONS"),
+            ("TIM HORTONS #1234 OTTAWA", "TIM HORTONS"),
+            ("Tim Hortons 1234 Ottawa ON", "TIM HORTONS"),
+            ("SQ *BLUE BOTTLE COFFEE", "BLUE BOTTLE COFFEE"),
+            ("PAYPAL *NETFLIX", "NETFLIX"),
+            ("AMZN MKTP CA*2K3L91", "AMZN MKTP CA"),
+            ("UBER* TRIP 2K3L9X", "UBER"),
+            ("SHELL C12345 KANATA ON", "SHELL"),
+            ("LOBLAWS 1019", "LOBLAWS"),
+            ("7-ELEVEN 35012", "7-ELEVEN"),
+            ("LONDON DRUGS 12", "LONDON DRUGS"),
+            ("  OTTAWA  ", "OTTAWA"),
+            ("MCDONALD'S #40123", "MCDONALD'S"),
+        ];
+        for (description, expected) in cases {
+            assert_eq!(normalize(description), expected, "{}", description);
+        }
+        assert_eq!(normalize("   "), "");
+    }
+
+    #[test]
+    fn test_display_name() {
+        assert_eq!(display_name("TIM HORTONS"), "Tim Hortons");
+        assert_eq!(display_name("AMAZON.CA"), "Amazon.ca");
+    }
+
+    fn merchant_db() -> Database {
+        let db = Database::new(":memory:".to_string()).unwrap();
+        db.insert_user(&User {
+            id: 1,
+            name: "Alice".into(),
+        })
+        .unwrap();
+        db.insert_account(&ChequingAccount {
+            user_id: 1,
+            account_number: 1001,
+            balance: Money::cad(0),
+        })
+        .unwrap();
+        for (day, description) in [
+            (1, "TIM HORTONS #7525, NEPEAN"),
+            (2, "TIM HORTONS #1234 OTTAWA"),
+            (3, "AMZN MKTP CA*2K3L91"),
+            (4, "AMAZON.CA"),
+        ] {
+            db.insert_transaction(&Transaction {
+                user_id: 1,
+                account_number: 1001,
+                transaction_date: chrono::NaiveDate::from_ymd_opt(2025, 6, day).unwrap(),
+                description_1: description.into(),
+                ..Transaction::dummy()
+            })
+            .unwrap();
+        }
+        db
+    }
+
+    fn merchant_named(db: &Database, name: &str) -> Merchant {
+        db.get_merchants(1)
+            .unwrap()
+            .into_iter()
+            .find(|m| m.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_transactions_share_a_merchant() {
+        let db = merchant_db();
+        let names: Vec<String> = db
+            .get_merchants(1)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.name)
+            .collect();
+        assert_eq!(names, vec!["Amazon.ca", "Amzn Mktp Ca", "Tim Hortons"]);
+
+        let tims = merchant_named(&db, "Tim Hortons");
+        let page = db
+            .query_transactions(
+                1,
+                &TransactionQuery {
+                    merchant_id: Some(tims.id),
+                    ..TransactionQuery::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(page.total, 2);
+        assert!(page
+            .transactions
+            .iter()
+            .all(|t| t.merchant == "Tim Hortons"));
+    }
+
+    #[test]
+    fn test_alias_merges_merchants() {
+        let db = merchant_db();
+        let amazon = merchant_named(&db, "Amazon.ca");
+        rename(
+            &db,
+            1,
+            amazon.id,
+            &MerchantUpdate {
+                name: "Amazon".into(),
+            },
+        )
+        .unwrap();
+
+        let merged = add_alias(&db, 1, amazon.id, "AMZN MKTP CA*XYZ123").unwrap();
+        assert_eq!(merged.aliases.len(), 1);
+        assert_eq!(merged.aliases[0].alias, "AMZN MKTP CA");
+        assert_eq!(db.get_merchants(1).unwrap().len(), 2);
+
+        // existing and future transactions land on the merchant
+        db.insert_transaction(&Transaction {
+            user_id: 1,
+            account_number: 1001,
+            description_1: "AMZN MKTP CA*99ZZ88".into(),
+            ..Transaction::dummy()
+        })
+        .unwrap();
+        let amazon_total = db
+            .get_transactions(1)
+            .unwrap()
+            .iter()
+            .filter(|t| t.merchant_id == Some(amazon.id) && t.merchant == "Amazon")
+            .count();
+        assert_eq!(amazon_total, 3);
+
+        let tims = merchant_named(&db, "Tim Hortons");
+        assert!(matches!(
+            add_alias(&db, 1, tims.id, "AMZN MKTP CA"),
+            Err(MerchantError::AliasTaken)
+        ));
+        assert!(matches!(
+            add_alias(&db, 1, tims.id, "  "),
+            Err(MerchantError::InvalidAlias)
+        ));
+
+        remove_alias(&db, 1, amazon.id, merged.aliases[0].id).unwrap();
+        assert!(matches!(
+            remove_alias(&db, 1, amazon.id, merged.aliases[0].id),
+            Err(MerchantError::NotFound)
+        ));
+    }
+}
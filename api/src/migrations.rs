@@ -0,0 +1,1607 @@
+use rusqlite::{ffi, Connection, Error, Result, Transaction};
+
+use chrono::NaiveDate;
+use lazy_static::lazy_static;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+// Schema migrations, applied in order by `Database::new`.
+// The applied version is tracked in `PRAGMA user_version`, so a fresh
+// database starts at 0 and runs everything. Never edit a migration that
+// has shipped, add a new one at the end instead.
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    up: fn(&Transaction) -> Result<()>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        up: initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "transaction fingerprints",
+        up: transaction_fingerprints,
+    },
+    Migration {
+        version: 3,
+        description: "money as integer minor units",
+        up: money_minor_units,
+    },
+    Migration {
+        version: 4,
+        description: "ISO-8601 transaction dates",
+        up: iso_transaction_dates,
+    },
+    Migration {
+        version: 5,
+        description: "passwords and sessions",
+        up: passwords_and_sessions,
+    },
+    Migration {
+        version: 6,
+        description: "category rules",
+        up: category_rules,
+    },
+    Migration {
+        version: 7,
+        description: "category hierarchy",
+        up: category_hierarchy,
+    },
+    Migration {
+        version: 8,
+        description: "transaction notes",
+        up: transaction_notes,
+    },
+    Migration {
+        version: 9,
+        description: "budgets",
+        up: budgets,
+    },
+    Migration {
+        version: 10,
+        description: "transaction query indexes",
+        up: transaction_query_indexes,
+    },
+    Migration {
+        version: 11,
+        description: "transaction full-text search",
+        up: transaction_search,
+    },
+    Migration {
+        version: 12,
+        description: "merchants",
+        up: merchants,
+    },
+    Migration {
+        version: 13,
+        description: "transfer links",
+        up: transfer_links,
+    },
+    Migration {
+        version: 14,
+        description: "statement balances",
+        up: statement_balances,
+    },
+    Migration {
+        version: 15,
+        description: "balance snapshots",
+        up: balance_snapshots,
+    },
+    Migration {
+        version: 16,
+        description: "interest plans",
+        up: interest_plans,
+    },
+    Migration {
+        version: 17,
+        description: "currencies and exchange rates",
+        up: currencies,
+    },
+    Migration {
+        version: 18,
+        description: "stable account numbers",
+        up: account_keys,
+    },
+    Migration {
+        version: 19,
+        description: "account opening and closing",
+        up: account_periods,
+    },
+    Migration {
+        version: 20,
+        description: "investments",
+        up: investments,
+    },
+    Migration {
+        version: 21,
+        description: "loans",
+        up: loans,
+    },
+    Migration {
+        version: 22,
+        description: "keyed account numbers",
+        up: keyed_account_numbers,
+    },
+    Migration {
+        version: 23,
+        description: "exchange rates per user",
+        up: user_exchange_rates,
+    },
+    Migration {
+        version: 24,
+        description: "prices per user",
+        up: user_prices,
+    },
+];
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub fn current_version(conn: &Connection) -> Result<i64> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    migrate_to(conn, latest_version())
+}
+
+// Applies every migration above the current version up to and including `target`.
+// Each migration runs in its own transaction together with the version bump,
+// so a failure leaves the database at the last good version.
+pub fn migrate_to(conn: &mut Connection, target: i64) -> Result<()> {
+    let current = current_version(conn)?;
+    if current > latest_version() {
+        return Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_MISMATCH),
+            Some(format!(
+                "database schema version {} is newer than supported version {}",
+                current,
+                latest_version()
+            )),
+        ));
+    }
+
+    // foreign keys have to be off while tables are rebuilt, otherwise
+    // dropping a referenced table cascades into its children
+    conn.pragma_update(None, "foreign_keys", "OFF")?;
+
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current && m.version <= target)
+    {
+        let tx = conn.transaction()?;
+        (migration.up)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    conn.pragma_update(None, "foreign_keys", "ON")?;
+    Ok(())
+}
+
+fn initial_schema(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS Users (
+            user_id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS Account (
+            user_id INTEGER NOT NULL,
+            account_type TEXT,
+            account_number INTEGER PRIMARY KEY,
+            balance REAL,
+            interest_rate REAL,
+            credit_limit REAL,
+
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS Transactions(
+            transaction_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            account_number INTEGER NOT NULL,
+            account_type TEXT NOT NULL,
+            transaction_date TEXT NOT NULL,
+            cheque_number TEXT,
+            description_1 TEXT,
+            description_2 TEXT,
+            cad REAL,
+            usd REAL,
+            category TEXT,
+
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE,
+            FOREIGN KEY(account_number) REFERENCES Account(account_number) ON DELETE CASCADE ON UPDATE CASCADE
+        );",
+    )
+}
+
+// Fingerprints as the migrations that write them computed them. These are copies
+// rather than calls into `transaction`, so changing how imports fingerprint rows
+// cannot change what an old migration writes. Such a change needs a migration of
+// its own, see `test_fingerprints_agree_with_imports`.
+fn fingerprint_key(
+    account_number: i64,
+    transaction_date: &str,
+    cad: i64,
+    usd: i64,
+    description_1: &str,
+    description_2: &str,
+) -> String {
+    let normalize = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+    format!(
+        "{}\x1f{}\x1f{}\x1f{}\x1f{}\x1f{}",
+        account_number,
+        transaction_date.trim(),
+        cad,
+        usd,
+        normalize(description_1),
+        normalize(description_2),
+    )
+}
+
+fn fingerprint_from_key(key: &str, occurrence: u32) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(key.as_bytes());
+    hasher.update(b"\x1f");
+    hasher.update(occurrence.to_string().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+// Adds a unique fingerprint per transaction so re-imports can be detected.
+// Existing rows are numbered in insertion order, so rows that were already
+// imported twice stay as two occurrences instead of failing the unique index.
+fn transaction_fingerprints(tx: &Transaction) -> Result<()> {
+    tx.execute("ALTER TABLE Transactions ADD COLUMN fingerprint TEXT", ())?;
+
+    let rows = {
+        let mut stmt = tx.prepare(
+            "SELECT transaction_id, account_number, transaction_date, cad, usd, description_1, description_2
+            FROM Transactions ORDER BY transaction_id",
+        )?;
+        let rows = stmt.query_map((), |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                real_fingerprint_key(
+                    row.get(1)?,
+                    &row.get::<_, String>(2)?,
+                    row.get::<_, Option<f64>>(3)?.unwrap_or(0.0),
+                    row.get::<_, Option<f64>>(4)?.unwrap_or(0.0),
+                    &row.get::<_, Option<String>>(5)?.unwrap_or_default(),
+                    &row.get::<_, Option<String>>(6)?.unwrap_or_default(),
+                ),
+            ))
+        })?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+
+    let mut occurrences = std::collections::HashMap::new();
+    let mut update =
+        tx.prepare("UPDATE Transactions SET fingerprint = ? WHERE transaction_id = ?")?;
+    for (transaction_id, key) in rows {
+        let occurrence = occurrences.entry(key.clone()).or_insert(0);
+        update.execute((fingerprint_from_key(&key, *occurrence), transaction_id))?;
+        *occurrence += 1;
+    }
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_transactions_fingerprint ON Transactions(fingerprint)",
+        (),
+    )?;
+    Ok(())
+}
+
+// The key as version 2 computed it, from amounts stored as REAL dollars
+fn real_fingerprint_key(
+    account_number: i64,
+    transaction_date: &str,
+    cad: f64,
+    usd: f64,
+    description_1: &str,
+    description_2: &str,
+) -> String {
+    fingerprint_key(
+        account_number,
+        transaction_date,
+        (cad * 100.0).round() as i64,
+        (usd * 100.0).round() as i64,
+        description_1,
+        description_2,
+    )
+}
+
+// Rebuilds the tables with amounts stored as INTEGER cents. SQLite keeps REAL affinity
+// on the old columns, so converting in place would turn the integers back into floats.
+fn money_minor_units(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE Account_new (
+            user_id INTEGER NOT NULL,
+            account_type TEXT,
+            account_number INTEGER PRIMARY KEY,
+            balance INTEGER NOT NULL DEFAULT 0,
+            interest_rate REAL,
+            credit_limit INTEGER NOT NULL DEFAULT 0,
+
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE
+        );
+        INSERT INTO Account_new (user_id, account_type, account_number, balance, interest_rate, credit_limit)
+            SELECT user_id, account_type, account_number,
+                CAST(ROUND(COALESCE(balance, 0) * 100) AS INTEGER),
+                interest_rate,
+                CAST(ROUND(COALESCE(credit_limit, 0) * 100) AS INTEGER)
+            FROM Account;
+        DROP TABLE Account;
+        ALTER TABLE Account_new RENAME TO Account;
+
+        CREATE TABLE Transactions_new(
+            transaction_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            account_number INTEGER NOT NULL,
+            account_type TEXT NOT NULL,
+            transaction_date TEXT NOT NULL,
+            cheque_number TEXT,
+            description_1 TEXT,
+            description_2 TEXT,
+            cad INTEGER NOT NULL DEFAULT 0,
+            usd INTEGER NOT NULL DEFAULT 0,
+            category TEXT,
+            fingerprint TEXT,
+
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE,
+            FOREIGN KEY(account_number) REFERENCES Account(account_number) ON DELETE CASCADE ON UPDATE CASCADE
+        );
+        INSERT INTO Transactions_new (transaction_id, user_id, account_number, account_type, transaction_date,
+                cheque_number, description_1, description_2, cad, usd, category, fingerprint)
+            SELECT transaction_id, user_id, account_number, account_type, transaction_date,
+                cheque_number, description_1, description_2,
+                CAST(ROUND(COALESCE(cad, 0) * 100) AS INTEGER),
+                CAST(ROUND(COALESCE(usd, 0) * 100) AS INTEGER),
+                category, fingerprint
+            FROM Transactions;
+        DROP TABLE Transactions;
+        ALTER TABLE Transactions_new RENAME TO Transactions;
+        CREATE UNIQUE INDEX idx_transactions_fingerprint ON Transactions(fingerprint);",
+    )
+}
+
+// Date formats seen in bank exports before version 4, tried in order. A copy of
+// what the parser read then, so later formats do not change this migration.
+const LEGACY_DATE_FORMATS: &[&str] =
+    &["%Y-%m-%d", "%m/%d/%Y", "%b %d, %Y", "%B %d, %Y", "%Y/%m/%d"];
+
+fn legacy_date(date: &str) -> Option<NaiveDate> {
+    LEGACY_DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(date.trim(), format).ok())
+}
+
+// Rewrites every transaction date as YYYY-MM-DD so they sort and compare as text,
+// then recomputes fingerprints since they include the date. A date that cannot be
+// parsed is not guessed: the migration fails naming every such row, so they can be
+// dated by hand before upgrading again.
+fn iso_transaction_dates(tx: &Transaction) -> Result<()> {
+    let rows = {
+        let mut stmt = tx.prepare(
+            "SELECT transaction_id, account_number, transaction_date, cad, usd, description_1, description_2
+            FROM Transactions ORDER BY transaction_id",
+        )?;
+        let rows = stmt.query_map((), |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, Option<String>>(5)?.unwrap_or_default(),
+                row.get::<_, Option<String>>(6)?.unwrap_or_default(),
+            ))
+        })?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+
+    tx.execute("DROP INDEX idx_transactions_fingerprint", ())?;
+
+    let mut occurrences = std::collections::HashMap::new();
+    let mut update = tx.prepare(
+        "UPDATE Transactions SET transaction_date = ?, fingerprint = ? WHERE transaction_id = ?",
+    )?;
+    let mut undated = Vec::new();
+    for (transaction_id, account_number, date, cad, usd, description_1, description_2) in rows {
+        let Some(date) = legacy_date(&date) else {
+            undated.push(format!("transaction {} ({:?})", transaction_id, date));
+            continue;
+        };
+        let date = date.to_string();
+        let key = fingerprint_key(
+            account_number,
+            &date,
+            cad,
+            usd,
+            &description_1,
+            &description_2,
+        );
+        let occurrence = occurrences.entry(key.clone()).or_insert(0);
+        update.execute((
+            &date,
+            fingerprint_from_key(&key, *occurrence),
+            transaction_id,
+        ))?;
+        *occurrence += 1;
+    }
+    if !undated.is_empty() {
+        return Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_CONSTRAINT),
+            Some(format!("unreadable dates: {}", undated.join(", "))),
+        ));
+    }
+
+    tx.execute_batch(
+        "CREATE UNIQUE INDEX idx_transactions_fingerprint ON Transactions(fingerprint);
+        CREATE INDEX idx_transactions_user_date ON Transactions(user_id, transaction_date);
+        CREATE INDEX idx_transactions_account_date ON Transactions(account_number, transaction_date);",
+    )
+}
+
+// Existing users keep a NULL password hash until they set one.
+// Session tokens are only stored hashed.
+fn passwords_and_sessions(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE Users ADD COLUMN password_hash TEXT;
+        CREATE UNIQUE INDEX idx_users_name ON Users(name);
+
+        CREATE TABLE Sessions (
+            token_hash TEXT PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE
+        );
+        CREATE INDEX idx_sessions_user ON Sessions(user_id);",
+    )
+}
+
+// User-editable categorization rules. Conditions left NULL are not checked,
+// amounts are in minor units like everywhere else.
+fn category_rules(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE CategoryRules (
+            rule_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            category TEXT NOT NULL,
+            priority INTEGER NOT NULL DEFAULT 100,
+            description_contains TEXT,
+            description_regex TEXT,
+            min_amount INTEGER,
+            max_amount INTEGER,
+            account_type TEXT,
+
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE
+        );
+        CREATE INDEX idx_category_rules_user ON CategoryRules(user_id);",
+    )
+}
+
+// Categories become rows with stable ids and an optional parent. Every distinct
+// category name already on a user's transactions becomes a top-level category,
+// and Transactions is rebuilt to point at it by id instead of holding the name.
+fn category_hierarchy(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE Categories (
+            category_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            parent_id INTEGER,
+            name TEXT NOT NULL,
+            color TEXT,
+            icon TEXT,
+
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE,
+            FOREIGN KEY(parent_id) REFERENCES Categories(category_id) ON DELETE SET NULL
+        );
+        CREATE UNIQUE INDEX idx_categories_user_name ON Categories(user_id, name COLLATE NOCASE);
+        CREATE INDEX idx_categories_parent ON Categories(parent_id);
+
+        INSERT INTO Categories (user_id, name)
+            SELECT user_id, MIN(TRIM(category)) FROM Transactions
+            WHERE TRIM(COALESCE(category, '')) <> ''
+            GROUP BY user_id, TRIM(category) COLLATE NOCASE;
+
+        CREATE TABLE Transactions_new(
+            transaction_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            account_number INTEGER NOT NULL,
+            account_type TEXT NOT NULL,
+            transaction_date TEXT NOT NULL,
+            cheque_number TEXT,
+            description_1 TEXT,
+            description_2 TEXT,
+            cad INTEGER NOT NULL DEFAULT 0,
+            usd INTEGER NOT NULL DEFAULT 0,
+            category_id INTEGER,
+            fingerprint TEXT,
+
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE,
+            FOREIGN KEY(account_number) REFERENCES Account(account_number) ON DELETE CASCADE ON UPDATE CASCADE,
+            FOREIGN KEY(category_id) REFERENCES Categories(category_id) ON DELETE SET NULL
+        );
+        INSERT INTO Transactions_new (transaction_id, user_id, account_number, account_type, transaction_date,
+                cheque_number, description_1, description_2, cad, usd, category_id, fingerprint)
+            SELECT t.transaction_id, t.user_id, t.account_number, t.account_type, t.transaction_date,
+                t.cheque_number, t.description_1, t.description_2, t.cad, t.usd,
+                (SELECT c.category_id FROM Categories c
+                    WHERE c.user_id = t.user_id AND c.name = TRIM(t.category) COLLATE NOCASE),
+                t.fingerprint
+            FROM Transactions t;
+        DROP TABLE Transactions;
+        ALTER TABLE Transactions_new RENAME TO Transactions;
+        CREATE UNIQUE INDEX idx_transactions_fingerprint ON Transactions(fingerprint);
+        CREATE INDEX idx_transactions_user_date ON Transactions(user_id, transaction_date);
+        CREATE INDEX idx_transactions_account_date ON Transactions(account_number, transaction_date);
+        CREATE INDEX idx_transactions_category ON Transactions(category_id);",
+    )
+}
+
+// Free-form notes users can attach to a transaction
+fn transaction_notes(tx: &Transaction) -> Result<()> {
+    tx.execute_batch("ALTER TABLE Transactions ADD COLUMN notes TEXT;")
+}
+
+// One budget per category and period type. Amounts are CAD minor units.
+fn budgets(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE Budgets (
+            budget_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            category_id INTEGER NOT NULL,
+            period TEXT NOT NULL CHECK (period IN ('weekly', 'monthly', 'quarterly', 'yearly')),
+            amount INTEGER NOT NULL CHECK (amount > 0),
+            rollover INTEGER NOT NULL DEFAULT 0,
+            start_date TEXT NOT NULL,
+
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE,
+            FOREIGN KEY(category_id) REFERENCES Categories(category_id) ON DELETE CASCADE
+        );
+        CREATE UNIQUE INDEX idx_budgets_category_period ON Budgets(user_id, category_id, period);",
+    )
+}
+
+// Filters offered by the transaction listing besides date and category
+fn transaction_query_indexes(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE INDEX idx_transactions_user_type_date ON Transactions(user_id, account_type, transaction_date);
+        CREATE INDEX idx_transactions_user_amount ON Transactions(user_id, cad);",
+    )
+}
+
+// FTS5 index over the descriptions, its rowid is the transaction_id. Triggers keep it
+// in step with Transactions so every insert and edit path is covered.
+fn transaction_search(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE TransactionSearch USING fts5(
+            description_1, description_2,
+            content = 'Transactions', content_rowid = 'transaction_id'
+        );
+        INSERT INTO TransactionSearch(TransactionSearch) VALUES ('rebuild');
+
+        CREATE TRIGGER transactions_search_insert AFTER INSERT ON Transactions BEGIN
+            INSERT INTO TransactionSearch(rowid, description_1, description_2)
+                VALUES (new.transaction_id, new.description_1, new.description_2);
+        END;
+        CREATE TRIGGER transactions_search_delete AFTER DELETE ON Transactions BEGIN
+            INSERT INTO TransactionSearch(TransactionSearch, rowid, description_1, description_2)
+                VALUES ('delete', old.transaction_id, old.description_1, old.description_2);
+        END;
+        CREATE TRIGGER transactions_search_update AFTER UPDATE OF description_1, description_2 ON Transactions BEGIN
+            INSERT INTO TransactionSearch(TransactionSearch, rowid, description_1, description_2)
+                VALUES ('delete', old.transaction_id, old.description_1, old.description_2);
+            INSERT INTO TransactionSearch(rowid, description_1, description_2)
+                VALUES (new.transaction_id, new.description_1, new.description_2);
+        END;",
+    )
+}
+
+lazy_static! {
+    static ref PROCESSOR_PREFIX: Regex =
+        Regex::new(r"^(?:SQ|SQU|PAYPAL|PP|TST|SP|IZ|ZTL|CKO|FS|GOOGLE|APPLE\.COM/BILL)\s*\*\s*")
+            .unwrap();
+    static ref STORE_NUMBER: Regex = Regex::new(r"#\s*\d.*$").unwrap();
+}
+
+#[rustfmt::skip]
+const LOCATIONS: &[&str] = &[
+    "ON", "QC", "BC", "AB", "MB", "SK", "NS", "NB", "NL", "PE", "YT", "NT", "NU", "CANADA",
+    "OTTAWA", "NEPEAN", "KANATA", "ORLEANS", "GLOUCESTER", "GATINEAU", "TORONTO", "NORTH YORK",
+    "SCARBOROUGH", "ETOBICOKE", "MISSISSAUGA", "BRAMPTON", "MARKHAM", "VAUGHAN", "OAKVILLE",
+    "HAMILTON", "KITCHENER", "WATERLOO", "KINGSTON", "MONTREAL", "LAVAL", "QUEBEC", "VANCOUVER",
+    "BURNABY", "SURREY", "VICTORIA", "CALGARY", "EDMONTON", "WINNIPEG", "REGINA", "SASKATOON",
+    "HALIFAX", "MONCTON", "FREDERICTON", "ST. JOHN'S", "CHARLOTTETOWN",
+];
+
+fn is_reference(token: &str) -> bool {
+    let digits = token.chars().filter(char::is_ascii_digit).count();
+    let alphanumeric = token.chars().all(|c| c.is_ascii_alphanumeric());
+    alphanumeric && (digits == token.len() || (digits >= 2 && token.len() >= 5))
+}
+
+// Merchant keys and names as version 15 computed them, copied from `merchant` for
+// the same reason as the fingerprint functions, see
+// `test_merchant_keys_agree_with_imports`.
+fn merchant_key(description: &str) -> String {
+    let upper = description.trim().to_uppercase();
+    let mut rest = PROCESSOR_PREFIX.replace(&upper, "").into_owned();
+
+    if let Some(idx) = rest.find(',') {
+        rest.truncate(idx);
+    }
+    rest = STORE_NUMBER.replace(&rest, "").into_owned();
+    if let Some(idx) = rest.find('*').filter(|&idx| idx > 0) {
+        rest.truncate(idx);
+    }
+
+    let mut tokens: Vec<&str> = rest
+        .split_whitespace()
+        .filter(|token| !is_reference(token))
+        .collect();
+    while tokens.len() > 1 {
+        let joined = tokens.join(" ");
+        let Some(location) = LOCATIONS
+            .iter()
+            .find(|location| joined.ends_with(&format!(" {}", location)))
+        else {
+            break;
+        };
+        let words = location.split_whitespace().count();
+        if tokens.len() <= words {
+            break;
+        }
+        tokens.truncate(tokens.len() - words);
+    }
+
+    let normalized = tokens.join(" ");
+    let normalized = normalized.trim_matches(|c: char| !c.is_alphanumeric() && c != '\'');
+    if normalized.is_empty() {
+        return upper.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+    normalized.to_string()
+}
+
+fn merchant_name(key: &str) -> String {
+    key.split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_string() + &chars.as_str().to_lowercase(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Merchants are keyed by the normalized description, aliases map further
+// normalized descriptions onto a merchant. Existing transactions are linked here.
+fn merchants(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE Merchants (
+            merchant_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            key TEXT NOT NULL,
+
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE
+        );
+        CREATE UNIQUE INDEX idx_merchants_user_key ON Merchants(user_id, key);
+        CREATE TABLE MerchantAliases (
+            alias_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            merchant_id INTEGER NOT NULL,
+            alias TEXT NOT NULL,
+
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE,
+            FOREIGN KEY(merchant_id) REFERENCES Merchants(merchant_id) ON DELETE CASCADE
+        );
+        CREATE UNIQUE INDEX idx_merchant_aliases_user_alias ON MerchantAliases(user_id, alias);
+        CREATE INDEX idx_merchant_aliases_merchant ON MerchantAliases(merchant_id);
+        ALTER TABLE Transactions ADD COLUMN merchant_id INTEGER
+            REFERENCES Merchants(merchant_id) ON DELETE SET NULL;
+        CREATE INDEX idx_transactions_merchant ON Transactions(merchant_id);",
+    )?;
+
+    let rows = {
+        let mut stmt =
+            tx.prepare("SELECT transaction_id, user_id, description_1 FROM Transactions")?;
+        let rows = stmt
+            .query_map((), |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        rows
+    };
+    for (transaction_id, user_id, description) in rows {
+        let key = merchant_key(&description);
+        if key.is_empty() {
+            continue;
+        }
+        tx.execute(
+            "INSERT OR IGNORE INTO Merchants (user_id, name, key) VALUES (?, ?, ?)",
+            (user_id, merchant_name(&key), &key),
+        )?;
+        tx.execute(
+            "UPDATE Transactions SET merchant_id =
+                (SELECT merchant_id FROM Merchants WHERE user_id = ? AND key = ?)
+            WHERE transaction_id = ?",
+            (user_id, &key, transaction_id),
+        )?;
+    }
+    Ok(())
+}
+
+// Both sides of a transfer between a user's own accounts point at each other
+fn transfer_links(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE Transactions ADD COLUMN transfer_id INTEGER
+            REFERENCES Transactions(transaction_id) ON DELETE SET NULL;
+        CREATE INDEX idx_transactions_transfer ON Transactions(transfer_id);",
+    )
+}
+
+// Balance the statement printed next to a transaction, in minor units
+fn statement_balances(tx: &Transaction) -> Result<()> {
+    tx.execute_batch("ALTER TABLE Transactions ADD COLUMN statement_balance INTEGER;")
+}
+
+// Account balances recorded as of a date, the points net worth history is
+// anchored to
+fn balance_snapshots(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE BalanceSnapshots (
+            snapshot_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            account_number INTEGER NOT NULL,
+            snapshot_date TEXT NOT NULL,
+            balance INTEGER NOT NULL,
+
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE,
+            FOREIGN KEY(account_number) REFERENCES Account(account_number) ON DELETE CASCADE ON UPDATE CASCADE
+        );
+        CREATE UNIQUE INDEX idx_balance_snapshots_account_date
+            ON BalanceSnapshots(account_number, snapshot_date);",
+    )
+}
+
+// How an account earns or charges interest beyond its flat Account.interest_rate
+fn interest_plans(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE InterestPlans (
+            account_number INTEGER PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            compounding TEXT NOT NULL CHECK (compounding IN ('daily', 'monthly')),
+            grace_period INTEGER NOT NULL DEFAULT 1,
+            statement_day INTEGER NOT NULL DEFAULT 1 CHECK (statement_day BETWEEN 1 AND 28),
+            payment_due_days INTEGER NOT NULL DEFAULT 21,
+
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE,
+            FOREIGN KEY(account_number) REFERENCES Account(account_number) ON DELETE CASCADE ON UPDATE CASCADE
+        );
+        CREATE TABLE InterestTiers (
+            tier_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            account_number INTEGER NOT NULL,
+            min_balance INTEGER NOT NULL DEFAULT 0,
+            rate REAL NOT NULL,
+
+            FOREIGN KEY(account_number) REFERENCES InterestPlans(account_number) ON DELETE CASCADE ON UPDATE CASCADE
+        );
+        CREATE TABLE InterestPromotions (
+            promotion_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            account_number INTEGER NOT NULL,
+            rate REAL NOT NULL,
+            start_date TEXT NOT NULL,
+            end_date TEXT NOT NULL,
+
+            FOREIGN KEY(account_number) REFERENCES InterestPlans(account_number) ON DELETE CASCADE ON UPDATE CASCADE
+        );",
+    )
+}
+
+// Transactions say which of cad and usd holds their amount. Rows with only a
+// USD amount are USD, as are accounts with nothing but USD rows.
+fn currencies(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE Transactions ADD COLUMN currency TEXT NOT NULL DEFAULT 'CAD';
+        UPDATE Transactions SET currency = 'USD' WHERE cad = 0 AND usd != 0;
+        ALTER TABLE Account ADD COLUMN currency TEXT NOT NULL DEFAULT 'CAD';
+        UPDATE Account SET currency = 'USD' WHERE account_number IN (
+            SELECT account_number FROM Transactions
+            GROUP BY account_number
+            HAVING SUM(currency = 'USD') = COUNT(*)
+        );
+        ALTER TABLE Users ADD COLUMN reporting_currency TEXT NOT NULL DEFAULT 'CAD';
+        CREATE TABLE ExchangeRates (
+            base TEXT NOT NULL,
+            quote TEXT NOT NULL,
+            rate_date TEXT NOT NULL,
+            rate REAL NOT NULL CHECK (rate > 0),
+
+            PRIMARY KEY (base, quote, rate_date)
+        );",
+    )
+}
+
+// Tables holding an account number, updated by hand since foreign keys are off
+const ACCOUNT_TABLES: [&str; 6] = [
+    "Account",
+    "Transactions",
+    "BalanceSnapshots",
+    "InterestPlans",
+    "InterestTiers",
+    "InterestPromotions",
+];
+
+// Account numbers used to be a std hash of the bank's number, which is not stable
+// across Rust versions. Accounts are renumbered 1, 2, ... by owner and old number,
+// and the old number is kept as legacy_hash so the next import can recognize the
+// account and record its stable key. Fingerprints include the account number, so
+// the number in each key is swapped for the new one. The rest of the key must be
+// the row as the bank sent it, which only holds while the row reproduces its stored
+// fingerprint. Rows edited since keep their fingerprint, and imports match them
+// through the legacy number.
+fn account_keys(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE Account ADD COLUMN account_key TEXT;
+        ALTER TABLE Account ADD COLUMN mask TEXT;
+        ALTER TABLE Account ADD COLUMN nickname TEXT;
+        ALTER TABLE Account ADD COLUMN institution TEXT;
+        ALTER TABLE Account ADD COLUMN legacy_hash INTEGER;
+        UPDATE Account SET legacy_hash = account_number;
+        CREATE UNIQUE INDEX idx_account_key ON Account(user_id, account_key);
+
+        CREATE TEMP TABLE Renumbered AS
+            SELECT account_number AS old,
+                ROW_NUMBER() OVER (ORDER BY user_id, account_number) AS new
+            FROM Account;",
+    )?;
+    // through negative numbers so no new number meets an old one still in place
+    for table in ACCOUNT_TABLES {
+        tx.execute_batch(&format!(
+            "UPDATE {table} SET account_number = -(
+                SELECT new FROM Renumbered WHERE old = {table}.account_number
+            ) WHERE account_number IN (SELECT old FROM Renumbered);
+            UPDATE {table} SET account_number = -account_number WHERE account_number < 0;"
+        ))?;
+    }
+
+    let rows = {
+        let mut stmt = tx.prepare(
+            "SELECT transaction_id, old, new, transaction_date, cad, usd, description_1, description_2,
+                fingerprint, count(*) OVER (PARTITION BY new)
+            FROM Transactions JOIN Renumbered ON new = account_number
+            WHERE fingerprint IS NOT NULL
+            ORDER BY transaction_id",
+        )?;
+        let rows = stmt.query_map((), |row| {
+            let date = row.get::<_, String>(3)?;
+            let (cad, usd) = (row.get(4)?, row.get(5)?);
+            let description_1 = row.get::<_, Option<String>>(6)?.unwrap_or_default();
+            let description_2 = row.get::<_, Option<String>>(7)?.unwrap_or_default();
+            let key = |account_number| {
+                fingerprint_key(
+                    account_number,
+                    &date,
+                    cad,
+                    usd,
+                    &description_1,
+                    &description_2,
+                )
+            };
+            Ok((
+                row.get::<_, i64>(0)?,
+                key(row.get(1)?),
+                key(row.get(2)?),
+                row.get::<_, String>(8)?,
+                row.get::<_, u32>(9)?,
+            ))
+        })?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+    tx.execute_batch(
+        "DROP TABLE Renumbered;
+        DROP INDEX idx_transactions_fingerprint;",
+    )?;
+
+    let mut update =
+        tx.prepare("UPDATE Transactions SET fingerprint = ? WHERE transaction_id = ?")?;
+    for (transaction_id, old_key, new_key, fingerprint, account_rows) in rows {
+        // a deleted duplicate leaves a gap, so the occurrence is searched for
+        let occurrence =
+            (0..account_rows).find(|&n| fingerprint_from_key(&old_key, n) == fingerprint);
+        if let Some(occurrence) = occurrence {
+            update.execute((fingerprint_from_key(&new_key, occurrence), transaction_id))?;
+        }
+    }
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_transactions_fingerprint ON Transactions(fingerprint)",
+        (),
+    )?;
+    Ok(())
+}
+
+// Accounts entered by hand start on a date with a balance, and any account can
+// be closed. The opening balance is in minor units of the account's currency.
+fn account_periods(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE Account ADD COLUMN opened_on TEXT;
+        ALTER TABLE Account ADD COLUMN opening_balance INTEGER;
+        ALTER TABLE Account ADD COLUMN closed_on TEXT;",
+    )
+}
+
+// Trades annotate the cash transaction they were paid with, so the account's
+// balance keeps following its transactions. Prices are shared by every user
+// like exchange rates. ContributionRoom holds the room a registered plan had on
+// January 1 as the CRA reported it, in CAD minor units.
+fn investments(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE Trades (
+            trade_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            account_number INTEGER NOT NULL,
+            transaction_id INTEGER NOT NULL UNIQUE,
+            kind TEXT NOT NULL CHECK (kind IN ('Buy', 'Sell', 'Dividend', 'Contribution', 'Withdrawal')),
+            symbol TEXT,
+            quantity REAL NOT NULL DEFAULT 0,
+
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE,
+            FOREIGN KEY(account_number) REFERENCES Account(account_number) ON DELETE CASCADE ON UPDATE CASCADE,
+            FOREIGN KEY(transaction_id) REFERENCES Transactions(transaction_id) ON DELETE CASCADE
+        );
+        CREATE INDEX idx_trades_user ON Trades(user_id, account_number);
+        CREATE TABLE Prices (
+            symbol TEXT NOT NULL,
+            price_date TEXT NOT NULL,
+            price REAL NOT NULL CHECK (price >= 0),
+            currency TEXT NOT NULL,
+
+            PRIMARY KEY (symbol, price_date)
+        );
+        CREATE TABLE ContributionRoom (
+            user_id INTEGER NOT NULL,
+            account_type TEXT NOT NULL CHECK (account_type IN ('TFSA', 'RRSP', 'FHSA')),
+            year INTEGER NOT NULL,
+            room INTEGER NOT NULL,
+
+            PRIMARY KEY (user_id, account_type, year),
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE
+        );",
+    )
+}
+
+// A loan's terms sit on its account row next to the rate, the principal and
+// payment in minor units of the account's currency. LoanPayments splits a
+// payment matched to the schedule, which is usually a withdrawal from another
+// account, into the principal that lowers the loan and the interest charged.
+fn loans(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE Account ADD COLUMN principal INTEGER;
+        ALTER TABLE Account ADD COLUMN amortization_months INTEGER;
+        ALTER TABLE Account ADD COLUMN term_months INTEGER;
+        ALTER TABLE Account ADD COLUMN payment_frequency TEXT;
+        ALTER TABLE Account ADD COLUMN compounding TEXT;
+        ALTER TABLE Account ADD COLUMN first_payment_date TEXT;
+        ALTER TABLE Account ADD COLUMN payment INTEGER;
+        CREATE TABLE LoanPayments (
+            transaction_id INTEGER PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            account_number INTEGER NOT NULL,
+            installment INTEGER NOT NULL,
+            principal INTEGER NOT NULL,
+            interest INTEGER NOT NULL,
+
+            UNIQUE (account_number, installment),
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE,
+            FOREIGN KEY(account_number) REFERENCES Account(account_number) ON DELETE CASCADE ON UPDATE CASCADE,
+            FOREIGN KEY(transaction_id) REFERENCES Transactions(transaction_id) ON DELETE CASCADE
+        );",
+    )
+}
+
+// Account keys became an HMAC under a secret kept beside the database, see
+// `BankNumber::key`. The unkeyed hashes stored before cannot be converted without
+// the numbers, so they are only kept until the next import matches the account,
+// which clears them.
+fn keyed_account_numbers(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE Account ADD COLUMN unkeyed_key TEXT;
+        UPDATE Account SET unkeyed_key = account_key, account_key = NULL;",
+    )
+}
+
+// Exchange rates were shared, so any user could change everyone's conversions.
+// Each user now keeps their own, starting from a copy of the shared ones.
+fn user_exchange_rates(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE ExchangeRates_new (
+            user_id INTEGER NOT NULL,
+            base TEXT NOT NULL,
+            quote TEXT NOT NULL,
+            rate_date TEXT NOT NULL,
+            rate REAL NOT NULL CHECK (rate > 0),
+
+            PRIMARY KEY (user_id, base, quote, rate_date),
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE
+        );
+        INSERT INTO ExchangeRates_new (user_id, base, quote, rate_date, rate)
+            SELECT user_id, base, quote, rate_date, rate FROM ExchangeRates, Users;
+        DROP TABLE ExchangeRates;
+        ALTER TABLE ExchangeRates_new RENAME TO ExchangeRates;",
+    )
+}
+
+// Prices were shared like exchange rates, and are split by user the same way
+fn user_prices(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE Prices_new (
+            user_id INTEGER NOT NULL,
+            symbol TEXT NOT NULL,
+            price_date TEXT NOT NULL,
+            price REAL NOT NULL CHECK (price >= 0),
+            currency TEXT NOT NULL,
+
+            PRIMARY KEY (user_id, symbol, price_date),
+            FOREIGN KEY(user_id) REFERENCES Users(user_id) ON DELETE CASCADE ON UPDATE CASCADE
+        );
+        INSERT INTO Prices_new (user_id, symbol, price_date, price, currency)
+            SELECT user_id, symbol, price_date, price, currency FROM Prices, Users;
+        DROP TABLE Prices;
+        ALTER TABLE Prices_new RENAME TO Prices;",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table_exists(conn: &Connection, name: &str) -> bool {
+        conn.query_row(
+            "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = ?",
+            [name],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap()
+            == 1
+    }
+
+    #[test]
+    fn test_versions_are_sequential() {
+        for (idx, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(
+                migration.version,
+                idx as i64 + 1,
+                "{}",
+                migration.description
+            );
+        }
+    }
+
+    #[test]
+    fn test_fresh_database_is_migrated_to_latest() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(current_version(&conn).unwrap(), 0);
+
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        assert!(table_exists(&conn, "Users"));
+        assert!(table_exists(&conn, "Account"));
+        assert!(table_exists(&conn, "Transactions"));
+        assert!(table_exists(&conn, "CategoryRules"));
+        assert!(table_exists(&conn, "Categories"));
+        assert!(table_exists(&conn, "Budgets"));
+        assert!(table_exists(&conn, "TransactionSearch"));
+        assert!(table_exists(&conn, "Merchants"));
+        assert!(table_exists(&conn, "MerchantAliases"));
+        assert!(table_exists(&conn, "BalanceSnapshots"));
+        assert!(table_exists(&conn, "InterestPlans"));
+        assert!(table_exists(&conn, "ExchangeRates"));
+        assert!(table_exists(&conn, "Trades"));
+        assert!(table_exists(&conn, "Prices"));
+        assert!(table_exists(&conn, "ContributionRoom"));
+        assert!(table_exists(&conn, "LoanPayments"));
+    }
+
+    #[test]
+    fn test_upgrade_from_every_version() {
+        for start in 0..=latest_version() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            migrate_to(&mut conn, start).unwrap();
+            assert_eq!(current_version(&conn).unwrap(), start);
+
+            migrate(&mut conn).unwrap();
+            assert_eq!(current_version(&conn).unwrap(), latest_version());
+        }
+    }
+
+    #[test]
+    fn test_migrate_is_idempotent() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        migrate(&mut conn).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn test_legacy_unversioned_database_is_adopted() {
+        // databases created by the old one-shot schema have user_version 0
+        // but already contain the tables and data
+        let mut conn = Connection::open_in_memory().unwrap();
+        {
+            let tx = conn.transaction().unwrap();
+            initial_schema(&tx).unwrap();
+            tx.execute("INSERT INTO Users (user_id, name) VALUES (1, 'Alice')", ())
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        migrate(&mut conn).unwrap();
+
+        let name: String = conn
+            .query_row("SELECT name FROM Users WHERE user_id = 1", (), |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(name, "Alice");
+    }
+
+    #[test]
+    fn test_fingerprints_agree_with_imports() {
+        let key = fingerprint_key(2, "2025-05-12", -1599, 0, "NETFLIX  COM", " LOS GATOS");
+        assert_eq!(
+            key,
+            crate::transaction::fingerprint_key(
+                2,
+                "2025-05-12",
+                -1599,
+                0,
+                "NETFLIX  COM",
+                " LOS GATOS"
+            )
+        );
+        assert_eq!(
+            fingerprint_from_key(&key, 1),
+            crate::transaction::fingerprint_from_key(&key, 1)
+        );
+    }
+
+    #[test]
+    fn test_merchant_keys_agree_with_imports() {
+        for description in [
+            "TIM HORTONS #7525, NEPEAN",
+            "SQ *TIM HORTONS 1234 OTTAWA ON",
+            "7-ELEVEN 2K3L91 TORONTO",
+            "AMAZON.CA*C12345",
+            "  ",
+        ] {
+            let key = merchant_key(description);
+            assert_eq!(key, crate::merchant::normalize(description));
+            assert_eq!(merchant_name(&key), crate::merchant::display_name(&key));
+        }
+    }
+
+    #[test]
+    fn test_fingerprints_backfilled_for_existing_rows() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, 1).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Users (user_id, name) VALUES (1, 'Alice');
+            INSERT INTO Account (user_id, account_type, account_number) VALUES (1, 'Chequing', 1001);
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, description_1, description_2, cad, usd)
+                VALUES (1, 1001, 'Chequing', '5/12/2025', 'COFFEE', '', -2.5, 0);
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, description_1, description_2, cad, usd)
+                VALUES (1, 1001, 'Chequing', '5/12/2025', 'COFFEE', '', -2.5, 0);",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let distinct: i64 = conn
+            .query_row(
+                "SELECT count(DISTINCT fingerprint) FROM Transactions WHERE fingerprint IS NOT NULL",
+                (),
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(distinct, 2);
+    }
+
+    #[test]
+    fn test_money_converted_to_minor_units() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, 2).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Users (user_id, name) VALUES (1, 'Alice');
+            INSERT INTO Account (user_id, account_type, account_number, balance, interest_rate, credit_limit)
+                VALUES (1, 'Credit', 1001, 1166.17, 0.0, 5000.0);
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, description_1, description_2, cad, usd, fingerprint)
+                VALUES (1, 1001, 'Credit', '5/12/2025', 'COFFEE', '', -0.29, 0.07, 'abc');",
+        )
+        .unwrap();
+
+        migrate_to(&mut conn, 3).unwrap();
+
+        let (balance, limit): (i64, i64) = conn
+            .query_row(
+                "SELECT balance, credit_limit FROM Account WHERE account_number = 1001",
+                (),
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((balance, limit), (116617, 500000));
+
+        let (id, cad, usd, fingerprint): (i64, i64, i64, String) = conn
+            .query_row(
+                "SELECT transaction_id, cad, usd, fingerprint FROM Transactions",
+                (),
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!((id, cad, usd, fingerprint.as_str()), (1, -29, 7, "abc"));
+
+        let violations: i64 = conn
+            .query_row("SELECT count(*) FROM pragma_foreign_key_check", (), |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(violations, 0);
+    }
+
+    #[test]
+    fn test_dates_converted_to_iso() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, 3).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Users (user_id, name) VALUES (1, 'Alice');
+            INSERT INTO Account (user_id, account_type, account_number) VALUES (1, 'Chequing', 1001);
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, description_1, description_2, cad, usd, fingerprint)
+                VALUES (1, 1001, 'Chequing', '5/12/2025', 'COFFEE', '', -250, 0, 'a');
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, description_1, description_2, cad, usd, fingerprint)
+                VALUES (1, 1001, 'Chequing', 'May 12, 2025', 'COFFEE', '', -250, 0, 'b');",
+        )
+        .unwrap();
+
+        // stop before accounts are renumbered
+        migrate_to(&mut conn, 17).unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT transaction_date, fingerprint FROM Transactions ORDER BY transaction_id",
+            )
+            .unwrap();
+        let rows: Vec<(String, String)> = stmt
+            .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+
+        assert_eq!(rows[0].0, "2025-05-12");
+        assert_eq!(rows[1].0, "2025-05-12");
+        // same purchase in two formats becomes two occurrences of one key
+        let key = fingerprint_key(1001, "2025-05-12", -250, 0, "COFFEE", "");
+        assert_eq!(rows[0].1, fingerprint_from_key(&key, 0));
+        assert_eq!(rows[1].1, fingerprint_from_key(&key, 1));
+    }
+
+    #[test]
+    fn test_unparseable_date_fails_migration() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, 3).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Users (user_id, name) VALUES (1, 'Alice');
+            INSERT INTO Account (user_id, account_type, account_number) VALUES (1, 'Chequing', 1001);
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, cad, usd)
+                VALUES (1, 1001, 'Chequing', 'someday', 0, 0);
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, cad, usd)
+                VALUES (1, 1001, 'Chequing', '5/12/2025', -250, 0);",
+        )
+        .unwrap();
+
+        let error = migrate(&mut conn).unwrap_err().to_string();
+        assert!(error.contains("transaction 1 (\"someday\")"), "{}", error);
+        assert_eq!(current_version(&conn).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_categories_backfilled_from_names() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, 6).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Users (user_id, name) VALUES (1, 'Alice');
+            INSERT INTO Users (user_id, name) VALUES (2, 'Bob');
+            INSERT INTO Account (user_id, account_type, account_number) VALUES (1, 'Chequing', 1001);
+            INSERT INTO Account (user_id, account_type, account_number) VALUES (2, 'Chequing', 2002);
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, cad, category, fingerprint)
+                VALUES (1, 1001, 'Chequing', '2025-05-12', -250, 'Coffee', 'a');
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, cad, category, fingerprint)
+                VALUES (1, 1001, 'Chequing', '2025-05-13', -250, ' coffee ', 'b');
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, cad, category, fingerprint)
+                VALUES (1, 1001, 'Chequing', '2025-05-14', -250, '', 'c');
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, cad, category, fingerprint)
+                VALUES (2, 2002, 'Chequing', '2025-05-14', -250, 'Coffee', 'd');",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let categories: i64 = conn
+            .query_row("SELECT count(*) FROM Categories", (), |row| row.get(0))
+            .unwrap();
+        assert_eq!(categories, 2);
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT Categories.user_id, Categories.name FROM Transactions
+                LEFT JOIN Categories ON Categories.category_id = Transactions.category_id
+                ORDER BY transaction_id",
+            )
+            .unwrap();
+        let rows: Vec<(Option<i64>, Option<String>)> = stmt
+            .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(rows[0], (Some(1), Some("Coffee".to_string())));
+        assert_eq!(rows[1], rows[0]);
+        assert_eq!(rows[2], (None, None));
+        assert_eq!(rows[3], (Some(2), Some("Coffee".to_string())));
+    }
+
+    #[test]
+    fn test_search_index_backfilled() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, 10).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Users (user_id, name) VALUES (1, 'Alice');
+            INSERT INTO Account (user_id, account_type, account_number) VALUES (1, 'Credit', 1001);
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, description_1, description_2, cad, fingerprint)
+                VALUES (1, 1001, 'Credit', '2025-05-12', 'AMZN MKTP CA', 'WWW.AMAZON.CA', -2599, 'a');",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let matches: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM TransactionSearch WHERE TransactionSearch MATCH 'amazon'",
+                (),
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(matches, 1);
+    }
+
+    #[test]
+    fn test_merchants_backfilled_from_descriptions() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, 11).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Users (user_id, name) VALUES (1, 'Alice');
+            INSERT INTO Account (user_id, account_type, account_number) VALUES (1, 'Credit', 1001);
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, description_1, description_2, cad, fingerprint)
+                VALUES (1, 1001, 'Credit', '2025-05-12', 'TIM HORTONS #7525, NEPEAN', '', -250, 'a');
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, description_1, description_2, cad, fingerprint)
+                VALUES (1, 1001, 'Credit', '2025-05-13', 'TIM HORTONS #1234 OTTAWA', '', -250, 'b');
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, description_1, description_2, cad, fingerprint)
+                VALUES (1, 1001, 'Credit', '2025-05-13', '', '', -250, 'c');",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT Merchants.name FROM Transactions
+                LEFT JOIN Merchants ON Merchants.merchant_id = Transactions.merchant_id
+                ORDER BY transaction_id",
+            )
+            .unwrap();
+        let names: Vec<Option<String>> = stmt
+            .query_map((), |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            names,
+            vec![
+                Some("Tim Hortons".to_string()),
+                Some("Tim Hortons".to_string()),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_usd_rows_and_accounts_backfilled() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, 16).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Users (user_id, name) VALUES (1, 'Alice');
+            INSERT INTO Account (user_id, account_type, account_number) VALUES (1, 'Credit', 1001);
+            INSERT INTO Account (user_id, account_type, account_number) VALUES (1, 'Chequing', 2002);
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, cad, usd, fingerprint)
+                VALUES (1, 1001, 'Credit', '2025-05-12', 0, -1599, 'a');
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, cad, usd, fingerprint)
+                VALUES (1, 2002, 'Chequing', '2025-05-12', -2500, 0, 'b');
+            INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, cad, usd, fingerprint)
+                VALUES (1, 2002, 'Chequing', '2025-05-13', 0, 1000, 'c');",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let currencies = |sql: &str| -> Vec<String> {
+            let mut stmt = conn.prepare(sql).unwrap();
+            stmt.query_map((), |row| row.get(0))
+                .unwrap()
+                .collect::<Result<_>>()
+                .unwrap()
+        };
+        assert_eq!(
+            currencies("SELECT currency FROM Transactions ORDER BY transaction_id"),
+            vec!["USD", "CAD", "USD"]
+        );
+        // a CAD account with the odd USD row stays CAD
+        assert_eq!(
+            currencies("SELECT currency FROM Account ORDER BY account_number"),
+            vec!["USD", "CAD"]
+        );
+    }
+
+    #[test]
+    fn test_accounts_renumbered_with_legacy_hash() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, 17).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Users (user_id, name) VALUES (1, 'Alice');
+            INSERT INTO Users (user_id, name) VALUES (2, 'Bob');
+            INSERT INTO Account (user_id, account_type, account_number) VALUES (2, 'Chequing', 51234567890);
+            INSERT INTO Account (user_id, account_type, account_number) VALUES (1, 'Credit', 98765432100);
+            INSERT INTO Account (user_id, account_type, account_number) VALUES (1, 'Savings', 2);
+            INSERT INTO BalanceSnapshots (user_id, account_number, snapshot_date, balance)
+                VALUES (1, 98765432100, '2025-05-12', 1599);
+            INSERT INTO InterestPlans (account_number, user_id, compounding) VALUES (2, 1, 'daily');
+            INSERT INTO InterestTiers (account_number, min_balance, rate) VALUES (2, 0, 0.02);",
+        )
+        .unwrap();
+        let key = fingerprint_key(98765432100, "2025-05-12", -1599, 0, "NETFLIX", "");
+        conn.execute(
+            "INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, description_1, cad, usd, fingerprint)
+            VALUES (1, 98765432100, 'Credit', '2025-05-12', 'NETFLIX', -1599, 0, ?)",
+            [fingerprint_from_key(&key, 0)],
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let accounts: Vec<(i64, i64, i64)> = conn
+            .prepare(
+                "SELECT account_number, user_id, legacy_hash FROM Account ORDER BY account_number",
+            )
+            .unwrap()
+            .query_map((), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            accounts,
+            vec![(1, 1, 2), (2, 1, 98765432100), (3, 2, 51234567890)]
+        );
+
+        let number = |sql: &str| conn.query_row(sql, (), |row| row.get::<_, i64>(0)).unwrap();
+        assert_eq!(number("SELECT account_number FROM Transactions"), 2);
+        assert_eq!(number("SELECT account_number FROM BalanceSnapshots"), 2);
+        assert_eq!(number("SELECT account_number FROM InterestPlans"), 1);
+        assert_eq!(number("SELECT account_number FROM InterestTiers"), 1);
+
+        let fingerprint: String = conn
+            .query_row("SELECT fingerprint FROM Transactions", (), |row| row.get(0))
+            .unwrap();
+        let key = fingerprint_key(2, "2025-05-12", -1599, 0, "NETFLIX", "");
+        assert_eq!(fingerprint, fingerprint_from_key(&key, 0));
+    }
+
+    #[test]
+    fn test_renumbering_keeps_edited_rows_fingerprints() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, 17).unwrap();
+        let key = fingerprint_key(98765432100, "2025-05-12", -1599, 0, "NETFLIX", "");
+        let fingerprints = [fingerprint_from_key(&key, 0), fingerprint_from_key(&key, 1)];
+        conn.execute_batch(
+            "INSERT INTO Users (user_id, name) VALUES (1, 'Alice');
+            INSERT INTO Account (user_id, account_type, account_number) VALUES (1, 'Credit', 98765432100);",
+        )
+        .unwrap();
+        // the second charge was renamed after it was imported
+        for (description, fingerprint) in ["NETFLIX", "Streaming"].iter().zip(&fingerprints) {
+            conn.execute(
+                "INSERT INTO Transactions (user_id, account_number, account_type, transaction_date, description_1, cad, usd, fingerprint)
+                VALUES (1, 98765432100, 'Credit', '2025-05-12', ?, -1599, 0, ?)",
+                (description, fingerprint),
+            )
+            .unwrap();
+        }
+
+        migrate(&mut conn).unwrap();
+
+        let stored: Vec<String> = conn
+            .prepare("SELECT fingerprint FROM Transactions ORDER BY transaction_id")
+            .unwrap()
+            .query_map((), |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        let key = fingerprint_key(1, "2025-05-12", -1599, 0, "NETFLIX", "");
+        assert_eq!(stored[0], fingerprint_from_key(&key, 0));
+        assert_eq!(stored[1], fingerprints[1]);
+    }
+
+    #[test]
+    fn test_shared_exchange_rates_copied_to_every_user() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, 22).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Users (user_id, name) VALUES (1, 'Alice');
+            INSERT INTO Users (user_id, name) VALUES (2, 'Bob');
+            INSERT INTO ExchangeRates (base, quote, rate_date, rate) VALUES ('USD', 'CAD', '2025-01-02', 1.44);",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let users: Vec<i64> = conn
+            .prepare("SELECT user_id FROM ExchangeRates WHERE rate = 1.44 ORDER BY user_id")
+            .unwrap()
+            .query_map((), |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(users, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_shared_prices_copied_to_every_user() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, 23).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Users (user_id, name) VALUES (1, 'Alice');
+            INSERT INTO Users (user_id, name) VALUES (2, 'Bob');
+            INSERT INTO Prices (symbol, price_date, price, currency) VALUES ('VFV', '2024-02-01', 125.5, 'CAD');",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let users: Vec<i64> = conn
+            .prepare("SELECT user_id FROM Prices WHERE symbol = 'VFV' ORDER BY user_id")
+            .unwrap()
+            .query_map((), |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(users, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_newer_database_is_rejected() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+        assert!(migrate(&mut conn).is_err());
+    }
+}
@@ -0,0 +1,363 @@
+use core::fmt;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+// ISO 4217 currency code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const CAD: Currency = Currency(*b"CAD");
+    pub const USD: Currency = Currency(*b"USD");
+
+    pub fn code(&self) -> &str {
+        // only ever built from ASCII letters
+        std::str::from_utf8(&self.0).unwrap()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InvalidCurrency(String);
+impl fmt::Display for InvalidCurrency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid currency code: {}", self.0)
+    }
+}
+
+impl FromStr for Currency {
+    type Err = InvalidCurrency;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.trim().to_uppercase();
+        match code.as_bytes() {
+            [a, b, c] if code.bytes().all(|b| b.is_ascii_alphabetic()) => {
+                Ok(Currency([*a, *b, *c]))
+            }
+            _ => Err(InvalidCurrency(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl rusqlite::ToSql for Currency {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(self.code().into())
+    }
+}
+
+impl rusqlite::types::FromSql for Currency {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        let code = value.as_str()?;
+        Currency::from_str(code)
+            .map_err(|e| rusqlite::types::FromSqlError::Other(e.to_string().into()))
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Currency::from_str(&s).map_err(de::Error::custom)
+    }
+}
+
+// Exact amount of money in minor units (cents). All amounts and balances use this
+// instead of f64 so sums never drift. Arithmetic between different currencies
+// is a bug and panics, convert first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Money {
+    minor: i64,
+    currency: Currency,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidAmount(String);
+impl fmt::Display for InvalidAmount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid amount: {}", self.0)
+    }
+}
+
+impl Money {
+    pub fn from_minor(minor: i64, currency: Currency) -> Money {
+        Money { minor, currency }
+    }
+
+    pub fn cad(minor: i64) -> Money {
+        Money::from_minor(minor, Currency::CAD)
+    }
+
+    pub fn usd(minor: i64) -> Money {
+        Money::from_minor(minor, Currency::USD)
+    }
+
+    pub fn zero(currency: Currency) -> Money {
+        Money::from_minor(0, currency)
+    }
+
+    pub fn minor_units(&self) -> i64 {
+        self.minor
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.minor == 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.minor < 0
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.minor > 0
+    }
+
+    pub fn abs(&self) -> Money {
+        Money::from_minor(self.minor.abs(), self.currency)
+    }
+
+    // Only for display and outside APIs that want a JSON number, never for arithmetic
+    pub fn to_f64(&self) -> f64 {
+        self.minor as f64 / 100.0
+    }
+
+    // Parses amounts the way banks print them: `-$1,167.81`, `$25.00`, `(12.50)`, `-213.45`.
+    // More than two decimal places is rejected rather than rounded.
+    pub fn parse(s: &str, currency: Currency) -> Result<Money, InvalidAmount> {
+        let invalid = || InvalidAmount(s.to_string());
+
+        let mut text = s.trim().replace([',', '$', ' '], "");
+        let mut negative = false;
+        if text.starts_with('(') && text.ends_with(')') {
+            negative = true;
+            text = text[1..text.len() - 1].to_string();
+        }
+        if let Some(rest) = text.strip_prefix('-') {
+            negative = !negative;
+            text = rest.to_string();
+        } else if let Some(rest) = text.strip_prefix('+') {
+            text = rest.to_string();
+        }
+
+        let (whole, fraction) = match text.split_once('.') {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (text.as_str(), ""),
+        };
+        if (whole.is_empty() && fraction.is_empty())
+            || fraction.len() > 2
+            || !whole.bytes().all(|b| b.is_ascii_digit())
+            || !fraction.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        let whole: i64 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|_| invalid())?
+        };
+        let cents: i64 = format!("{:0<2}", fraction).parse().map_err(|_| invalid())?;
+        let minor = whole
+            .checked_mul(100)
+            .and_then(|m| m.checked_add(cents))
+            .ok_or_else(invalid)?;
+
+        Ok(Money::from_minor(
+            if negative { -minor } else { minor },
+            currency,
+        ))
+    }
+
+    // `-1167.81`, without grouping or currency
+    pub fn to_decimal_string(&self) -> String {
+        let sign = if self.minor < 0 { "-" } else { "" };
+        let abs = self.minor.unsigned_abs();
+        format!("{}{}.{:02}", sign, abs / 100, abs % 100)
+    }
+
+    fn assert_same_currency(&self, other: &Money) {
+        assert_eq!(
+            self.currency, other.currency,
+            "cannot combine {} and {} amounts",
+            self.currency, other.currency
+        );
+    }
+}
+
+impl Default for Money {
+    fn default() -> Self {
+        Money::zero(Currency::CAD)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.to_decimal_string(), self.currency)
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+    fn add(self, other: Money) -> Money {
+        self.assert_same_currency(&other);
+        Money::from_minor(self.minor + other.minor, self.currency)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Money) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+    fn sub(self, other: Money) -> Money {
+        self.assert_same_currency(&other);
+        Money::from_minor(self.minor - other.minor, self.currency)
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, other: Money) {
+        *self = *self - other;
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+    fn neg(self) -> Money {
+        Money::from_minor(-self.minor, self.currency)
+    }
+}
+
+impl PartialOrd for Money {
+    fn partial_cmp(&self, other: &Money) -> Option<std::cmp::Ordering> {
+        if self.currency != other.currency {
+            return None;
+        }
+        Some(self.minor.cmp(&other.minor))
+    }
+}
+
+// Serialized as {"amount": "-1167.81", "currency": "CAD"} so clients never see a float
+#[derive(Serialize, Deserialize)]
+struct MoneyJson {
+    amount: String,
+    currency: Currency,
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        MoneyJson {
+            amount: self.to_decimal_string(),
+            currency: self.currency,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = MoneyJson::deserialize(deserializer)?;
+        Money::parse(&json.amount, json.currency).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bank_formats() {
+        let cases = [
+            ("-$1,167.81", -116781),
+            ("$1,166.17", 116617),
+            ("$25.00", 2500),
+            ("-213.45", -21345),
+            ("1000", 100000),
+            ("750.7", 75070),
+            ("-.5", -50),
+            ("(12.34)", -1234),
+            ("+3.00", 300),
+            (" -$0.01 ", -1),
+        ];
+        for (text, minor) in cases {
+            assert_eq!(
+                Money::parse(text, Currency::CAD).unwrap(),
+                Money::cad(minor),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_garbage() {
+        for text in ["", "$", "abc", "1.234", "1.2.3", "--5", "1e3"] {
+            assert!(Money::parse(text, Currency::CAD).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_sums_do_not_drift() {
+        let mut total = Money::cad(0);
+        for _ in 0..1000 {
+            total += Money::parse("0.10", Currency::CAD).unwrap();
+        }
+        assert_eq!(total, Money::cad(10000));
+    }
+
+    #[test]
+    fn test_decimal_string() {
+        assert_eq!(Money::cad(-116781).to_decimal_string(), "-1167.81");
+        assert_eq!(Money::cad(5).to_decimal_string(), "0.05");
+        assert_eq!(Money::cad(-5).to_decimal_string(), "-0.05");
+        assert_eq!(Money::usd(100).to_string(), "1.00 USD");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_mixed_currency_arithmetic_panics() {
+        let _ = Money::cad(100) + Money::usd(100);
+    }
+
+    #[test]
+    fn test_mixed_currency_is_unordered() {
+        assert!(Money::cad(100) > Money::cad(99));
+        assert_eq!(Money::cad(100).partial_cmp(&Money::usd(100)), None);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let money = Money::usd(-4210);
+        let json = serde_json::to_value(money).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"amount": "-42.10", "currency": "USD"})
+        );
+        assert_eq!(serde_json::from_value::<Money>(json).unwrap(), money);
+    }
+
+    #[test]
+    fn test_currency_codes() {
+        assert_eq!(Currency::from_str("usd").unwrap(), Currency::USD);
+        assert_eq!(Currency::from_str("EUR").unwrap().code(), "EUR");
+        assert!(Currency::from_str("EURO").is_err());
+        assert!(Currency::from_str("E1R").is_err());
+    }
+}
@@ -0,0 +1,722 @@
+use core::fmt;
+
+use chrono::{Datelike, Days, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    account::{AccountPeriod, AccountType, BankAccount},
+    database::Database,
+    exchange::{self, ExchangeError, ExchangeRates},
+    investment::Portfolio,
+    loan::LoanPayment,
+    money::{Currency, Money},
+    reconcile::balance_change,
+};
+
+// Longest series returned, a little under three years of days
+pub const MAX_POINTS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval {
+    Daily,
+    #[default]
+    Monthly,
+}
+
+impl Interval {
+    // End of the period before the one ending at `date`
+    pub fn previous(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Interval::Daily => date - Days::new(1),
+            Interval::Monthly => date.with_day(1).unwrap() - Days::new(1),
+        }
+    }
+}
+
+// An account's balance as of the end of `date`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BalanceSnapshot {
+    pub account_number: i64,
+    pub date: NaiveDate,
+    pub balance: Money,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct NetWorthPoint {
+    pub date: NaiveDate,
+    // chequing and savings balances
+    pub assets: Money,
+    // credit card and loan balances owed
+    pub liabilities: Money,
+    pub net_worth: Money,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Change {
+    pub date: NaiveDate,
+    // net worth on `date`
+    pub net_worth: Money,
+    pub amount: Money,
+    // share of the earlier net worth, None when it was zero
+    pub rate: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct NetWorthSummary {
+    pub date: NaiveDate,
+    pub net_worth: Money,
+    pub previous_period: Change,
+    pub year_over_year: Change,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NetWorth {
+    pub interval: Interval,
+    pub currency: Currency,
+    pub series: Vec<NetWorthPoint>,
+    pub summary: NetWorthSummary,
+}
+
+#[derive(Debug)]
+pub enum NetWorthError {
+    InvalidRange,
+    TooManyPoints,
+    Exchange(ExchangeError),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for NetWorthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetWorthError::InvalidRange => write!(f, "from must not be after to"),
+            NetWorthError::TooManyPoints => write!(
+                f,
+                "Net worth series is limited to {} points, use a shorter range or interval",
+                MAX_POINTS
+            ),
+            NetWorthError::Exchange(err) => write!(f, "{}", err),
+            NetWorthError::Database(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for NetWorthError {}
+
+impl From<rusqlite::Error> for NetWorthError {
+    fn from(err: rusqlite::Error) -> Self {
+        NetWorthError::Database(err)
+    }
+}
+
+impl From<ExchangeError> for NetWorthError {
+    fn from(err: ExchangeError) -> Self {
+        NetWorthError::Exchange(err)
+    }
+}
+
+// Dates of the series within `from..=to`: every day, or the end of every month
+// with the last one cut short at `to`
+pub fn period_ends(from: NaiveDate, to: NaiveDate, interval: Interval) -> Vec<NaiveDate> {
+    let mut dates = Vec::new();
+    match interval {
+        Interval::Daily => {
+            let mut day = from;
+            while day <= to {
+                dates.push(day);
+                day = day + Days::new(1);
+            }
+        }
+        Interval::Monthly => {
+            let mut month = from.with_day(1).unwrap();
+            while month <= to {
+                let next = month + Months::new(1);
+                dates.push((next - Days::new(1)).min(to));
+                month = next;
+            }
+        }
+    }
+    dates
+}
+
+// What is known about one account's balance over time. The balance on a day is
+// worked back from the first anchor on or after it, a snapshot or else the
+// current balance, by undoing the transactions in between. An account entered
+// by hand has no balance before the day it was opened.
+#[derive(Debug, Clone)]
+pub struct AccountHistory {
+    pub account_type: AccountType,
+    opened_on: Option<NaiveDate>,
+    // oldest first, the current balance last
+    anchors: Vec<(NaiveDate, Money)>,
+    // how each transaction moved the balance, oldest first
+    changes: Vec<(NaiveDate, Money)>,
+}
+
+impl AccountHistory {
+    pub fn new(
+        account: &dyn BankAccount,
+        period: AccountPeriod,
+        snapshots: &[BalanceSnapshot],
+        changes: Vec<(NaiveDate, Money)>,
+    ) -> AccountHistory {
+        let mut anchors: Vec<(NaiveDate, Money)> = snapshots
+            .iter()
+            .filter(|s| s.account_number == *account.account_number())
+            .map(|s| (s.date, s.balance))
+            .collect();
+        anchors.sort_by_key(|(date, _)| *date);
+        anchors.push((NaiveDate::MAX, account.balance()));
+        AccountHistory {
+            account_type: account.account_type(),
+            opened_on: period.opened_on,
+            anchors,
+            changes,
+        }
+    }
+
+    // The account's currency, which every balance and change is in
+    pub fn currency(&self) -> Currency {
+        self.anchors.last().unwrap().1.currency()
+    }
+
+    // How each transaction moved the balance, oldest first
+    pub fn changes(&self) -> &[(NaiveDate, Money)] {
+        &self.changes
+    }
+
+    pub fn balance_on(&self, date: NaiveDate) -> Money {
+        if self.opened_on.is_some_and(|opened| date < opened) {
+            return Money::zero(self.currency());
+        }
+        let (anchor_date, anchor) = *self
+            .anchors
+            .iter()
+            .find(|(anchor_date, _)| *anchor_date >= date)
+            .unwrap_or(self.anchors.last().unwrap());
+        self.changes
+            .iter()
+            .filter(|(day, _)| *day > date && *day <= anchor_date)
+            .fold(anchor, |balance, (_, change)| balance - *change)
+    }
+}
+
+// Balances and the market value of holdings are converted into `currency` at
+// the rate on `date`
+pub fn point(
+    histories: &[AccountHistory],
+    portfolio: &Portfolio,
+    rates: &ExchangeRates,
+    currency: Currency,
+    date: NaiveDate,
+) -> Result<NetWorthPoint, ExchangeError> {
+    let convert = |amount: Money| {
+        rates
+            .convert(amount, currency, date)
+            .ok_or(ExchangeError::MissingRate {
+                from: amount.currency(),
+                to: currency,
+            })
+    };
+    let mut assets = Money::zero(currency);
+    let mut liabilities = Money::zero(currency);
+    for history in histories {
+        let converted = convert(history.balance_on(date))?;
+        match history.account_type {
+            AccountType::Credit | AccountType::Loan => liabilities += converted,
+            _ => assets += converted,
+        }
+    }
+    for value in portfolio.values_on(date) {
+        assets += convert(value)?;
+    }
+    Ok(NetWorthPoint {
+        date,
+        assets,
+        liabilities,
+        net_worth: assets - liabilities,
+    })
+}
+
+fn change(
+    histories: &[AccountHistory],
+    portfolio: &Portfolio,
+    rates: &ExchangeRates,
+    current: Money,
+    date: NaiveDate,
+) -> Result<Change, ExchangeError> {
+    let earlier = point(histories, portfolio, rates, current.currency(), date)?.net_worth;
+    let amount = current - earlier;
+    Ok(Change {
+        date,
+        net_worth: earlier,
+        amount,
+        rate: (!earlier.is_zero())
+            .then(|| amount.minor_units() as f64 / earlier.abs().minor_units() as f64),
+    })
+}
+
+// A loan is also paid down by the principal of the payments matched to it
+// from other accounts, see `loan::match_payments`
+fn with_loan_payments(
+    account: &dyn BankAccount,
+    mut changes: Vec<(NaiveDate, Money)>,
+    payments: &[LoanPayment],
+) -> Vec<(NaiveDate, Money)> {
+    let paid = payments
+        .iter()
+        .filter(|p| p.account_number == *account.account_number())
+        .map(|p| (p.date, -p.principal));
+    changes.extend(paid);
+    changes.sort_by_key(|(date, _)| *date);
+    changes
+}
+
+// The balance history of one of the user's accounts
+pub fn account_history(
+    db: &Database,
+    user_id: i64,
+    account: &dyn BankAccount,
+) -> Result<AccountHistory, rusqlite::Error> {
+    let snapshots = db.get_balance_snapshots(user_id)?;
+    let period = db
+        .get_account_periods(user_id)?
+        .remove(account.account_number())
+        .unwrap_or_default();
+    let changes = db
+        .get_account_transactions(user_id, *account.account_number())?
+        .iter()
+        .map(|t| {
+            (
+                t.transaction_date,
+                balance_change(account.account_type(), t.amount()),
+            )
+        })
+        .collect();
+    let changes = with_loan_payments(account, changes, &db.get_loan_payments(user_id)?);
+    Ok(AccountHistory::new(account, period, &snapshots, changes))
+}
+
+fn histories(db: &Database, user_id: i64) -> Result<Vec<AccountHistory>, rusqlite::Error> {
+    let snapshots = db.get_balance_snapshots(user_id)?;
+    let periods = db.get_account_periods(user_id)?;
+    let transactions = db.get_transactions(user_id)?;
+    let payments = db.get_loan_payments(user_id)?;
+    let histories = db
+        .get_accounts_by_user(user_id)?
+        .iter()
+        .map(|account| {
+            let changes = transactions
+                .iter()
+                .filter(|t| t.account_number == *account.account_number())
+                .map(|t| {
+                    (
+                        t.transaction_date,
+                        balance_change(account.account_type(), t.amount()),
+                    )
+                })
+                .collect();
+            let changes = with_loan_payments(account.as_ref(), changes, &payments);
+            let period = periods
+                .get(account.account_number())
+                .copied()
+                .unwrap_or_default();
+            AccountHistory::new(account.as_ref(), period, &snapshots, changes)
+        })
+        .collect();
+    Ok(histories)
+}
+
+// Records each account's balance as of its latest transaction, or `today` for
+// an account without any. Later history is anchored to these even once the
+// current balance moves on.
+pub fn take_snapshots(
+    db: &Database,
+    user_id: i64,
+    today: NaiveDate,
+) -> Result<(), rusqlite::Error> {
+    let transactions = db.get_transactions(user_id)?;
+    for account in db.get_accounts_by_user(user_id)? {
+        let date = transactions
+            .iter()
+            .filter(|t| t.account_number == *account.account_number())
+            .map(|t| t.transaction_date)
+            .max()
+            .unwrap_or(today);
+        db.upsert_balance_snapshot(user_id, *account.account_number(), date, account.balance())?;
+    }
+    Ok(())
+}
+
+// Net worth in `currency` at the end of each period in `from..=to`, with the
+// change at `to` against the previous period and the year before
+pub fn net_worth(
+    db: &Database,
+    user_id: i64,
+    from: NaiveDate,
+    to: NaiveDate,
+    interval: Interval,
+    currency: Currency,
+) -> Result<NetWorth, NetWorthError> {
+    if from > to {
+        return Err(NetWorthError::InvalidRange);
+    }
+    let dates = period_ends(from, to, interval);
+    if dates.len() > MAX_POINTS {
+        return Err(NetWorthError::TooManyPoints);
+    }
+
+    let histories = histories(db, user_id)?;
+    let portfolio = Portfolio::load(db, user_id)?;
+    let rates = exchange::load_rates(db, user_id)?;
+    let series = dates
+        .iter()
+        .map(|date| point(&histories, &portfolio, &rates, currency, *date))
+        .collect::<Result<Vec<_>, _>>()?;
+    let current = point(&histories, &portfolio, &rates, currency, to)?.net_worth;
+    Ok(NetWorth {
+        interval,
+        currency,
+        series,
+        summary: NetWorthSummary {
+            date: to,
+            net_worth: current,
+            previous_period: change(
+                &histories,
+                &portfolio,
+                &rates,
+                current,
+                interval.previous(to),
+            )?,
+            year_over_year: change(
+                &histories,
+                &portfolio,
+                &rates,
+                current,
+                to - Months::new(12),
+            )?,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::{ChequingAccount, CreditAccount};
+    use crate::investment;
+    use crate::manual;
+    use crate::transaction::Transaction;
+    use crate::user::User;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_period_ends() {
+        assert_eq!(
+            period_ends(date(2025, 1, 15), date(2025, 3, 10), Interval::Monthly),
+            vec![date(2025, 1, 31), date(2025, 2, 28), date(2025, 3, 10)]
+        );
+        assert_eq!(
+            period_ends(date(2025, 2, 27), date(2025, 3, 1), Interval::Daily),
+            vec![date(2025, 2, 27), date(2025, 2, 28), date(2025, 3, 1)]
+        );
+        assert_eq!(
+            Interval::Monthly.previous(date(2025, 3, 10)),
+            date(2025, 2, 28)
+        );
+    }
+
+    // $1,500 in chequing and $200 owed on the card now
+    fn networth_db() -> Database {
+        let db = Database::new(":memory:".to_string()).unwrap();
+        db.insert_user(&User {
+            id: 1,
+            name: "Alice".into(),
+        })
+        .unwrap();
+        db.insert_account(&ChequingAccount {
+            user_id: 1,
+            account_number: 1001,
+            balance: Money::cad(150000),
+        })
+        .unwrap();
+        db.insert_account(&CreditAccount {
+            user_id: 1,
+            account_number: 2002,
+            balance_owed: Money::cad(20000),
+            credit_limit: Money::cad(500000),
+            interest_rate: 0.0,
+        })
+        .unwrap();
+
+        let rows = [
+            (1001, date(2024, 3, 1), "PAYROLL", 100000),
+            (1001, date(2025, 1, 1), "PAYROLL", 100000),
+            (1001, date(2025, 2, 1), "PAYROLL", 100000),
+            (1001, date(2025, 2, 15), "RENT", -50000),
+            (2002, date(2025, 2, 20), "LOBLAWS", -20000),
+            (1001, date(2025, 3, 1), "PAYROLL", 100000),
+            (1001, date(2025, 3, 2), "PAYMENT - VISA", -30000),
+            (2002, date(2025, 3, 3), "PAYMENT THANK YOU", 30000),
+            (2002, date(2025, 3, 5), "NETFLIX", -20000),
+        ];
+        for (account_number, transaction_date, description, cad) in rows {
+            db.insert_transaction(&Transaction {
+                user_id: 1,
+                account_number,
+                account_type: if account_number == 2002 {
+                    AccountType::Credit
+                } else {
+                    AccountType::Chequing
+                },
+                transaction_date,
+                description_1: description.into(),
+                cad: Money::cad(cad),
+                ..Transaction::dummy()
+            })
+            .unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn test_monthly_series_and_summary() {
+        let db = networth_db();
+        let worth = net_worth(
+            &db,
+            1,
+            date(2025, 1, 1),
+            date(2025, 3, 31),
+            Interval::Monthly,
+            Currency::CAD,
+        )
+        .unwrap();
+
+        let net: Vec<i64> = worth
+            .series
+            .iter()
+            .map(|p| p.net_worth.minor_units())
+            .collect();
+        assert_eq!(net, vec![20000, 50000, 130000]);
+        assert_eq!(worth.series[1].assets, Money::cad(80000));
+        assert_eq!(worth.series[1].liabilities, Money::cad(30000));
+        assert_eq!(worth.series[2].liabilities, Money::cad(20000));
+
+        let summary = worth.summary;
+        assert_eq!(summary.net_worth, Money::cad(130000));
+        assert_eq!(summary.previous_period.date, date(2025, 2, 28));
+        assert_eq!(summary.previous_period.amount, Money::cad(80000));
+        assert!((summary.previous_period.rate.unwrap() - 1.6).abs() < 1e-9);
+        // overdrawn a year ago, before most of the paycheques
+        assert_eq!(summary.year_over_year.net_worth, Money::cad(-80000));
+        assert_eq!(summary.year_over_year.amount, Money::cad(210000));
+
+        assert!(matches!(
+            net_worth(
+                &db,
+                1,
+                date(2025, 3, 31),
+                date(2025, 1, 1),
+                Interval::Daily,
+                Currency::CAD
+            ),
+            Err(NetWorthError::InvalidRange)
+        ));
+        assert!(matches!(
+            net_worth(
+                &db,
+                1,
+                date(2020, 1, 1),
+                date(2025, 1, 1),
+                Interval::Daily,
+                Currency::CAD
+            ),
+            Err(NetWorthError::TooManyPoints)
+        ));
+    }
+
+    #[test]
+    fn test_snapshots_anchor_history() {
+        let db = networth_db();
+        take_snapshots(&db, 1, date(2025, 4, 1)).unwrap();
+        let snapshots = db.get_balance_snapshots(1).unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].date, date(2025, 3, 2));
+        assert_eq!(snapshots[0].balance, Money::cad(150000));
+
+        // a snapshot holds for its day even if the transactions since disagree
+        db.upsert_balance_snapshot(1, 1001, date(2025, 2, 28), Money::cad(25000))
+            .unwrap();
+        let worth = net_worth(
+            &db,
+            1,
+            date(2025, 2, 1),
+            date(2025, 3, 31),
+            Interval::Monthly,
+            Currency::CAD,
+        )
+        .unwrap();
+        assert_eq!(worth.series[0].assets, Money::cad(25000));
+        assert_eq!(worth.series[1].assets, Money::cad(150000));
+        // and everything before it follows from the snapshot
+        let january = net_worth(
+            &db,
+            1,
+            date(2025, 1, 31),
+            date(2025, 1, 31),
+            Interval::Daily,
+            Currency::CAD,
+        )
+        .unwrap();
+        assert_eq!(january.series[0].assets, Money::cad(-25000));
+    }
+
+    #[test]
+    fn test_manual_account_counts_from_opening() {
+        let db = networth_db();
+        let cash = manual::create_account(
+            &db,
+            1,
+            manual::NewAccount {
+                account_type: AccountType::Chequing,
+                nickname: "Cash".into(),
+                institution: None,
+                opened_on: date(2025, 2, 10),
+                opening_balance: Money::cad(30000),
+                credit_limit: None,
+                interest_rate: 0.0,
+                loan: None,
+            },
+        )
+        .unwrap();
+        manual::add_transaction(
+            &db,
+            1,
+            manual::NewTransaction {
+                account_number: cash,
+                transaction_date: date(2025, 3, 4),
+                description: "FARMERS MARKET".into(),
+                amount: Money::cad(-5000),
+                notes: String::new(),
+                category_id: None,
+                category: None,
+            },
+        )
+        .unwrap();
+
+        let worth = net_worth(
+            &db,
+            1,
+            date(2025, 1, 1),
+            date(2025, 3, 31),
+            Interval::Monthly,
+            Currency::CAD,
+        )
+        .unwrap();
+        let assets: Vec<Money> = worth.series.iter().map(|p| p.assets).collect();
+        assert_eq!(
+            assets,
+            vec![
+                // before the cash was counted
+                Money::cad(30000),
+                Money::cad(80000 + 30000),
+                Money::cad(150000 + 25000),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_holdings_count_at_market_value() {
+        let db = networth_db();
+        let tfsa = manual::create_account(
+            &db,
+            1,
+            manual::NewAccount {
+                account_type: AccountType::Tfsa,
+                nickname: "TFSA".into(),
+                institution: None,
+                opened_on: date(2025, 1, 2),
+                opening_balance: Money::cad(100000),
+                credit_limit: None,
+                interest_rate: 0.0,
+                loan: None,
+            },
+        )
+        .unwrap();
+        investment::record_trade(
+            &db,
+            1,
+            tfsa,
+            investment::NewTrade {
+                trade_date: date(2025, 1, 6),
+                kind: investment::TradeKind::Buy,
+                symbol: Some("XEQT".into()),
+                quantity: 20.0,
+                amount: Money::cad(60000),
+                notes: String::new(),
+            },
+        )
+        .unwrap();
+        investment::import_prices(&db, 1, "date,symbol,price\n2025-02-28,XEQT,32.50\n").unwrap();
+
+        let worth = net_worth(
+            &db,
+            1,
+            date(2025, 1, 1),
+            date(2025, 2, 28),
+            Interval::Monthly,
+            Currency::CAD,
+        )
+        .unwrap();
+        let tfsa_assets: Vec<Money> = worth
+            .series
+            .iter()
+            .map(|p| p.assets)
+            .zip([Money::cad(30000), Money::cad(80000)])
+            .map(|(assets, chequing)| assets - chequing)
+            .collect();
+        // cash left plus the shares, at cost until the first price
+        assert_eq!(
+            tfsa_assets,
+            vec![Money::cad(40000 + 60000), Money::cad(40000 + 65000)]
+        );
+    }
+
+    #[test]
+    fn test_usd_accounts_are_converted() {
+        let db = networth_db();
+        db.insert_account(&CreditAccount {
+            user_id: 1,
+            account_number: 4004,
+            balance_owed: Money::usd(10000),
+            credit_limit: Money::usd(100000),
+            interest_rate: 0.0,
+        })
+        .unwrap();
+        let worth = |db: &Database| {
+            net_worth(
+                db,
+                1,
+                date(2025, 3, 31),
+                date(2025, 3, 31),
+                Interval::Daily,
+                Currency::CAD,
+            )
+        };
+        assert!(matches!(
+            worth(&db),
+            Err(NetWorthError::Exchange(ExchangeError::MissingRate { .. }))
+        ));
+
+        db.insert_exchange_rates(
+            1,
+            &exchange::parse_rates("date,FXUSDCAD\n2025-03-28,1.4\n").unwrap(),
+        )
+        .unwrap();
+        let worth = worth(&db).unwrap();
+        assert_eq!(worth.series[0].liabilities, Money::cad(20000 + 14000));
+        assert_eq!(worth.summary.net_worth, Money::cad(150000 - 34000));
+    }
+}
@@ -1,10 +1,12 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::format;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
 use std::path::Path;
 
-use crate::account::AccountType;
+use chrono::NaiveDate;
+
+use crate::account::{AccountType, BankNumber};
+use crate::importer::{ImportOptions, ImporterRegistry};
+use crate::money::{Currency, Money};
 use crate::transaction::Transaction;
 
 extern crate regex;
@@ -15,6 +17,7 @@ pub enum ParseError {
     Io(std::io::Error),
     Regex(regex::Error),
     ParseFloat(String),
+    InvalidDate(String),
     InvalidFormat(String),
 }
 
@@ -25,6 +28,7 @@ impl std::fmt::Display for ParseError {
             ParseError::InvalidFormat(msg) => write!(f, "Invalid format: {}", msg),
             ParseError::Regex(err) => write!(f, "Regex error: {}", err),
             ParseError::ParseFloat(msg) => write!(f, "Parse float error: {}", msg),
+            ParseError::InvalidDate(msg) => write!(f, "Invalid date: {}", msg),
         }
     }
 }
@@ -43,24 +47,178 @@ impl From<regex::Error> for ParseError {
     }
 }
 
-pub fn parse_csv_to_transactions(
-    user_id: i64,
-    path: &Path,
-) -> Result<Vec<Transaction>, ParseError> {
-    let f = File::open(path)?;
-    let mut reader = BufReader::new(f);
+// Transactions read from one statement file, plus any rows that could not be read.
+// A bad row does not fail the whole import, it is reported here with its line number.
+#[derive(Debug, Default)]
+pub struct ParsedStatement {
+    pub transactions: Vec<Transaction>,
+    // the bank's number of each account in an export, by the provisional
+    // account number its rows carry until the import assigns the real one
+    pub bank_numbers: HashMap<i64, BankNumber>,
+    pub errors: Vec<LineError>,
+    pub balance: Option<Money>,
+    pub credit_limit: Option<Money>,
+}
 
-    let mut header = String::new();
-    reader.read_line(&mut header)?;
+#[derive(Debug)]
+pub struct LineError {
+    pub line: usize,
+    pub error: ParseError,
+}
 
-    let mut transactions: Vec<Transaction> = vec![];
+impl std::fmt::Display for LineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+// One CSV record with the line it starts on, quotes already removed
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvRecord {
+    pub line: usize,
+    pub fields: Vec<String>,
+}
+
+enum FieldEnd {
+    Comma,
+    Eol,
+    Eof,
+}
 
-    for line in reader.lines() {
-        let transaction = Transaction::from_rbc_csv(user_id, line?);
-        transactions.push(transaction);
+// RFC 4180 reader: quoted fields may contain commas, line breaks and doubled quotes.
+// Handles CRLF, a leading BOM and blank lines. A malformed record is returned as an
+// error for its starting line and reading resumes on the next line.
+pub fn parse_csv(input: &str) -> Vec<Result<CsvRecord, LineError>> {
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+    let mut chars = input.chars().peekable();
+    let mut records = Vec::new();
+    let mut line = 1;
+
+    while chars.peek().is_some() {
+        let start = line;
+        match read_csv_record(&mut chars, &mut line) {
+            Ok(fields) => {
+                if fields.len() > 1 || !fields[0].is_empty() {
+                    records.push(Ok(CsvRecord {
+                        line: start,
+                        fields,
+                    }));
+                }
+            }
+            Err(msg) => {
+                skip_csv_line(&mut chars, &mut line);
+                records.push(Err(LineError {
+                    line: start,
+                    error: ParseError::InvalidFormat(msg),
+                }));
+            }
+        }
     }
 
-    Ok(transactions)
+    records
+}
+
+type CsvChars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+fn read_csv_record(chars: &mut CsvChars, line: &mut usize) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    loop {
+        let (field, end) = read_csv_field(chars, line)?;
+        fields.push(field);
+        match end {
+            FieldEnd::Comma => continue,
+            FieldEnd::Eol | FieldEnd::Eof => return Ok(fields),
+        }
+    }
+}
+
+fn read_csv_field(chars: &mut CsvChars, line: &mut usize) -> Result<(String, FieldEnd), String> {
+    let mut field = String::new();
+
+    if chars.peek() == Some(&'"') {
+        chars.next();
+        loop {
+            match chars.next() {
+                None => return Err("unterminated quoted field".to_string()),
+                Some('"') if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                Some('"') => break,
+                Some(c) => {
+                    if c == '\n' {
+                        *line += 1;
+                    }
+                    field.push(c);
+                }
+            }
+        }
+        return match read_csv_separator(chars, line) {
+            Some(end) => Ok((field, end)),
+            None => Err(format!(
+                "unexpected character after closing quote of \"{}\"",
+                field
+            )),
+        };
+    }
+
+    loop {
+        match chars.peek() {
+            Some('"') => return Err(format!("unexpected quote after \"{}\"", field)),
+            Some(',') | Some('\r') | Some('\n') | None => {
+                return match read_csv_separator(chars, line) {
+                    Some(end) => Ok((field, end)),
+                    None => Err("stray carriage return".to_string()),
+                }
+            }
+            Some(&c) => {
+                chars.next();
+                field.push(c);
+            }
+        }
+    }
+}
+
+fn read_csv_separator(chars: &mut CsvChars, line: &mut usize) -> Option<FieldEnd> {
+    match chars.next() {
+        None => Some(FieldEnd::Eof),
+        Some(',') => Some(FieldEnd::Comma),
+        Some('\n') => {
+            *line += 1;
+            Some(FieldEnd::Eol)
+        }
+        Some('\r') if chars.peek() == Some(&'\n') => {
+            chars.next();
+            *line += 1;
+            Some(FieldEnd::Eol)
+        }
+        Some(_) => None,
+    }
+}
+
+fn skip_csv_line(chars: &mut CsvChars, line: &mut usize) {
+    for c in chars.by_ref() {
+        if c == '\n' {
+            *line += 1;
+            return;
+        }
+    }
+}
+
+// Parses a bank CSV export, picking the bank format from its header row. The rows
+// carry provisional account numbers, see `ParsedStatement::bank_numbers`. Rows that
+// could not be read are returned alongside the others.
+pub fn parse_csv_to_transactions(
+    user_id: i64,
+    path: &Path,
+) -> Result<(Vec<Transaction>, Vec<LineError>), ParseError> {
+    let input = std::fs::read_to_string(path)?;
+    let options = ImportOptions {
+        user_id,
+        ..Default::default()
+    };
+    let statement = ImporterRegistry::default().import(&input, &options)?;
+    Ok((statement.transactions, statement.errors))
 }
 
 fn _capture_groups(regex: &Regex, line: &str) -> Vec<String> {
@@ -72,6 +230,22 @@ fn _capture_groups(regex: &Regex, line: &str) -> Vec<String> {
         .collect()
 }
 
+pub(crate) fn parse_amount(amount: &str, currency: Currency) -> Result<Money, ParseError> {
+    Money::parse(amount, currency).map_err(|_| ParseError::ParseFloat(amount.to_string()))
+}
+
+// Date formats seen in bank exports, tried in order.
+// Slashed dates are month first, the way RBC writes them (5/12/2025).
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%m/%d/%Y", "%b %d, %Y", "%B %d, %Y", "%Y/%m/%d"];
+
+pub fn parse_date(date: &str) -> Result<NaiveDate, ParseError> {
+    let date = date.trim();
+    DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(date, format).ok())
+        .ok_or_else(|| ParseError::InvalidDate(date.to_string()))
+}
+
 // parse the extracted transaction data from python script
 // Format: date, description, withdrawal, deposit, balance
 // XXX 00, 0000 description withdrawal deposit balance
@@ -83,25 +257,41 @@ pub fn parse_extracted_transactions(
     path: &Path,
     account_number: i64,
     account_type: AccountType,
-    balance: &mut f64,
-    credit_limit: &mut f64,
+    balance: &mut Money,
+    credit_limit: &mut Money,
 ) -> Result<Vec<Transaction>, ParseError> {
-    let f = File::open(path)?;
-    let reader = BufReader::new(f);
+    let input = std::fs::read_to_string(path)?;
+    let mut statement = parse_statement_text(&input, 1, account_number, account_type);
+
+    if !statement.errors.is_empty() {
+        return Err(statement.errors.remove(0).error);
+    }
+    if let Some(b) = statement.balance {
+        *balance = b;
+    }
+    if let Some(limit) = statement.credit_limit {
+        *credit_limit = limit;
+    }
 
-    let mut transactions: Vec<Transaction> = vec![];
+    Ok(statement.transactions)
+}
 
-    let lines_iter = reader.lines();
+pub fn parse_statement_text(
+    input: &str,
+    user_id: i64,
+    account_number: i64,
+    account_type: AccountType,
+) -> ParsedStatement {
+    let mut statement = ParsedStatement::default();
 
     let regex =
         Regex::new(r"(?m)([a-zA-Z]{3}) ([0-9]+), ([0-9]{4}) (.*?)(( [-$]+[0-9,]+.[0-9]*)+)")
             .unwrap();
 
     let mut previous_line = String::new();
-    let mut idx = 0;
-    for line in lines_iter {
-        idx += 1;
-        let current_line = line?;
+    for (idx, line) in input.lines().enumerate() {
+        let idx = idx + 1;
+        let current_line = line.to_string();
 
         // skip links
         if current_line.contains("http") {
@@ -110,11 +300,12 @@ pub fn parse_extracted_transactions(
         }
 
         if !regex.is_match(&current_line) {
-            if !transactions.is_empty()
+            if !statement.transactions.is_empty()
                 && regex.is_match(&previous_line)
                 && current_line.split(" ").collect::<Vec<&str>>().len() < 5
             {
-                transactions
+                statement
+                    .transactions
                     .last_mut()
                     .unwrap()
                     .description_2
@@ -122,16 +313,19 @@ pub fn parse_extracted_transactions(
             } else if idx == 6 {
                 // balance and credit line
                 let split_line = current_line.split(" ").collect::<Vec<_>>();
-                *balance = split_line[0]
-                    .replace("$", "")
-                    .replace(",", "")
-                    .parse::<f64>()
-                    .map_err(|_| ParseError::ParseFloat(current_line.clone()))?;
-                *credit_limit = split_line[1]
-                    .replace("$", "")
-                    .replace(",", "")
-                    .parse::<f64>()
-                    .map_err(|_| ParseError::ParseFloat(current_line.clone()))?;
+                match (
+                    parse_amount(split_line[0], Currency::CAD),
+                    parse_amount(split_line.get(1).unwrap_or(&""), Currency::CAD),
+                ) {
+                    (Ok(balance), Ok(credit_limit)) => {
+                        statement.balance = Some(balance);
+                        statement.credit_limit = Some(credit_limit);
+                    }
+                    _ => statement.errors.push(LineError {
+                        line: idx,
+                        error: ParseError::ParseFloat(current_line.clone()),
+                    }),
+                }
             }
             previous_line = current_line.clone();
             continue;
@@ -146,25 +340,51 @@ pub fn parse_extracted_transactions(
             split_line.pop_front().unwrap_or_default(),
             split_line.pop_front().unwrap_or_default(),
         ));
+        let date = match parse_date(&date) {
+            Ok(date) => date,
+            Err(error) => {
+                statement.errors.push(LineError { line: idx, error });
+                previous_line = current_line.clone();
+                continue;
+            }
+        };
 
         let mut description = String::new();
-        while !split_line.front().map(|w| w.contains("$")).unwrap_or(false) {
-            description.push_str(format!("{} ", split_line.pop_front().unwrap()).as_str());
+        while let Some(word) = split_line.front().filter(|w| !w.contains('$')) {
+            description.push_str(format!("{} ", word).as_str());
+            split_line.pop_front();
+        }
+        if split_line.is_empty() {
+            statement.errors.push(LineError {
+                line: idx,
+                error: ParseError::ParseFloat(current_line.clone()),
+            });
+            previous_line = current_line.clone();
+            continue;
         }
 
         let amount = split_line.pop_front().unwrap_or_default();
-        let mut amount_num = amount
-            .replace("$", "")
-            .replace(",", "")
-            .parse::<f64>()
-            .map_err(|_| ParseError::ParseFloat(amount.to_string()))?;
+        let mut amount_num = match parse_amount(amount, Currency::CAD) {
+            Ok(amount) => amount,
+            Err(error) => {
+                statement.errors.push(LineError { line: idx, error });
+                previous_line = current_line.clone();
+                continue;
+            }
+        };
 
         if account_type == AccountType::Credit {
-            amount_num *= -1.0;
+            amount_num = -amount_num;
         }
 
+        // the account balance after the transaction, when the statement prints one
+        let statement_balance = split_line
+            .pop_front()
+            .filter(|b| b.contains('$'))
+            .and_then(|b| parse_amount(b, Currency::CAD).ok());
+
         let transaction = Transaction {
-            user_id: 1,
+            user_id,
             account_type,
             account_number,
             transaction_date: date,
@@ -172,25 +392,35 @@ pub fn parse_extracted_transactions(
             description_1: description,
             description_2: String::new(),
             cad: amount_num,
-            usd: 0.0,
+            usd: Money::usd(0),
+            id: 0,
+            category_id: None,
+            notes: String::new(),
+            merchant_id: None,
+            merchant: String::new(),
+            transfer_id: None,
+            statement_balance,
+            currency: Currency::CAD,
             category: String::new(),
         };
 
         previous_line = current_line.clone();
-        transactions.push(transaction);
+        statement.transactions.push(transaction);
     }
 
-    Ok(transactions)
+    statement
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::calculate_hash;
-
     use super::*;
     use std::io::Write;
     use tempfile::NamedTempFile;
 
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
     fn write_sample_data(file: &mut NamedTempFile) {
         writeln!(
             file,
@@ -235,33 +465,36 @@ mod tests {
         let path = file.path();
         let transactions = parse_extracted_transactions(
             path,
-            calculate_hash(&"4325".to_string()),
+            4325,
             AccountType::Chequing,
-            &mut 0.0,
-            &mut 0.0,
+            &mut Money::default(),
+            &mut Money::default(),
         )
         .unwrap();
 
         assert_eq!(transactions.len(), 5);
 
-        assert_eq!(transactions[0].transaction_date, "May 12, 2025");
+        assert_eq!(transactions[0].transaction_date, date(2025, 5, 12));
         assert!(transactions[0]
             .description_1
             .contains("Deposit Account-7535"));
-        assert_eq!(transactions[0].cad, -850.00);
+        assert_eq!(transactions[0].cad, Money::cad(-85000));
+        assert_eq!(transactions[0].statement_balance, Some(Money::cad(31617)));
+        assert_eq!(transactions[1].statement_balance, Some(Money::cad(116617)));
 
-        assert_eq!(transactions[2].transaction_date, "May 8, 2025");
-        assert_eq!(transactions[2].cad, 25.00);
+        assert_eq!(transactions[2].transaction_date, date(2025, 5, 8));
+        assert_eq!(transactions[2].cad, Money::cad(2500));
         assert_eq!(transactions[2].description_2, "Galine");
 
-        assert_eq!(transactions[3].transaction_date, "May 7, 2025");
+        assert_eq!(transactions[3].transaction_date, date(2025, 5, 7));
         assert!(transactions[3].description_1.contains("8069"));
-        assert_eq!(transactions[3].cad, -213.45);
+        assert_eq!(transactions[3].cad, Money::cad(-21345));
 
-        assert_eq!(transactions[4].transaction_date, "May 7, 2025");
+        assert_eq!(transactions[4].transaction_date, date(2025, 5, 7));
         assert_eq!(transactions[4].description_1.trim(), "Payroll Deposit");
         assert_eq!(transactions[4].description_2, "CANADA");
-        assert_eq!(transactions[4].cad, 750.78);
+        assert_eq!(transactions[4].cad, Money::cad(75078));
+        assert_eq!(transactions[4].statement_balance, None);
     }
 
     #[test]
@@ -272,19 +505,19 @@ mod tests {
         let path = file.path();
         let result = parse_extracted_transactions(
             path,
-            calculate_hash(&"123456".to_string()),
+            123456,
             AccountType::Chequing,
-            &mut 0.0,
-            &mut 0.0,
+            &mut Money::default(),
+            &mut Money::default(),
         )
         .unwrap();
 
         assert_eq!(result.len(), 1);
         let txn = &result[0];
-        assert_eq!(txn.transaction_date, "Jan 01, 2024");
+        assert_eq!(txn.transaction_date, date(2024, 1, 1));
         assert_eq!(txn.description_1.trim(), "Deposit Description");
         assert_eq!(txn.description_2, "");
-        assert_eq!(txn.cad, 100.0);
+        assert_eq!(txn.cad, Money::cad(10000));
     }
 
     #[test]
@@ -296,10 +529,10 @@ mod tests {
         let path = file.path();
         let result = parse_extracted_transactions(
             path,
-            calculate_hash(&"123456".to_string()),
+            123456,
             AccountType::Savings,
-            &mut 0.0,
-            &mut 0.0,
+            &mut Money::default(),
+            &mut Money::default(),
         )
         .unwrap();
 
@@ -317,10 +550,10 @@ mod tests {
         let path = file.path();
         let result = parse_extracted_transactions(
             path,
-            calculate_hash(&"123456".to_string()),
+            123456,
             AccountType::Chequing,
-            &mut 0.0,
-            &mut 0.0,
+            &mut Money::default(),
+            &mut Money::default(),
         )
         .unwrap();
 
@@ -340,10 +573,10 @@ mod tests {
         let path = file.path();
         let result = parse_extracted_transactions(
             path,
-            calculate_hash(&"123456".to_string()),
+            123456,
             AccountType::Chequing,
-            &mut 0.0,
-            &mut 0.0,
+            &mut Money::default(),
+            &mut Money::default(),
         )
         .unwrap();
 
@@ -351,6 +584,129 @@ mod tests {
         assert_eq!(result[0].description_1.trim(), "Online Payment");
     }
 
+    #[test]
+    fn test_parse_date_formats() {
+        assert_eq!(parse_date("5/12/2025").unwrap(), date(2025, 5, 12));
+        assert_eq!(parse_date("05/02/2025").unwrap(), date(2025, 5, 2));
+        assert_eq!(parse_date("May 12, 2025").unwrap(), date(2025, 5, 12));
+        assert_eq!(parse_date("Jan 01, 2024").unwrap(), date(2024, 1, 1));
+        assert_eq!(parse_date("September 3, 2024").unwrap(), date(2024, 9, 3));
+        assert_eq!(parse_date(" 2025-01-01 ").unwrap(), date(2025, 1, 1));
+    }
+
+    #[test]
+    fn test_parse_date_rejects_invalid() {
+        for text in ["", "13/01/2025", "Feb 30, 2024", "yesterday", "2025-1"] {
+            assert!(
+                matches!(parse_date(text), Err(ParseError::InvalidDate(_))),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_statement_date_is_reported() {
+        let statement = parse_statement_text(
+            "Jan 01, 2024 Deposit $100.00\nFeb 30, 2024 Deposit $5.00\n",
+            1,
+            42,
+            AccountType::Chequing,
+        );
+
+        assert_eq!(statement.transactions.len(), 1);
+        assert_eq!(statement.errors.len(), 1);
+        assert_eq!(statement.errors[0].line, 2);
+        assert!(matches!(
+            statement.errors[0].error,
+            ParseError::InvalidDate(_)
+        ));
+    }
+
+    #[test]
+    fn test_statement_line_without_dollar_amount_is_reported() {
+        let statement = parse_statement_text(
+            "Jan 01, 2024 Refund -100.00\nJan 02, 2024 Deposit $5.00\n",
+            1,
+            42,
+            AccountType::Chequing,
+        );
+
+        assert_eq!(statement.transactions.len(), 1);
+        assert_eq!(statement.errors.len(), 1);
+        assert_eq!(statement.errors[0].line, 1);
+        assert!(matches!(
+            statement.errors[0].error,
+            ParseError::ParseFloat(_)
+        ));
+    }
+
+    fn csv_fields(input: &str) -> Vec<Vec<String>> {
+        parse_csv(input)
+            .into_iter()
+            .map(|r| r.unwrap().fields)
+            .collect()
+    }
+
+    #[test]
+    fn test_csv_quoted_commas_and_quotes() {
+        let rows = csv_fields("a,\"TIM HORTONS, NEPEAN\",\"5\"\" TV\",\n");
+        assert_eq!(rows, vec![vec!["a", "TIM HORTONS, NEPEAN", "5\" TV", ""]]);
+    }
+
+    #[test]
+    fn test_csv_crlf_bom_and_blank_lines() {
+        let parsed = parse_csv("\u{feff}h1,h2\r\n1,2\r\n\r\n3,4\r\n\r\n\n");
+        let records: Vec<CsvRecord> = parsed.into_iter().map(|r| r.unwrap()).collect();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].fields, vec!["h1", "h2"]);
+        assert_eq!(records[2].fields, vec!["3", "4"]);
+        assert_eq!(records[2].line, 4);
+    }
+
+    #[test]
+    fn test_csv_multiline_field_keeps_line_numbers() {
+        let parsed = parse_csv("\"line one\nline two\",x\ny,z\n");
+        let records: Vec<CsvRecord> = parsed.into_iter().map(|r| r.unwrap()).collect();
+
+        assert_eq!(records[0].fields[0], "line one\nline two");
+        assert_eq!(records[0].line, 1);
+        assert_eq!(records[1].line, 3);
+    }
+
+    #[test]
+    fn test_csv_malformed_rows() {
+        let parsed = parse_csv("ok,1\n\"bad\"x,2\nstray\"quote,3\nok,4\n\"unterminated,5\n");
+
+        assert_eq!(parsed.len(), 5);
+        assert!(parsed[0].is_ok());
+        for (idx, line) in [(1, 2), (2, 3), (4, 5)] {
+            let err = parsed[idx].as_ref().unwrap_err();
+            assert_eq!(err.line, line);
+            assert!(matches!(err.error, ParseError::InvalidFormat(_)));
+        }
+        assert_eq!(parsed[3].as_ref().unwrap().fields, vec!["ok", "4"]);
+    }
+
+    #[test]
+    fn test_csv_file_returns_row_errors() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(
+            file,
+            "\"Account Type\",\"Account Number\",\"Transaction Date\",\"Cheque Number\",\"Description 1\",\"Description 2\",\"CAD$\",\"USD$\"
+Chequing,00000-1234567,5/12/2025,,\"TIM HORTONS\",,-2.45,
+Chequing,00000-1234567,someday,,\"AMAZON\",,-30.00,
+"
+        )
+        .unwrap();
+
+        let (transactions, errors) = parse_csv_to_transactions(7, file.path()).unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 3);
+    }
+
     #[test]
     fn test_multiple_transactions_parsing() {
         let mut file = NamedTempFile::new().unwrap();
@@ -360,17 +716,17 @@ mod tests {
         let path = file.path();
         let result = parse_extracted_transactions(
             path,
-            calculate_hash(&"123456".to_string()),
+            123456,
             AccountType::Chequing,
-            &mut 0.0,
-            &mut 0.0,
+            &mut Money::default(),
+            &mut Money::default(),
         )
         .unwrap();
 
         assert_eq!(result.len(), 2);
         assert_eq!(result[0].description_1.trim(), "Salary");
-        assert_eq!(result[0].cad, 1000.0);
+        assert_eq!(result[0].cad, Money::cad(100000));
         assert_eq!(result[1].description_1.trim(), "Grocery Store");
-        assert_eq!(result[1].cad, -123.45);
+        assert_eq!(result[1].cad, Money::cad(-12345));
     }
 }
@@ -0,0 +1,407 @@
+use core::fmt;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::{
+    account::{AccountType, BankAccount},
+    database::Database,
+    money::Money,
+    transaction::Transaction,
+};
+
+// A transaction with the account balance right after it
+#[derive(Debug, Clone, Serialize)]
+pub struct LedgerEntry {
+    #[serde(flatten)]
+    pub transaction: Transaction,
+    pub balance: Money,
+}
+
+// Where the recorded transactions disagree with the balances printed on statements
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Discrepancy {
+    // The statement balance moved by more than the recorded transactions dated
+    // after `after` and up to `through`, a transaction of `amount` is missing
+    Missing {
+        after: NaiveDate,
+        through: NaiveDate,
+        amount: Money,
+    },
+    // Recorded twice but only printed once on the statement
+    Duplicate {
+        transaction_id: i64,
+        date: NaiveDate,
+        amount: Money,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReconciliationStatus {
+    Balanced,
+    Discrepancies,
+    // no statement printed balances to check against
+    Unverified,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Reconciliation {
+    pub account_number: i64,
+    pub account_type: AccountType,
+    // the account balance, taken as the balance after its latest transaction
+    pub balance: Money,
+    pub status: ReconciliationStatus,
+    // days with a printed balance that were checked
+    pub checkpoints: usize,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+#[derive(Debug)]
+pub enum ReconcileError {
+    AccountNotFound,
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for ReconcileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReconcileError::AccountNotFound => write!(f, "Account not found"),
+            ReconcileError::Database(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ReconcileError {}
+
+impl From<rusqlite::Error> for ReconcileError {
+    fn from(err: rusqlite::Error) -> Self {
+        ReconcileError::Database(err)
+    }
+}
+
+// How a transaction moves the balance the bank reports. Credit card and loan
+// balances are the amount owed, so spending (a negative amount) raises them.
+pub fn balance_change(account_type: AccountType, amount: Money) -> Money {
+    match account_type {
+        AccountType::Credit | AccountType::Loan => -amount,
+        _ => amount,
+    }
+}
+
+// Running balance of an account whose transactions are oldest first, worked
+// backwards from `anchor`, the balance after the last of them
+pub fn running_balances(
+    account_type: AccountType,
+    anchor: Money,
+    transactions: Vec<Transaction>,
+) -> Vec<LedgerEntry> {
+    let mut balance = anchor;
+    let mut entries: Vec<LedgerEntry> = transactions
+        .into_iter()
+        .rev()
+        .map(|transaction| {
+            let entry_balance = balance;
+            balance -= balance_change(account_type, transaction.amount());
+            LedgerEntry {
+                transaction,
+                balance: entry_balance,
+            }
+        })
+        .collect();
+    entries.reverse();
+    entries
+}
+
+// The balance printed at the end of each day that has one, oldest first.
+// Statements list the newest transaction first, so the day ends on the first
+// printed balance imported for it.
+fn printed_checkpoints(transactions: &[Transaction]) -> Vec<(NaiveDate, Money)> {
+    let mut checkpoints: Vec<(NaiveDate, i64, Money)> = Vec::new();
+    for transaction in transactions {
+        let Some(printed) = transaction.statement_balance else {
+            continue;
+        };
+        match checkpoints.last_mut() {
+            Some((date, id, balance)) if *date == transaction.transaction_date => {
+                if transaction.id < *id {
+                    *id = transaction.id;
+                    *balance = printed;
+                }
+            }
+            _ => checkpoints.push((transaction.transaction_date, transaction.id, printed)),
+        }
+    }
+    checkpoints
+        .into_iter()
+        .map(|(date, _, balance)| (date, balance))
+        .collect()
+}
+
+// Explains a gap of `difference` between two checkpoints. A recorded
+// transaction that accounts for it and has an identical twin on the same day
+// is a duplicate, anything else is a transaction missing from the records.
+fn discrepancy(
+    account_type: AccountType,
+    after: NaiveDate,
+    through: NaiveDate,
+    difference: Money,
+    span: &[&Transaction],
+    transactions: &[Transaction],
+) -> Discrepancy {
+    let duplicate = span.iter().rev().find(|t| {
+        balance_change(account_type, t.amount()) == -difference
+            && transactions.iter().any(|other| {
+                other.id < t.id
+                    && other.transaction_date == t.transaction_date
+                    && other.fingerprint_key() == t.fingerprint_key()
+            })
+    });
+    match duplicate {
+        Some(t) => Discrepancy::Duplicate {
+            transaction_id: t.id,
+            date: t.transaction_date,
+            amount: t.amount(),
+        },
+        None => Discrepancy::Missing {
+            after,
+            through,
+            amount: balance_change(account_type, difference),
+        },
+    }
+}
+
+// Checks an account's transactions, oldest first, against the balances its
+// statements printed and against the account balance. Each stretch between
+// two checkpoints is compared on its own so one missing transaction is
+// reported once rather than on every later day.
+pub fn reconcile_account(
+    account: &dyn BankAccount,
+    transactions: &[Transaction],
+) -> Reconciliation {
+    let account_type = account.account_type();
+    let mut checkpoints = printed_checkpoints(transactions);
+    let printed = checkpoints.len();
+    if let Some(last) = transactions.last() {
+        checkpoints.push((last.transaction_date, account.balance()));
+    }
+
+    let mut discrepancies = Vec::new();
+    if printed > 0 {
+        for pair in checkpoints.windows(2) {
+            let ((after, opening), (through, closing)) = (pair[0], pair[1]);
+            let span: Vec<&Transaction> = transactions
+                .iter()
+                .filter(|t| t.transaction_date > after && t.transaction_date <= through)
+                .collect();
+            let recorded = span
+                .iter()
+                .fold(Money::zero(account.balance().currency()), |sum, t| {
+                    sum + balance_change(account_type, t.amount())
+                });
+            let difference = closing - opening - recorded;
+            if !difference.is_zero() {
+                discrepancies.push(discrepancy(
+                    account_type,
+                    after,
+                    through,
+                    difference,
+                    &span,
+                    transactions,
+                ));
+            }
+        }
+    }
+
+    let status = if printed == 0 {
+        ReconciliationStatus::Unverified
+    } else if discrepancies.is_empty() {
+        ReconciliationStatus::Balanced
+    } else {
+        ReconciliationStatus::Discrepancies
+    };
+    Reconciliation {
+        account_number: *account.account_number(),
+        account_type,
+        balance: account.balance(),
+        status,
+        checkpoints: printed,
+        discrepancies,
+    }
+}
+
+// Reconciles each of the user's accounts
+pub fn reconcile(db: &Database, user_id: i64) -> Result<Vec<Reconciliation>, ReconcileError> {
+    let mut reconciliations = Vec::new();
+    for account in db.get_accounts_by_user(user_id)? {
+        let transactions = db.get_account_transactions(user_id, *account.account_number())?;
+        reconciliations.push(reconcile_account(account.as_ref(), &transactions));
+    }
+    reconciliations.sort_by_key(|r| r.account_number);
+    Ok(reconciliations)
+}
+
+// An account's transactions with their running balance, oldest first
+pub fn ledger(
+    db: &Database,
+    user_id: i64,
+    account_number: i64,
+) -> Result<Vec<LedgerEntry>, ReconcileError> {
+    let account = db
+        .get_accounts_by_user(user_id)?
+        .into_iter()
+        .find(|a| *a.account_number() == account_number)
+        .ok_or(ReconcileError::AccountNotFound)?;
+    let transactions = db.get_account_transactions(user_id, account_number)?;
+    Ok(running_balances(
+        account.account_type(),
+        account.balance(),
+        transactions,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::{ChequingAccount, CreditAccount};
+
+    fn date(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 5, d).unwrap()
+    }
+
+    // rows as a newest first statement lists them: day, description, amount, printed balance
+    fn statement(
+        account_type: AccountType,
+        rows: &[(u32, &str, i64, Option<i64>)],
+    ) -> Vec<Transaction> {
+        let mut transactions: Vec<Transaction> = rows
+            .iter()
+            .enumerate()
+            .map(|(id, &(day, description, cad, printed))| Transaction {
+                id: id as i64 + 1,
+                account_type,
+                transaction_date: date(day),
+                description_1: description.into(),
+                cad: Money::cad(cad),
+                statement_balance: printed.map(Money::cad),
+                ..Transaction::dummy()
+            })
+            .collect();
+        transactions.sort_by_key(|t| (t.transaction_date, t.id));
+        transactions
+    }
+
+    fn chequing(balance: i64) -> ChequingAccount {
+        ChequingAccount {
+            user_id: 1,
+            account_number: 1001,
+            balance: Money::cad(balance),
+        }
+    }
+
+    const SAMPLE: &[(u32, &str, i64, Option<i64>)] = &[
+        (12, "Online Transfer", -85000, Some(31617)),
+        (9, "Online Transfer", -2664, Some(116617)),
+        (8, "e-Transfer", 2500, Some(119281)),
+        (7, "Online Banking transfer", -21345, Some(116781)),
+        (7, "Payroll Deposit", 75078, None),
+    ];
+
+    #[test]
+    fn test_running_balances_from_anchor() {
+        let entries = running_balances(
+            AccountType::Chequing,
+            Money::cad(31617),
+            statement(AccountType::Chequing, SAMPLE),
+        );
+        let balances: Vec<i64> = entries.iter().map(|e| e.balance.minor_units()).collect();
+        assert_eq!(
+            balances,
+            vec![116781 - 75078, 116781, 119281, 116617, 31617]
+        );
+
+        // paying a card down lowers what is owed
+        let entries = running_balances(
+            AccountType::Credit,
+            Money::cad(10000),
+            statement(
+                AccountType::Credit,
+                &[(2, "PAYMENT", 5000, None), (1, "LOBLAWS", -3000, None)],
+            ),
+        );
+        assert_eq!(entries[0].balance, Money::cad(15000));
+        assert_eq!(entries[1].balance, Money::cad(10000));
+    }
+
+    #[test]
+    fn test_statement_that_adds_up_is_balanced() {
+        let transactions = statement(AccountType::Chequing, SAMPLE);
+        let reconciliation = reconcile_account(&chequing(31617), &transactions);
+        assert_eq!(reconciliation.status, ReconciliationStatus::Balanced);
+        assert_eq!(reconciliation.checkpoints, 4);
+
+        let unverified = statement(AccountType::Chequing, &[(1, "Payroll", 100, None)]);
+        let reconciliation = reconcile_account(&chequing(0), &unverified);
+        assert_eq!(reconciliation.status, ReconciliationStatus::Unverified);
+        assert!(reconciliation.discrepancies.is_empty());
+    }
+
+    #[test]
+    fn test_missing_transaction() {
+        // the $26.64 on the 9th never made it in
+        let rows: Vec<_> = SAMPLE.iter().copied().filter(|r| r.0 != 9).collect();
+        let transactions = statement(AccountType::Chequing, &rows);
+        let reconciliation = reconcile_account(&chequing(31617), &transactions);
+        assert_eq!(reconciliation.status, ReconciliationStatus::Discrepancies);
+        assert_eq!(
+            reconciliation.discrepancies,
+            vec![Discrepancy::Missing {
+                after: date(8),
+                through: date(12),
+                amount: Money::cad(-2664),
+            }]
+        );
+
+        // a balance set after the last statement was printed
+        let transactions = statement(AccountType::Chequing, SAMPLE);
+        let reconciliation = reconcile_account(&chequing(46617), &transactions);
+        assert_eq!(
+            reconciliation.discrepancies,
+            vec![Discrepancy::Missing {
+                after: date(12),
+                through: date(12),
+                amount: Money::cad(15000),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_duplicated_transaction() {
+        let account = CreditAccount {
+            user_id: 1,
+            account_number: 2002,
+            balance_owed: Money::cad(9000),
+            credit_limit: Money::cad(500000),
+            interest_rate: 0.0,
+        };
+        let transactions = statement(
+            AccountType::Credit,
+            &[
+                (3, "LOBLAWS", -3000, Some(9000)),
+                (2, "TIM HORTONS", -500, Some(6000)),
+                (2, "TIM HORTONS", -500, None),
+                (1, "NETFLIX", -5500, Some(5500)),
+            ],
+        );
+        let reconciliation = reconcile_account(&account, &transactions);
+        assert_eq!(
+            reconciliation.discrepancies,
+            vec![Discrepancy::Duplicate {
+                transaction_id: 3,
+                date: date(2),
+                amount: Money::cad(-500),
+            }]
+        );
+    }
+}
@@ -0,0 +1,486 @@
+use chrono::{Months, NaiveDate, TimeDelta};
+use serde::Serialize;
+
+use crate::{database::Database, money::Money};
+
+// How far back charges are looked at, enough for two yearly charges
+const LOOKBACK_MONTHS: u32 = 25;
+
+// Share of the gaps that have to fit the cadence, and of the charges that have to
+// be near the usual amount. Leaves room for a skipped month or a one-off refund.
+const REGULAR_SHARE: f64 = 0.75;
+const AMOUNT_TOLERANCE: f64 = 0.25;
+
+// A charge at a known merchant, see `Database::merchant_charges`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Charge {
+    pub merchant_id: i64,
+    pub merchant: String,
+    pub category: String,
+    // the category's parent, empty for top-level categories
+    pub parent_category: String,
+    pub date: NaiveDate,
+    pub amount: Money,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cadence {
+    Weekly,
+    Biweekly,
+    Monthly,
+    Yearly,
+}
+
+impl Cadence {
+    const ALL: [Cadence; 4] = [
+        Cadence::Weekly,
+        Cadence::Biweekly,
+        Cadence::Monthly,
+        Cadence::Yearly,
+    ];
+
+    // Typical length in days
+    fn days(self) -> i64 {
+        match self {
+            Cadence::Weekly => 7,
+            Cadence::Biweekly => 14,
+            Cadence::Monthly => 30,
+            Cadence::Yearly => 365,
+        }
+    }
+
+    // Gaps between charges accepted as one period, months and years vary in length
+    // and charges landing on weekends or holidays move by a day or two
+    fn accepts(self, gap: i64) -> bool {
+        match self {
+            Cadence::Weekly => (6..=8).contains(&gap),
+            Cadence::Biweekly => (12..=16).contains(&gap),
+            Cadence::Monthly => (26..=35).contains(&gap),
+            Cadence::Yearly => (350..=380).contains(&gap),
+        }
+    }
+
+    fn min_occurrences(self) -> usize {
+        match self {
+            Cadence::Weekly => 4,
+            Cadence::Biweekly | Cadence::Monthly => 3,
+            Cadence::Yearly => 2,
+        }
+    }
+
+    // Days a charge may be late before it counts as missed
+    fn grace_days(self) -> i64 {
+        match self {
+            Cadence::Weekly => 3,
+            Cadence::Biweekly => 4,
+            Cadence::Monthly => 7,
+            Cadence::Yearly => 14,
+        }
+    }
+
+    pub fn next(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Cadence::Weekly => date + TimeDelta::days(7),
+            Cadence::Biweekly => date + TimeDelta::days(14),
+            Cadence::Monthly => date + Months::new(1),
+            Cadence::Yearly => date + Months::new(12),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecurringKind {
+    Subscription,
+    Bill,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecurringAlert {
+    // the latest charge differs from the one before it
+    PriceChanged {
+        previous: Money,
+        current: Money,
+        date: NaiveDate,
+    },
+    // the expected charge has not shown up
+    Missed {
+        expected: NaiveDate,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecurringPayment {
+    pub merchant_id: i64,
+    pub merchant: String,
+    pub category: String,
+    pub cadence: Cadence,
+    pub kind: RecurringKind,
+    pub occurrences: usize,
+    pub first_date: NaiveDate,
+    pub last_date: NaiveDate,
+    // latest charge, what the next one is expected to be
+    pub amount: Money,
+    pub next_date: NaiveDate,
+    pub alerts: Vec<RecurringAlert>,
+}
+
+fn median(mut values: Vec<i64>) -> i64 {
+    values.sort_unstable();
+    values[values.len() / 2]
+}
+
+fn mostly<T>(items: &[T], f: impl Fn(&T) -> bool) -> bool {
+    items.iter().filter(|item| f(item)).count() as f64 >= items.len() as f64 * REGULAR_SHARE
+}
+
+// Categorized bills and subscriptions are taken at their word, otherwise a
+// fixed price suggests a subscription and a varying one a bill
+fn kind(charge: &Charge, amounts: &[Money]) -> RecurringKind {
+    let is = |name: &str| {
+        charge.category.eq_ignore_ascii_case(name)
+            || charge.parent_category.eq_ignore_ascii_case(name)
+    };
+    if is("Subscriptions") {
+        RecurringKind::Subscription
+    } else if is("Bills") {
+        RecurringKind::Bill
+    } else if amounts.iter().all(|a| *a == amounts[0]) {
+        RecurringKind::Subscription
+    } else {
+        RecurringKind::Bill
+    }
+}
+
+// Decides whether one merchant's charges, oldest first, recur. Charges on the
+// same day count as one. Returns None when they don't recur or when more than
+// two expected charges in a row are missing, which means the payment ended.
+pub fn detect_series(charges: &[Charge], today: NaiveDate) -> Option<RecurringPayment> {
+    let mut days: Vec<(NaiveDate, Money)> = Vec::new();
+    for charge in charges {
+        match days.last_mut() {
+            Some((date, amount)) if *date == charge.date => *amount += charge.amount,
+            _ => days.push((charge.date, charge.amount)),
+        }
+    }
+
+    let gaps: Vec<i64> = days
+        .windows(2)
+        .map(|pair| (pair[1].0 - pair[0].0).num_days())
+        .collect();
+    if gaps.is_empty() {
+        return None;
+    }
+    let cadence = Cadence::ALL.into_iter().find(|cadence| {
+        days.len() >= cadence.min_occurrences() && mostly(&gaps, |gap| cadence.accepts(*gap))
+    })?;
+
+    let amounts: Vec<Money> = days.iter().map(|(_, amount)| *amount).collect();
+    let usual = median(amounts.iter().map(|a| a.minor_units().abs()).collect());
+    let near_usual = |amount: &Money| {
+        (amount.minor_units().abs() - usual).abs() as f64 <= usual as f64 * AMOUNT_TOLERANCE
+    };
+    if !mostly(&amounts, near_usual) {
+        return None;
+    }
+
+    let (first_date, _) = days[0];
+    let (last_date, amount) = days[days.len() - 1];
+    let next_date = cadence.next(last_date);
+    let late = (today - next_date).num_days();
+    if late > 2 * cadence.days() {
+        return None;
+    }
+
+    let mut alerts = Vec::new();
+    let previous = amounts[amounts.len() - 2];
+    if previous != amount {
+        alerts.push(RecurringAlert::PriceChanged {
+            previous,
+            current: amount,
+            date: last_date,
+        });
+    }
+    if late > cadence.grace_days() {
+        alerts.push(RecurringAlert::Missed {
+            expected: next_date,
+        });
+    }
+
+    let latest = &charges[charges.len() - 1];
+    Some(RecurringPayment {
+        merchant_id: latest.merchant_id,
+        merchant: latest.merchant.clone(),
+        category: latest.category.clone(),
+        cadence,
+        kind: kind(latest, &amounts),
+        occurrences: days.len(),
+        first_date,
+        last_date,
+        amount,
+        next_date,
+        alerts,
+    })
+}
+
+// Recurring payments among the user's spending, soonest expected first
+pub fn detect(
+    db: &Database,
+    user_id: i64,
+    today: NaiveDate,
+) -> Result<Vec<RecurringPayment>, rusqlite::Error> {
+    let charges = db.merchant_charges(user_id, today - Months::new(LOOKBACK_MONTHS))?;
+    // a merchant billing in two currencies is two series, charges come
+    // ordered by merchant, currency and date
+    let mut payments: Vec<RecurringPayment> = charges
+        .chunk_by(|a, b| {
+            a.merchant_id == b.merchant_id && a.amount.currency() == b.amount.currency()
+        })
+        .filter_map(|series| detect_series(series, today))
+        .collect();
+    payments.sort_by(|a, b| {
+        a.next_date
+            .cmp(&b.next_date)
+            .then(a.merchant.cmp(&b.merchant))
+    });
+    Ok(payments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::ChequingAccount;
+    use crate::transaction::Transaction;
+    use crate::user::User;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn series(category: &str, charges: &[(NaiveDate, i64)]) -> Vec<Charge> {
+        charges
+            .iter()
+            .map(|(date, cad)| Charge {
+                merchant_id: 1,
+                merchant: "Netflix".into(),
+                category: category.into(),
+                parent_category: String::new(),
+                date: *date,
+                amount: Money::cad(*cad),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_monthly_subscription_with_price_change() {
+        let charges = series(
+            "Subscriptions",
+            &[
+                (date(2025, 1, 15), -1599),
+                (date(2025, 2, 15), -1599),
+                (date(2025, 3, 17), -1599),
+                (date(2025, 4, 15), -1899),
+            ],
+        );
+        let payment = detect_series(&charges, date(2025, 4, 20)).unwrap();
+
+        assert_eq!(payment.cadence, Cadence::Monthly);
+        assert_eq!(payment.kind, RecurringKind::Subscription);
+        assert_eq!(payment.occurrences, 4);
+        assert_eq!(payment.amount, Money::cad(-1899));
+        assert_eq!(payment.next_date, date(2025, 5, 15));
+        assert_eq!(
+            payment.alerts,
+            vec![RecurringAlert::PriceChanged {
+                previous: Money::cad(-1599),
+                current: Money::cad(-1899),
+                date: date(2025, 4, 15),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_cadences() {
+        let every = |days: i64, count: i64| -> Vec<(NaiveDate, i64)> {
+            (0..count)
+                .map(|i| (date(2024, 1, 1) + TimeDelta::days(days * i), -1000))
+                .collect()
+        };
+        let today = |charges: &[(NaiveDate, i64)]| charges.last().unwrap().0;
+
+        let weekly = every(7, 5);
+        let payment = detect_series(&series("", &weekly), today(&weekly)).unwrap();
+        assert_eq!(payment.cadence, Cadence::Weekly);
+        // a fixed price without a category reads as a subscription
+        assert_eq!(payment.kind, RecurringKind::Subscription);
+
+        let biweekly = every(14, 3);
+        let payment = detect_series(&series("", &biweekly), today(&biweekly)).unwrap();
+        assert_eq!(payment.cadence, Cadence::Biweekly);
+
+        let yearly = vec![(date(2023, 6, 1), -9900), (date(2024, 6, 2), -9900)];
+        let payment = detect_series(&series("", &yearly), date(2024, 6, 3)).unwrap();
+        assert_eq!(payment.cadence, Cadence::Yearly);
+        assert_eq!(payment.next_date, date(2025, 6, 2));
+
+        // too few weekly charges to tell
+        assert_eq!(
+            detect_series(&series("", &every(7, 3)), date(2024, 1, 15)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_irregular_spending_is_not_recurring() {
+        let groceries = series(
+            "Groceries",
+            &[
+                (date(2025, 1, 2), -8000),
+                (date(2025, 1, 9), -4000),
+                (date(2025, 1, 20), -12000),
+                (date(2025, 2, 14), -6000),
+                (date(2025, 2, 16), -3000),
+            ],
+        );
+        assert_eq!(detect_series(&groceries, date(2025, 2, 20)), None);
+
+        // regular dates but wildly different amounts
+        let shopping = series(
+            "Shopping",
+            &[
+                (date(2025, 1, 1), -500),
+                (date(2025, 2, 1), -25000),
+                (date(2025, 3, 1), -3000),
+                (date(2025, 4, 1), -90000),
+            ],
+        );
+        assert_eq!(detect_series(&shopping, date(2025, 4, 2)), None);
+    }
+
+    #[test]
+    fn test_varying_bill_and_missed_charge() {
+        let hydro = series(
+            "Utilities",
+            &[
+                (date(2025, 1, 10), -9000),
+                (date(2025, 2, 10), -10500),
+                (date(2025, 3, 10), -9800),
+            ],
+        );
+        let mut charges = hydro.clone();
+        for charge in &mut charges {
+            charge.parent_category = "Bills".into();
+        }
+
+        let payment = detect_series(&charges, date(2025, 4, 12)).unwrap();
+        assert_eq!(payment.kind, RecurringKind::Bill);
+        assert!(!payment
+            .alerts
+            .iter()
+            .any(|a| matches!(a, RecurringAlert::Missed { .. })));
+
+        let payment = detect_series(&charges, date(2025, 4, 25)).unwrap();
+        assert!(payment.alerts.contains(&RecurringAlert::Missed {
+            expected: date(2025, 4, 10)
+        }));
+
+        // outside Bills a varying amount still reads as a bill
+        assert_eq!(
+            detect_series(&hydro, date(2025, 3, 11)).unwrap().kind,
+            RecurringKind::Bill
+        );
+
+        // long gone
+        assert_eq!(detect_series(&charges, date(2025, 7, 1)), None);
+    }
+
+    #[test]
+    fn test_detect_from_database() {
+        let db = Database::new(":memory:".to_string()).unwrap();
+        db.insert_user(&User {
+            id: 1,
+            name: "Alice".into(),
+        })
+        .unwrap();
+        db.insert_account(&ChequingAccount {
+            user_id: 1,
+            account_number: 1001,
+            balance: Money::cad(0),
+        })
+        .unwrap();
+        let rows = [
+            (date(2025, 1, 3), "SPOTIFY P1A2B3C4", -1199, "Subscriptions"),
+            (date(2025, 2, 3), "SPOTIFY P9Z8Y7X6", -1199, "Subscriptions"),
+            (date(2025, 3, 3), "SPOTIFY P5K5K5K5", -1199, "Subscriptions"),
+            (date(2025, 1, 5), "LOBLAWS 1019", -8000, "Groceries"),
+            (date(2025, 2, 21), "LOBLAWS 1019", -3000, "Groceries"),
+            (date(2025, 3, 2), "LOBLAWS 1019", -9000, "Groceries"),
+            // monthly card payments are transfers, not bills
+            (date(2025, 1, 25), "PAYMENT VISA", -20000, "Transfers"),
+            (date(2025, 2, 25), "PAYMENT VISA", -20000, "Transfers"),
+            (date(2025, 3, 25), "PAYMENT VISA", -20000, "Transfers"),
+        ];
+        for (transaction_date, description, cad, category) in rows {
+            db.insert_transaction(&Transaction {
+                user_id: 1,
+                account_number: 1001,
+                transaction_date,
+                description_1: description.into(),
+                cad: Money::cad(cad),
+                category: category.into(),
+                ..Transaction::dummy()
+            })
+            .unwrap();
+        }
+
+        let payments = detect(&db, 1, date(2025, 3, 28)).unwrap();
+        assert_eq!(payments.len(), 1);
+        assert_eq!(payments[0].merchant, "Spotify");
+        assert_eq!(payments[0].kind, RecurringKind::Subscription);
+        assert_eq!(payments[0].next_date, date(2025, 4, 3));
+        assert!(detect(&db, 2, date(2025, 3, 28)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_merchant_billing_in_two_currencies() {
+        let db = Database::new(":memory:".to_string()).unwrap();
+        db.insert_user(&User {
+            id: 1,
+            name: "Alice".into(),
+        })
+        .unwrap();
+        db.insert_account(&ChequingAccount {
+            user_id: 1,
+            account_number: 1001,
+            balance: Money::cad(0),
+        })
+        .unwrap();
+        // the last CAD charge and the first USD one fall on the same day
+        let rows = [
+            (date(2025, 1, 3), Money::cad(-1199)),
+            (date(2025, 2, 3), Money::cad(-1199)),
+            (date(2025, 3, 3), Money::cad(-1199)),
+            (date(2025, 3, 3), Money::usd(-999)),
+            (date(2025, 4, 3), Money::usd(-999)),
+        ];
+        for (transaction_date, amount) in rows {
+            let mut transaction = Transaction {
+                user_id: 1,
+                account_number: 1001,
+                transaction_date,
+                description_1: "SPOTIFY P1A2B3C4".into(),
+                category: "Subscriptions".into(),
+                ..Transaction::dummy()
+            };
+            transaction.set_amount(amount);
+            db.insert_transaction(&transaction).unwrap();
+        }
+
+        let payments = detect(&db, 1, date(2025, 4, 5)).unwrap();
+        assert_eq!(payments.len(), 1);
+        assert_eq!(payments[0].amount, Money::cad(-1199));
+        assert_eq!(payments[0].occurrences, 3);
+    }
+}
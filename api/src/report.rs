@@ -0,0 +1,381 @@
+use chrono::{Datelike, Months, NaiveDate};
+use serde::Serialize;
+
+use crate::{
+    database::Database,
+    money::{Currency, Money},
+};
+
+// Which transactions a report covers. Linked transfers and transactions filed
+// under the user's "Transfers" category are money moving between their own
+// accounts and are left out, otherwise a credit card payment would count as both.
+#[derive(Debug, Clone, Copy)]
+pub struct ReportFilter {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub account_number: Option<i64>,
+    // amounts are converted into this at the rate on each transaction's date
+    pub currency: Currency,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MonthTotals {
+    // YYYY-MM
+    pub month: String,
+    pub income: Money,
+    pub expenses: Money,
+    pub transactions: i64,
+}
+
+// A category or merchant ranked by spending within a month
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Ranked {
+    pub name: String,
+    pub total: Money,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MonthlySummary {
+    pub month: String,
+    pub income: Money,
+    // positive, money spent
+    pub expenses: Money,
+    // income minus expenses
+    pub net: Money,
+    // share of income kept, None without income
+    pub savings_rate: Option<f64>,
+    pub transactions: i64,
+    pub top_categories: Vec<Ranked>,
+    pub top_merchants: Vec<Ranked>,
+}
+
+impl MonthlySummary {
+    fn empty(month: String, currency: Currency) -> MonthlySummary {
+        MonthlySummary {
+            month,
+            income: Money::zero(currency),
+            expenses: Money::zero(currency),
+            net: Money::zero(currency),
+            savings_rate: None,
+            transactions: 0,
+            top_categories: Vec::new(),
+            top_merchants: Vec::new(),
+        }
+    }
+}
+
+// Every month touched by `from..=to` as YYYY-MM
+pub fn months_between(from: NaiveDate, to: NaiveDate) -> Vec<String> {
+    let mut months = Vec::new();
+    let mut month = from.with_day(1).unwrap();
+    while month <= to {
+        months.push(month.format("%Y-%m").to_string());
+        month = month + Months::new(1);
+    }
+    months
+}
+
+// Income, expenses and the top `top` categories and merchants for each month
+// in the filter, including months without transactions. All the summing is
+// done by SQLite.
+pub fn monthly(
+    db: &Database,
+    user_id: i64,
+    filter: &ReportFilter,
+    top: usize,
+) -> Result<Vec<MonthlySummary>, rusqlite::Error> {
+    let mut summaries: Vec<MonthlySummary> = months_between(filter.from, filter.to)
+        .into_iter()
+        .map(|month| MonthlySummary::empty(month, filter.currency))
+        .collect();
+    let find =
+        |summaries: &[MonthlySummary], month: &str| summaries.iter().position(|s| s.month == month);
+
+    for totals in db.monthly_totals(user_id, filter)? {
+        if let Some(idx) = find(&summaries, &totals.month) {
+            let summary = &mut summaries[idx];
+            summary.income = totals.income;
+            summary.expenses = totals.expenses;
+            summary.net = totals.income - totals.expenses;
+            summary.transactions = totals.transactions;
+            if totals.income.is_positive() {
+                summary.savings_rate =
+                    Some(summary.net.minor_units() as f64 / totals.income.minor_units() as f64);
+            }
+        }
+    }
+    for (month, ranked) in db.monthly_top_categories(user_id, filter, top)? {
+        if let Some(idx) = find(&summaries, &month) {
+            summaries[idx].top_categories.push(ranked);
+        }
+    }
+    for (month, ranked) in db.monthly_top_merchants(user_id, filter, top)? {
+        if let Some(idx) = find(&summaries, &month) {
+            summaries[idx].top_merchants.push(ranked);
+        }
+    }
+
+    Ok(summaries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::{AccountType, ChequingAccount, CreditAccount};
+    use crate::exchange;
+    use crate::transaction::Transaction;
+    use crate::user::User;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    // Two months of a chequing account and a credit card, plus another
+    // user's data that must never show up
+    fn fixture_db() -> Database {
+        let db = Database::new(":memory:".to_string()).unwrap();
+        for (id, name) in [(1, "Alice"), (2, "Bob")] {
+            db.insert_user(&User {
+                id,
+                name: name.into(),
+            })
+            .unwrap();
+        }
+        db.insert_account(&ChequingAccount {
+            user_id: 1,
+            account_number: 1001,
+            balance: Money::cad(0),
+        })
+        .unwrap();
+        db.insert_account(&CreditAccount {
+            user_id: 1,
+            account_number: 2002,
+            balance_owed: Money::cad(0),
+            credit_limit: Money::cad(500000),
+            interest_rate: 0.0,
+        })
+        .unwrap();
+        db.insert_account(&ChequingAccount {
+            user_id: 2,
+            account_number: 3003,
+            balance: Money::cad(0),
+        })
+        .unwrap();
+
+        let rows = [
+            (1, 1001, date(2025, 1, 2), "PAYROLL ACME", 300000, "Income"),
+            (
+                1,
+                1001,
+                date(2025, 1, 5),
+                "LOBLAWS 1019",
+                -12000,
+                "Groceries",
+            ),
+            (
+                1,
+                2002,
+                date(2025, 1, 6),
+                "TIM HORTONS #1",
+                -500,
+                "Coffee Shops",
+            ),
+            (
+                1,
+                2002,
+                date(2025, 1, 7),
+                "TIM HORTONS #1",
+                -700,
+                "Coffee Shops",
+            ),
+            (
+                1,
+                2002,
+                date(2025, 1, 9),
+                "LOBLAWS 1019",
+                -8000,
+                "Groceries",
+            ),
+            (1, 1001, date(2025, 1, 20), "RENT", -150000, "Rent"),
+            // card payment, counted on neither side
+            (
+                1,
+                1001,
+                date(2025, 1, 25),
+                "PAYMENT VISA",
+                -20000,
+                "Transfers",
+            ),
+            (
+                1,
+                2002,
+                date(2025, 1, 25),
+                "PAYMENT THANK YOU",
+                20000,
+                "Transfers",
+            ),
+            (1, 1001, date(2025, 3, 2), "PAYROLL ACME", 300000, "Income"),
+            (1, 2002, date(2025, 3, 3), "UBER EATS", -4000, "Restaurants"),
+            (
+                2,
+                3003,
+                date(2025, 1, 3),
+                "LOBLAWS 1019",
+                -99900,
+                "Groceries",
+            ),
+        ];
+        for (user_id, account_number, transaction_date, description, cad, category) in rows {
+            db.insert_transaction(&Transaction {
+                user_id,
+                account_number,
+                account_type: if account_number == 2002 {
+                    AccountType::Credit
+                } else {
+                    AccountType::Chequing
+                },
+                transaction_date,
+                description_1: description.to_string(),
+                cad: Money::cad(cad),
+                category: category.to_string(),
+                ..Transaction::dummy()
+            })
+            .unwrap();
+        }
+        db
+    }
+
+    fn filter(account_number: Option<i64>) -> ReportFilter {
+        ReportFilter {
+            from: date(2025, 1, 1),
+            to: date(2025, 3, 31),
+            account_number,
+            currency: Currency::CAD,
+        }
+    }
+
+    #[test]
+    fn test_months_between() {
+        assert_eq!(
+            months_between(date(2024, 11, 15), date(2025, 2, 1)),
+            vec!["2024-11", "2024-12", "2025-01", "2025-02"]
+        );
+        assert!(months_between(date(2025, 2, 1), date(2025, 1, 1)).is_empty());
+    }
+
+    #[test]
+    fn test_monthly_income_and_expenses() {
+        let db = fixture_db();
+        let report = monthly(&db, 1, &filter(None), 3).unwrap();
+
+        assert_eq!(report.len(), 3);
+        let january = &report[0];
+        assert_eq!(january.month, "2025-01");
+        assert_eq!(january.income, Money::cad(300000));
+        assert_eq!(january.expenses, Money::cad(171200));
+        assert_eq!(january.net, Money::cad(128800));
+        assert_eq!(january.transactions, 6);
+        assert!((january.savings_rate.unwrap() - 0.4293).abs() < 0.0001);
+
+        // nothing in February, but the month is still there
+        assert_eq!(report[1].month, "2025-02");
+        assert_eq!(report[1].transactions, 0);
+        assert_eq!(report[1].savings_rate, None);
+
+        assert_eq!(report[2].net, Money::cad(296000));
+    }
+
+    #[test]
+    fn test_top_categories_and_merchants() {
+        let db = fixture_db();
+        let january = monthly(&db, 1, &filter(None), 2).unwrap().remove(0);
+
+        let categories: Vec<(&str, i64, i64)> = january
+            .top_categories
+            .iter()
+            .map(|r| (r.name.as_str(), r.total.minor_units(), r.count))
+            .collect();
+        assert_eq!(
+            categories,
+            vec![("Rent", 150000, 1), ("Groceries", 20000, 2)]
+        );
+
+        let merchants: Vec<(&str, i64, i64)> = january
+            .top_merchants
+            .iter()
+            .map(|r| (r.name.as_str(), r.total.minor_units(), r.count))
+            .collect();
+        assert_eq!(merchants, vec![("Rent", 150000, 1), ("Loblaws", 20000, 2)]);
+    }
+
+    #[test]
+    fn test_filter_by_account() {
+        let db = fixture_db();
+        let report = monthly(&db, 1, &filter(Some(2002)), 5).unwrap();
+
+        assert_eq!(report[0].income, Money::cad(0));
+        assert_eq!(report[0].expenses, Money::cad(9200));
+        assert_eq!(report[0].top_merchants[0].name, "Loblaws");
+        assert_eq!(report[0].top_merchants[1].name, "Tim Hortons");
+        assert_eq!(report[0].top_merchants[1].count, 2);
+
+        // another user's account number filters to nothing
+        let report = monthly(&db, 1, &filter(Some(3003)), 5).unwrap();
+        assert!(report.iter().all(|m| m.transactions == 0));
+    }
+
+    #[test]
+    fn test_usd_card_is_converted_on_its_date() {
+        let db = fixture_db();
+        db.insert_account(&CreditAccount {
+            user_id: 1,
+            account_number: 4004,
+            balance_owed: Money::usd(0),
+            credit_limit: Money::usd(100000),
+            interest_rate: 0.0,
+        })
+        .unwrap();
+        let mut netflix = Transaction {
+            user_id: 1,
+            account_number: 4004,
+            account_type: AccountType::Credit,
+            transaction_date: date(2025, 1, 10),
+            description_1: "NETFLIX.COM".into(),
+            category: "Subscriptions".into(),
+            ..Transaction::dummy()
+        };
+        netflix.set_amount(Money::usd(-1599));
+        db.insert_transaction(&netflix).unwrap();
+
+        let rates = exchange::parse_rates(
+            "date,base,quote,rate\n2025-01-02,USD,CAD,1.44\n2025-01-13,USD,CAD,1.5\n",
+        )
+        .unwrap();
+        db.insert_exchange_rates(1, &rates).unwrap();
+
+        // the charge counts at the rate of the 10th, not the later one
+        let january = monthly(&db, 1, &filter(None), 5).unwrap().remove(0);
+        assert_eq!(january.expenses, Money::cad(171200 + 2303));
+        assert_eq!(january.transactions, 7);
+
+        // rates are the user's own, another user's do not apply
+        let other = exchange::parse_rates("date,base,quote,rate\n2025-01-09,USD,CAD,3\n").unwrap();
+        db.insert_exchange_rates(2, &other).unwrap();
+        let january = monthly(&db, 1, &filter(None), 5).unwrap().remove(0);
+        assert_eq!(january.expenses, Money::cad(171200 + 2303));
+
+        let in_usd = ReportFilter {
+            currency: Currency::USD,
+            ..filter(None)
+        };
+        let january = monthly(&db, 1, &in_usd, 5).unwrap().remove(0);
+        assert_eq!(january.income, Money::usd(208333));
+        let subscriptions = january
+            .top_categories
+            .iter()
+            .find(|r| r.name == "Subscriptions")
+            .unwrap();
+        assert_eq!(subscriptions.total, Money::usd(1599));
+    }
+}
@@ -0,0 +1,257 @@
+use core::fmt;
+
+use crate::{
+    database::Database,
+    transaction::{TransactionPage, TransactionQuery},
+};
+
+#[derive(Debug)]
+pub enum SearchError {
+    EmptyQuery,
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SearchError::EmptyQuery => write!(f, "Search query has no words to look for"),
+            SearchError::Database(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+impl From<rusqlite::Error> for SearchError {
+    fn from(err: rusqlite::Error) -> Self {
+        SearchError::Database(err)
+    }
+}
+
+enum Token {
+    Or,
+    Term { text: String, prefix: bool },
+}
+
+fn term(text: &str, prefix: bool) -> Option<Token> {
+    let text = text.replace('"', "");
+    if !text.chars().any(char::is_alphanumeric) {
+        return None;
+    }
+    Some(Token::Term { text, prefix })
+}
+
+fn tokens(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        match chars.peek() {
+            None => break,
+            Some('"') => {
+                chars.next();
+                // an unclosed quote runs to the end of the input
+                let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                let prefix = chars.next_if_eq(&'*').is_some();
+                tokens.extend(term(&phrase, prefix));
+            }
+            Some(_) => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && c != '"') {
+                    word.push(c);
+                }
+                if word == "OR" {
+                    tokens.push(Token::Or);
+                } else {
+                    let stem = word.trim_end_matches('*');
+                    tokens.extend(term(stem, stem.len() < word.len()));
+                }
+            }
+        }
+    }
+    tokens
+}
+
+// Turns what a user typed into an FTS5 query. Words match whole tokens, `word*`
+// matches by prefix, "quoted words" match as a phrase and OR matches either
+// side, all other terms must match. Terms are always quoted, so punctuation in
+// merchant names (AMAZON.CA, 7-ELEVEN) is never read as query syntax.
+// Returns None when there is nothing to search for.
+pub fn fts_query(input: &str) -> Option<String> {
+    let mut query = String::new();
+    let mut pending_or = false;
+    for token in tokens(input) {
+        match token {
+            Token::Or => pending_or = !query.is_empty(),
+            Token::Term { text, prefix } => {
+                if pending_or {
+                    query.push_str(" OR ");
+                } else if !query.is_empty() {
+                    query.push(' ');
+                }
+                pending_or = false;
+                query.push('"');
+                query.push_str(&text);
+                query.push('"');
+                if prefix {
+                    query.push('*');
+                }
+            }
+        }
+    }
+    (!query.is_empty()).then_some(query)
+}
+
+// Transactions whose descriptions match `input`, best match first. The filters
+// and page of `query` apply as in a listing, its sort order does not.
+pub fn search(
+    db: &Database,
+    user_id: i64,
+    input: &str,
+    query: &TransactionQuery,
+) -> Result<TransactionPage, SearchError> {
+    let fts = fts_query(input).ok_or(SearchError::EmptyQuery)?;
+    Ok(db.search_transactions(user_id, &fts, query)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::{AccountType, CreditAccount};
+    use crate::money::Money;
+    use crate::transaction::{Transaction, TransactionUpdate};
+    use crate::user::User;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(fts_query("amazon").unwrap(), r#""amazon""#);
+        assert_eq!(fts_query("amaz*").unwrap(), r#""amaz"*"#);
+        assert_eq!(
+            fts_query(r#""tim hortons" nepean"#).unwrap(),
+            r#""tim hortons" "nepean""#
+        );
+        assert_eq!(
+            fts_query("AMAZON OR AMZN").unwrap(),
+            r#""AMAZON" OR "AMZN""#
+        );
+        // stray operators and punctuation are not query syntax
+        assert_eq!(fts_query("OR amazon.ca OR").unwrap(), r#""amazon.ca""#);
+        assert_eq!(
+            fts_query("7-ELEVEN (gas)").unwrap(),
+            r#""7-ELEVEN" "(gas)""#
+        );
+        assert_eq!(
+            fts_query(r#""unclosed phrase"#).unwrap(),
+            r#""unclosed phrase""#
+        );
+        assert_eq!(fts_query("  * \"\" - "), None);
+    }
+
+    fn search_db() -> Database {
+        let db = Database::new(":memory:".to_string()).unwrap();
+        db.insert_user(&User {
+            id: 1,
+            name: "Alice".into(),
+        })
+        .unwrap();
+        db.insert_account(&CreditAccount {
+            user_id: 1,
+            account_number: 2002,
+            balance_owed: Money::cad(0),
+            credit_limit: Money::cad(500000),
+            interest_rate: 0.0,
+        })
+        .unwrap();
+        let rows = [
+            (1, "AMZN MKTP CA*2K3L91", "WWW.AMAZON.CA"),
+            (2, "AMAZON.CA", "AMAZON AMAZON"),
+            (3, "TIM HORTONS #1234", "NEPEAN"),
+            (4, "HORTONS TIM", ""),
+            (5, "AMAZON PRIME", ""),
+        ];
+        for (day, description_1, description_2) in rows {
+            db.insert_transaction(&Transaction {
+                user_id: 1,
+                account_type: AccountType::Credit,
+                account_number: 2002,
+                transaction_date: NaiveDate::from_ymd_opt(2025, 4, day).unwrap(),
+                description_1: description_1.into(),
+                description_2: description_2.into(),
+                cad: Money::cad(-1000 * day as i64),
+                ..Transaction::dummy()
+            })
+            .unwrap();
+        }
+        db
+    }
+
+    fn found(db: &Database, input: &str, query: &TransactionQuery) -> Vec<String> {
+        search(db, 1, input, query)
+            .unwrap()
+            .transactions
+            .into_iter()
+            .map(|t| t.description_1)
+            .collect()
+    }
+
+    #[test]
+    fn test_search_ranks_matches() {
+        let db = search_db();
+        let all = TransactionQuery::default();
+
+        assert_eq!(found(&db, "amazon OR amzn", &all).len(), 3);
+        // mentions amazon the most
+        assert_eq!(found(&db, "amazon", &all)[0], "AMAZON.CA");
+
+        assert_eq!(found(&db, "amaz*", &all).len(), 3);
+        assert_eq!(
+            found(&db, "\"tim hortons\"", &all),
+            vec!["TIM HORTONS #1234"]
+        );
+        assert_eq!(found(&db, "tim hortons", &all).len(), 2);
+        assert_eq!(found(&db, "nepean", &all), vec!["TIM HORTONS #1234"]);
+        assert!(found(&db, "walmart", &all).is_empty());
+    }
+
+    #[test]
+    fn test_search_applies_filters() {
+        let db = search_db();
+        let query = TransactionQuery {
+            from: NaiveDate::from_ymd_opt(2025, 4, 2),
+            limit: 1,
+            ..TransactionQuery::default()
+        };
+        let page = search(&db, 1, "amazon", &query).unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.transactions.len(), 1);
+
+        assert_eq!(search(&db, 2, "amazon", &query).unwrap().total, 0);
+        assert!(matches!(
+            search(&db, 1, " ** ", &query),
+            Err(SearchError::EmptyQuery)
+        ));
+    }
+
+    #[test]
+    fn test_search_follows_edits_and_deletes() {
+        let db = search_db();
+        let all = TransactionQuery::default();
+        let prime = search(&db, 1, "prime", &all).unwrap().transactions[0].id;
+
+        db.update_transaction(
+            1,
+            prime,
+            &TransactionUpdate {
+                description_1: Some("NETFLIX.COM".into()),
+                ..TransactionUpdate::default()
+            },
+        )
+        .unwrap();
+        assert!(found(&db, "prime", &all).is_empty());
+        assert_eq!(found(&db, "netflix", &all), vec!["NETFLIX.COM"]);
+
+        db.delete_transaction(1, prime).unwrap();
+        assert!(found(&db, "netflix", &all).is_empty());
+    }
+}
@@ -1,25 +1,144 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 
 use crate::{
-    account::{AccountType, BankAccount, ChequingAccount, CreditAccount, SavingsAccount},
-    calculate_hash,
+    account::{
+        AccountType, BankAccount, ChequingAccount, CreditAccount, InvestmentAccount, LoanAccount,
+        SavingsAccount,
+    },
+    category::explicit_null,
+    money::{Currency, Money},
 };
 
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use serde_json;
+use sha2::{Digest, Sha256};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transaction {
+    // transaction_id once stored, 0 before that
+    #[serde(default)]
+    pub id: i64,
     pub user_id: i64,
     pub account_type: AccountType,
     pub account_number: i64,
-    pub transaction_date: String,
+    pub transaction_date: NaiveDate,
     pub cheque_number: String,
     pub description_1: String,
     pub description_2: String,
-    pub cad: f64,
-    pub usd: f64,
+    pub cad: Money,
+    pub usd: Money,
+    // id of the category in the Categories table, `category` is its name
+    pub category_id: Option<i64>,
     pub category: String,
+    #[serde(default)]
+    pub notes: String,
+    // id in the Merchants table, `merchant` is its name
+    #[serde(default)]
+    pub merchant_id: Option<i64>,
+    #[serde(default)]
+    pub merchant: String,
+    // the other side when this is a transfer between the user's own accounts
+    #[serde(default)]
+    pub transfer_id: Option<i64>,
+    // account balance printed on the statement after this transaction, if any
+    #[serde(default)]
+    pub statement_balance: Option<Money>,
+    // which of `cad` and `usd` holds the amount, see `amount`
+    #[serde(default = "default_currency")]
+    pub currency: Currency,
+}
+
+fn default_currency() -> Currency {
+    Currency::CAD
+}
+
+// Edits a user can make to a stored transaction. Missing fields are left alone,
+// `"category_id": null` clears the category. The fingerprint keeps describing the
+// row as the bank sent it, so an edited transaction is still a duplicate on re-import.
+#[derive(Debug, Default, Deserialize)]
+pub struct TransactionUpdate {
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub category_id: Option<Option<i64>>,
+    // category by name, created if the user has none by that name
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub description_1: Option<String>,
+    #[serde(default)]
+    pub description_2: Option<String>,
+}
+
+// Page size of a transaction listing when none is asked for, and the largest allowed
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub enum TransactionSort {
+    #[serde(rename = "date")]
+    DateAsc,
+    // newest first
+    #[default]
+    #[serde(rename = "-date")]
+    DateDesc,
+    #[serde(rename = "amount")]
+    AmountAsc,
+    #[serde(rename = "-amount")]
+    AmountDesc,
+}
+
+// Filters and page of a transaction listing. Filters left as None match every
+// transaction, amounts compare against the signed amount in the transaction's
+// own currency.
+#[derive(Debug, Clone)]
+pub struct TransactionQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub account_number: Option<i64>,
+    pub account_type: Option<AccountType>,
+    // also matches the category's subcategories
+    pub category_id: Option<i64>,
+    pub merchant_id: Option<i64>,
+    pub uncategorized: bool,
+    pub min_amount: Option<Money>,
+    pub max_amount: Option<Money>,
+    // substring of the descriptions or notes, ignoring case
+    pub search: Option<String>,
+    pub sort: TransactionSort,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+impl Default for TransactionQuery {
+    fn default() -> Self {
+        TransactionQuery {
+            from: None,
+            to: None,
+            account_number: None,
+            account_type: None,
+            category_id: None,
+            merchant_id: None,
+            uncategorized: false,
+            min_amount: None,
+            max_amount: None,
+            search: None,
+            sort: TransactionSort::default(),
+            limit: DEFAULT_PAGE_SIZE,
+            offset: 0,
+        }
+    }
+}
+
+// One page of a transaction listing, `total` counts every match
+#[derive(Debug, Serialize)]
+pub struct TransactionPage {
+    pub transactions: Vec<Transaction>,
+    pub total: i64,
+    pub limit: usize,
+    pub offset: usize,
 }
 
 impl Transaction {
@@ -28,76 +147,96 @@ impl Transaction {
             user_id: 0,
             account_type: AccountType::Credit,
             account_number: i64::MAX,
-            transaction_date: "".to_string(),
+            transaction_date: NaiveDate::default(),
             cheque_number: "".to_string(),
             description_1: "TIM HORTONS #7525, NEPEAN".to_string(),
             description_2: "".to_string(),
-            cad: 0.0,
-            usd: 0.0,
+            cad: Money::cad(0),
+            usd: Money::usd(0),
+            id: 0,
+            category_id: None,
+            notes: String::new(),
+            merchant_id: None,
+            merchant: String::new(),
+            transfer_id: None,
+            statement_balance: None,
+            currency: Currency::CAD,
             category: "".to_string(),
         }
     }
 
-    pub fn from_rbc_csv(user_id: i64, line: String) -> Transaction {
-        let parts = line.split(',').collect::<Vec<_>>();
-        let account_type = parts[0].to_string().to_lowercase();
-        let real_account_type = AccountType::from_str(match account_type.as_str() {
-            "visa" => "credit",
-            _ => account_type.as_str(),
-        })
-        .unwrap_or_else(|e| {
-            println!("Failed to parse account type: {}", e);
-            AccountType::Unknown
-        });
+    // Identity of the transaction as the bank reports it, used to detect re-imports.
+    // Identical rows on the same day (two coffees at the same shop) share a key
+    // and are told apart by their occurrence index, see `fingerprints`.
+    pub fn fingerprint_key(&self) -> String {
+        fingerprint_key(
+            self.account_number,
+            &self.transaction_date.to_string(),
+            self.cad.minor_units(),
+            self.usd.minor_units(),
+            &self.description_1,
+            &self.description_2,
+        )
+    }
 
-        Transaction {
-            user_id,
-            account_type: real_account_type,
-            account_number: calculate_hash(&parts[1].to_string().replace("-", "")),
-            transaction_date: parts[2].to_string(),
-            cheque_number: parts[3].to_string(),
-            description_1: parts[4].trim().replace("\"", "").to_string(),
-            description_2: parts[5].trim().replace("\"", "").to_string(),
-            cad: parts[6].parse::<f64>().unwrap_or(0.0),
-            usd: parts[7].parse::<f64>().unwrap_or(0.0),
-            category: "".to_string(),
+    // The signed amount in the transaction's own currency
+    pub fn amount(&self) -> Money {
+        match self.currency {
+            Currency::USD => self.usd,
+            // other currencies are kept in the cad column
+            currency => Money::from_minor(self.cad.minor_units(), currency),
         }
     }
 
-    pub fn from_cibc_csv(user_id: i64, account_type: AccountType, line: String) -> Transaction {
-        let parts = line.split(',').collect::<Vec<_>>();
-        Transaction {
-            user_id,
-            account_type,
-            account_number: calculate_hash(&parts[0].to_string().replace("-", "")),
-            transaction_date: parts[1].to_string(),
-            cheque_number: parts[2].to_string(),
-            description_1: parts[3].trim().replace("\"", "").to_string(),
-            description_2: parts[4].trim().replace("\"", "").to_string(),
-            cad: parts[5].parse::<f64>().unwrap_or(0.0),
-            usd: parts[6].parse::<f64>().unwrap_or(0.0),
-            category: "".to_string(),
+    // Sets the amount and currency, the other column is zeroed
+    pub fn set_amount(&mut self, amount: Money) {
+        self.currency = amount.currency();
+        if amount.currency() == Currency::USD {
+            self.cad = Money::cad(0);
+            self.usd = amount;
+        } else {
+            self.cad = Money::from_minor(amount.minor_units(), Currency::CAD);
+            self.usd = Money::usd(0);
         }
     }
 
+    pub fn fingerprint(&self, occurrence: u32) -> String {
+        fingerprint_from_key(&self.fingerprint_key(), occurrence)
+    }
+
     pub fn extract_account(&self) -> Option<Box<dyn BankAccount>> {
         match self.account_type {
             AccountType::Savings => Some(Box::new(SavingsAccount::new(
                 self.user_id,
                 self.account_number,
-                0.0,
+                Money::zero(self.currency),
                 0.0,
             ))),
             AccountType::Credit => Some(Box::new(CreditAccount::new(
                 self.user_id,
                 self.account_number,
-                0.0,
-                0.0,
+                Money::zero(self.currency),
+                Money::zero(self.currency),
             ))),
             AccountType::Chequing => Some(Box::new(ChequingAccount::new(
                 self.user_id,
                 self.account_number,
-                0.0,
+                Money::zero(self.currency),
+            ))),
+            AccountType::NonRegistered
+            | AccountType::Tfsa
+            | AccountType::Rrsp
+            | AccountType::Fhsa => Some(Box::new(InvestmentAccount::new(
+                self.user_id,
+                self.account_number,
+                self.account_type,
+                Money::zero(self.currency),
+            ))),
+            AccountType::Loan => Some(Box::new(LoanAccount::new(
+                self.user_id,
+                self.account_number,
+                Money::zero(self.currency),
+                None,
             ))),
             AccountType::Unknown => None,
         }
@@ -120,18 +259,22 @@ impl Transaction {
             String::from(format!("{} {}", self.description_1, self.description_2).trim());
 
         // amount should be -1 for expense or 1 or income
-        let mut amount = self.cad;
+        let mut amount = self.amount();
 
-        if self.account_type == AccountType::Chequing && amount > 0.0 {
-            amount *= -1.0;
+        if self.account_type == AccountType::Chequing && amount.is_positive() {
+            amount = -amount;
             merchant = "".to_string();
         }
 
-        serde_json::json!({"name": name, "merchant": merchant, "amount": amount})
+        serde_json::json!({"name": name, "merchant": merchant, "amount": amount.to_f64()})
     }
 
+    // Expects the Transactions columns followed by the category name, as selected
+    // by the queries in `Database`
     pub fn from_row(row: &rusqlite::Row) -> Result<Transaction, rusqlite::Error> {
+        let currency = row.get(16)?;
         Ok(Transaction {
+            id: row.get(0)?,
             user_id: row.get(1)?,
             account_type: AccountType::from_str(&row.get::<_, String>(3)?).unwrap(),
             account_number: row.get(2)?,
@@ -139,9 +282,100 @@ impl Transaction {
             cheque_number: row.get(5)?,
             description_1: row.get(6)?,
             description_2: row.get(7)?,
-            cad: row.get(8)?,
-            usd: row.get(9)?,
-            category: row.get(10)?,
+            cad: Money::cad(row.get(8)?),
+            usd: Money::usd(row.get(9)?),
+            category_id: row.get(10)?,
+            notes: row.get::<_, Option<String>>(12)?.unwrap_or_default(),
+            merchant_id: row.get(13)?,
+            transfer_id: row.get(14)?,
+            statement_balance: row
+                .get::<_, Option<i64>>(15)?
+                .map(|balance| Money::from_minor(balance, currency)),
+            currency,
+            category: row.get::<_, Option<String>>(17)?.unwrap_or_default(),
+            merchant: row.get::<_, Option<String>>(18)?.unwrap_or_default(),
         })
     }
 }
+
+pub(crate) fn fingerprint_key(
+    account_number: i64,
+    transaction_date: &str,
+    cad: i64,
+    usd: i64,
+    description_1: &str,
+    description_2: &str,
+) -> String {
+    let normalize = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+    format!(
+        "{}\x1f{}\x1f{}\x1f{}\x1f{}\x1f{}",
+        account_number,
+        transaction_date.trim(),
+        cad,
+        usd,
+        normalize(description_1),
+        normalize(description_2),
+    )
+}
+
+pub(crate) fn fingerprint_from_key(key: &str, occurrence: u32) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(key.as_bytes());
+    hasher.update(b"\x1f");
+    hasher.update(occurrence.to_string().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+// Fingerprints for a batch in statement order. The nth identical row of a batch
+// gets occurrence n, so re-importing an overlapping export yields the same values.
+pub fn fingerprints(transactions: &[Transaction]) -> Vec<String> {
+    let mut occurrences: HashMap<String, u32> = HashMap::new();
+    transactions
+        .iter()
+        .map(|t| {
+            let key = t.fingerprint_key();
+            let occurrence = occurrences.entry(key.clone()).or_insert(0);
+            let fingerprint = fingerprint_from_key(&key, *occurrence);
+            *occurrence += 1;
+            fingerprint
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_is_stable() {
+        let a = Transaction::dummy();
+        let b = Transaction::dummy();
+        assert_eq!(a.fingerprint(0), b.fingerprint(0));
+        assert_ne!(a.fingerprint(0), a.fingerprint(1));
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_whitespace_and_category() {
+        let a = Transaction::dummy();
+        let mut b = Transaction::dummy();
+        b.description_1 = format!(" {}  ", a.description_1.replace(' ', "  "));
+        b.category = "Coffee".to_string();
+        assert_eq!(a.fingerprint(0), b.fingerprint(0));
+    }
+
+    #[test]
+    fn test_fingerprint_changes_with_amount() {
+        let a = Transaction::dummy();
+        let mut b = Transaction::dummy();
+        b.cad = Money::cad(1);
+        assert_ne!(a.fingerprint(0), b.fingerprint(0));
+    }
+
+    #[test]
+    fn test_fingerprints_number_same_day_duplicates() {
+        let batch = vec![Transaction::dummy(), Transaction::dummy()];
+        let fingerprints = fingerprints(&batch);
+        assert_eq!(fingerprints[0], batch[0].fingerprint(0));
+        assert_eq!(fingerprints[1], batch[1].fingerprint(1));
+    }
+}
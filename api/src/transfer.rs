@@ -0,0 +1,324 @@
+use core::fmt;
+
+use serde::Serialize;
+
+use crate::{database::Database, transaction::Transaction};
+
+// Days between the two sides of a transfer, e-transfers and card payments
+// can post a business day or two apart
+pub const DEFAULT_WINDOW_DAYS: i64 = 3;
+
+// Both sides of a transfer between a user's own accounts
+#[derive(Debug, Clone, Serialize)]
+pub struct TransferPair {
+    // the outflow
+    pub from: Transaction,
+    // the inflow
+    pub to: Transaction,
+}
+
+// An outflow and inflow that could be a transfer, see `Database::transfer_candidates`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferCandidate {
+    pub outflow_id: i64,
+    pub inflow_id: i64,
+    pub days_apart: i64,
+    // either side reads like a transfer or payment
+    pub described: bool,
+}
+
+#[derive(Debug)]
+pub enum TransferError {
+    NotFound,
+    AlreadyLinked,
+    NotATransfer,
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransferError::NotFound => write!(f, "Transaction not found"),
+            TransferError::AlreadyLinked => {
+                write!(f, "Transaction is already linked to a transfer")
+            }
+            TransferError::NotATransfer => write!(
+                f,
+                "A transfer needs opposite amounts in two different accounts"
+            ),
+            TransferError::Database(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for TransferError {}
+
+impl From<rusqlite::Error> for TransferError {
+    fn from(err: rusqlite::Error) -> Self {
+        TransferError::Database(err)
+    }
+}
+
+// Picks pairs so every transaction is used at most once, closest dates first
+// and, among those, pairs that describe themselves as transfers
+pub fn pick_pairs(mut candidates: Vec<TransferCandidate>) -> Vec<(i64, i64)> {
+    candidates.sort_by_key(|c| (c.days_apart, !c.described, c.outflow_id, c.inflow_id));
+    let mut used = std::collections::HashSet::new();
+    let mut pairs = Vec::new();
+    for candidate in candidates {
+        if used.contains(&candidate.outflow_id) || used.contains(&candidate.inflow_id) {
+            continue;
+        }
+        used.insert(candidate.outflow_id);
+        used.insert(candidate.inflow_id);
+        pairs.push((candidate.outflow_id, candidate.inflow_id));
+    }
+    pairs
+}
+
+// Links unlinked transactions that mirror each other across the user's accounts
+// within `window_days`, returning the number of pairs linked
+pub fn match_transfers(
+    db: &Database,
+    user_id: i64,
+    window_days: i64,
+) -> Result<usize, rusqlite::Error> {
+    let pairs = pick_pairs(db.transfer_candidates(user_id, window_days)?);
+    for (outflow_id, inflow_id) in &pairs {
+        db.link_transfer(user_id, *outflow_id, *inflow_id)?;
+    }
+    Ok(pairs.len())
+}
+
+// Links two transactions by hand, in either order
+pub fn link(
+    db: &Database,
+    user_id: i64,
+    first_id: i64,
+    second_id: i64,
+) -> Result<TransferPair, TransferError> {
+    let first = db
+        .get_transaction(user_id, first_id)?
+        .ok_or(TransferError::NotFound)?;
+    let second = db
+        .get_transaction(user_id, second_id)?
+        .ok_or(TransferError::NotFound)?;
+    if first.transfer_id.is_some() || second.transfer_id.is_some() {
+        return Err(TransferError::AlreadyLinked);
+    }
+    if first.amount().is_zero()
+        || first.amount() != -second.amount()
+        || first.account_number == second.account_number
+    {
+        return Err(TransferError::NotATransfer);
+    }
+
+    let (from, to) = if first.amount().is_negative() {
+        (first, second)
+    } else {
+        (second, first)
+    };
+    db.link_transfer(user_id, from.id, to.id)?;
+    Ok(TransferPair {
+        from: Transaction {
+            transfer_id: Some(to.id),
+            ..from
+        },
+        to: Transaction {
+            transfer_id: Some(from.id),
+            ..to
+        },
+    })
+}
+
+// Unlinks the transfer the transaction belongs to, both sides count as
+// spending and income again
+pub fn unlink(db: &Database, user_id: i64, transaction_id: i64) -> Result<(), TransferError> {
+    if !db.unlink_transfer(user_id, transaction_id)? {
+        return Err(TransferError::NotFound);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::{AccountType, ChequingAccount, CreditAccount};
+    use crate::money::{Currency, Money};
+    use crate::report::{self, ReportFilter};
+    use crate::user::User;
+    use chrono::NaiveDate;
+
+    fn candidate(outflow_id: i64, inflow_id: i64, days_apart: i64) -> TransferCandidate {
+        TransferCandidate {
+            outflow_id,
+            inflow_id,
+            days_apart,
+            described: false,
+        }
+    }
+
+    #[test]
+    fn test_pick_pairs_uses_each_transaction_once() {
+        let pairs = pick_pairs(vec![
+            candidate(1, 10, 2),
+            candidate(1, 11, 0),
+            candidate(2, 11, 1),
+            candidate(2, 10, 1),
+        ]);
+        assert_eq!(pairs, vec![(1, 11), (2, 10)]);
+
+        let described = TransferCandidate {
+            described: true,
+            ..candidate(3, 12, 1)
+        };
+        assert_eq!(
+            pick_pairs(vec![candidate(4, 12, 1), described]),
+            vec![(3, 12)]
+        );
+    }
+
+    fn date(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 5, d).unwrap()
+    }
+
+    fn transfer_db() -> Database {
+        let db = Database::new(":memory:".to_string()).unwrap();
+        for (id, name) in [(1, "Alice"), (2, "Bob")] {
+            db.insert_user(&User {
+                id,
+                name: name.into(),
+            })
+            .unwrap();
+        }
+        db.insert_account(&ChequingAccount {
+            user_id: 1,
+            account_number: 1001,
+            balance: Money::cad(0),
+        })
+        .unwrap();
+        db.insert_account(&CreditAccount {
+            user_id: 1,
+            account_number: 2002,
+            balance_owed: Money::cad(0),
+            credit_limit: Money::cad(500000),
+            interest_rate: 0.0,
+        })
+        .unwrap();
+        db.insert_account(&ChequingAccount {
+            user_id: 2,
+            account_number: 3003,
+            balance: Money::cad(0),
+        })
+        .unwrap();
+
+        let rows = [
+            (1, 1001, date(2), "PAYROLL ACME", 300000),
+            (1, 1001, date(5), "PAYMENT - VISA", -45000),
+            (1, 2002, date(6), "PAYMENT THANK YOU", 45000),
+            (
+                1,
+                1001,
+                date(10),
+                "Online Transfer to Deposit Account-7535",
+                -20000,
+            ),
+            (1, 2002, date(10), "LOBLAWS 1019", -20000),
+            // too far from the outflow on the 10th
+            (1, 2002, date(20), "REFUND", 20000),
+            // another user's matching amount
+            (2, 3003, date(5), "E-TRANSFER", 45000),
+        ];
+        for (user_id, account_number, transaction_date, description, cad) in rows {
+            db.insert_transaction(&Transaction {
+                user_id,
+                account_number,
+                account_type: if account_number == 2002 {
+                    AccountType::Credit
+                } else {
+                    AccountType::Chequing
+                },
+                transaction_date,
+                description_1: description.into(),
+                cad: Money::cad(cad),
+                ..Transaction::dummy()
+            })
+            .unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn test_match_links_card_payment() {
+        let db = transfer_db();
+        assert_eq!(match_transfers(&db, 1, DEFAULT_WINDOW_DAYS).unwrap(), 1);
+        // nothing left to match
+        assert_eq!(match_transfers(&db, 1, DEFAULT_WINDOW_DAYS).unwrap(), 0);
+
+        let pairs = db.get_transfers(1).unwrap();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].from.description_1, "PAYMENT - VISA");
+        assert_eq!(pairs[0].to.description_1, "PAYMENT THANK YOU");
+        assert_eq!(pairs[0].from.transfer_id, Some(pairs[0].to.id));
+        assert!(db.get_transfers(2).unwrap().is_empty());
+
+        // the payment is neither spending nor income any more
+        let filter = ReportFilter {
+            from: date(1),
+            to: date(31),
+            account_number: None,
+            currency: Currency::CAD,
+        };
+        let may = report::monthly(&db, 1, &filter, 5).unwrap().remove(0);
+        assert_eq!(may.income, Money::cad(320000));
+        assert_eq!(may.expenses, Money::cad(40000));
+
+        // a wider window also pairs the refund
+        assert_eq!(match_transfers(&db, 1, 10).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_link_and_unlink_by_hand() {
+        let db = transfer_db();
+        let transactions = db.get_transactions(1).unwrap();
+        let id = |description: &str| {
+            transactions
+                .iter()
+                .find(|t| t.description_1 == description)
+                .unwrap()
+                .id
+        };
+
+        assert!(matches!(
+            link(&db, 1, id("PAYROLL ACME"), id("PAYMENT - VISA")),
+            Err(TransferError::NotATransfer)
+        ));
+        assert!(matches!(
+            link(&db, 2, id("PAYMENT THANK YOU"), id("PAYMENT - VISA")),
+            Err(TransferError::NotFound)
+        ));
+
+        let pair = link(&db, 1, id("PAYMENT THANK YOU"), id("PAYMENT - VISA")).unwrap();
+        assert_eq!(pair.from.description_1, "PAYMENT - VISA");
+        assert!(matches!(
+            link(&db, 1, id("REFUND"), id("PAYMENT - VISA")),
+            Err(TransferError::AlreadyLinked)
+        ));
+
+        unlink(&db, 1, id("PAYMENT THANK YOU")).unwrap();
+        assert!(db.get_transfers(1).unwrap().is_empty());
+        assert!(matches!(
+            unlink(&db, 1, id("PAYMENT THANK YOU")),
+            Err(TransferError::NotFound)
+        ));
+
+        // deleting one side unlinks the other
+        link(&db, 1, id("PAYMENT THANK YOU"), id("PAYMENT - VISA")).unwrap();
+        db.delete_transaction(1, id("PAYMENT - VISA")).unwrap();
+        let remaining = db
+            .get_transaction(1, id("PAYMENT THANK YOU"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(remaining.transfer_id, None);
+    }
+}